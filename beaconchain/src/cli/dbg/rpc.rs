@@ -4,15 +4,20 @@ use std::{
     time::Instant,
 };
 
+use apibara_dna_common::{fixture::FixtureStore, ingestion::BlockIngestion};
 use clap::Subcommand;
 use error_stack::{Result, ResultExt};
 use serde::Serialize;
 use tracing::info;
 
 use crate::{
-    cli::rpc::RpcArgs,
+    cli::{rpc::RpcArgs, start::BeaconChainArgs},
     error::BeaconChainError,
-    provider::{http::BlockId, models},
+    ingestion::BeaconChainBlockIngestion,
+    provider::{
+        http::{BeaconApiProvider, BlockId},
+        models,
+    },
 };
 
 #[derive(Subcommand, Debug)]
@@ -62,6 +67,24 @@ pub enum DebugRpcCommand {
         #[arg(long)]
         json: Option<PathBuf>,
     },
+    /// Record the responses needed to ingest a range of slots.
+    ///
+    /// The recorded responses can be replayed offline, for example in tests.
+    Record {
+        #[clap(flatten)]
+        rpc: RpcArgs,
+        #[clap(flatten)]
+        beaconchain: BeaconChainArgs,
+        /// Directory where to store the responses.
+        #[arg(long)]
+        fixtures_dir: PathBuf,
+        /// First slot to record.
+        #[arg(long)]
+        from_block: u64,
+        /// Last slot to record (inclusive).
+        #[arg(long)]
+        to_block: u64,
+    },
 }
 
 impl DebugRpcCommand {
@@ -71,11 +94,12 @@ impl DebugRpcCommand {
             .change_context(BeaconChainError)
             .attach_printable("failed to create RPC provider")?;
 
-        let block_id = self.block_id()?;
-
         let start = Instant::now();
         let elapsed = match self {
-            DebugRpcCommand::GetHeader { json, .. } => {
+            DebugRpcCommand::GetHeader {
+                ref block_id, json, ..
+            } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting header");
                 let header = rpc_provider
                     .get_header(block_id)
@@ -89,7 +113,10 @@ impl DebugRpcCommand {
                 }
                 elapsed
             }
-            DebugRpcCommand::GetBlock { json, .. } => {
+            DebugRpcCommand::GetBlock {
+                ref block_id, json, ..
+            } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting block");
                 let block = rpc_provider
                     .get_block(block_id)
@@ -103,7 +130,10 @@ impl DebugRpcCommand {
                 }
                 elapsed
             }
-            DebugRpcCommand::GetBlobSidecar { json, .. } => {
+            DebugRpcCommand::GetBlobSidecar {
+                ref block_id, json, ..
+            } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting blob sidecar");
                 let sidecar = rpc_provider
                     .get_blob_sidecar(block_id)
@@ -117,7 +147,10 @@ impl DebugRpcCommand {
                 }
                 elapsed
             }
-            DebugRpcCommand::GetValidators { json, .. } => {
+            DebugRpcCommand::GetValidators {
+                ref block_id, json, ..
+            } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting validators");
                 let validators = rpc_provider
                     .get_validators(block_id)
@@ -131,6 +164,23 @@ impl DebugRpcCommand {
                 }
                 elapsed
             }
+            DebugRpcCommand::Record {
+                beaconchain,
+                fixtures_dir,
+                from_block,
+                to_block,
+                ..
+            } => {
+                record_blocks(
+                    rpc_provider,
+                    beaconchain,
+                    fixtures_dir,
+                    from_block,
+                    to_block,
+                )
+                .await?;
+                start.elapsed()
+            }
         };
 
         info!(elapsed = ?elapsed, "debug rpc command completed");
@@ -138,40 +188,58 @@ impl DebugRpcCommand {
         Ok(())
     }
 
-    fn rpc_provider(&self) -> Result<crate::provider::http::BeaconApiProvider, BeaconChainError> {
+    fn rpc_provider(&self) -> Result<BeaconApiProvider, BeaconChainError> {
         match self {
             DebugRpcCommand::GetHeader { rpc, .. } => rpc.to_beacon_api_provider(),
             DebugRpcCommand::GetBlock { rpc, .. } => rpc.to_beacon_api_provider(),
             DebugRpcCommand::GetBlobSidecar { rpc, .. } => rpc.to_beacon_api_provider(),
             DebugRpcCommand::GetValidators { rpc, .. } => rpc.to_beacon_api_provider(),
+            DebugRpcCommand::Record { rpc, .. } => rpc.to_beacon_api_provider(),
         }
     }
+}
 
-    fn block_id(&self) -> Result<BlockId, BeaconChainError> {
-        let block_id = match self {
-            DebugRpcCommand::GetHeader { block_id, .. } => block_id,
-            DebugRpcCommand::GetBlock { block_id, .. } => block_id,
-            DebugRpcCommand::GetBlobSidecar { block_id, .. } => block_id,
-            DebugRpcCommand::GetValidators { block_id, .. } => block_id,
-        };
-
-        match block_id.as_str() {
-            "head" => Ok(BlockId::Head),
-            "finalized" => Ok(BlockId::Finalized),
-            str_value => {
-                if let Ok(slot) = str_value.parse::<u64>() {
-                    return Ok(BlockId::Slot(slot));
-                }
-                if let Ok(block_root) = str_value.parse::<models::B256>() {
-                    return Ok(BlockId::BlockRoot(block_root));
-                }
-                Err(BeaconChainError)
-                    .attach_printable_lazy(|| format!("invalid block id: {}", str_value))
+fn parse_block_id(block_id: &str) -> Result<BlockId, BeaconChainError> {
+    match block_id {
+        "head" => Ok(BlockId::Head),
+        "finalized" => Ok(BlockId::Finalized),
+        str_value => {
+            if let Ok(slot) = str_value.parse::<u64>() {
+                return Ok(BlockId::Slot(slot));
             }
+            if let Ok(block_root) = str_value.parse::<models::B256>() {
+                return Ok(BlockId::BlockRoot(block_root));
+            }
+            Err(BeaconChainError)
+                .attach_printable_lazy(|| format!("invalid block id: {}", str_value))
         }
     }
 }
 
+async fn record_blocks(
+    provider: BeaconApiProvider,
+    beaconchain: BeaconChainArgs,
+    fixtures_dir: PathBuf,
+    from_block: u64,
+    to_block: u64,
+) -> Result<(), BeaconChainError> {
+    let provider = provider.with_recorder(FixtureStore::new(&fixtures_dir));
+    let ingestion = BeaconChainBlockIngestion::new(provider, beaconchain.to_beacon_chain_options());
+
+    for block_number in from_block..=to_block {
+        info!(block_number, "recording slot");
+        ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .change_context(BeaconChainError)
+            .attach_printable_lazy(|| format!("failed to record slot {block_number}"))?;
+    }
+
+    info!(dir = %fixtures_dir.display(), "recorded slots");
+
+    Ok(())
+}
+
 fn write_json(path: impl AsRef<Path>, data: &impl Serialize) -> Result<(), BeaconChainError> {
     use std::fs::File;
     use std::io::Write;
@@ -1,15 +1,21 @@
 mod dbg;
+mod repair;
 mod rpc;
 mod start;
 
+use apibara_dna_common::{
+    compaction::{GarbageCollectCommand, RecompactCommand},
+    dbg::DebugReorgsCommand,
+    snapshot::{ExportCommand, ImportCommand},
+};
 use clap::{Parser, Subcommand};
-use error_stack::Result;
+use error_stack::{Result, ResultExt};
 use start::StartCommand;
 use tokio_util::sync::CancellationToken;
 
 use crate::error::BeaconChainError;
 
-use self::dbg::DebugRpcCommand;
+use self::{dbg::DebugRpcCommand, repair::RepairCommand};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -26,8 +32,21 @@ pub enum Command {
     #[command(name = "dbg-rpc")]
     DebugRpc {
         #[clap(subcommand)]
-        command: DebugRpcCommand,
+        command: Box<DebugRpcCommand>,
     },
+    /// List the chain reorganizations seen by the ingestion service.
+    #[command(name = "dbg-reorgs")]
+    DebugReorgs(Box<DebugReorgsCommand>),
+    /// Rebuild segments and groups with a different segment or group size.
+    Recompact(Box<RecompactCommand>),
+    /// Re-ingest a range of finalized blocks and overwrite the stored data.
+    Repair(Box<RepairCommand>),
+    /// Export a snapshot of the bucket and ingestion state.
+    Export(Box<ExportCommand>),
+    /// Import a snapshot exported with the `export` command.
+    Import(Box<ImportCommand>),
+    /// Delete objects that are not reachable anymore.
+    Gc(Box<GarbageCollectCommand>),
 }
 
 impl Cli {
@@ -35,6 +54,12 @@ impl Cli {
         match self.command {
             Command::Start(command) => command.run(ct).await,
             Command::DebugRpc { command } => command.run().await,
+            Command::DebugReorgs(command) => command.run(ct).await.change_context(BeaconChainError),
+            Command::Recompact(command) => command.run(ct).await.change_context(BeaconChainError),
+            Command::Repair(command) => command.run(ct).await,
+            Command::Export(command) => command.run(ct).await.change_context(BeaconChainError),
+            Command::Import(command) => command.run(ct).await.change_context(BeaconChainError),
+            Command::Gc(command) => command.run(ct).await.change_context(BeaconChainError),
         }
     }
 }
@@ -0,0 +1,33 @@
+use apibara_dna_common::{compaction::RepairArgs, ChainSupport};
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cli::{rpc::RpcArgs, start::BeaconChainArgs},
+    error::BeaconChainError,
+    BeaconChainChainSupport,
+};
+
+#[derive(Args, Debug)]
+pub struct RepairCommand {
+    #[clap(flatten)]
+    beaconchain: BeaconChainArgs,
+    #[clap(flatten)]
+    rpc: RpcArgs,
+    #[clap(flatten)]
+    repair: RepairArgs,
+}
+
+impl RepairCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), BeaconChainError> {
+        let provider = self.rpc.to_beacon_api_provider()?;
+        let options = self.beaconchain.to_beacon_chain_options();
+        let beaconchain_chain = BeaconChainChainSupport::new(provider, options);
+
+        self.repair
+            .run(beaconchain_chain.block_ingestion(), ct)
+            .await
+            .change_context(BeaconChainError)
+    }
+}
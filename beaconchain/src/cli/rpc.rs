@@ -1,26 +1,30 @@
 use std::time::Duration;
 
+use apibara_dna_common::provider_pool::ProviderPoolArgs;
 use clap::Args;
 use error_stack::{Result, ResultExt};
-use reqwest::{
-    header::{HeaderMap, HeaderName, HeaderValue},
-    Url,
-};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 
 use crate::{
     error::BeaconChainError,
-    provider::http::{BeaconApiProvider, BeaconApiProviderOptions},
+    provider::{
+        events::BeaconEventsSubscription,
+        http::{BeaconApiProvider, BeaconApiProviderOptions},
+    },
 };
 
 #[derive(Args, Clone, Debug)]
 pub struct RpcArgs {
-    /// Beacon RPC URL.
+    /// Beacon RPC URLs.
+    ///
+    /// Pass multiple URLs to spread requests and fail over between them.
     #[arg(
         long = "rpc.url",
         env = "BEACON_RPC_URL",
+        value_delimiter = ',',
         default_value = "http://localhost:3500"
     )]
-    pub rpc_url: String,
+    pub rpc_url: Vec<String>,
 
     /// Timeout for normal requests.
     #[arg(
@@ -41,49 +45,82 @@ pub struct RpcArgs {
     /// Headers to send with the requests.
     #[arg(long = "rpc.headers", env = "BEACON_RPC_HEADERS")]
     pub rpc_headers: Vec<String>,
+
+    /// Subscribe to the node's `head` and `finalized_checkpoint` events.
+    ///
+    /// The node is polled while the event stream is disconnected.
+    #[arg(
+        long = "rpc.subscribe-events",
+        env = "BEACON_RPC_SUBSCRIBE_EVENTS",
+        default_value = "false"
+    )]
+    pub rpc_subscribe_events: bool,
+
+    #[clap(flatten)]
+    pub pool: ProviderPoolArgs,
 }
 
 impl RpcArgs {
     pub fn to_beacon_api_provider(&self) -> Result<BeaconApiProvider, BeaconChainError> {
-        let url = self
-            .rpc_url
-            .parse::<Url>()
-            .change_context(BeaconChainError)
-            .attach_printable("failed to parse RPC URL")
-            .attach_printable_lazy(|| format!("url: {}", self.rpc_url))?;
-
-        let headers = {
-            let mut headers = HeaderMap::default();
-
-            for kv in self.rpc_headers.iter() {
-                let (key, value) = kv
-                    .split_once(':')
-                    .ok_or(BeaconChainError)
-                    .attach_printable("invalid header")
-                    .attach_printable_lazy(|| format!("header: {}", kv))?;
-
-                headers.insert(
-                    key.parse::<HeaderName>()
-                        .change_context(BeaconChainError)
-                        .attach_printable("invalid header name")
-                        .attach_printable_lazy(|| format!("header name: {}", key))?,
-                    value
-                        .parse::<HeaderValue>()
-                        .change_context(BeaconChainError)
-                        .attach_printable("invalid header value")
-                        .attach_printable_lazy(|| format!("header value: {}", value))?,
-                );
-            }
-
-            headers
-        };
+        let upstreams = self
+            .pool
+            .to_upstreams(&self.rpc_url)
+            .change_context(BeaconChainError)?;
+        let pool = self
+            .pool
+            .to_provider_pool_options()
+            .change_context(BeaconChainError)?;
 
         let options = BeaconApiProviderOptions {
             timeout: Duration::from_secs(self.rpc_timeout_sec),
             validators_timeout: Duration::from_secs(self.rpc_validators_timeout_sec),
-            headers,
+            headers: self.headers()?,
+            pool,
         };
 
-        Ok(BeaconApiProvider::new(url, options))
+        BeaconApiProvider::new(upstreams, options).change_context(BeaconChainError)
+    }
+
+    /// Returns the event stream subscription, using the first RPC URL.
+    pub fn to_head_subscription(
+        &self,
+    ) -> Result<Option<BeaconEventsSubscription>, BeaconChainError> {
+        if !self.rpc_subscribe_events {
+            return Ok(None);
+        }
+
+        let url = self
+            .rpc_url
+            .first()
+            .ok_or(BeaconChainError)
+            .attach_printable("missing rpc url")?;
+
+        Ok(Some(BeaconEventsSubscription::new(url, self.headers()?)))
+    }
+
+    fn headers(&self) -> Result<HeaderMap<HeaderValue>, BeaconChainError> {
+        let mut headers = HeaderMap::default();
+
+        for kv in self.rpc_headers.iter() {
+            let (key, value) = kv
+                .split_once(':')
+                .ok_or(BeaconChainError)
+                .attach_printable("invalid header")
+                .attach_printable_lazy(|| format!("header: {}", kv))?;
+
+            headers.insert(
+                key.parse::<HeaderName>()
+                    .change_context(BeaconChainError)
+                    .attach_printable("invalid header name")
+                    .attach_printable_lazy(|| format!("header name: {}", key))?,
+                value
+                    .parse::<HeaderValue>()
+                    .change_context(BeaconChainError)
+                    .attach_printable("invalid header value")
+                    .attach_printable_lazy(|| format!("header value: {}", value))?,
+            );
+        }
+
+        Ok(headers)
     }
 }
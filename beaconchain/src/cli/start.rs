@@ -36,7 +36,8 @@ impl StartCommand {
     pub async fn run(self, ct: CancellationToken) -> Result<(), BeaconChainError> {
         info!("Starting Beaconchain DNA server");
         let provider = self.rpc.to_beacon_api_provider()?;
-        let options = self.beaconchain.to_beacon_chain_options();
+        let mut options = self.beaconchain.to_beacon_chain_options();
+        options.head_subscription = self.rpc.to_head_subscription()?;
         let beaconchain_chain = BeaconChainChainSupport::new(provider, options);
 
         run_server(beaconchain_chain, self.start, env!("CARGO_PKG_VERSION"), ct)
@@ -49,6 +50,7 @@ impl BeaconChainArgs {
     pub fn to_beacon_chain_options(&self) -> BeaconChainOptions {
         BeaconChainOptions {
             sample_validators: self.sample_validators,
+            head_subscription: None,
         }
     }
 }
@@ -8,12 +8,13 @@ use apibara_dna_common::{
         JoinFragment, JoinGroupFragment,
     },
     index::{BitmapIndexBuilder, ScalarValue},
-    ingestion::{BlockIngestion, IngestionError},
+    ingestion::{BlockIngestion, HeadNotifications, IngestionError},
     join::{JoinToManyIndex, JoinToManyIndexBuilder, JoinToOneIndex, JoinToOneIndexBuilder},
     Cursor, Hash,
 };
 use error_stack::{FutureExt, Result, ResultExt};
 use prost::Message;
+use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
 
 use crate::{
@@ -187,6 +188,11 @@ impl BeaconChainBlockIngestion {
 }
 
 impl BlockIngestion for BeaconChainBlockIngestion {
+    fn head_notifications(&self, ct: CancellationToken) -> Option<HeadNotifications> {
+        let subscription = self.options.head_subscription.clone()?;
+        Some(HeadNotifications::spawn(subscription, ct))
+    }
+
     #[tracing::instrument("beaconchain_get_head_cursor", skip(self), err(Debug), level = "debug")]
     async fn get_head_cursor(&self) -> Result<Cursor, IngestionError> {
         let cursor = self
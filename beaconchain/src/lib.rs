@@ -5,7 +5,7 @@ use fragment::{
     VALIDATOR_FRAGMENT_ID, VALIDATOR_FRAGMENT_NAME,
 };
 use ingestion::BeaconChainBlockIngestion;
-use provider::http::BeaconApiProvider;
+use provider::{events::BeaconEventsSubscription, http::BeaconApiProvider};
 
 pub mod cli;
 pub mod error;
@@ -23,6 +23,8 @@ pub struct BeaconChainChainSupport {
 #[derive(Debug, Clone)]
 pub struct BeaconChainOptions {
     pub sample_validators: u64,
+    /// Subscribe to head and finality events instead of polling.
+    pub head_subscription: Option<BeaconEventsSubscription>,
 }
 
 impl BeaconChainChainSupport {
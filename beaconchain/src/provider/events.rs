@@ -0,0 +1,94 @@
+//! Subscribe to the Beacon API event stream.
+use apibara_dna_common::ingestion::{HeadEvent, HeadEventStream, HeadSubscription, IngestionError};
+use error_stack::{Result, ResultExt};
+use futures::StreamExt;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT},
+    Client, Response,
+};
+
+/// Receive `head` and `finalized_checkpoint` events from the node.
+#[derive(Debug, Clone)]
+pub struct BeaconEventsSubscription {
+    client: Client,
+    url: String,
+    headers: HeaderMap<HeaderValue>,
+}
+
+impl BeaconEventsSubscription {
+    pub fn new(url: impl Into<String>, headers: HeaderMap<HeaderValue>) -> Self {
+        let url = url.into().trim_end_matches('/').to_string();
+        Self {
+            client: Client::new(),
+            url,
+            headers,
+        }
+    }
+}
+
+impl HeadSubscription for BeaconEventsSubscription {
+    async fn connect(&self) -> Result<HeadEventStream, IngestionError> {
+        let url = format!(
+            "{}/eth/v1/events?topics=head,finalized_checkpoint",
+            self.url
+        );
+
+        let response = self
+            .client
+            .get(&url)
+            .header(ACCEPT, "text/event-stream")
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .change_context(IngestionError::RpcRequest)
+            .attach_printable("failed to connect to event stream")
+            .attach_printable_lazy(|| format!("url: {url}"))?;
+
+        if !response.status().is_success() {
+            return Err(IngestionError::RpcRequest)
+                .attach_printable("failed to connect to event stream")
+                .attach_printable_lazy(|| format!("status: {}", response.status()));
+        }
+
+        let stream =
+            futures::stream::unfold((response, Vec::new()), |(response, buffer)| async move {
+                next_event(response, buffer).await
+            });
+
+        Ok(stream.boxed())
+    }
+}
+
+type EventStreamState = (Response, Vec<u8>);
+
+/// Read lines from the response until an event we're interested in.
+async fn next_event(
+    mut response: Response,
+    mut buffer: Vec<u8>,
+) -> Option<(Result<HeadEvent, IngestionError>, EventStreamState)> {
+    loop {
+        while let Some(position) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line = buffer.drain(..=position).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+
+            let event = match line.trim().strip_prefix("event:").map(str::trim) {
+                Some("head") => HeadEvent::Head,
+                Some("finalized_checkpoint") => HeadEvent::Finalized,
+                _ => continue,
+            };
+
+            return Some((Ok(event), (response, buffer)));
+        }
+
+        match response.chunk().await {
+            Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+            Ok(None) => return None,
+            Err(err) => {
+                let err = Err(err)
+                    .change_context(IngestionError::RpcRequest)
+                    .attach_printable("failed to read event stream");
+                return Some((err, (response, buffer)));
+            }
+        }
+    }
+}
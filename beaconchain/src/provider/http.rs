@@ -1,15 +1,23 @@
 use std::{fmt::Debug, time::Duration};
 
+use apibara_dna_common::{
+    fixture::FixtureStore,
+    provider_pool::{
+        ConsistentRead, ProviderPool, ProviderPoolOptions, UpstreamConfig, UpstreamError,
+    },
+};
 use error_stack::{Report, Result, ResultExt};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Client,
 };
+use serde::{de::DeserializeOwned, Serialize};
 
 use crate::provider::models;
 
 #[derive(Debug)]
 pub enum BeaconApiError {
+    Configuration,
     Request,
     NotFound,
     BadRequest,
@@ -34,6 +42,21 @@ pub enum BlockId {
 
 #[derive(Clone)]
 pub struct BeaconApiProvider {
+    backend: BeaconApiBackend,
+    recorder: Option<FixtureStore>,
+}
+
+#[derive(Clone)]
+enum BeaconApiBackend {
+    /// Send requests to the upstream beacon nodes.
+    Pool(ProviderPool<BeaconApiUpstream>),
+    /// Serve responses recorded previously.
+    Replay(FixtureStore),
+}
+
+/// A client for a single beacon node.
+#[derive(Clone)]
+struct BeaconApiUpstream {
     client: Client,
     url: String,
     options: BeaconApiProviderOptions,
@@ -51,10 +74,209 @@ pub struct BeaconApiProviderOptions {
     pub validators_timeout: Duration,
     /// Headers to send with the requests.
     pub headers: HeaderMap<HeaderValue>,
+    /// Upstream pool options.
+    pub pool: ProviderPoolOptions,
 }
 
 impl BeaconApiProvider {
-    pub fn new(url: impl Into<String>, options: BeaconApiProviderOptions) -> Self {
+    pub fn new(
+        upstreams: Vec<UpstreamConfig>,
+        options: BeaconApiProviderOptions,
+    ) -> Result<Self, BeaconApiError> {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|upstream| {
+                let provider = BeaconApiUpstream::new(upstream.url.clone(), options.clone());
+                (upstream, provider)
+            })
+            .collect();
+
+        let pool = ProviderPool::new(upstreams, options.pool)
+            .change_context(BeaconApiError::Configuration)?;
+
+        Ok(Self {
+            backend: BeaconApiBackend::Pool(pool),
+            recorder: None,
+        })
+    }
+
+    /// Create a provider that serves the responses recorded in the given fixture store.
+    pub fn replay(fixtures: FixtureStore) -> Self {
+        Self {
+            backend: BeaconApiBackend::Replay(fixtures),
+            recorder: None,
+        }
+    }
+
+    /// Record all responses to the given fixture store.
+    pub fn with_recorder(mut self, fixtures: FixtureStore) -> Self {
+        self.recorder = Some(fixtures);
+        self
+    }
+
+    pub async fn get_header(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::HeaderResponse, BeaconApiError> {
+        let key = block_id.to_string();
+        let pool = match &self.backend {
+            BeaconApiBackend::Pool(pool) => pool,
+            BeaconApiBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_header", &key).await;
+            }
+        };
+
+        let request = |upstream: BeaconApiUpstream| {
+            let block_id = block_id.clone();
+            async move { upstream.get_header(block_id).await }
+        };
+
+        let slot = |header: &models::HeaderResponse| header.data.header.message.slot;
+
+        let response = match block_id {
+            BlockId::Head => {
+                pool.request_consistent(ConsistentRead::Head, "get_header", request, slot)
+                    .await?
+            }
+            BlockId::Finalized => {
+                pool.request_consistent(ConsistentRead::Finalized, "get_header", request, slot)
+                    .await?
+            }
+            _ => pool.request("get_header", request).await?,
+        };
+
+        self.record("get_header", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_block(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::BeaconBlockResponse, BeaconApiError> {
+        let key = block_id.to_string();
+        let pool = match &self.backend {
+            BeaconApiBackend::Pool(pool) => pool,
+            BeaconApiBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_block", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_block(block_id).await }
+            })
+            .await?;
+
+        self.record("get_block", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_blob_sidecar(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::BlobSidecarResponse, BeaconApiError> {
+        let key = block_id.to_string();
+        let pool = match &self.backend {
+            BeaconApiBackend::Pool(pool) => pool,
+            BeaconApiBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_blob_sidecar", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_blob_sidecar", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_blob_sidecar(block_id).await }
+            })
+            .await?;
+
+        self.record("get_blob_sidecar", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_validators(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::ValidatorsResponse, BeaconApiError> {
+        let key = block_id.to_string();
+        let pool = match &self.backend {
+            BeaconApiBackend::Pool(pool) => pool,
+            BeaconApiBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_validators", &key).await;
+            }
+        };
+
+        let response = pool
+            .request("get_validators", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_validators(block_id).await }
+            })
+            .await?;
+
+        self.record("get_validators", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_block_root(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::BlockRootResponse, BeaconApiError> {
+        let key = block_id.to_string();
+        let pool = match &self.backend {
+            BeaconApiBackend::Pool(pool) => pool,
+            BeaconApiBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_root", &key).await;
+            }
+        };
+
+        let response = pool
+            .request("get_block_root", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_block_root(block_id).await }
+            })
+            .await?;
+
+        self.record("get_block_root", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    async fn record<T>(&self, method: &str, key: &str, value: &T) -> Result<(), BeaconApiError>
+    where
+        T: Serialize,
+    {
+        let Some(recorder) = &self.recorder else {
+            return Ok(());
+        };
+
+        recorder
+            .put(method, key, value)
+            .await
+            .change_context(BeaconApiError::Request)
+            .attach_printable("failed to record response")
+    }
+}
+
+async fn replay<T>(fixtures: &FixtureStore, method: &str, key: &str) -> Result<T, BeaconApiError>
+where
+    T: DeserializeOwned,
+{
+    fixtures
+        .get(method, key)
+        .await
+        .change_context(BeaconApiError::Request)?
+        .ok_or(BeaconApiError::NotFound)
+        .attach_printable("response not recorded")
+        .attach_printable_lazy(|| format!("method: {method}, key: {key}"))
+}
+
+impl BeaconApiUpstream {
+    fn new(url: impl Into<String>, options: BeaconApiProviderOptions) -> Self {
         let url = url.into().trim_end_matches('/').to_string();
         Self {
             client: Client::new(),
@@ -63,7 +285,7 @@ impl BeaconApiProvider {
         }
     }
 
-    pub async fn get_header(
+    async fn get_header(
         &self,
         block_id: BlockId,
     ) -> Result<models::HeaderResponse, BeaconApiError> {
@@ -71,7 +293,7 @@ impl BeaconApiProvider {
         self.send_request(request, self.options.timeout).await
     }
 
-    pub async fn get_block(
+    async fn get_block(
         &self,
         block_id: BlockId,
     ) -> Result<models::BeaconBlockResponse, BeaconApiError> {
@@ -79,7 +301,7 @@ impl BeaconApiProvider {
         self.send_request(request, self.options.timeout).await
     }
 
-    pub async fn get_blob_sidecar(
+    async fn get_blob_sidecar(
         &self,
         block_id: BlockId,
     ) -> Result<models::BlobSidecarResponse, BeaconApiError> {
@@ -93,7 +315,7 @@ impl BeaconApiProvider {
         }
     }
 
-    pub async fn get_validators(
+    async fn get_validators(
         &self,
         block_id: BlockId,
     ) -> Result<models::ValidatorsResponse, BeaconApiError> {
@@ -102,7 +324,7 @@ impl BeaconApiProvider {
             .await
     }
 
-    pub async fn get_block_root(
+    async fn get_block_root(
         &self,
         block_id: BlockId,
     ) -> Result<models::BlockRootResponse, BeaconApiError> {
@@ -239,6 +461,7 @@ impl BeaconApiErrorExt for Report<BeaconApiError> {
 impl std::fmt::Display for BeaconApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            BeaconApiError::Configuration => write!(f, "configuration error"),
             BeaconApiError::Request => write!(f, "failed to send request"),
             BeaconApiError::DeserializeResponse => write!(f, "failed to deserialize response"),
             BeaconApiError::NotFound => write!(f, "not found"),
@@ -252,6 +475,19 @@ impl std::fmt::Display for BeaconApiError {
 
 impl error_stack::Context for BeaconApiError {}
 
+impl UpstreamError for BeaconApiError {
+    fn is_upstream_failure(&self) -> bool {
+        matches!(
+            self,
+            BeaconApiError::Request
+                | BeaconApiError::Timeout
+                | BeaconApiError::Unauthorized
+                | BeaconApiError::ServerError
+                | BeaconApiError::DeserializeResponse
+        )
+    }
+}
+
 impl HeaderRequest {
     pub fn new(block_id: BlockId) -> Self {
         Self { block_id }
@@ -328,6 +564,7 @@ impl Default for BeaconApiProviderOptions {
             timeout: Duration::from_secs(5),
             validators_timeout: Duration::from_secs(60),
             headers: HeaderMap::default(),
+            pool: ProviderPoolOptions::default(),
         }
     }
 }
@@ -1,3 +1,4 @@
+pub mod events;
 pub mod http;
 pub mod models;
 pub mod utils;
@@ -0,0 +1,51 @@
+//! Ingest the slots in `tests/fixtures` and compare them with the golden files.
+//!
+//! The committed slots are synthetic, see `tests/fixtures/README.md`. Record real slots with:
+//!
+//! ```sh
+//! apibara-dna-beaconchain dbg-rpc record --fixtures-dir beaconchain/tests/fixtures --from-block N --to-block M
+//! DNA_UPDATE_GOLDEN=1 cargo test -p apibara-dna-beaconchain --test test_golden
+//! ```
+use std::path::PathBuf;
+
+use apibara_dna_beaconchain::{
+    ingestion::BeaconChainBlockIngestion, provider::http::BeaconApiProvider, BeaconChainOptions,
+};
+use apibara_dna_common::{
+    fixture::{assert_golden, FixtureStore},
+    ingestion::BlockIngestion,
+};
+
+#[tokio::test]
+async fn test_golden_blocks() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let fixtures = FixtureStore::new(&dir);
+
+    let slots = fixtures.keys("get_block_root").await.unwrap();
+    assert!(!slots.is_empty(), "no slots recorded in {}", dir.display());
+
+    // Must match the default `--beaconchain.sample-validators` used when recording.
+    let ingestion = BeaconChainBlockIngestion::new(
+        BeaconApiProvider::replay(fixtures),
+        BeaconChainOptions {
+            sample_validators: 32,
+            head_subscription: None,
+        },
+    );
+
+    for key in slots {
+        let slot = key
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("invalid slot fixture key: {key}"));
+        let (block_info, block) = ingestion
+            .ingest_block_by_number(slot)
+            .await
+            .unwrap_or_else(|err| panic!("failed to ingest slot {slot}: {err:?}"));
+
+        assert_eq!(block_info.number, slot);
+        assert!(!block.body.is_empty());
+
+        let data = rkyv::to_bytes::<rkyv::rancor::Error>(&block).unwrap();
+        assert_golden(dir.join(format!("golden/{slot}.bin")), &data);
+    }
+}
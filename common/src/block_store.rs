@@ -7,14 +7,16 @@ use crate::{
     chain::PendingBlockInfo,
     file_cache::{FileCache, FileFetch},
     fragment,
-    object_store::{DeleteOptions, GetOptions, ObjectETag, ObjectStore, PutOptions},
+    object_store::{
+        DeleteOptions, GetOptions, ObjectETag, ObjectStore, ObjectStoreResultExt, PutOptions,
+    },
     segment::{SegmentGroup, SerializedSegment},
     Cursor,
 };
 
-static BLOCK_PREFIX: &str = "block";
-static SEGMENT_PREFIX: &str = "segment";
-static GROUP_PREFIX: &str = "group";
+pub(crate) static BLOCK_PREFIX: &str = "block";
+pub(crate) static SEGMENT_PREFIX: &str = "segment";
+pub(crate) static GROUP_PREFIX: &str = "group";
 
 #[derive(Debug)]
 pub struct BlockStoreError;
@@ -121,15 +123,30 @@ impl BlockStoreReader {
         self.get_segment(first_cursor, "index")
     }
 
+    pub fn get_segment(&self, first_cursor: &Cursor, name: impl Into<String>) -> FileFetch {
+        self.get_segment_or_empty(first_cursor, name.into(), false)
+    }
+
+    /// Like `get_segment`, but returns empty data if the segment doesn't exist.
+    ///
+    /// Segments built before a body fragment was introduced don't have it.
+    pub fn get_body_segment(&self, first_cursor: &Cursor, name: impl Into<String>) -> FileFetch {
+        self.get_segment_or_empty(first_cursor, name.into(), true)
+    }
+
     #[tracing::instrument(
         name = "block_store_get_segment",
         skip_all,
         fields(name, cache_hit),
         level = "debug"
     )]
-    pub fn get_segment(&self, first_cursor: &Cursor, name: impl Into<String>) -> FileFetch {
+    fn get_segment_or_empty(
+        &self,
+        first_cursor: &Cursor,
+        name: String,
+        allow_missing: bool,
+    ) -> FileFetch {
         let current_span = tracing::Span::current();
-        let name = name.into();
         let key = format_segment_key(first_cursor, &name);
 
         current_span.record("name", &name);
@@ -144,6 +161,7 @@ impl BlockStoreReader {
                 async move {
                     match client.get(&key, GetOptions::default()).await {
                         Ok(response) => Ok(response.body),
+                        Err(err) if allow_missing && err.is_not_found() => Ok(Bytes::new()),
                         Err(err) => {
                             Err(anyhow!(err)).with_context(|| format!("segment key: {key}"))
                         }
@@ -181,6 +199,34 @@ impl BlockStoreReader {
 
         self.file_cache.index.get_or_fetch(&key, fetch_group)
     }
+
+    /// Remove the cached block, if any, and return its cache key.
+    pub fn invalidate_block(&self, cursor: &Cursor) -> String {
+        let key = format_block_key(cursor);
+        self.invalidate(&key);
+        key
+    }
+
+    /// Remove the cached segment, if any, and return its cache key.
+    pub fn invalidate_segment(&self, first_cursor: &Cursor, name: &str) -> String {
+        let key = format_segment_key(first_cursor, name);
+        self.invalidate(&key);
+        key
+    }
+
+    /// Remove the cached group, if any, and return its cache key.
+    pub fn invalidate_group(&self, cursor: &Cursor) -> String {
+        let key = format_group_key(cursor);
+        self.invalidate(&key);
+        key
+    }
+
+    /// Remove the cached file with the given key, if any.
+    pub fn invalidate(&self, key: &str) {
+        let key = key.to_string();
+        self.file_cache.general.remove(&key);
+        self.file_cache.index.remove(&key);
+    }
 }
 
 impl UncachedBlockStoreReader {
@@ -260,6 +306,66 @@ impl UncachedBlockStoreReader {
         Ok(response.body)
     }
 
+    /// List the names of all segments starting at the given block.
+    pub async fn list_segment_names(
+        &self,
+        first_block_number: u64,
+    ) -> Result<Vec<String>, BlockStoreError> {
+        let prefix = format_segment_prefix(first_block_number);
+
+        let names = self
+            .client
+            .list(&prefix, Default::default())
+            .await
+            .change_context(BlockStoreError)
+            .attach_printable("failed to list segments")
+            .attach_printable_lazy(|| format!("first block: {}", first_block_number))?
+            .object_ids
+            .iter()
+            .filter_map(|object_id| object_id.rsplit('/').next().map(ToString::to_string))
+            .collect();
+
+        Ok(names)
+    }
+
+    /// Copy all blocks (canonical, reorged and pending) with the given number to another store.
+    pub async fn copy_blocks_with_prefix(
+        &self,
+        block_number: u64,
+        target: &BlockStoreWriter,
+    ) -> Result<usize, BlockStoreError> {
+        let prefix = format_block_prefix(block_number);
+
+        let object_ids = self
+            .client
+            .list(&prefix, Default::default())
+            .await
+            .change_context(BlockStoreError)?
+            .object_ids;
+
+        for object_id in object_ids.iter() {
+            let key = self.client.relative_path(object_id);
+
+            let response = self
+                .client
+                .get(key, GetOptions::default())
+                .await
+                .change_context(BlockStoreError)
+                .attach_printable("failed to get block")
+                .attach_printable_lazy(|| format!("key: {}", key))?;
+
+            target
+                .client
+                .put(key, response.body, PutOptions::default())
+                .await
+                .change_context(BlockStoreError)
+                .attach_printable("failed to put block")
+                .attach_printable_lazy(|| format!("key: {}", key))?;
+        }
+
+        Ok(object_ids.len())
+    }
+
     #[tracing::instrument(name = "uncached_block_store_get_group", skip_all, level = "debug")]
     pub async fn get_group(&self, cursor: &Cursor) -> Result<Bytes, BlockStoreError> {
         let key = format_group_key(cursor);
@@ -357,6 +463,43 @@ impl BlockStoreWriter {
         Ok(())
     }
 
+    /// Delete all segments (index, header and fragments) starting at the given block.
+    pub async fn delete_segment(&self, first_block_number: u64) -> Result<(), BlockStoreError> {
+        let prefix = format_segment_prefix(first_block_number);
+        self.delete_all_with_prefix(&prefix).await
+    }
+
+    /// Delete the segment group starting at the given block.
+    pub async fn delete_group(&self, first_block_number: u64) -> Result<(), BlockStoreError> {
+        let prefix = format_group_prefix(first_block_number);
+        self.delete_all_with_prefix(&prefix).await
+    }
+
+    async fn delete_all_with_prefix(&self, prefix: &str) -> Result<(), BlockStoreError> {
+        for object_id in self
+            .client
+            .list(prefix, Default::default())
+            .await
+            .change_context(BlockStoreError)?
+            .object_ids
+            .iter()
+        {
+            self.client
+                .delete(
+                    object_id,
+                    DeleteOptions {
+                        fullpath: Some(true),
+                    },
+                )
+                .await
+                .change_context(BlockStoreError)
+                .attach_printable("failed to delete object")
+                .attach_printable_lazy(|| format!("object_id: {}", object_id))?;
+        }
+
+        Ok(())
+    }
+
     pub async fn put_segment(
         &self,
         first_cursor: &Cursor,
@@ -418,16 +561,24 @@ fn format_pending_block_key(block_number: u64, generation: u64) -> String {
     )
 }
 
-fn format_block_key(cursor: &Cursor) -> String {
+pub(crate) fn format_block_key(cursor: &Cursor) -> String {
     format!("{}/{}", format_block_prefix(cursor.number), cursor.hash)
 }
 
+fn format_segment_prefix(first_block_number: u64) -> String {
+    format!("{}/{:0>10}/", SEGMENT_PREFIX, first_block_number)
+}
+
 fn format_segment_key(first_block: &Cursor, name: &str) -> String {
-    format!("{}/{:0>10}/{}", SEGMENT_PREFIX, first_block.number, name)
+    format!("{}{}", format_segment_prefix(first_block.number), name)
+}
+
+fn format_group_prefix(first_block_number: u64) -> String {
+    format!("{}/{:0>10}/", GROUP_PREFIX, first_block_number)
 }
 
 fn format_group_key(first_block: &Cursor) -> String {
-    format!("{}/{:0>10}/index", GROUP_PREFIX, first_block.number)
+    format!("{}index", format_group_prefix(first_block.number))
 }
 
 impl error_stack::Context for BlockStoreError {}
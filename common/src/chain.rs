@@ -69,6 +69,52 @@ pub enum ReconnectAction {
 
 pub type ReorgMap = BTreeMap<Hash, Cursor>;
 
+/// A chain reorganization recorded in the canonical chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reorg {
+    /// The most recent block shared by the old and new chain.
+    pub target: Cursor,
+    /// The blocks removed from the chain, sorted by block number.
+    pub orphaned: Vec<Cursor>,
+}
+
+impl Reorg {
+    /// Group orphaned blocks by their reorg target.
+    ///
+    /// Blocks orphaned by different reorgs to the same target are grouped together.
+    pub fn from_orphaned(orphaned: impl IntoIterator<Item = (Cursor, Cursor)>) -> Vec<Self> {
+        let mut by_target = BTreeMap::<(u64, Hash), Reorg>::new();
+
+        for (block, target) in orphaned {
+            by_target
+                .entry((target.number, target.hash.clone()))
+                .or_insert_with(|| Reorg {
+                    target,
+                    orphaned: Vec::new(),
+                })
+                .orphaned
+                .push(block);
+        }
+
+        by_target
+            .into_values()
+            .map(|mut reorg| {
+                reorg.orphaned.sort_by_key(|cursor| cursor.number);
+                reorg
+            })
+            .collect()
+    }
+
+    /// How many blocks were removed from the chain.
+    pub fn depth(&self) -> u64 {
+        self.orphaned
+            .iter()
+            .map(|cursor| cursor.number - self.target.number)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Archive, Serialize, Deserialize)]
 pub struct CanonicalBlock {
     pub hash: Hash,
@@ -511,6 +557,31 @@ impl CanonicalChainSegment {
     }
 }
 
+impl CanonicalChainSegment {
+    /// Returns the blocks orphaned between `from` and `to` (inclusive), together with
+    /// their reorg target.
+    pub fn orphaned_blocks(&self, from: u64, to: u64) -> Vec<(Cursor, Cursor)> {
+        let canonical =
+            self.canonical.iter().enumerate().map(|(offset, block)| {
+                (self.info.first_block.number + offset as u64, &block.reorgs)
+            });
+        let extra = self
+            .extra_reorgs
+            .iter()
+            .map(|extra| (extra.block_number, &extra.reorgs));
+
+        canonical
+            .chain(extra)
+            .filter(|(block_number, _)| *block_number >= from && *block_number <= to)
+            .flat_map(|(block_number, reorgs)| {
+                reorgs.iter().map(move |(hash, target)| {
+                    (Cursor::new(block_number, hash.clone()), target.clone())
+                })
+            })
+            .collect()
+    }
+}
+
 impl Default for CanonicalChainBuilder {
     fn default() -> Self {
         Self::new()
@@ -553,7 +624,7 @@ impl ReconnectAction {
 mod tests {
     use crate::{new_test_cursor, Hash};
 
-    use super::{BlockInfo, CanonicalChainBuilder, ReconnectAction};
+    use super::{BlockInfo, CanonicalChainBuilder, ReconnectAction, Reorg};
 
     fn genesis_block(chain: u8) -> BlockInfo {
         let c = new_test_cursor(1_000, chain);
@@ -832,6 +903,28 @@ mod tests {
                 assert_eq!(canon.hash, new_test_cursor(block_number, 2).hash);
                 assert!(canon.reorgs.is_empty());
             }
+
+            let reorgs = Reorg::from_orphaned(segment.orphaned_blocks(1_000, 1_013));
+            assert_eq!(reorgs.len(), 2);
+
+            assert_eq!(reorgs[0].target, first_checkpoint.cursor());
+            assert_eq!(reorgs[0].depth(), 4);
+            assert_eq!(
+                reorgs[0]
+                    .orphaned
+                    .iter()
+                    .map(|cursor| cursor.number)
+                    .collect::<Vec<_>>(),
+                vec![1_004, 1_005, 1_006, 1_007]
+            );
+
+            assert_eq!(reorgs[1].target, second_checkpoint.cursor());
+            assert_eq!(reorgs[1].depth(), 5);
+            assert_eq!(reorgs[1].orphaned.len(), 5);
+
+            let reorgs = Reorg::from_orphaned(segment.orphaned_blocks(1_008, 1_013));
+            assert_eq!(reorgs.len(), 1);
+            assert_eq!(reorgs[0].orphaned.len(), 3);
         }
     }
 }
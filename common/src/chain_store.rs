@@ -4,11 +4,13 @@ use error_stack::{Result, ResultExt};
 use crate::{
     chain::CanonicalChainSegment,
     file_cache::{FileCache, FileCacheError},
-    object_store::{GetOptions, ObjectETag, ObjectStore, ObjectStoreResultExt, PutOptions},
+    object_store::{
+        DeleteOptions, GetOptions, ObjectETag, ObjectStore, ObjectStoreResultExt, PutOptions,
+    },
 };
 
-static CANONICAL_PREFIX: &str = "canon";
-static RECENT_CHAIN_SEGMENT_NAME: &str = "recent";
+pub(crate) static CANONICAL_PREFIX: &str = "canon";
+pub(crate) static RECENT_CHAIN_SEGMENT_NAME: &str = "recent";
 
 #[derive(Debug)]
 pub struct ChainStoreError;
@@ -40,6 +42,23 @@ impl ChainStore {
         self.put_impl(&filename, segment).await
     }
 
+    /// Delete the chain segment starting at the given block.
+    pub async fn delete(&self, first_block_number: u64) -> Result<(), ChainStoreError> {
+        let filename = self.segment_filename(first_block_number);
+        let key = self.format_key(&filename);
+
+        self.cache.general.remove(&key);
+
+        match self.client.delete(&key, DeleteOptions::default()).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.is_not_found() => Ok(()),
+            Err(err) => Err(err)
+                .change_context(ChainStoreError)
+                .attach_printable("failed to delete chain segment")
+                .attach_printable_lazy(|| format!("name: {}", filename)),
+        }
+    }
+
     pub async fn put_recent(
         &self,
         segment: &CanonicalChainSegment,
@@ -1,7 +1,7 @@
 use error_stack::{Result, ResultExt};
 use tracing::debug;
 
-use crate::chain::{CanonicalChainSegment, ReconnectAction};
+use crate::chain::{CanonicalChainSegment, ReconnectAction, Reorg};
 use crate::chain_store::ChainStore;
 use crate::Cursor;
 
@@ -35,7 +35,9 @@ pub enum NextCursor {
 pub struct FullCanonicalChain {
     store: ChainStore,
     pub(crate) starting_block: u64,
-    chain_segment_size: usize,
+    /// The first block that has not been expired by the retention policy.
+    pub(crate) first_available_block: u64,
+    pub(crate) chain_segment_size: usize,
     recent: CanonicalChainSegment,
 }
 
@@ -43,6 +45,7 @@ impl FullCanonicalChain {
     pub async fn initialize(
         store: ChainStore,
         starting_block: u64,
+        expired: Option<u64>,
         chain_segment_size: usize,
     ) -> Result<Self, ChainViewError> {
         let recent = store
@@ -53,9 +56,12 @@ impl FullCanonicalChain {
             .ok_or(ChainViewError)
             .attach_printable("recent canonical chain segment not found")?;
 
+        let first_available_block = expired.map(|block| block + 1).unwrap_or(starting_block);
+
         Ok(Self {
             store,
             starting_block,
+            first_available_block,
             chain_segment_size,
             recent,
         })
@@ -66,7 +72,7 @@ impl FullCanonicalChain {
         cursor: &Option<Cursor>,
     ) -> Result<NextCursor, ChainViewError> {
         let Some(cursor) = cursor else {
-            let first_available = self.get_canonical_impl(self.starting_block).await?;
+            let first_available = self.get_canonical_impl(self.first_available_block).await?;
             return Ok(NextCursor::Continue {
                 cursor: first_available,
                 is_head: false,
@@ -129,8 +135,8 @@ impl FullCanonicalChain {
             ));
         }
 
-        if block_number < self.starting_block {
-            let first_available = self.get_canonical_impl(self.starting_block).await?;
+        if block_number < self.first_available_block {
+            let first_available = self.get_canonical_impl(self.first_available_block).await?;
             return Ok(CanonicalCursor::BeforeAvailable(first_available));
         }
 
@@ -138,6 +144,30 @@ impl FullCanonicalChain {
         Ok(CanonicalCursor::Canonical(cursor))
     }
 
+    /// Returns the reorgs that orphaned blocks between `from` and `to` (inclusive).
+    pub async fn get_reorgs(&self, from: u64, to: u64) -> Result<Vec<Reorg>, ChainViewError> {
+        let mut orphaned = Vec::new();
+        let mut block_number = u64::max(from, self.first_available_block);
+
+        while block_number <= to {
+            let segment = self.get_chain_segment(block_number).await?;
+            orphaned.extend(segment.orphaned_blocks(block_number, to));
+
+            // The recent segment also contains reorgs after the current head.
+            if self.recent.info.first_block.number <= block_number {
+                break;
+            }
+
+            block_number = segment.info.last_block.number + 1;
+        }
+
+        Ok(Reorg::from_orphaned(orphaned))
+    }
+
+    pub fn set_expired(&mut self, expired: u64) {
+        self.first_available_block = u64::max(self.first_available_block, expired + 1);
+    }
+
     pub async fn refresh_recent(&mut self) -> Result<(), ChainViewError> {
         debug!("refreshing recent canonical chain segment");
 
@@ -185,7 +215,34 @@ impl FullCanonicalChain {
     }
 }
 
-fn chain_segment_start(block_number: u64, starting_block: u64, chain_segment_size: usize) -> u64 {
+/// Returns the first block of the chain segment containing `block_number`.
+///
+/// Blocks before the starting block belong to the first chain segment.
+pub(crate) fn chain_segment_start(
+    block_number: u64,
+    starting_block: u64,
+    chain_segment_size: usize,
+) -> u64 {
     let chain_segment_size = chain_segment_size as u64;
-    (block_number - starting_block) / chain_segment_size * chain_segment_size + starting_block
+    block_number.saturating_sub(starting_block) / chain_segment_size * chain_segment_size
+        + starting_block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::chain_segment_start;
+
+    #[test]
+    fn test_chain_segment_start() {
+        assert_eq!(chain_segment_start(1_000, 1_000, 100), 1_000);
+        assert_eq!(chain_segment_start(1_099, 1_000, 100), 1_000);
+        assert_eq!(chain_segment_start(1_100, 1_000, 100), 1_100);
+        assert_eq!(chain_segment_start(1_250, 1_000, 100), 1_200);
+    }
+
+    #[test]
+    fn test_chain_segment_start_before_starting_block() {
+        assert_eq!(chain_segment_start(0, 1_000, 100), 1_000);
+        assert_eq!(chain_segment_start(999, 1_000, 100), 1_000);
+    }
 }
@@ -7,6 +7,7 @@ pub struct ChainViewMetrics {
     pub finalized: Gauge<u64>,
     pub segmented: Gauge<u64>,
     pub grouped: Gauge<u64>,
+    pub expired: Gauge<u64>,
 }
 
 impl Default for ChainViewMetrics {
@@ -34,6 +35,10 @@ impl Default for ChainViewMetrics {
                 .u64_gauge("dna.chain_view.grouped")
                 .with_description("chain view's grouped block")
                 .build(),
+            expired: meter
+                .u64_gauge("dna.chain_view.expired")
+                .with_description("chain view's most recent expired block")
+                .build(),
         }
     }
 }
@@ -8,3 +8,5 @@ pub use self::error::ChainViewError;
 pub use self::full::{CanonicalCursor, NextCursor, ValidatedCursor};
 pub use self::sync::{chain_view_sync_loop, ChainViewSyncService};
 pub use self::view::ChainView;
+
+pub(crate) use self::view::group_start_block;
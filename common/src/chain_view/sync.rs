@@ -7,6 +7,7 @@ use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 use crate::{
+    block_store::BlockStoreReader,
     chain_store::ChainStore,
     file_cache::FileCache,
     ingestion::{IngestionStateClient, IngestionStateUpdate},
@@ -20,6 +21,7 @@ pub struct ChainViewSyncService {
     tx: tokio::sync::watch::Sender<Option<ChainView>>,
     etcd_client: EtcdClient,
     chain_store: ChainStore,
+    block_store: BlockStoreReader,
 }
 
 impl ChainViewSyncService {
@@ -29,11 +31,13 @@ impl ChainViewSyncService {
         etcd_client: EtcdClient,
         object_store: ObjectStore,
     ) -> Self {
+        let block_store = BlockStoreReader::new(object_store.clone(), chain_file_cache.clone());
         let chain_store = ChainStore::new(object_store, chain_file_cache);
         Self {
             tx,
             etcd_client,
             chain_store,
+            block_store,
         }
     }
 
@@ -93,6 +97,16 @@ impl ChainViewSyncService {
             .await
             .change_context(ChainViewError)?;
 
+        let expired = ingestion_state_client
+            .get_expired()
+            .await
+            .change_context(ChainViewError)?;
+
+        let ended = ingestion_state_client
+            .get_ended()
+            .await
+            .change_context(ChainViewError)?;
+
         loop {
             if ct.is_cancelled() {
                 return Ok(());
@@ -130,6 +144,7 @@ impl ChainViewSyncService {
         let canonical_chain = FullCanonicalChain::initialize(
             self.chain_store.clone(),
             starting_block,
+            expired,
             chain_segment_size,
         )
         .await?;
@@ -154,6 +169,7 @@ impl ChainViewSyncService {
             finalized,
             segmented,
             grouped,
+            ended,
             segment_size as u64,
             group_size as u64,
             canonical_chain,
@@ -207,12 +223,25 @@ impl ChainViewSyncService {
                         IngestionStateUpdate::Grouped(block) => {
                             chain_view.set_grouped_block(block).await;
                         }
+                        IngestionStateUpdate::Expired(block) => {
+                            chain_view.set_expired_block(block).await;
+                        }
                         IngestionStateUpdate::Pending(generation) => {
                             chain_view.set_pending_generation(generation).await;
                         }
                         IngestionStateUpdate::Ingested(_etag) => {
                             chain_view.refresh_recent().await?;
                         }
+                        IngestionStateUpdate::Ended(block) => {
+                            chain_view.set_ended_block(block).await;
+                        }
+                        IngestionStateUpdate::Repaired(keys) => {
+                            // Repair doesn't change the chain view, only the stored data.
+                            for key in keys {
+                                self.block_store.invalidate(&key);
+                            }
+                            continue;
+                        }
                     }
 
                     self.tx
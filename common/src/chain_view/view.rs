@@ -4,11 +4,11 @@ use error_stack::Result;
 use tokio::sync::{Notify, RwLock};
 use tracing::debug;
 
-use crate::Cursor;
+use crate::{chain::Reorg, Cursor};
 
 use super::{
     error::ChainViewError,
-    full::{FullCanonicalChain, NextCursor, ValidatedCursor},
+    full::{chain_segment_start, FullCanonicalChain, NextCursor, ValidatedCursor},
     metrics::ChainViewMetrics,
     CanonicalCursor,
 };
@@ -22,6 +22,7 @@ pub(crate) struct ChainViewInner {
     pending_generation: Option<u64>,
     segmented: Option<u64>,
     grouped: Option<u64>,
+    ended: Option<u64>,
     canonical: FullCanonicalChain,
     segment_size: u64,
     group_size: u64,
@@ -37,6 +38,7 @@ impl ChainView {
         finalized: u64,
         segmented: Option<u64>,
         grouped: Option<u64>,
+        ended: Option<u64>,
         segment_size: u64,
         group_size: u64,
         canonical: FullCanonicalChain,
@@ -46,6 +48,7 @@ impl ChainView {
             segmented,
             pending_generation: None,
             grouped,
+            ended,
             canonical,
             segment_size,
             group_size,
@@ -97,6 +100,12 @@ impl ChainView {
         inner.get_group_end_block(block)
     }
 
+    /// Returns the first block ingested, segments and groups start from this block.
+    pub async fn get_starting_block(&self) -> u64 {
+        let inner = self.0.read().await;
+        inner.canonical.starting_block
+    }
+
     pub async fn get_blocks_in_group(&self) -> u64 {
         let inner = self.0.read().await;
         inner.group_size * inner.segment_size
@@ -126,6 +135,11 @@ impl ChainView {
         inner.canonical.get_canonical(block_number).await
     }
 
+    pub async fn get_reorgs(&self, from: u64, to: u64) -> Result<Vec<Reorg>, ChainViewError> {
+        let inner = self.0.read().await;
+        inner.canonical.get_reorgs(from, to).await
+    }
+
     pub async fn get_head(&self) -> Result<Cursor, ChainViewError> {
         let inner = self.0.read().await;
         inner.canonical.get_head().await
@@ -163,9 +177,12 @@ impl ChainView {
         notify.notified().await;
     }
 
+    /// Returns the first block available in the chain view.
+    ///
+    /// This is the ingestion starting block, unless older blocks have been expired.
     pub async fn get_starting_cursor(&self) -> Result<Cursor, ChainViewError> {
         let inner = self.0.read().await;
-        let starting_block = inner.canonical.starting_block;
+        let starting_block = inner.canonical.first_available_block;
         match inner.canonical.get_canonical(starting_block).await? {
             CanonicalCursor::Canonical(cursor) => Ok(cursor),
             _ => Ok(Cursor::new_finalized(starting_block)),
@@ -180,6 +197,20 @@ impl ChainView {
         }
     }
 
+    pub async fn get_chain_segment_size(&self) -> u64 {
+        let inner = self.0.read().await;
+        inner.canonical.chain_segment_size as u64
+    }
+
+    pub async fn get_chain_segment_start_block(&self, block: u64) -> u64 {
+        let inner = self.0.read().await;
+        chain_segment_start(
+            block,
+            inner.canonical.starting_block,
+            inner.canonical.chain_segment_size,
+        )
+    }
+
     pub async fn get_grouped_cursor(&self) -> Result<Option<Cursor>, ChainViewError> {
         let inner = self.0.read().await;
         let Some(grouped) = inner.grouped else {
@@ -204,6 +235,14 @@ impl ChainView {
         }
     }
 
+    /// Returns the last block of the chain, if ingestion was bounded and reached it.
+    ///
+    /// No block after this one will ever be ingested.
+    pub async fn get_ended_block(&self) -> Option<u64> {
+        let inner = self.0.read().await;
+        inner.ended
+    }
+
     pub async fn get_pending_generation(&self) -> Option<u64> {
         let inner = self.0.read().await;
         inner.pending_generation
@@ -235,6 +274,19 @@ impl ChainView {
         inner.grouped = Some(block);
     }
 
+    pub(crate) async fn set_ended_block(&self, block: Option<u64>) {
+        let mut inner = self.0.write().await;
+        inner.ended = block;
+        // Wake up streams waiting at the head so they can terminate.
+        inner.head_notify.notify_waiters();
+    }
+
+    pub(crate) async fn set_expired_block(&self, block: u64) {
+        let mut inner = self.0.write().await;
+        inner.metrics.expired.record(block, &[]);
+        inner.canonical.set_expired(block);
+    }
+
     pub(crate) async fn refresh_recent(&self) -> Result<(), ChainViewError> {
         let mut inner = self.0.write().await;
 
@@ -314,9 +366,16 @@ impl ChainViewInner {
     }
 
     pub fn get_group_start_block(&self, block: u64) -> u64 {
-        let starting_block = self.canonical.starting_block;
         let blocks_in_group = self.group_size * self.segment_size;
-        let group_count = (block - starting_block) / blocks_in_group;
-        starting_block + group_count * blocks_in_group
+        group_start_block(block, self.canonical.starting_block, blocks_in_group)
     }
 }
+
+/// Returns the first block of the group containing `block`.
+///
+/// Groups start at the chain's starting block, which doesn't need to be a multiple of the
+/// group size.
+pub(crate) fn group_start_block(block: u64, starting_block: u64, blocks_in_group: u64) -> u64 {
+    let group_count = (block - starting_block) / blocks_in_group;
+    starting_block + group_count * blocks_in_group
+}
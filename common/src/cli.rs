@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
 use apibara_etcd::{AuthOptions, EtcdClient, EtcdClientError, EtcdClientOptions};
 use aws_config::{meta::region::RegionProviderChain, Region};
@@ -77,6 +77,17 @@ pub struct EtcdArgs {
         default_value = "300"
     )]
     pub etcd_auth_token_ttl: u64,
+    /// Store the coordination state in this file instead of etcd.
+    ///
+    /// Use this to run ingestion, compaction and the server in a single process without an
+    /// etcd cluster. The file can't be shared between processes, a second process using it fails
+    /// to start.
+    #[arg(
+        long = "etcd.embedded-state-file",
+        env = "DNA_ETCD_EMBEDDED_STATE_FILE",
+        conflicts_with = "etcd_endpoints"
+    )]
+    pub etcd_embedded_state_file: Option<PathBuf>,
 }
 
 impl ObjectStoreArgs {
@@ -170,6 +181,10 @@ impl EtcdArgs {
             auth,
         };
 
+        if let Some(path) = self.etcd_embedded_state_file {
+            return EtcdClient::embedded(path, options).await;
+        }
+
         EtcdClient::connect(self.etcd_endpoints, options).await
     }
 }
@@ -1,4 +1,9 @@
+use std::time::Duration;
+
 use clap::Args;
+use error_stack::{Result, ResultExt};
+
+use super::{gc::GarbageCollectorOptions, CompactionError};
 
 #[derive(Args, Debug)]
 pub struct CompactionArgs {
@@ -19,13 +24,155 @@ pub struct CompactionArgs {
         default_value = "100"
     )]
     pub compaction_group_size: usize,
+    /// How many blocks of history to keep.
+    ///
+    /// Segment groups older than `head - retention_blocks` are deleted, together with their
+    /// segments and canonical chain segments. If not set, history is kept forever.
+    #[clap(
+        long = "compaction.retention-blocks",
+        env = "DNA_COMPACTION_RETENTION_BLOCKS"
+    )]
+    pub compaction_retention_blocks: Option<u64>,
+    /// How long to keep history, for example "30d".
+    ///
+    /// The period is converted to a number of blocks using `compaction.block-time`, so it's only
+    /// as accurate as the chain's average block time.
+    #[clap(
+        long = "compaction.retention-period",
+        env = "DNA_COMPACTION_RETENTION_PERIOD",
+        conflicts_with = "compaction_retention_blocks",
+        requires = "compaction_block_time"
+    )]
+    pub compaction_retention_period: Option<String>,
+    /// The chain's average block time, for example "2s".
+    #[clap(long = "compaction.block-time", env = "DNA_COMPACTION_BLOCK_TIME")]
+    pub compaction_block_time: Option<String>,
+    /// How often to delete orphaned objects, for example "6h".
+    ///
+    /// If not set, orphaned objects are never deleted.
+    #[clap(long = "compaction.gc-interval", env = "DNA_COMPACTION_GC_INTERVAL")]
+    pub compaction_gc_interval: Option<String>,
+    /// Only delete orphaned objects last modified before this delay, for example "1h".
+    #[clap(
+        long = "compaction.gc-safety-delay",
+        env = "DNA_COMPACTION_GC_SAFETY_DELAY",
+        default_value = "1h"
+    )]
+    pub compaction_gc_safety_delay: String,
+    /// Log the orphaned objects without deleting them.
+    #[clap(long = "compaction.gc-dry-run", env = "DNA_COMPACTION_GC_DRY_RUN")]
+    pub compaction_gc_dry_run: bool,
+    /// How many segments and groups to build concurrently.
+    #[clap(
+        long = "compaction.workers",
+        env = "DNA_COMPACTION_WORKERS",
+        default_value = "1"
+    )]
+    pub compaction_workers: usize,
+    /// Share segment compaction between all nodes with compaction enabled.
+    ///
+    /// Segments are leased through etcd, so nodes not holding the compaction lock help
+    /// build segments while the lock holder advances the segmented watermark.
+    #[clap(long = "compaction.distributed", env = "DNA_COMPACTION_DISTRIBUTED")]
+    pub compaction_distributed: bool,
 }
 
 impl CompactionArgs {
-    pub fn to_compaction_options(&self) -> super::CompactionServiceOptions {
-        super::CompactionServiceOptions {
+    pub fn to_compaction_options(
+        &self,
+    ) -> Result<super::CompactionServiceOptions, CompactionError> {
+        let gc_interval = self
+            .compaction_gc_interval
+            .as_ref()
+            .map(|interval| {
+                duration_str::parse_std(interval).or_else(|err| {
+                    Err(CompactionError)
+                        .attach_printable("failed to parse gc interval")
+                        .attach_printable(format!("error: {}", err))
+                })
+            })
+            .transpose()?;
+        let gc_safety_delay =
+            duration_str::parse_std(&self.compaction_gc_safety_delay).or_else(|err| {
+                Err(CompactionError)
+                    .attach_printable("failed to parse gc safety delay")
+                    .attach_printable(format!("error: {}", err))
+            })?;
+
+        let retention_blocks = match (
+            self.compaction_retention_period.as_ref(),
+            self.compaction_block_time.as_ref(),
+        ) {
+            (Some(period), Some(block_time)) => {
+                let period = duration_str::parse_std(period).or_else(|err| {
+                    Err(CompactionError)
+                        .attach_printable("failed to parse retention period")
+                        .attach_printable(format!("error: {}", err))
+                })?;
+                let block_time = duration_str::parse_std(block_time).or_else(|err| {
+                    Err(CompactionError)
+                        .attach_printable("failed to parse block time")
+                        .attach_printable(format!("error: {}", err))
+                })?;
+                Some(retention_blocks_for_period(period, block_time)?)
+            }
+            (Some(_), None) => {
+                return Err(CompactionError)
+                    .attach_printable("retention period requires the chain block time");
+            }
+            (None, _) => self.compaction_retention_blocks,
+        };
+
+        Ok(super::CompactionServiceOptions {
             segment_size: self.compaction_segment_size,
             group_size: self.compaction_group_size,
-        }
+            retention_blocks,
+            gc_interval,
+            gc: GarbageCollectorOptions {
+                safety_delay: gc_safety_delay,
+                dry_run: self.compaction_gc_dry_run,
+            },
+            workers: self.compaction_workers,
+            distributed: self.compaction_distributed,
+        })
+    }
+}
+
+/// Returns how many blocks are produced in the retention period, rounded up.
+fn retention_blocks_for_period(
+    period: Duration,
+    block_time: Duration,
+) -> Result<u64, CompactionError> {
+    if block_time.is_zero() {
+        return Err(CompactionError).attach_printable("block time must be greater than zero");
+    }
+
+    Ok(period.as_millis().div_ceil(block_time.as_millis().max(1)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retention_blocks_for_period;
+
+    #[test]
+    fn test_retention_blocks_for_period() {
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        assert_eq!(
+            retention_blocks_for_period(30 * day, Duration::from_secs(2)).unwrap(),
+            1_296_000
+        );
+        assert_eq!(
+            retention_blocks_for_period(day, Duration::from_millis(250)).unwrap(),
+            345_600
+        );
+        // Keep slightly more history rather than less.
+        assert_eq!(
+            retention_blocks_for_period(Duration::from_secs(25), Duration::from_secs(12)).unwrap(),
+            3
+        );
+        assert!(retention_blocks_for_period(day, Duration::ZERO).is_err());
     }
 }
@@ -0,0 +1,796 @@
+//! Garbage collection of objects that are not reachable anymore.
+//!
+//! The following objects are considered orphaned:
+//!
+//!  - blocks at or below the pruned watermark, left by interrupted prune runs.
+//!  - blocks that are not part of the canonical chain, left by chain reorganizations.
+//!  - pending blocks superseded by a newer generation or by an ingested block.
+//!  - segments, groups and canonical chain segments before the first available block,
+//!    left by interrupted retention runs.
+//!  - segments and groups not aligned to the current segment and group sizes.
+//!
+//! The collector only runs on the object store prefix referenced by the ingestion state.
+//! Other prefixes, for example the one holding data staged by re-compaction, use a
+//! different layout and are never collected.
+//!
+//! Objects are only deleted if they were last modified before the safety delay, so that
+//! objects being written while the collector runs are never deleted.
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime},
+};
+
+use apibara_etcd::LockOptions;
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::{
+    block_store::{format_block_key, BLOCK_PREFIX, GROUP_PREFIX, SEGMENT_PREFIX},
+    chain::CanonicalChainSegment,
+    chain_store::{ChainStore, CANONICAL_PREFIX, RECENT_CHAIN_SEGMENT_NAME},
+    cli::{EtcdArgs, ObjectStoreArgs},
+    file_cache::FileCacheArgs,
+    ingestion::IngestionStateClient,
+    object_store::{DeleteOptions, ListOptions, ObjectStore, ObjectStoreResultExt},
+    options_store::OptionsStore,
+    Cursor,
+};
+
+use super::{metrics::CompactionMetrics, service::lock_keep_alive_loop, CompactionError};
+
+/// Number of objects requested in a single list request.
+const LIST_PAGE_SIZE: u32 = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct GarbageCollectorOptions {
+    /// Only delete objects last modified before this delay.
+    pub safety_delay: Duration,
+    /// Log the orphaned objects without deleting them.
+    pub dry_run: bool,
+}
+
+impl Default for GarbageCollectorOptions {
+    fn default() -> Self {
+        Self {
+            safety_delay: Duration::from_secs(60 * 60),
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GarbageCollectionReport {
+    pub scanned: usize,
+    pub orphaned: usize,
+    pub deleted: usize,
+    pub reclaimed_bytes: u64,
+}
+
+pub struct GarbageCollector {
+    object_store: ObjectStore,
+    chain_store: ChainStore,
+    state_client: IngestionStateClient,
+    options_store: OptionsStore,
+    options: GarbageCollectorOptions,
+    metrics: CompactionMetrics,
+}
+
+/// The objects reachable from the ingestion state.
+struct Reachable {
+    starting_block: u64,
+    first_available_block: u64,
+    pruned: Option<u64>,
+    segment_size: u64,
+    blocks_in_group: u64,
+    chain_segment_size: u64,
+    last_ingested: u64,
+    pending_generation: Option<u64>,
+    canonical_blocks: HashSet<String>,
+}
+
+impl GarbageCollector {
+    pub fn new(
+        object_store: ObjectStore,
+        chain_store: ChainStore,
+        state_client: IngestionStateClient,
+        options_store: OptionsStore,
+        options: GarbageCollectorOptions,
+        metrics: CompactionMetrics,
+    ) -> Self {
+        Self {
+            object_store,
+            chain_store,
+            state_client,
+            options_store,
+            options,
+            metrics,
+        }
+    }
+
+    pub async fn collect(
+        &mut self,
+        ct: &CancellationToken,
+    ) -> Result<GarbageCollectionReport, CompactionError> {
+        let mut report = GarbageCollectionReport::default();
+
+        // Read the state before listing so that objects created while listing are newer
+        // than the state and protected by the safety delay.
+        let Some(reachable) = self.reachable().await? else {
+            debug!("no ingestion state, skipping garbage collection");
+            return Ok(report);
+        };
+
+        let deadline = SystemTime::now() - self.options.safety_delay;
+
+        info!(
+            dry_run = self.options.dry_run,
+            safety_delay = ?self.options.safety_delay,
+            "starting garbage collection"
+        );
+
+        for prefix in [CANONICAL_PREFIX, SEGMENT_PREFIX, GROUP_PREFIX, BLOCK_PREFIX] {
+            let mut options = ListOptions {
+                max_keys: Some(LIST_PAGE_SIZE),
+                ..Default::default()
+            };
+
+            loop {
+                if ct.is_cancelled() {
+                    return Ok(report);
+                }
+
+                let page = self
+                    .object_store
+                    .list_page(&format!("{prefix}/"), options.clone())
+                    .await
+                    .change_context(CompactionError)
+                    .attach_printable("failed to list objects")?;
+
+                for object in page.objects {
+                    report.scanned += 1;
+
+                    let path = self.object_store.relative_path(&object.object_id);
+                    if !reachable.is_orphan(path) {
+                        continue;
+                    }
+
+                    let Some(last_modified) = object.last_modified else {
+                        continue;
+                    };
+
+                    if last_modified > deadline {
+                        continue;
+                    }
+
+                    report.orphaned += 1;
+
+                    if self.options.dry_run {
+                        info!(path, size = object.size, "orphaned object");
+                        continue;
+                    }
+
+                    debug!(path, size = object.size, "deleting orphaned object");
+
+                    match self
+                        .object_store
+                        .delete(
+                            &object.object_id,
+                            DeleteOptions {
+                                fullpath: Some(true),
+                            },
+                        )
+                        .await
+                    {
+                        Ok(_) => {}
+                        Err(err) if err.is_not_found() => continue,
+                        Err(err) => {
+                            return Err(err)
+                                .change_context(CompactionError)
+                                .attach_printable("failed to delete orphaned object")
+                                .attach_printable_lazy(|| format!("path: {path}"));
+                        }
+                    }
+
+                    report.deleted += 1;
+                    report.reclaimed_bytes += object.size;
+
+                    self.metrics.gc_deleted.add(1, &[]);
+                    self.metrics.gc_reclaimed.add(object.size, &[]);
+                }
+
+                match page.continuation_token {
+                    None => break,
+                    token => options.continuation_token = token,
+                }
+            }
+        }
+
+        info!(
+            scanned = report.scanned,
+            orphaned = report.orphaned,
+            deleted = report.deleted,
+            reclaimed_bytes = report.reclaimed_bytes,
+            "garbage collection completed"
+        );
+
+        Ok(report)
+    }
+
+    async fn reachable(&mut self) -> Result<Option<Reachable>, CompactionError> {
+        if let Some(active_prefix) = self
+            .options_store
+            .get_object_store_prefix()
+            .await
+            .change_context(CompactionError)?
+        {
+            if active_prefix != self.object_store.prefix() {
+                warn!(
+                    active_prefix,
+                    prefix = self.object_store.prefix(),
+                    "object store prefix is not active, skipping garbage collection"
+                );
+                return Ok(None);
+            }
+        }
+
+        let Some(starting_block) = self
+            .state_client
+            .get_starting_block()
+            .await
+            .change_context(CompactionError)?
+        else {
+            return Ok(None);
+        };
+
+        let first_available_block = self
+            .state_client
+            .get_expired()
+            .await
+            .change_context(CompactionError)?
+            .map(|expired| expired + 1)
+            .unwrap_or(starting_block);
+
+        let pruned = self
+            .state_client
+            .get_pruned()
+            .await
+            .change_context(CompactionError)?;
+
+        let pending_generation = self
+            .state_client
+            .get_pending()
+            .await
+            .change_context(CompactionError)?;
+
+        let (Some(chain_segment_size), Some(segment_size), Some(group_size)) = (
+            self.options_store
+                .get_chain_segment_size()
+                .await
+                .change_context(CompactionError)?,
+            self.options_store
+                .get_segment_size()
+                .await
+                .change_context(CompactionError)?,
+            self.options_store
+                .get_group_size()
+                .await
+                .change_context(CompactionError)?,
+        ) else {
+            return Ok(None);
+        };
+
+        let Some(recent) = self
+            .chain_store
+            .get_recent(None)
+            .await
+            .change_context(CompactionError)?
+        else {
+            return Ok(None);
+        };
+
+        let chain_segment_size = chain_segment_size as u64;
+        let last_ingested = recent.info.last_block.number;
+
+        // Only blocks that were not pruned yet need to be checked against the canonical chain.
+        let first_block = u64::max(
+            pruned
+                .map(|pruned| pruned + 1)
+                .unwrap_or(first_available_block),
+            first_available_block,
+        );
+
+        let mut canonical_blocks = HashSet::new();
+        let mut chain_segment_start = (first_block - starting_block) / chain_segment_size
+            * chain_segment_size
+            + starting_block;
+
+        while chain_segment_start < recent.info.first_block.number {
+            let segment = self
+                .chain_store
+                .get(chain_segment_start)
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("chain segment not found")
+                .attach_printable_lazy(|| format!("first block: {chain_segment_start}"))?;
+
+            add_canonical_blocks(&mut canonical_blocks, &segment, first_block);
+
+            chain_segment_start += chain_segment_size;
+        }
+
+        add_canonical_blocks(&mut canonical_blocks, &recent, first_block);
+
+        Ok(Some(Reachable {
+            starting_block,
+            first_available_block,
+            pruned,
+            segment_size: segment_size as u64,
+            blocks_in_group: (segment_size * group_size) as u64,
+            chain_segment_size,
+            last_ingested,
+            pending_generation,
+            canonical_blocks,
+        }))
+    }
+}
+
+fn add_canonical_blocks(
+    canonical_blocks: &mut HashSet<String>,
+    segment: &CanonicalChainSegment,
+    first_block: u64,
+) {
+    for (index, block) in segment.canonical.iter().enumerate() {
+        let number = segment.info.first_block.number + index as u64;
+        if number < first_block {
+            continue;
+        }
+
+        let cursor = Cursor::new(number, block.hash.clone());
+        canonical_blocks.insert(format_block_key(&cursor));
+    }
+}
+
+impl Reachable {
+    fn is_orphan(&self, path: &str) -> bool {
+        let mut parts = path.split('/');
+        let (Some(prefix), Some(name)) = (parts.next(), parts.next()) else {
+            return false;
+        };
+
+        if prefix == CANONICAL_PREFIX {
+            if name == RECENT_CHAIN_SEGMENT_NAME {
+                return false;
+            }
+
+            let Some(first_block) = name
+                .strip_prefix("z-")
+                .and_then(|number| number.parse::<u64>().ok())
+            else {
+                return false;
+            };
+
+            return first_block + self.chain_segment_size <= self.first_available_block;
+        }
+
+        let Ok(number) = name.parse::<u64>() else {
+            return false;
+        };
+
+        if prefix == SEGMENT_PREFIX {
+            return number < self.first_available_block
+                || !(number - self.starting_block).is_multiple_of(self.segment_size);
+        }
+
+        if prefix == GROUP_PREFIX {
+            return number < self.first_available_block
+                || !(number - self.starting_block).is_multiple_of(self.blocks_in_group);
+        }
+
+        if prefix != BLOCK_PREFIX {
+            return false;
+        }
+
+        let Some(filename) = parts.next() else {
+            return false;
+        };
+
+        if let Some(generation) = filename.strip_prefix("pending-") {
+            let Ok(generation) = generation.parse::<u64>() else {
+                return false;
+            };
+
+            let is_current = number > self.last_ingested
+                && matches!(self.pending_generation, Some(current) if generation >= current);
+
+            return !is_current;
+        }
+
+        if number < self.first_available_block {
+            return true;
+        }
+
+        if let Some(pruned) = self.pruned {
+            if number <= pruned {
+                return true;
+            }
+        }
+
+        number <= self.last_ingested && !self.canonical_blocks.contains(path)
+    }
+}
+
+/// Delete orphaned objects once.
+#[derive(Args, Debug)]
+pub struct GarbageCollectCommand {
+    #[clap(flatten)]
+    pub object_store: ObjectStoreArgs,
+    #[clap(flatten)]
+    pub etcd: EtcdArgs,
+    #[clap(flatten)]
+    pub cache: FileCacheArgs,
+    /// Only delete objects last modified before this delay, for example "1h".
+    #[arg(
+        long = "gc.safety-delay",
+        env = "DNA_GC_SAFETY_DELAY",
+        default_value = "1h"
+    )]
+    pub safety_delay: String,
+    /// Log the orphaned objects without deleting them.
+    #[arg(long = "gc.dry-run", env = "DNA_GC_DRY_RUN")]
+    pub dry_run: bool,
+}
+
+impl GarbageCollectCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), CompactionError> {
+        let safety_delay = duration_str::parse_std(&self.safety_delay).or_else(|err| {
+            Err(CompactionError)
+                .attach_printable("failed to parse gc safety delay")
+                .attach_printable(format!("error: {}", err))
+        })?;
+
+        let object_store = self
+            .object_store
+            .into_object_store_client()
+            .await
+            .change_context(CompactionError)?;
+
+        let etcd_client = self
+            .etcd
+            .into_etcd_client()
+            .await
+            .change_context(CompactionError)?;
+
+        let file_cache = self
+            .cache
+            .to_file_cache()
+            .await
+            .change_context(CompactionError)?;
+
+        // Run under the compaction lock so that the collector never races with retention.
+        let mut lock_client = etcd_client.lock_client(LockOptions::default());
+
+        info!("acquiring compaction lock");
+        let Some(mut lock) = lock_client
+            .lock("compaction/lock", ct.clone())
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to acquire compaction lock")?
+        else {
+            return Ok(());
+        };
+
+        let mut collector = GarbageCollector::new(
+            object_store.clone(),
+            ChainStore::new(object_store, file_cache),
+            IngestionStateClient::new(&etcd_client),
+            OptionsStore::new(&etcd_client),
+            GarbageCollectorOptions {
+                safety_delay,
+                dry_run: self.dry_run,
+            },
+            CompactionMetrics::default(),
+        );
+
+        let result = tokio::select! {
+            result = collector.collect(&ct) => result.map(|_| ()),
+            result = lock_keep_alive_loop(&mut lock, ct.clone()) => result,
+        };
+
+        lock_client
+            .unlock(lock)
+            .await
+            .change_context(CompactionError)?;
+
+        result
+    }
+}
+
+/// Periodically delete orphaned objects.
+pub struct GarbageCollectionService {
+    interval: Duration,
+    collector: GarbageCollector,
+}
+
+impl GarbageCollectionService {
+    pub fn new(interval: Duration, collector: GarbageCollector) -> Self {
+        Self {
+            interval,
+            collector,
+        }
+    }
+
+    pub async fn start(mut self, ct: CancellationToken) -> Result<(), CompactionError> {
+        loop {
+            if ct.is_cancelled() {
+                return Ok(());
+            }
+
+            info!("compaction: gc tick");
+
+            self.collector.collect(&ct).await?;
+
+            let Some(_) = ct
+                .run_until_cancelled(tokio::time::sleep(self.interval))
+                .await
+            else {
+                return Ok(());
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use apibara_etcd::EtcdClient;
+    use bytes::Bytes;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        block_store::format_block_key,
+        chain::{BlockInfo, CanonicalChainBuilder},
+        chain_store::ChainStore,
+        compaction::metrics::CompactionMetrics,
+        file_cache::testing::memory_file_cache,
+        ingestion::IngestionStateClient,
+        new_test_cursor,
+        object_store::{MemoryClient, ObjectStore, ObjectStoreOptions, PutOptions},
+        options_store::OptionsStore,
+    };
+
+    use super::{GarbageCollector, GarbageCollectorOptions, Reachable};
+
+    fn reachable() -> Reachable {
+        let mut canonical_blocks = HashSet::new();
+        canonical_blocks.insert(format_block_key(&new_test_cursor(250, 0)));
+
+        Reachable {
+            starting_block: 0,
+            first_available_block: 100,
+            pruned: Some(199),
+            segment_size: 10,
+            blocks_in_group: 100,
+            chain_segment_size: 50,
+            last_ingested: 300,
+            pending_generation: Some(2),
+            canonical_blocks,
+        }
+    }
+
+    #[test]
+    fn test_is_orphan_chain_segments() {
+        let reachable = reachable();
+
+        assert!(!reachable.is_orphan("canon/recent"));
+        assert!(reachable.is_orphan("canon/z-0000000000"));
+        assert!(reachable.is_orphan("canon/z-0000000050"));
+        assert!(!reachable.is_orphan("canon/z-0000000100"));
+        assert!(!reachable.is_orphan("canon/unknown"));
+    }
+
+    #[test]
+    fn test_is_orphan_segments_and_groups() {
+        let reachable = reachable();
+
+        assert!(reachable.is_orphan("segment/0000000090/index"));
+        assert!(!reachable.is_orphan("segment/0000000100/index"));
+        assert!(!reachable.is_orphan("segment/0000000310/log"));
+        assert!(reachable.is_orphan("segment/0000000105/index"));
+
+        assert!(reachable.is_orphan("group/0000000000/index"));
+        assert!(!reachable.is_orphan("group/0000000100/index"));
+        assert!(reachable.is_orphan("group/0000000150/index"));
+    }
+
+    #[test]
+    fn test_is_orphan_blocks() {
+        let reachable = reachable();
+        let canonical = format_block_key(&new_test_cursor(250, 0));
+        let reorged = format_block_key(&new_test_cursor(250, 1));
+
+        // Expired and pruned blocks.
+        assert!(reachable.is_orphan("block/0000000050/0xaa"));
+        assert!(reachable.is_orphan("block/0000000150/0xaa"));
+
+        assert!(!reachable.is_orphan(&canonical));
+        assert!(reachable.is_orphan(&reorged));
+
+        // Blocks after the last ingested block are being ingested.
+        assert!(!reachable.is_orphan("block/0000000301/0xaa"));
+
+        assert!(!reachable.is_orphan("block/0000000301/pending-0002"));
+        assert!(!reachable.is_orphan("block/0000000301/pending-0003"));
+        assert!(reachable.is_orphan("block/0000000301/pending-0001"));
+        assert!(reachable.is_orphan("block/0000000300/pending-0002"));
+    }
+
+    #[test]
+    fn test_is_orphan_ignores_unknown_objects() {
+        let reachable = reachable();
+
+        assert!(!reachable.is_orphan("other/0000000000/index"));
+        assert!(!reachable.is_orphan("segment/not-a-number/index"));
+        assert!(!reachable.is_orphan("block/0000000150"));
+    }
+
+    async fn collector_for_prefix(
+        etcd_client: &EtcdClient,
+        memory_client: &MemoryClient,
+        prefix: &str,
+    ) -> (ObjectStore, GarbageCollector) {
+        let object_store = ObjectStore::new_memory(
+            memory_client.clone(),
+            ObjectStoreOptions {
+                bucket: "test".to_string(),
+                prefix: Some(prefix.to_string()),
+            },
+        );
+
+        let chain_store = ChainStore::new(object_store.clone(), memory_file_cache().await);
+
+        let mut chain_builder = CanonicalChainBuilder::new();
+        let mut parent = new_test_cursor(0, 1).hash;
+        for number in 0..5 {
+            let hash = new_test_cursor(number, 0).hash;
+            chain_builder
+                .grow(BlockInfo {
+                    number,
+                    hash: hash.clone(),
+                    parent,
+                })
+                .unwrap();
+            parent = hash;
+        }
+        chain_store
+            .put_recent(&chain_builder.current_segment().unwrap())
+            .await
+            .unwrap();
+
+        let collector = GarbageCollector::new(
+            object_store.clone(),
+            chain_store,
+            IngestionStateClient::new(etcd_client),
+            OptionsStore::new(etcd_client),
+            GarbageCollectorOptions {
+                safety_delay: Duration::ZERO,
+                dry_run: false,
+            },
+            CompactionMetrics::default(),
+        );
+
+        (object_store, collector)
+    }
+
+    #[tokio::test]
+    async fn test_collect_follows_list_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+
+        IngestionStateClient::new(&etcd_client)
+            .put_starting_block(0)
+            .await
+            .unwrap();
+        let mut options_store = OptionsStore::new(&etcd_client);
+        options_store.set_chain_segment_size(1_000).await.unwrap();
+        options_store.set_segment_size(10).await.unwrap();
+        options_store.set_group_size(10).await.unwrap();
+
+        let memory_client = MemoryClient::new();
+        let (object_store, mut collector) =
+            collector_for_prefix(&etcd_client, &memory_client, "live").await;
+
+        // More segments than returned by a single list request, most of them misaligned.
+        for number in 0..1_500u64 {
+            object_store
+                .put(
+                    &format!("segment/{number:0>10}/index"),
+                    Bytes::from_static(b"segment"),
+                    PutOptions::default(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let canonical = format_block_key(&new_test_cursor(1, 0));
+        let reorged = format_block_key(&new_test_cursor(1, 1));
+        for path in [&canonical, &reorged] {
+            object_store
+                .put(path, Bytes::from_static(b"block"), PutOptions::default())
+                .await
+                .unwrap();
+        }
+
+        let report = collector.collect(&CancellationToken::new()).await.unwrap();
+
+        assert_eq!(report.scanned, 1 + 1_500 + 2);
+        assert_eq!(report.orphaned, 1_350 + 1);
+        assert_eq!(report.deleted, 1_350 + 1);
+
+        let remaining = object_store
+            .list("segment/", Default::default())
+            .await
+            .unwrap();
+        assert_eq!(remaining.objects.len(), 150);
+
+        let blocks = object_store
+            .list("block/", Default::default())
+            .await
+            .unwrap()
+            .object_ids
+            .iter()
+            .map(|object_id| object_store.relative_path(object_id).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![canonical]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_skips_inactive_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+
+        IngestionStateClient::new(&etcd_client)
+            .put_starting_block(0)
+            .await
+            .unwrap();
+        let mut options_store = OptionsStore::new(&etcd_client);
+        options_store.set_chain_segment_size(1_000).await.unwrap();
+        options_store.set_segment_size(10).await.unwrap();
+        options_store.set_group_size(10).await.unwrap();
+        options_store
+            .set_object_store_prefix("live/")
+            .await
+            .unwrap();
+
+        // Data staged with a different layout, for example by re-compaction.
+        let memory_client = MemoryClient::new();
+        let (staged_store, mut collector) =
+            collector_for_prefix(&etcd_client, &memory_client, "staged").await;
+
+        staged_store
+            .put(
+                "segment/0000000004/index",
+                Bytes::from_static(b"segment"),
+                PutOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let report = collector.collect(&CancellationToken::new()).await.unwrap();
+        assert_eq!(report.scanned, 0);
+        assert_eq!(report.deleted, 0);
+
+        let remaining = staged_store
+            .list("segment/", Default::default())
+            .await
+            .unwrap();
+        assert_eq!(remaining.objects.len(), 1);
+    }
+}
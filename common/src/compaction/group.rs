@@ -1,5 +1,8 @@
+use std::collections::BTreeSet;
+
 use apibara_observability::RecordRequest;
 use error_stack::{Result, ResultExt};
+use futures::stream::FuturesUnordered;
 use futures_buffered::FuturesOrderedBounded;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
@@ -7,7 +10,7 @@ use tracing::{debug, info};
 
 use crate::{
     block_store::{BlockStoreWriter, UncachedBlockStoreReader},
-    chain_view::{ChainView, NextCursor},
+    chain_view::ChainView,
     compaction::group_builder::SegmentGroupBuilder,
     fragment::IndexGroupFragment,
     ingestion::IngestionStateClient,
@@ -19,7 +22,9 @@ use super::{metrics::CompactionMetrics, CompactionError};
 
 const MAX_BUFFERED_SEGMENTS: usize = 128;
 
+#[derive(Clone)]
 pub struct SegmentGroupService {
+    workers: usize,
     segment_size: usize,
     group_size: usize,
     chain_view: ChainView,
@@ -30,7 +35,9 @@ pub struct SegmentGroupService {
 }
 
 impl SegmentGroupService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        workers: usize,
         segment_size: usize,
         group_size: usize,
         chain_view: ChainView,
@@ -40,6 +47,7 @@ impl SegmentGroupService {
         metrics: CompactionMetrics,
     ) -> Self {
         Self {
+            workers: usize::max(workers, 1),
             segment_size,
             group_size,
             chain_view,
@@ -53,6 +61,26 @@ impl SegmentGroupService {
     pub async fn start(mut self, ct: CancellationToken) -> Result<(), CompactionError> {
         let blocks_in_group = (self.group_size * self.segment_size) as u64;
 
+        let mut next_to_commit = if let Some(cursor) = self
+            .chain_view
+            .get_grouped_cursor()
+            .await
+            .change_context(CompactionError)?
+        {
+            cursor.number + 1
+        } else {
+            self.chain_view
+                .get_starting_cursor()
+                .await
+                .change_context(CompactionError)?
+                .number
+        };
+        let mut next_to_schedule = next_to_commit;
+
+        let mut in_flight = FuturesUnordered::new();
+        // Groups uploaded but not yet covered by the watermark.
+        let mut built = BTreeSet::<u64>::new();
+
         loop {
             if ct.is_cancelled() {
                 return Ok(());
@@ -60,29 +88,15 @@ impl SegmentGroupService {
 
             tokio::time::sleep(std::time::Duration::from_secs(1)).await;
 
-            let first_block_in_group = if let Some(cursor) = self
-                .chain_view
-                .get_grouped_cursor()
-                .await
-                .change_context(CompactionError)?
-            {
-                let NextCursor::Continue { cursor, .. } = self
-                    .chain_view
-                    .get_next_cursor(&Some(cursor.clone()))
+            // Only advance the watermark over a contiguous range of groups.
+            while built.remove(&next_to_commit) {
+                let last_block_in_group = next_to_commit + blocks_in_group - 1;
+                self.state_client
+                    .put_grouped(last_block_in_group)
                     .await
-                    .change_context(CompactionError)?
-                else {
-                    return Err(CompactionError)
-                        .attach_printable("chain view returned invalid cursor")
-                        .attach_printable_lazy(|| format!("cursor: {cursor}"));
-                };
-                cursor
-            } else {
-                self.chain_view
-                    .get_starting_cursor()
-                    .await
-                    .change_context(CompactionError)?
-            };
+                    .change_context(CompactionError)?;
+                next_to_commit += blocks_in_group;
+            }
 
             let Some(segmented) = self
                 .chain_view
@@ -100,18 +114,32 @@ impl SegmentGroupService {
             };
 
             info!(
-                next_cursor = %first_block_in_group,
+                next_group = next_to_commit,
+                in_flight = in_flight.len(),
                 blocks_in_group = %blocks_in_group,
                 segmented = %segmented,
                 "compaction: group tick"
             );
 
-            if first_block_in_group.number + blocks_in_group <= segmented.number {
-                let creation_metrics = self.metrics.group_creation.clone();
-                self.compact_group(first_block_in_group, blocks_in_group)
-                    .record_request(creation_metrics)
-                    .await?;
-            } else {
+            while in_flight.len() < self.workers
+                && next_to_schedule + blocks_in_group <= segmented.number
+            {
+                let first_block_in_group = next_to_schedule;
+                next_to_schedule += blocks_in_group;
+
+                let service = self.clone();
+                let handle = tokio::spawn(async move {
+                    let creation_metrics = service.metrics.group_creation.clone();
+                    service
+                        .compact_group(Cursor::new_finalized(first_block_in_group), blocks_in_group)
+                        .record_request(creation_metrics)
+                        .await
+                });
+
+                in_flight.push(async move { (first_block_in_group, handle.await) });
+            }
+
+            if in_flight.is_empty() {
                 info!("compaction waiting for segmented change");
                 let Some(_) = ct
                     .run_until_cancelled(self.chain_view.segmented_changed())
@@ -119,12 +147,23 @@ impl SegmentGroupService {
                 else {
                     return Ok(());
                 };
+                continue;
+            }
+
+            tokio::select! {
+                _ = ct.cancelled() => {
+                    return Ok(());
+                }
+                Some((first_block_in_group, result)) = in_flight.next() => {
+                    result.change_context(CompactionError)??;
+                    built.insert(first_block_in_group);
+                }
             }
         }
     }
 
     async fn compact_group(
-        &mut self,
+        &self,
         first_block_in_group: Cursor,
         blocks_in_group: u64,
     ) -> Result<(), CompactionError> {
@@ -240,11 +279,6 @@ impl SegmentGroupService {
 
         self.metrics.group_size.record(size as u64, &[]);
 
-        self.state_client
-            .put_grouped(last_block_in_group)
-            .await
-            .change_context(CompactionError)?;
-
         Ok(())
     }
 }
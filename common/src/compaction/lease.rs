@@ -0,0 +1,559 @@
+//! Share segment compaction between multiple nodes.
+//!
+//! Segments are leased one at a time using an etcd lock keyed by the segment's first block.
+//! Once a segment is uploaded, the node that built it records its last block so that the
+//! node holding the compaction lock can advance the segmented watermark.
+use std::{collections::BTreeSet, future::Future, time::Duration};
+
+use apibara_etcd::{EtcdClient, KvClient, LockOptions};
+use error_stack::{Result, ResultExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    block_store::{BlockStoreWriter, UncachedBlockStoreReader},
+    chain_view::{CanonicalCursor, ChainView},
+    ingestion::{state_client::SEGMENTED_KEY, IngestionStateClient},
+    object_store::ObjectStore,
+    Cursor,
+};
+
+use super::{
+    metrics::CompactionMetrics, segment::SegmentWorker, CompactionError, CompactionServiceOptions,
+};
+
+static SEGMENT_LEASE_PREFIX_KEY: &str = "compaction/segment-lease/";
+static SEGMENT_BUILT_PREFIX_KEY: &str = "compaction/segment-built/";
+
+/// How long a segment lease lasts if the node building the segment stops renewing it.
+const SEGMENT_LEASE_TTL: Duration = Duration::from_secs(60);
+
+/// How many segments ahead of the watermark helpers look for work, per worker.
+const LOOKAHEAD_PER_WORKER: u64 = 4;
+
+#[derive(Clone)]
+pub struct SegmentLeases {
+    etcd_client: EtcdClient,
+    kv_client: KvClient,
+    state_client: IngestionStateClient,
+    lease_ttl: Duration,
+}
+
+/// Result of building a leased segment.
+#[derive(Debug, Clone, Copy)]
+pub enum LeasedSegment {
+    /// The segment is built and ends at the given block.
+    Built(u64),
+    /// Another node is building the segment, or it's already part of the segmented range.
+    Busy,
+}
+
+/// Build segments leased from the node holding the compaction lock.
+pub struct SegmentHelperService {
+    workers: usize,
+    worker: SegmentWorker,
+    leases: SegmentLeases,
+}
+
+impl SegmentLeases {
+    pub fn new(etcd_client: &EtcdClient) -> Self {
+        let kv_client = etcd_client.kv_client();
+        let state_client = IngestionStateClient::new(etcd_client);
+
+        Self {
+            etcd_client: etcd_client.clone(),
+            kv_client,
+            state_client,
+            lease_ttl: SEGMENT_LEASE_TTL,
+        }
+    }
+
+    /// Build the segment starting at `first_block` if no other node is building it.
+    pub async fn build(
+        self,
+        worker: SegmentWorker,
+        first_block: Cursor,
+    ) -> Result<LeasedSegment, CompactionError> {
+        let first_block_number = first_block.number;
+        self.run_leased(first_block_number, worker.build_segment(first_block))
+            .await
+    }
+
+    /// Run `build` while holding the lease on the segment starting at `first_block`.
+    ///
+    /// The lease is kept alive until `build` completes, then the segment is marked as built.
+    async fn run_leased(
+        mut self,
+        first_block: u64,
+        build: impl Future<Output = Result<u64, CompactionError>>,
+    ) -> Result<LeasedSegment, CompactionError> {
+        let mut lock_client = self.etcd_client.lock_client(LockOptions {
+            ttl: self.lease_ttl.as_secs() as i64,
+        });
+
+        let Some(mut lock) = lock_client
+            .try_lock(lease_key(first_block))
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to lease segment")?
+        else {
+            return Ok(LeasedSegment::Busy);
+        };
+
+        // The segment may have been committed while our view of the chain was stale.
+        let segmented = self
+            .state_client
+            .get_segmented()
+            .await
+            .change_context(CompactionError)?;
+        if segmented.is_some_and(|segmented| segmented >= first_block) {
+            lock_client
+                .unlock(lock)
+                .await
+                .change_context(CompactionError)?;
+            return Ok(LeasedSegment::Busy);
+        }
+
+        // Another node may have finished the segment just before we leased it.
+        if let Some(last_block) = self.get_built(first_block).await? {
+            lock_client
+                .unlock(lock)
+                .await
+                .change_context(CompactionError)?;
+            return Ok(LeasedSegment::Built(last_block));
+        }
+
+        tokio::pin!(build);
+
+        // Refresh the lease well before it expires.
+        let mut keep_alive = tokio::time::interval(self.lease_ttl / 12);
+
+        let last_block = loop {
+            tokio::select! {
+                result = &mut build => {
+                    break result?;
+                }
+                _ = keep_alive.tick() => {
+                    lock.keep_alive()
+                        .await
+                        .change_context(CompactionError)
+                        .attach_printable("failed to renew segment lease")
+                        .attach_printable_lazy(|| format!("first block: {first_block}"))?;
+                }
+            }
+        };
+
+        self.kv_client
+            .put(built_key(first_block), last_block.to_string())
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to mark segment as built")?;
+
+        lock_client
+            .unlock(lock)
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to release segment lease")?;
+
+        Ok(LeasedSegment::Built(last_block))
+    }
+
+    /// Returns the last block of the segment starting at `first_block`, if it was built.
+    pub async fn get_built(&mut self, first_block: u64) -> Result<Option<u64>, CompactionError> {
+        let response = self
+            .kv_client
+            .get(built_key(first_block))
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to get built segment")?;
+
+        let Some(kv) = response.kvs().first() else {
+            return Ok(None);
+        };
+
+        let value = String::from_utf8(kv.value().to_vec())
+            .change_context(CompactionError)
+            .attach_printable("failed to decode built segment")?;
+
+        let last_block = value
+            .parse::<u64>()
+            .change_context(CompactionError)
+            .attach_printable("failed to parse built segment")?;
+
+        Ok(Some(last_block))
+    }
+
+    /// Advance the segmented watermark and forget about the built segment.
+    pub async fn commit(
+        &mut self,
+        first_block: u64,
+        last_block: u64,
+    ) -> Result<(), CompactionError> {
+        self.kv_client
+            .put_and_delete(
+                SEGMENTED_KEY,
+                last_block.to_string(),
+                built_key(first_block),
+            )
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to put segmented block")?;
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn with_lease_ttl(mut self, lease_ttl: Duration) -> Self {
+        self.lease_ttl = lease_ttl;
+        self
+    }
+}
+
+fn lease_key(first_block: u64) -> String {
+    format!("{SEGMENT_LEASE_PREFIX_KEY}{first_block:010}")
+}
+
+fn built_key(first_block: u64) -> String {
+    format!("{SEGMENT_BUILT_PREFIX_KEY}{first_block:010}")
+}
+
+/// Help the node holding the compaction lock build segments, until cancelled.
+pub async fn segment_helper_loop(
+    etcd_client: EtcdClient,
+    object_store: ObjectStore,
+    mut chain_view: tokio::sync::watch::Receiver<Option<ChainView>>,
+    options: CompactionServiceOptions,
+    metrics: CompactionMetrics,
+    ct: CancellationToken,
+) -> Result<(), CompactionError> {
+    let chain_view = loop {
+        if let Some(chain_view) = chain_view.borrow().clone() {
+            break chain_view;
+        }
+
+        let Some(changed) = ct.run_until_cancelled(chain_view.changed()).await else {
+            return Ok(());
+        };
+
+        changed.change_context(CompactionError)?;
+    };
+
+    info!(
+        workers = options.workers,
+        "compaction: helping with segments"
+    );
+
+    SegmentHelperService::new(
+        options.workers,
+        options.segment_size,
+        &etcd_client,
+        object_store,
+        chain_view,
+        metrics,
+    )
+    .start(ct)
+    .await
+}
+
+impl SegmentHelperService {
+    pub fn new(
+        workers: usize,
+        segment_size: usize,
+        etcd_client: &EtcdClient,
+        object_store: ObjectStore,
+        chain_view: ChainView,
+        metrics: CompactionMetrics,
+    ) -> Self {
+        let worker = SegmentWorker::new(
+            segment_size,
+            chain_view,
+            UncachedBlockStoreReader::new(object_store.clone()),
+            BlockStoreWriter::new(object_store),
+            metrics,
+        );
+
+        Self {
+            workers: usize::max(workers, 1),
+            worker,
+            leases: SegmentLeases::new(etcd_client),
+        }
+    }
+
+    pub async fn start(self, ct: CancellationToken) -> Result<(), CompactionError> {
+        let segment_size = self.worker.segment_size() as u64;
+        let chain_view = self.worker.chain_view().clone();
+
+        let mut in_flight = FuturesUnordered::new();
+        let mut scheduled = BTreeSet::new();
+
+        loop {
+            if ct.is_cancelled() {
+                return Ok(());
+            }
+
+            let next_segment = if let Some(segmented) = chain_view
+                .get_segmented_cursor()
+                .await
+                .change_context(CompactionError)?
+            {
+                segmented.number + 1
+            } else {
+                chain_view
+                    .get_starting_cursor()
+                    .await
+                    .change_context(CompactionError)?
+                    .number
+            };
+
+            let head = chain_view
+                .get_head()
+                .await
+                .change_context(CompactionError)?;
+            let finalized = chain_view
+                .get_finalized_cursor()
+                .await
+                .change_context(CompactionError)?;
+            let latest_available = u64::min(finalized.number, head.number);
+
+            let lookahead =
+                next_segment + LOOKAHEAD_PER_WORKER * self.workers as u64 * segment_size;
+            let mut candidate = next_segment;
+
+            while in_flight.len() < self.workers
+                && candidate + segment_size <= latest_available
+                && candidate < lookahead
+            {
+                if scheduled.contains(&candidate) {
+                    candidate += segment_size;
+                    continue;
+                }
+
+                let mut leases = self.leases.clone();
+                if leases.get_built(candidate).await?.is_some() {
+                    candidate += segment_size;
+                    continue;
+                }
+
+                let CanonicalCursor::Canonical(first_block) = chain_view
+                    .get_canonical(candidate)
+                    .await
+                    .change_context(CompactionError)?
+                else {
+                    break;
+                };
+
+                let worker = self.worker.clone();
+                let handle = tokio::spawn(leases.build(worker, first_block));
+
+                scheduled.insert(candidate);
+                in_flight.push(async move { (candidate, handle.await) });
+
+                candidate += segment_size;
+            }
+
+            let state_change = if finalized.number < head.number {
+                chain_view.finalized_changed().boxed()
+            } else {
+                chain_view.head_changed().boxed()
+            };
+
+            tokio::select! {
+                _ = ct.cancelled() => {
+                    return Ok(());
+                }
+                Some((first_block, result)) = in_flight.next(), if !in_flight.is_empty() => {
+                    scheduled.remove(&first_block);
+
+                    match result.change_context(CompactionError)? {
+                        Ok(LeasedSegment::Built(last_block)) => {
+                            info!(first_block, last_block, "compaction: built leased segment");
+                        }
+                        Ok(LeasedSegment::Busy) => {}
+                        Err(err) => {
+                            warn!(first_block, error = ?err, "compaction: failed to build leased segment");
+                        }
+                    }
+                }
+                _ = state_change, if in_flight.len() < self.workers => {}
+                // Segments leased or built by other nodes don't trigger a chain change.
+                _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use apibara_etcd::{EtcdClient, LockOptions};
+    use error_stack::Result;
+
+    use crate::{compaction::CompactionError, ingestion::IngestionStateClient};
+
+    use super::{lease_key, LeasedSegment, SegmentLeases};
+
+    async fn build_after(delay: Duration, last_block: u64) -> Result<u64, CompactionError> {
+        tokio::time::sleep(delay).await;
+        Ok(last_block)
+    }
+
+    async fn never_built() -> Result<u64, CompactionError> {
+        panic!("segment should not be built");
+    }
+
+    #[tokio::test]
+    async fn test_build_marks_segment_as_built() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let mut leases = SegmentLeases::new(&etcd_client);
+
+        let result = leases
+            .clone()
+            .run_leased(0, build_after(Duration::ZERO, 9))
+            .await
+            .unwrap();
+        assert!(matches!(result, LeasedSegment::Built(9)));
+        assert_eq!(leases.get_built(0).await.unwrap(), Some(9));
+
+        // The lease is released once the segment is built.
+        let lock = etcd_client
+            .lock_client(LockOptions::default())
+            .try_lock(lease_key(0))
+            .await
+            .unwrap();
+        assert!(lock.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_build_is_busy_while_leased() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let leases = SegmentLeases::new(&etcd_client);
+
+        let _other_node = etcd_client
+            .lock_client(LockOptions::default())
+            .try_lock(lease_key(0))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result = leases.run_leased(0, never_built()).await.unwrap();
+        assert!(matches!(result, LeasedSegment::Busy));
+    }
+
+    #[tokio::test]
+    async fn test_build_skips_built_and_committed_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let mut leases = SegmentLeases::new(&etcd_client);
+
+        leases
+            .clone()
+            .run_leased(10, build_after(Duration::ZERO, 19))
+            .await
+            .unwrap();
+
+        // Another node finished the segment before this node leased it.
+        let result = leases.clone().run_leased(10, never_built()).await.unwrap();
+        assert!(matches!(result, LeasedSegment::Built(19)));
+
+        IngestionStateClient::new(&etcd_client)
+            .put_segmented(9)
+            .await
+            .unwrap();
+        leases.commit(10, 19).await.unwrap();
+
+        let result = leases.clone().run_leased(0, never_built()).await.unwrap();
+        assert!(matches!(result, LeasedSegment::Busy));
+        let result = leases.clone().run_leased(10, never_built()).await.unwrap();
+        assert!(matches!(result, LeasedSegment::Busy));
+    }
+
+    #[tokio::test]
+    async fn test_commit_advances_segmented() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let mut leases = SegmentLeases::new(&etcd_client);
+        let mut state_client = IngestionStateClient::new(&etcd_client);
+
+        for (first_block, last_block) in [(0, 9), (10, 19)] {
+            leases
+                .clone()
+                .run_leased(first_block, build_after(Duration::ZERO, last_block))
+                .await
+                .unwrap();
+        }
+
+        leases.commit(0, 9).await.unwrap();
+        assert_eq!(state_client.get_segmented().await.unwrap(), Some(9));
+        assert_eq!(leases.get_built(0).await.unwrap(), None);
+        assert_eq!(leases.get_built(10).await.unwrap(), Some(19));
+
+        leases.commit(10, 19).await.unwrap();
+        assert_eq!(state_client.get_segmented().await.unwrap(), Some(19));
+        assert_eq!(leases.get_built(10).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_lease_is_renewed_while_building() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let leases = SegmentLeases::new(&etcd_client).with_lease_ttl(Duration::from_secs(1));
+
+        // The build takes longer than the lease ttl.
+        let build =
+            tokio::spawn(leases.run_leased(0, build_after(Duration::from_millis(2_500), 9)));
+
+        let mut lock_client = etcd_client.lock_client(LockOptions { ttl: 1 });
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let other_node = lock_client.try_lock(lease_key(0)).await.unwrap();
+            assert!(other_node.is_none(), "lease expired while building");
+        }
+
+        let result = build.await.unwrap().unwrap();
+        assert!(matches!(result, LeasedSegment::Built(9)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let mut leases = SegmentLeases::new(&etcd_client);
+
+        // A node leases the segment, then stops renewing the lease.
+        let _crashed_node = etcd_client
+            .lock_client(LockOptions { ttl: 1 })
+            .try_lock(lease_key(0))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result = leases.clone().run_leased(0, never_built()).await.unwrap();
+        assert!(matches!(result, LeasedSegment::Busy));
+
+        tokio::time::sleep(Duration::from_millis(1_100)).await;
+
+        let result = leases
+            .clone()
+            .run_leased(0, build_after(Duration::ZERO, 9))
+            .await
+            .unwrap();
+        assert!(matches!(result, LeasedSegment::Built(9)));
+        assert_eq!(leases.get_built(0).await.unwrap(), Some(9));
+    }
+}
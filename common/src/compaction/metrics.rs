@@ -1,4 +1,4 @@
-use apibara_observability::{Gauge, Histogram, RequestMetrics};
+use apibara_observability::{Counter, Gauge, Histogram, RequestMetrics};
 
 #[derive(Debug, Clone)]
 pub struct CompactionMetrics {
@@ -6,6 +6,7 @@ pub struct CompactionMetrics {
     pub segmented: Gauge<u64>,
     pub grouped: Gauge<u64>,
     pub pruned: Gauge<u64>,
+    pub expired: Gauge<u64>,
     pub block_download: RequestMetrics,
     pub segment_creation: RequestMetrics,
     pub segment_upload: RequestMetrics,
@@ -14,6 +15,8 @@ pub struct CompactionMetrics {
     pub group_creation: RequestMetrics,
     pub group_upload: RequestMetrics,
     pub group_size: Histogram<u64>,
+    pub gc_deleted: Counter<u64>,
+    pub gc_reclaimed: Counter<u64>,
 }
 
 impl Default for CompactionMetrics {
@@ -37,6 +40,10 @@ impl Default for CompactionMetrics {
                 .u64_gauge("dna.compaction.pruned")
                 .with_description("dna compaction most recent pruned block")
                 .build(),
+            expired: meter
+                .u64_gauge("dna.compaction.expired")
+                .with_description("dna compaction most recent expired block")
+                .build(),
             block_download: RequestMetrics::new("dna_compaction", "dna.compaction.block_download"),
             segment_creation: RequestMetrics::new(
                 "dna_compaction",
@@ -85,6 +92,16 @@ impl Default for CompactionMetrics {
                     10_000_000_000.0,
                 ])
                 .build(),
+            gc_deleted: meter
+                .u64_counter("dna.compaction.gc_deleted")
+                .with_description("number of orphaned objects deleted")
+                .with_unit("{object}")
+                .build(),
+            gc_reclaimed: meter
+                .u64_counter("dna.compaction.gc_reclaimed")
+                .with_description("bytes reclaimed by deleting orphaned objects")
+                .with_unit("By")
+                .build(),
         }
     }
 }
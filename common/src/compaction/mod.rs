@@ -1,9 +1,14 @@
 mod cli;
 mod error;
+mod gc;
 mod group;
 mod group_builder;
+mod lease;
 mod metrics;
 mod prune;
+mod recompact;
+mod repair;
+mod retention;
 mod segment;
 mod segment_builder;
 mod service;
@@ -14,15 +19,24 @@ use metrics::CompactionMetrics;
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
-use crate::{chain_view::ChainView, object_store::ObjectStore, options_store::OptionsStore};
+use crate::{
+    chain_view::ChainView, file_cache::FileCache, object_store::ObjectStore,
+    options_store::OptionsStore,
+};
 
 pub use self::cli::CompactionArgs;
 pub use self::error::CompactionError;
+pub use self::gc::{
+    GarbageCollectCommand, GarbageCollectionReport, GarbageCollector, GarbageCollectorOptions,
+};
+pub use self::recompact::RecompactCommand;
+pub use self::repair::RepairArgs;
 pub use self::service::{CompactionService, CompactionServiceOptions};
 
 pub async fn compaction_service_loop(
     etcd_client: EtcdClient,
     object_store: ObjectStore,
+    file_cache: FileCache,
     chain_view: tokio::sync::watch::Receiver<Option<ChainView>>,
     options: CompactionServiceOptions,
     ct: CancellationToken,
@@ -38,9 +52,32 @@ pub async fn compaction_service_loop(
 
         metrics.up.record(1, &[KeyValue::new("active", false)]);
 
-        let Some(mut lock) = lock_client
-            .lock("compaction/lock", ct.clone())
-            .await
+        // While another node holds the lock, help it build segments.
+        let helper_ct = ct.child_token();
+        let helper_handle = if options.distributed {
+            tokio::spawn(lease::segment_helper_loop(
+                etcd_client.clone(),
+                object_store.clone(),
+                chain_view.clone(),
+                options.clone(),
+                metrics.clone(),
+                helper_ct.clone(),
+            ))
+            .into()
+        } else {
+            None
+        };
+
+        let lock = lock_client.lock("compaction/lock", ct.clone()).await;
+
+        helper_ct.cancel();
+        if let Some(helper_handle) = helper_handle {
+            if let Err(err) = helper_handle.await.change_context(CompactionError)? {
+                warn!(error = ?err, "compaction segment helper error");
+            }
+        }
+
+        let Some(mut lock) = lock
             .change_context(CompactionError)
             .attach_printable("failed to acquire compaction lock")?
         else {
@@ -98,6 +135,7 @@ pub async fn compaction_service_loop(
         let compaction_service = CompactionService::new(
             etcd_client.clone(),
             object_store.clone(),
+            file_cache.clone(),
             chain_view.clone(),
             options.clone(),
             metrics.clone(),
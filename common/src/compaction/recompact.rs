@@ -0,0 +1,931 @@
+//! Rebuild the segments and groups of an existing bucket with different sizes.
+//!
+//! Re-compaction is an offline job: it holds both the ingestion and compaction locks
+//! while running. The new data is written under a different object store prefix, the
+//! existing data is never modified. Once all data is uploaded, the new segment and group
+//! sizes and the compaction watermarks are staged under the `recompact/` etcd prefix,
+//! leaving the live state untouched.
+//!
+//! Staging also records the source prefix as the active object store prefix, so that the
+//! garbage collector never collects the staged data.
+//!
+//! Running the command again with `--recompact.cutover` switches the live state and the
+//! active prefix to the staged ones in a single etcd transaction. DNA should then be restarted with the new
+//! prefix, and the old prefix can be deleted after the new deployment has been confirmed.
+use apibara_etcd::{normalize_prefix, EtcdClient, KvClient, Lock, LockOptions};
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::{
+    block_store::{BlockStoreWriter, UncachedBlockStoreReader},
+    chain_store::ChainStore,
+    cli::{EtcdArgs, ObjectStoreArgs},
+    file_cache::{FileCache, FileCacheArgs},
+    fragment::{
+        Block, BodyFragment, HeaderFragment, IndexGroupFragment, JoinGroupFragment,
+        HEADER_FRAGMENT_NAME, INDEX_FRAGMENT_NAME, JOIN_FRAGMENT_NAME,
+    },
+    ingestion::{
+        state_client::{GROUPED_KEY, PRUNED_KEY, SEGMENTED_KEY},
+        IngestionStateClient, INGESTED_KEY,
+    },
+    object_store::ObjectStore,
+    options_store::{OptionsStore, GROUP_SIZE_KEY, OBJECT_STORE_PREFIX_KEY, SEGMENT_SIZE_KEY},
+    segment::Segment,
+    Cursor,
+};
+
+use super::{group_builder::SegmentGroupBuilder, segment_builder::SegmentBuilder, CompactionError};
+
+pub static RECOMPACT_PREFIX_KEY: &str = "recompact/";
+/// The object store prefix of the staged data.
+pub static RECOMPACT_TARGET_PREFIX_KEY: &str = "recompact/target_prefix";
+/// The ingested etag when the data was staged, used to detect blocks ingested since then.
+pub static RECOMPACT_SOURCE_INGESTED_KEY: &str = "recompact/source_ingested";
+
+#[derive(Args, Debug)]
+pub struct RecompactCommand {
+    #[clap(flatten)]
+    pub object_store: ObjectStoreArgs,
+    #[clap(flatten)]
+    pub etcd: EtcdArgs,
+    #[clap(flatten)]
+    pub cache: FileCacheArgs,
+    /// Under which prefix to store the re-compacted data.
+    #[arg(long = "recompact.target-prefix", env = "DNA_RECOMPACT_TARGET_PREFIX")]
+    pub target_prefix: String,
+    /// How many blocks in a single segment.
+    #[arg(long = "recompact.segment-size", env = "DNA_RECOMPACT_SEGMENT_SIZE")]
+    pub segment_size: usize,
+    /// How many segments in a single segment group.
+    #[arg(long = "recompact.group-size", env = "DNA_RECOMPACT_GROUP_SIZE")]
+    pub group_size: usize,
+    /// Switch the live options and watermarks to the data staged by a previous run.
+    ///
+    /// Without this flag, the re-compacted data is uploaded and staged but the live state
+    /// is left unchanged.
+    #[arg(long = "recompact.cutover", env = "DNA_RECOMPACT_CUTOVER")]
+    pub cutover: bool,
+}
+
+struct Recompaction {
+    segment_size: usize,
+    group_size: usize,
+    target_prefix: String,
+    source_prefix: String,
+    source_reader: UncachedBlockStoreReader,
+    source_chain_store: ChainStore,
+    target_writer: BlockStoreWriter,
+    target_chain_store: ChainStore,
+    state_client: IngestionStateClient,
+    options_store: OptionsStore,
+    etcd_client: EtcdClient,
+    /// Blocks waiting to be added to the next segment.
+    pending: Vec<(Cursor, Block)>,
+    group_builder: SegmentGroupBuilder,
+    segmented: Option<u64>,
+    grouped: Option<u64>,
+}
+
+impl RecompactCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), CompactionError> {
+        if self.segment_size == 0 || self.group_size == 0 {
+            return Err(CompactionError)
+                .attach_printable("segment size and group size must be greater than zero");
+        }
+
+        if self.object_store.s3_prefix.as_deref() == Some(self.target_prefix.as_str()) {
+            return Err(CompactionError)
+                .attach_printable("target prefix must be different from the current prefix");
+        }
+
+        let target_object_store = ObjectStoreArgs {
+            s3_prefix: Some(self.target_prefix.clone()),
+            ..self.object_store.clone()
+        }
+        .into_object_store_client()
+        .await
+        .change_context(CompactionError)?;
+
+        let source_object_store = self
+            .object_store
+            .into_object_store_client()
+            .await
+            .change_context(CompactionError)?;
+
+        let etcd_client = self
+            .etcd
+            .into_etcd_client()
+            .await
+            .change_context(CompactionError)?;
+
+        let file_cache = self
+            .cache
+            .to_file_cache()
+            .await
+            .change_context(CompactionError)?;
+
+        let mut lock_client = etcd_client.lock_client(LockOptions::default());
+
+        info!("acquiring ingestion lock");
+        let Some(mut ingestion_lock) = lock_client
+            .lock("ingestion/lock", ct.clone())
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to acquire ingestion lock")?
+        else {
+            return Ok(());
+        };
+
+        info!("acquiring compaction lock");
+        let Some(mut compaction_lock) = lock_client
+            .lock("compaction/lock", ct.clone())
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to acquire compaction lock")?
+        else {
+            lock_client
+                .unlock(ingestion_lock)
+                .await
+                .change_context(CompactionError)?;
+            return Ok(());
+        };
+
+        let result = if self.cutover {
+            cutover(&etcd_client, &self.target_prefix).await
+        } else {
+            Recompaction::new(
+                self.segment_size,
+                self.group_size,
+                self.target_prefix.clone(),
+                source_object_store,
+                target_object_store,
+                &etcd_client,
+                file_cache,
+            )
+            .run(&mut ingestion_lock, &mut compaction_lock, &ct)
+            .await
+        };
+
+        lock_client
+            .unlock(compaction_lock)
+            .await
+            .change_context(CompactionError)?;
+        lock_client
+            .unlock(ingestion_lock)
+            .await
+            .change_context(CompactionError)?;
+
+        result
+    }
+}
+
+impl Recompaction {
+    fn new(
+        segment_size: usize,
+        group_size: usize,
+        target_prefix: String,
+        source_object_store: ObjectStore,
+        target_object_store: ObjectStore,
+        etcd_client: &EtcdClient,
+        file_cache: FileCache,
+    ) -> Self {
+        Self {
+            segment_size,
+            group_size,
+            target_prefix,
+            source_prefix: source_object_store.prefix().to_string(),
+            source_reader: UncachedBlockStoreReader::new(source_object_store.clone()),
+            source_chain_store: ChainStore::new(source_object_store, file_cache.clone()),
+            target_writer: BlockStoreWriter::new(target_object_store.clone()),
+            target_chain_store: ChainStore::new(target_object_store, file_cache),
+            state_client: IngestionStateClient::new(etcd_client),
+            options_store: OptionsStore::new(etcd_client),
+            etcd_client: etcd_client.clone(),
+            pending: Vec::with_capacity(segment_size),
+            group_builder: SegmentGroupBuilder::new(segment_size),
+            segmented: None,
+            grouped: None,
+        }
+    }
+
+    async fn run(
+        mut self,
+        ingestion_lock: &mut Lock,
+        compaction_lock: &mut Lock,
+        ct: &CancellationToken,
+    ) -> Result<(), CompactionError> {
+        let starting_block = self
+            .state_client
+            .get_starting_block()
+            .await
+            .change_context(CompactionError)?
+            .ok_or(CompactionError)
+            .attach_printable("ingestion starting block not found")?;
+
+        if let Some(active_prefix) = self
+            .options_store
+            .get_object_store_prefix()
+            .await
+            .change_context(CompactionError)?
+        {
+            if active_prefix != self.source_prefix {
+                return Err(CompactionError)
+                    .attach_printable("source prefix is not the active object store prefix")
+                    .attach_printable_lazy(|| format!("active prefix: {active_prefix}"))
+                    .attach_printable_lazy(|| format!("source prefix: {}", self.source_prefix));
+            }
+        }
+
+        let first_block = self
+            .state_client
+            .get_expired()
+            .await
+            .change_context(CompactionError)?
+            .map(|expired| expired + 1)
+            .unwrap_or(starting_block);
+
+        let Some(source_segmented) = self
+            .state_client
+            .get_segmented()
+            .await
+            .change_context(CompactionError)?
+        else {
+            return Err(CompactionError).attach_printable("no segments to re-compact");
+        };
+
+        let source_segment_size =
+            self.options_store
+                .get_segment_size()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("segment size option not found")? as u64;
+
+        let chain_segment_size =
+            self.options_store
+                .get_chain_segment_size()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("chain segment size option not found")? as u64;
+
+        let blocks_in_group = (self.segment_size * self.group_size) as u64;
+        if !(first_block - starting_block).is_multiple_of(blocks_in_group) {
+            return Err(CompactionError)
+                .attach_printable("first available block is not aligned to the new group size")
+                .attach_printable_lazy(|| format!("starting block: {starting_block}"))
+                .attach_printable_lazy(|| format!("first available block: {first_block}"))
+                .attach_printable_lazy(|| format!("blocks in group: {blocks_in_group}"));
+        }
+
+        info!(
+            first_block,
+            segmented = source_segmented,
+            segment_size = self.segment_size,
+            group_size = self.group_size,
+            "starting re-compaction"
+        );
+
+        // Copy the canonical chain.
+        let recent = self
+            .source_chain_store
+            .get_recent(None)
+            .await
+            .change_context(CompactionError)?
+            .ok_or(CompactionError)
+            .attach_printable("recent canonical chain segment not found")?;
+
+        let mut chain_segment_start = (first_block - starting_block) / chain_segment_size
+            * chain_segment_size
+            + starting_block;
+
+        while chain_segment_start < recent.info.first_block.number {
+            debug!(first_block = chain_segment_start, "copying chain segment");
+
+            let segment = self
+                .source_chain_store
+                .get(chain_segment_start)
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("chain segment not found")
+                .attach_printable_lazy(|| format!("first block: {chain_segment_start}"))?;
+
+            self.target_chain_store
+                .put(&segment)
+                .await
+                .change_context(CompactionError)?;
+
+            chain_segment_start += chain_segment_size;
+        }
+
+        let recent_etag = self
+            .target_chain_store
+            .put_recent(&recent)
+            .await
+            .change_context(CompactionError)?;
+
+        // Rebuild segments and groups from the existing segments.
+        let mut source_segment_start = first_block;
+        while source_segment_start + source_segment_size - 1 <= source_segmented {
+            if ct.is_cancelled() {
+                return Err(CompactionError)
+                    .attach_printable("re-compaction cancelled before the data was staged");
+            }
+
+            ingestion_lock
+                .keep_alive()
+                .await
+                .change_context(CompactionError)?;
+            compaction_lock
+                .keep_alive()
+                .await
+                .change_context(CompactionError)?;
+
+            debug!(first_block = source_segment_start, "reading source segment");
+
+            for (cursor, block) in
+                read_segment_blocks(&self.source_reader, source_segment_start).await?
+            {
+                self.pending.push((cursor, block));
+
+                if self.pending.len() == self.segment_size {
+                    self.flush_segment().await?;
+                }
+            }
+
+            source_segment_start += source_segment_size;
+        }
+
+        // Blocks that don't fill a segment are stored as single blocks.
+        for (cursor, block) in std::mem::take(&mut self.pending) {
+            debug!(cursor = %cursor, "writing block");
+
+            self.target_writer
+                .put_block(&cursor, &block)
+                .await
+                .change_context(CompactionError)?;
+        }
+
+        // Copy the blocks that were not segmented yet.
+        for block_number in (source_segmented + 1)..=recent.info.last_block.number {
+            if ct.is_cancelled() {
+                return Err(CompactionError)
+                    .attach_printable("re-compaction cancelled before the data was staged");
+            }
+
+            ingestion_lock
+                .keep_alive()
+                .await
+                .change_context(CompactionError)?;
+            compaction_lock
+                .keep_alive()
+                .await
+                .change_context(CompactionError)?;
+
+            self.source_reader
+                .copy_blocks_with_prefix(block_number, &self.target_writer)
+                .await
+                .change_context(CompactionError)
+                .attach_printable_lazy(|| format!("block number: {block_number}"))?;
+        }
+
+        let source_ingested = self
+            .state_client
+            .get_ingested()
+            .await
+            .change_context(CompactionError)?
+            .ok_or(CompactionError)
+            .attach_printable("ingested block not found")?;
+
+        info!(
+            segmented = ?self.segmented,
+            grouped = ?self.grouped,
+            "all data uploaded. staging options and watermarks"
+        );
+
+        let mut put = vec![
+            (
+                OBJECT_STORE_PREFIX_KEY.to_string(),
+                self.source_prefix.clone().into_bytes(),
+            ),
+            (
+                RECOMPACT_TARGET_PREFIX_KEY.to_string(),
+                self.target_prefix.clone().into_bytes(),
+            ),
+            (
+                RECOMPACT_SOURCE_INGESTED_KEY.to_string(),
+                source_ingested.0.into_bytes(),
+            ),
+            (
+                staged_key(SEGMENT_SIZE_KEY),
+                self.segment_size.to_string().into_bytes(),
+            ),
+            (
+                staged_key(GROUP_SIZE_KEY),
+                self.group_size.to_string().into_bytes(),
+            ),
+            (staged_key(INGESTED_KEY), recent_etag.0.into_bytes()),
+        ];
+        let mut delete = Vec::new();
+
+        for (key, value) in [
+            (SEGMENTED_KEY, self.segmented),
+            (GROUPED_KEY, self.grouped),
+            (PRUNED_KEY, self.grouped),
+        ] {
+            match value {
+                Some(block) => put.push((staged_key(key), block.to_string().into_bytes())),
+                None => delete.push(staged_key(key)),
+            }
+        }
+
+        self.etcd_client
+            .kv_client()
+            .put_and_delete_many(&put, &delete)
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to stage options and watermarks")?;
+
+        info!(
+            target_prefix = self.target_prefix,
+            "re-compaction staged. run again with --recompact.cutover to switch to the new data"
+        );
+
+        Ok(())
+    }
+
+    async fn flush_segment(&mut self) -> Result<(), CompactionError> {
+        let mut builder = SegmentBuilder::default();
+
+        let first_block = self
+            .pending
+            .first()
+            .map(|(cursor, _)| cursor.clone())
+            .ok_or(CompactionError)
+            .attach_printable("no blocks to flush")?;
+
+        builder
+            .start_new_segment(first_block.clone())
+            .change_context(CompactionError)?;
+
+        let mut last_block = first_block.clone();
+        for (cursor, block) in self.pending.drain(..) {
+            builder
+                .add_block_data(&cursor, block)
+                .change_context(CompactionError)
+                .attach_printable_lazy(|| format!("cursor: {cursor}"))?;
+            last_block = cursor;
+        }
+
+        info!(first_block = %first_block, last_block = %last_block, "uploading segment");
+
+        for segment in builder.segment_data().change_context(CompactionError)? {
+            if segment.name == INDEX_FRAGMENT_NAME {
+                let index = rkyv::from_bytes::<Segment<IndexGroupFragment>, rkyv::rancor::Error>(
+                    &segment.data,
+                )
+                .change_context(CompactionError)?;
+
+                self.group_builder
+                    .add_segment(&index)
+                    .change_context(CompactionError)
+                    .attach_printable("failed to add segment to group")?;
+            }
+
+            self.target_writer
+                .put_segment(&first_block, segment)
+                .await
+                .change_context(CompactionError)
+                .attach_printable("failed to put segment")?;
+        }
+
+        self.segmented = Some(last_block.number);
+
+        if self.group_builder.segment_count == self.group_size {
+            let builder = std::mem::replace(
+                &mut self.group_builder,
+                SegmentGroupBuilder::new(self.segment_size),
+            );
+            let group = builder.build().change_context(CompactionError)?;
+            let first_block_in_group = group.first_block.clone();
+
+            info!(first_block = %first_block_in_group, last_block = %last_block, "uploading group");
+
+            self.target_writer
+                .put_group(&first_block_in_group, &group)
+                .await
+                .change_context(CompactionError)?;
+
+            self.grouped = Some(last_block.number);
+        }
+
+        Ok(())
+    }
+}
+
+/// Switch the live options and watermarks to the state staged by [Recompaction].
+///
+/// Fails if nothing is staged for the given prefix, or if blocks were ingested after the
+/// state was staged, since those blocks are missing from the re-compacted data.
+pub(super) async fn cutover(
+    etcd_client: &EtcdClient,
+    target_prefix: &str,
+) -> Result<(), CompactionError> {
+    let mut kv_client = etcd_client.kv_client();
+
+    let staged_target_prefix = get_value(&mut kv_client, RECOMPACT_TARGET_PREFIX_KEY)
+        .await?
+        .ok_or(CompactionError)
+        .attach_printable("no re-compaction staged")?;
+
+    if staged_target_prefix != target_prefix {
+        return Err(CompactionError)
+            .attach_printable("re-compaction staged for a different prefix")
+            .attach_printable_lazy(|| format!("staged prefix: {staged_target_prefix}"))
+            .attach_printable_lazy(|| format!("target prefix: {target_prefix}"));
+    }
+
+    let source_ingested = get_value(&mut kv_client, RECOMPACT_SOURCE_INGESTED_KEY).await?;
+    let live_ingested = get_value(&mut kv_client, INGESTED_KEY).await?;
+
+    if source_ingested.is_none() || source_ingested != live_ingested {
+        return Err(CompactionError)
+            .attach_printable("blocks were ingested after the re-compaction was staged")
+            .attach_printable("run the re-compaction again before the cutover");
+    }
+
+    let mut put = vec![(
+        OBJECT_STORE_PREFIX_KEY.to_string(),
+        normalize_prefix(Some(staged_target_prefix)).into_bytes(),
+    )];
+    let mut delete = vec![
+        RECOMPACT_TARGET_PREFIX_KEY.to_string(),
+        RECOMPACT_SOURCE_INGESTED_KEY.to_string(),
+    ];
+
+    // A live key missing from the staged state is deleted.
+    for key in [
+        SEGMENT_SIZE_KEY,
+        GROUP_SIZE_KEY,
+        INGESTED_KEY,
+        SEGMENTED_KEY,
+        GROUPED_KEY,
+        PRUNED_KEY,
+    ] {
+        let staged = staged_key(key);
+        match get_value(&mut kv_client, &staged).await? {
+            Some(value) => put.push((key.to_string(), value.into_bytes())),
+            None => delete.push(key.to_string()),
+        }
+        delete.push(staged);
+    }
+
+    info!(target_prefix, "switching options and watermarks");
+
+    kv_client
+        .put_and_delete_many(&put, &delete)
+        .await
+        .change_context(CompactionError)
+        .attach_printable("failed to switch options and watermarks")?;
+
+    info!("re-compaction cutover completed. restart DNA with the new prefix");
+
+    Ok(())
+}
+
+fn staged_key(key: &str) -> String {
+    format!("{RECOMPACT_PREFIX_KEY}{key}")
+}
+
+async fn get_value(kv_client: &mut KvClient, key: &str) -> Result<Option<String>, CompactionError> {
+    let response = kv_client
+        .get(key)
+        .await
+        .change_context(CompactionError)
+        .attach_printable_lazy(|| format!("key: {key}"))?;
+
+    let Some(kv) = response.kvs().first() else {
+        return Ok(None);
+    };
+
+    let value = String::from_utf8(kv.value().to_vec())
+        .change_context(CompactionError)
+        .attach_printable("failed to decode value")
+        .attach_printable_lazy(|| format!("key: {key}"))?;
+
+    Ok(Some(value))
+}
+
+/// Reassemble the blocks stored in the segment starting at the given block.
+pub(super) async fn read_segment_blocks(
+    reader: &UncachedBlockStoreReader,
+    first_block_number: u64,
+) -> Result<Vec<(Cursor, Block)>, CompactionError> {
+    let first_cursor = Cursor::new_finalized(first_block_number);
+
+    let index = reader
+        .get_segment(&first_cursor, INDEX_FRAGMENT_NAME)
+        .await
+        .change_context(CompactionError)?;
+    let index = rkyv::from_bytes::<Segment<IndexGroupFragment>, rkyv::rancor::Error>(&index)
+        .change_context(CompactionError)?;
+
+    let join = reader
+        .get_segment(&first_cursor, JOIN_FRAGMENT_NAME)
+        .await
+        .change_context(CompactionError)?;
+    let join = rkyv::from_bytes::<Segment<JoinGroupFragment>, rkyv::rancor::Error>(&join)
+        .change_context(CompactionError)?;
+
+    let header = reader
+        .get_segment(&first_cursor, HEADER_FRAGMENT_NAME)
+        .await
+        .change_context(CompactionError)?;
+    let header = rkyv::from_bytes::<Segment<HeaderFragment>, rkyv::rancor::Error>(&header)
+        .change_context(CompactionError)?;
+
+    let mut body = Vec::new();
+    for name in reader
+        .list_segment_names(first_block_number)
+        .await
+        .change_context(CompactionError)?
+    {
+        if name == INDEX_FRAGMENT_NAME || name == JOIN_FRAGMENT_NAME || name == HEADER_FRAGMENT_NAME
+        {
+            continue;
+        }
+
+        let segment = reader
+            .get_segment(&first_cursor, &name)
+            .await
+            .change_context(CompactionError)?;
+        let segment = rkyv::from_bytes::<Segment<BodyFragment>, rkyv::rancor::Error>(&segment)
+            .change_context(CompactionError)
+            .attach_printable_lazy(|| format!("segment name: {name}"))?;
+
+        body.push(segment);
+    }
+
+    body.sort_by_key(|segment| segment.data.first().map(|data| data.data.fragment_id));
+
+    let block_count = header.data.len();
+    if index.data.len() != block_count
+        || join.data.len() != block_count
+        || body.iter().any(|segment| segment.data.len() != block_count)
+    {
+        return Err(CompactionError)
+            .attach_printable("segment fragments have different lengths")
+            .attach_printable_lazy(|| format!("first block: {first_block_number}"));
+    }
+
+    let mut body = body
+        .into_iter()
+        .map(|segment| segment.data.into_iter())
+        .collect::<Vec<_>>();
+
+    let mut blocks = Vec::with_capacity(block_count);
+    for ((header, index), join) in header
+        .data
+        .into_iter()
+        .zip(index.data.into_iter())
+        .zip(join.data.into_iter())
+    {
+        if header.cursor != index.cursor || header.cursor != join.cursor {
+            return Err(CompactionError)
+                .attach_printable("segment fragments cursor mismatch")
+                .attach_printable_lazy(|| format!("cursor: {}", header.cursor));
+        }
+
+        let mut block_body = Vec::with_capacity(body.len());
+        for fragments in body.iter_mut() {
+            let fragment = fragments
+                .next()
+                .ok_or(CompactionError)
+                .attach_printable("missing body fragment")?;
+            block_body.push(fragment.data);
+        }
+
+        blocks.push((
+            header.cursor,
+            Block {
+                header: header.data,
+                index: index.data,
+                join: join.data,
+                body: block_body,
+            },
+        ));
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_etcd::{EtcdClient, LockOptions};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        block_store::{BlockStoreWriter, UncachedBlockStoreReader},
+        chain::{BlockInfo, CanonicalChainBuilder},
+        chain_store::ChainStore,
+        compaction::segment_builder::SegmentBuilder,
+        file_cache::testing::memory_file_cache,
+        fragment::{Block, BodyFragment, HeaderFragment, IndexGroupFragment, JoinGroupFragment},
+        ingestion::IngestionStateClient,
+        new_test_cursor,
+        object_store::{MemoryClient, ObjectETag, ObjectStore, ObjectStoreOptions},
+        options_store::OptionsStore,
+    };
+
+    use super::{cutover, read_segment_blocks, Recompaction, RECOMPACT_PREFIX_KEY};
+
+    fn test_block(number: u64) -> Block {
+        Block {
+            header: HeaderFragment {
+                data: number.to_be_bytes().to_vec(),
+            },
+            index: IndexGroupFragment {
+                indexes: Vec::default(),
+            },
+            join: JoinGroupFragment {
+                joins: Vec::default(),
+            },
+            body: vec![BodyFragment {
+                fragment_id: 2,
+                name: "transaction".to_string(),
+                data: vec![number.to_be_bytes().to_vec()],
+            }],
+        }
+    }
+
+    fn memory_object_store(client: &MemoryClient, prefix: &str) -> ObjectStore {
+        ObjectStore::new_memory(
+            client.clone(),
+            ObjectStoreOptions {
+                bucket: "test".to_string(),
+                prefix: Some(prefix.to_string()),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_recompaction_is_staged_until_cutover() {
+        let dir = tempfile::tempdir().unwrap();
+        let etcd_client = EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+            .await
+            .unwrap();
+        let memory_client = MemoryClient::new();
+        let source = memory_object_store(&memory_client, "source");
+        let target = memory_object_store(&memory_client, "target");
+        let file_cache = memory_file_cache().await;
+
+        // Blocks 0 to 3 stored in two segments of two blocks.
+        let source_writer = BlockStoreWriter::new(source.clone());
+        for first_block in [0, 2] {
+            let first_cursor = new_test_cursor(first_block, 0);
+            let mut builder = SegmentBuilder::default();
+            builder.start_new_segment(first_cursor.clone()).unwrap();
+            for number in first_block..first_block + 2 {
+                builder
+                    .add_block_data(&new_test_cursor(number, 0), test_block(number))
+                    .unwrap();
+            }
+            for segment in builder.segment_data().unwrap() {
+                source_writer
+                    .put_segment(&first_cursor, segment)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mut chain_builder = CanonicalChainBuilder::new();
+        let mut parent = new_test_cursor(0, 1).hash;
+        for number in 0..4 {
+            let hash = new_test_cursor(number, 0).hash;
+            chain_builder
+                .grow(BlockInfo {
+                    number,
+                    hash: hash.clone(),
+                    parent,
+                })
+                .unwrap();
+            parent = hash;
+        }
+        let recent = chain_builder.current_segment().unwrap();
+        let source_ingested = ChainStore::new(source.clone(), file_cache.clone())
+            .put_recent(&recent)
+            .await
+            .unwrap();
+
+        let mut state_client = IngestionStateClient::new(&etcd_client);
+        let mut options_store = OptionsStore::new(&etcd_client);
+        state_client.put_starting_block(0).await.unwrap();
+        state_client
+            .put_ingested(source_ingested.clone())
+            .await
+            .unwrap();
+        state_client.put_segmented(3).await.unwrap();
+        options_store.set_chain_segment_size(1_000).await.unwrap();
+        options_store.set_segment_size(2).await.unwrap();
+        options_store.set_group_size(10).await.unwrap();
+
+        let ct = CancellationToken::new();
+        let mut lock_client = etcd_client.lock_client(LockOptions::default());
+        let mut ingestion_lock = lock_client
+            .lock("ingestion/lock", ct.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut compaction_lock = lock_client
+            .lock("compaction/lock", ct.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        Recompaction::new(
+            4,
+            1,
+            "target".to_string(),
+            source,
+            target.clone(),
+            &etcd_client,
+            file_cache.clone(),
+        )
+        .run(&mut ingestion_lock, &mut compaction_lock, &ct)
+        .await
+        .unwrap();
+
+        // The new segment is uploaded, but the live state is unchanged.
+        let blocks = read_segment_blocks(&UncachedBlockStoreReader::new(target.clone()), 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|(cursor, _)| cursor.number)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+
+        assert_eq!(options_store.get_segment_size().await.unwrap(), Some(2));
+        assert_eq!(options_store.get_group_size().await.unwrap(), Some(10));
+        assert_eq!(state_client.get_segmented().await.unwrap(), Some(3));
+        assert_eq!(state_client.get_grouped().await.unwrap(), None);
+        assert_eq!(state_client.get_pruned().await.unwrap(), None);
+        assert_eq!(
+            state_client.get_ingested().await.unwrap(),
+            Some(source_ingested.clone())
+        );
+        assert_eq!(
+            options_store.get_object_store_prefix().await.unwrap(),
+            Some("source/".to_string())
+        );
+
+        // The cutover must target the staged prefix.
+        assert!(cutover(&etcd_client, "other").await.is_err());
+
+        // The cutover is refused if blocks were ingested after staging.
+        state_client
+            .put_ingested(ObjectETag("\"new\"".to_string()))
+            .await
+            .unwrap();
+        assert!(cutover(&etcd_client, "target").await.is_err());
+        assert_eq!(options_store.get_segment_size().await.unwrap(), Some(2));
+        state_client
+            .put_ingested(source_ingested.clone())
+            .await
+            .unwrap();
+
+        cutover(&etcd_client, "target").await.unwrap();
+
+        assert_eq!(options_store.get_segment_size().await.unwrap(), Some(4));
+        assert_eq!(options_store.get_group_size().await.unwrap(), Some(1));
+        assert_eq!(state_client.get_segmented().await.unwrap(), Some(3));
+        assert_eq!(state_client.get_grouped().await.unwrap(), Some(3));
+        assert_eq!(state_client.get_pruned().await.unwrap(), Some(3));
+        assert_eq!(
+            options_store.get_object_store_prefix().await.unwrap(),
+            Some("target/".to_string())
+        );
+
+        let target_ingested = state_client.get_ingested().await.unwrap().unwrap();
+        assert_ne!(target_ingested, source_ingested);
+        let target_recent = ChainStore::new(target, file_cache)
+            .get_recent(Some(target_ingested))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(target_recent.info.last_block.number, 3);
+
+        let staged = etcd_client
+            .kv_client()
+            .get_prefix(RECOMPACT_PREFIX_KEY)
+            .await
+            .unwrap();
+        assert!(staged.kvs().is_empty());
+    }
+}
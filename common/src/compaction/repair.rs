@@ -0,0 +1,856 @@
+//! Re-ingest a range of finalized blocks and overwrite the stored data.
+//!
+//! Repair is used when the data returned by the node was wrong at ingestion time, for example
+//! because of a buggy RPC provider. The blocks are fetched again through the chain's block
+//! ingestion and must have the same hash as the canonical chain, so that cursors and chain
+//! segments stay valid. The block objects are overwritten, then the segments and groups that
+//! contain the blocks are rebuilt.
+//!
+//! Repair holds the compaction lock while running, so it waits for the compaction service to
+//! release it. After each segment and group, the cache keys of the overwritten objects are
+//! published to the `ingestion/repaired` key, and running servers remove them from their file
+//! cache. Servers that are not watching the ingestion state at that time (for example, because
+//! they're restarting) keep the old data until it's evicted.
+//!
+//! If the repair is interrupted, the error reports the blocks left to repair.
+use std::collections::{BTreeMap, BTreeSet};
+
+use apibara_etcd::{Lock, LockOptions};
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::{
+    block_store::{BlockStoreReader, BlockStoreWriter, UncachedBlockStoreReader},
+    chain::CanonicalChainSegment,
+    chain_store::ChainStore,
+    cli::{EtcdArgs, ObjectStoreArgs},
+    file_cache::FileCacheArgs,
+    fragment::{Block, IndexGroupFragment},
+    ingestion::{BlockIngestion, IngestionStateClient},
+    options_store::OptionsStore,
+    segment::Segment,
+    Cursor,
+};
+
+use super::{
+    group_builder::SegmentGroupBuilder, recompact::read_segment_blocks,
+    segment_builder::SegmentBuilder, CompactionError,
+};
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    #[clap(flatten)]
+    pub object_store: ObjectStoreArgs,
+    #[clap(flatten)]
+    pub etcd: EtcdArgs,
+    #[clap(flatten)]
+    pub cache: FileCacheArgs,
+    /// First block to repair.
+    #[arg(long = "repair.from-block", env = "DNA_REPAIR_FROM_BLOCK")]
+    pub from_block: u64,
+    /// Last block to repair, inclusive.
+    #[arg(long = "repair.to-block", env = "DNA_REPAIR_TO_BLOCK")]
+    pub to_block: u64,
+}
+
+struct Repair<I> {
+    ingestion: I,
+    segment_size: u64,
+    group_size: u64,
+    chain_segment_size: u64,
+    starting_block: u64,
+    segmented: Option<u64>,
+    grouped: Option<u64>,
+    pruned: Option<u64>,
+    reader: BlockStoreReader,
+    uncached_reader: UncachedBlockStoreReader,
+    writer: BlockStoreWriter,
+    chain_store: ChainStore,
+    recent: CanonicalChainSegment,
+    /// The last chain segment used to check the repaired blocks.
+    chain_segment: Option<CanonicalChainSegment>,
+    state_client: IngestionStateClient,
+    /// Cache keys of the objects overwritten since they were last published.
+    invalidated: Vec<String>,
+}
+
+impl RepairArgs {
+    pub async fn run<I>(self, ingestion: I, ct: CancellationToken) -> Result<(), CompactionError>
+    where
+        I: BlockIngestion,
+    {
+        if self.from_block > self.to_block {
+            return Err(CompactionError)
+                .attach_printable("from block must not be after to block")
+                .attach_printable_lazy(|| format!("from block: {}", self.from_block))
+                .attach_printable_lazy(|| format!("to block: {}", self.to_block));
+        }
+
+        let object_store = self
+            .object_store
+            .into_object_store_client()
+            .await
+            .change_context(CompactionError)?;
+
+        let etcd_client = self
+            .etcd
+            .into_etcd_client()
+            .await
+            .change_context(CompactionError)?;
+
+        let file_cache = self
+            .cache
+            .to_file_cache()
+            .await
+            .change_context(CompactionError)?;
+
+        let mut state_client = IngestionStateClient::new(&etcd_client);
+        let mut options_store = OptionsStore::new(&etcd_client);
+
+        let mut lock_client = etcd_client.lock_client(LockOptions::default());
+
+        info!("acquiring compaction lock");
+        let Some(mut compaction_lock) = lock_client
+            .lock("compaction/lock", ct.clone())
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to acquire compaction lock")?
+        else {
+            return Err(CompactionError)
+                .attach_printable("repair cancelled while waiting for the compaction lock")
+                .attach_printable_lazy(|| {
+                    format!("remaining blocks: {}-{}", self.from_block, self.to_block)
+                });
+        };
+
+        // Read the watermarks after acquiring the lock so that they don't change while repairing.
+        let result = async {
+            let starting_block = state_client
+                .get_starting_block()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("ingestion starting block not found")?;
+
+            let first_block = state_client
+                .get_expired()
+                .await
+                .change_context(CompactionError)?
+                .map(|expired| expired + 1)
+                .unwrap_or(starting_block);
+
+            let finalized = state_client
+                .get_finalized()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("finalized block not found")?;
+
+            if self.from_block < first_block || self.to_block > finalized {
+                return Err(CompactionError)
+                    .attach_printable("only finalized blocks that are not expired can be repaired")
+                    .attach_printable_lazy(|| format!("first available block: {first_block}"))
+                    .attach_printable_lazy(|| format!("finalized block: {finalized}"));
+            }
+
+            let segment_size = options_store
+                .get_segment_size()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("segment size option not found")?
+                as u64;
+
+            let group_size = options_store
+                .get_group_size()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("group size option not found")?
+                as u64;
+
+            let chain_segment_size = options_store
+                .get_chain_segment_size()
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("chain segment size option not found")?
+                as u64;
+
+            let chain_store = ChainStore::new(object_store.clone(), file_cache.clone());
+
+            let recent = chain_store
+                .get_recent(None)
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("recent canonical chain segment not found")?;
+
+            let repair = Repair {
+                ingestion,
+                segment_size,
+                group_size,
+                chain_segment_size,
+                starting_block,
+                segmented: state_client
+                    .get_segmented()
+                    .await
+                    .change_context(CompactionError)?,
+                grouped: state_client
+                    .get_grouped()
+                    .await
+                    .change_context(CompactionError)?,
+                pruned: state_client
+                    .get_pruned()
+                    .await
+                    .change_context(CompactionError)?,
+                reader: BlockStoreReader::new(object_store.clone(), file_cache),
+                uncached_reader: UncachedBlockStoreReader::new(object_store.clone()),
+                writer: BlockStoreWriter::new(object_store),
+                chain_store,
+                recent,
+                chain_segment: None,
+                state_client,
+                invalidated: Vec::new(),
+            };
+
+            // Boxed, otherwise computing the layout of the future overflows the query depth limit.
+            Box::pin(repair.run(self.from_block, self.to_block, &mut compaction_lock, &ct)).await
+        }
+        .await;
+
+        lock_client
+            .unlock(compaction_lock)
+            .await
+            .change_context(CompactionError)?;
+
+        result
+    }
+}
+
+impl<I> Repair<I>
+where
+    I: BlockIngestion,
+{
+    async fn run(
+        mut self,
+        from_block: u64,
+        to_block: u64,
+        compaction_lock: &mut Lock,
+        ct: &CancellationToken,
+    ) -> Result<(), CompactionError> {
+        info!(
+            from_block,
+            to_block,
+            segmented = ?self.segmented,
+            grouped = ?self.grouped,
+            "starting repair"
+        );
+
+        let mut groups = BTreeSet::new();
+
+        let mut segment_start = self.segment_start(from_block);
+        while segment_start <= to_block {
+            if ct.is_cancelled() {
+                let next_block = u64::max(segment_start, from_block);
+                let repaired = if next_block == from_block {
+                    "none".to_string()
+                } else {
+                    format!("{from_block}-{}", next_block - 1)
+                };
+
+                return Err(CompactionError)
+                    .attach_printable("repair cancelled before completion")
+                    .attach_printable_lazy(|| format!("repaired blocks: {repaired}"))
+                    .attach_printable_lazy(|| {
+                        format!("remaining blocks: {next_block}-{to_block}")
+                    });
+            }
+
+            compaction_lock
+                .keep_alive()
+                .await
+                .change_context(CompactionError)?;
+
+            let segment_end = segment_start + self.segment_size - 1;
+
+            let mut repaired = BTreeMap::new();
+            for block_number in
+                u64::max(segment_start, from_block)..=u64::min(segment_end, to_block)
+            {
+                let (cursor, block) = self.repair_block(block_number).await?;
+                repaired.insert(cursor.number, (cursor, block));
+            }
+
+            if self
+                .segmented
+                .is_some_and(|segmented| segment_end <= segmented)
+            {
+                self.rebuild_segment(segment_start, repaired).await?;
+                groups.insert(self.group_start(segment_start));
+            }
+
+            self.publish_invalidated().await?;
+
+            segment_start += self.segment_size;
+        }
+
+        for group_start in groups {
+            let group_end = group_start + self.segment_size * self.group_size - 1;
+            if self.grouped.is_none_or(|grouped| group_end > grouped) {
+                continue;
+            }
+
+            if ct.is_cancelled() {
+                return Err(CompactionError)
+                    .attach_printable("repair cancelled before rebuilding all groups")
+                    .attach_printable_lazy(|| format!("repaired blocks: {from_block}-{to_block}"))
+                    .attach_printable_lazy(|| format!("next group: {group_start}"));
+            }
+
+            compaction_lock
+                .keep_alive()
+                .await
+                .change_context(CompactionError)?;
+
+            self.rebuild_group(group_start).await?;
+            self.publish_invalidated().await?;
+        }
+
+        info!(from_block, to_block, "repair completed");
+
+        Ok(())
+    }
+
+    /// Ingest the block again and overwrite the block object.
+    async fn repair_block(
+        &mut self,
+        block_number: u64,
+    ) -> Result<(Cursor, Block), CompactionError> {
+        let (block_info, block) = self
+            .ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to ingest block")
+            .attach_printable_lazy(|| format!("block number: {block_number}"))?;
+
+        let cursor = block_info.cursor();
+        let canonical = self.canonical_cursor(block_number).await?;
+
+        if cursor != canonical {
+            return Err(CompactionError)
+                .attach_printable("repaired block is not in the canonical chain")
+                .attach_printable_lazy(|| format!("repaired: {cursor}"))
+                .attach_printable_lazy(|| format!("canonical: {canonical}"));
+        }
+
+        // Blocks that were pruned are only stored in segments.
+        if self.pruned.is_none_or(|pruned| block_number > pruned) {
+            debug!(cursor = %cursor, "writing block");
+
+            self.writer
+                .put_block(&cursor, &block)
+                .await
+                .change_context(CompactionError)?;
+            let key = self.reader.invalidate_block(&cursor);
+            self.invalidated.push(key);
+        }
+
+        Ok((cursor, block))
+    }
+
+    /// Rebuild the segment, replacing the existing blocks with the repaired ones.
+    async fn rebuild_segment(
+        &mut self,
+        segment_start: u64,
+        mut repaired: BTreeMap<u64, (Cursor, Block)>,
+    ) -> Result<(), CompactionError> {
+        let blocks = read_segment_blocks(&self.uncached_reader, segment_start).await?;
+
+        let first_block = blocks
+            .first()
+            .map(|(cursor, _)| cursor.clone())
+            .ok_or(CompactionError)
+            .attach_printable("segment is empty")
+            .attach_printable_lazy(|| format!("first block: {segment_start}"))?;
+
+        let mut builder = SegmentBuilder::default();
+        builder
+            .start_new_segment(first_block.clone())
+            .change_context(CompactionError)?;
+
+        for (cursor, block) in blocks {
+            let block = match repaired.remove(&cursor.number) {
+                Some((_, repaired_block)) => repaired_block,
+                None => block,
+            };
+
+            builder
+                .add_block_data(&cursor, block)
+                .change_context(CompactionError)
+                .attach_printable_lazy(|| format!("cursor: {cursor}"))?;
+        }
+
+        info!(first_block = %first_block, "uploading repaired segment");
+
+        for segment in builder.segment_data().change_context(CompactionError)? {
+            let name = segment.name.clone();
+
+            self.writer
+                .put_segment(&first_block, segment)
+                .await
+                .change_context(CompactionError)
+                .attach_printable("failed to put segment")?;
+            let key = self.reader.invalidate_segment(&first_block, &name);
+            self.invalidated.push(key);
+        }
+
+        Ok(())
+    }
+
+    /// Rebuild the group from its (repaired) index segments.
+    async fn rebuild_group(&mut self, group_start: u64) -> Result<(), CompactionError> {
+        let mut builder = SegmentGroupBuilder::new(self.segment_size as usize);
+
+        for i in 0..self.group_size {
+            let segment_start = group_start + i * self.segment_size;
+            let index = self
+                .uncached_reader
+                .get_index_segment(&Cursor::new_finalized(segment_start))
+                .await
+                .change_context(CompactionError)?;
+            let index =
+                rkyv::from_bytes::<Segment<IndexGroupFragment>, rkyv::rancor::Error>(&index)
+                    .change_context(CompactionError)?;
+
+            builder
+                .add_segment(&index)
+                .change_context(CompactionError)
+                .attach_printable("failed to add segment to group")?;
+        }
+
+        let group = builder.build().change_context(CompactionError)?;
+        let first_block_in_group = group.first_block.clone();
+
+        info!(first_block = %first_block_in_group, "uploading repaired group");
+
+        self.writer
+            .put_group(&first_block_in_group, &group)
+            .await
+            .change_context(CompactionError)?;
+        let key = self.reader.invalidate_group(&first_block_in_group);
+        self.invalidated.push(key);
+
+        Ok(())
+    }
+
+    /// Ask the running servers to remove the overwritten objects from their cache.
+    async fn publish_invalidated(&mut self) -> Result<(), CompactionError> {
+        if self.invalidated.is_empty() {
+            return Ok(());
+        }
+
+        let keys = std::mem::take(&mut self.invalidated);
+        debug!(count = keys.len(), "publishing repaired objects");
+
+        self.state_client
+            .put_repaired(&keys)
+            .await
+            .change_context(CompactionError)
+            .attach_printable("failed to publish repaired objects")
+    }
+
+    async fn canonical_cursor(&mut self, block_number: u64) -> Result<Cursor, CompactionError> {
+        if block_number >= self.recent.info.first_block.number {
+            return self
+                .recent
+                .canonical(block_number)
+                .change_context(CompactionError);
+        }
+
+        let chain_segment_start = (block_number - self.starting_block) / self.chain_segment_size
+            * self.chain_segment_size
+            + self.starting_block;
+
+        let is_cached = self
+            .chain_segment
+            .as_ref()
+            .is_some_and(|segment| segment.info.first_block.number == chain_segment_start);
+
+        if !is_cached {
+            let segment = self
+                .chain_store
+                .get(chain_segment_start)
+                .await
+                .change_context(CompactionError)?
+                .ok_or(CompactionError)
+                .attach_printable("chain segment not found")
+                .attach_printable_lazy(|| format!("first block: {chain_segment_start}"))?;
+            self.chain_segment = Some(segment);
+        }
+
+        self.chain_segment
+            .as_ref()
+            .ok_or(CompactionError)?
+            .canonical(block_number)
+            .change_context(CompactionError)
+    }
+
+    fn segment_start(&self, block_number: u64) -> u64 {
+        (block_number - self.starting_block) / self.segment_size * self.segment_size
+            + self.starting_block
+    }
+
+    fn group_start(&self, block_number: u64) -> u64 {
+        let blocks_in_group = self.segment_size * self.group_size;
+        (block_number - self.starting_block) / blocks_in_group * blocks_in_group
+            + self.starting_block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use apibara_etcd::{EtcdClient, LockOptions};
+    use error_stack::Result;
+    use futures::StreamExt;
+    use tempfile::TempDir;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        block_store::{
+            format_block_key, BlockStoreReader, BlockStoreWriter, UncachedBlockStoreReader,
+        },
+        chain::{BlockInfo, CanonicalChainBuilder, CanonicalChainSegment},
+        chain_store::ChainStore,
+        compaction::{
+            recompact::read_segment_blocks, segment_builder::SegmentBuilder, CompactionError,
+        },
+        file_cache::{testing::memory_file_cache, FileCache},
+        fragment::{Block, BodyFragment, HeaderFragment, IndexGroupFragment, JoinGroupFragment},
+        ingestion::{BlockIngestion, IngestionError, IngestionStateClient, IngestionStateUpdate},
+        new_test_cursor,
+        object_store::{MemoryClient, ObjectStore, ObjectStoreOptions},
+        Cursor,
+    };
+
+    use super::Repair;
+
+    /// Blocks 0 to 5 are finalized, blocks 0 to 3 are segmented and grouped.
+    const LAST_BLOCK: u64 = 5;
+
+    /// The body data ends with the block version: 0 when first ingested, 1 after repair.
+    fn test_block(number: u64, version: u8) -> Block {
+        Block {
+            header: HeaderFragment {
+                data: number.to_be_bytes().to_vec(),
+            },
+            index: IndexGroupFragment {
+                indexes: Vec::default(),
+            },
+            join: JoinGroupFragment {
+                joins: Vec::default(),
+            },
+            body: vec![BodyFragment {
+                fragment_id: 2,
+                name: "transaction".to_string(),
+                data: vec![[number.to_be_bytes().as_slice(), &[version]].concat()],
+            }],
+        }
+    }
+
+    fn serialized_block(number: u64, version: u8) -> Vec<u8> {
+        rkyv::to_bytes::<rkyv::rancor::Error>(&test_block(number, version))
+            .unwrap()
+            .to_vec()
+    }
+
+    fn block_version(block: &Block) -> u8 {
+        *block.body[0].data[0].last().unwrap()
+    }
+
+    #[derive(Clone)]
+    struct TestIngestion {
+        /// Return blocks that are not in the canonical chain.
+        reorged: bool,
+        /// Cancel the token after ingesting this block.
+        cancel_after: Option<(u64, CancellationToken)>,
+    }
+
+    impl BlockIngestion for TestIngestion {
+        async fn get_head_cursor(&self) -> Result<Cursor, IngestionError> {
+            unimplemented!()
+        }
+
+        async fn get_finalized_cursor(&self) -> Result<Cursor, IngestionError> {
+            unimplemented!()
+        }
+
+        async fn get_block_info_by_number(
+            &self,
+            block_number: u64,
+        ) -> Result<BlockInfo, IngestionError> {
+            let chain = if self.reorged { 1 } else { 0 };
+            Ok(BlockInfo {
+                number: block_number,
+                hash: new_test_cursor(block_number, chain).hash,
+                parent: new_test_cursor(block_number.saturating_sub(1), 0).hash,
+            })
+        }
+
+        async fn ingest_block_by_number(
+            &self,
+            block_number: u64,
+        ) -> Result<(BlockInfo, Block), IngestionError> {
+            let block_info = self.get_block_info_by_number(block_number).await?;
+
+            if let Some((cancel_after, ct)) = &self.cancel_after {
+                if *cancel_after == block_number {
+                    ct.cancel();
+                }
+            }
+
+            Ok((block_info, test_block(block_number, 1)))
+        }
+    }
+
+    struct TestContext {
+        _dir: TempDir,
+        etcd_client: EtcdClient,
+        object_store: ObjectStore,
+        file_cache: FileCache,
+        recent: CanonicalChainSegment,
+    }
+
+    impl TestContext {
+        async fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let etcd_client =
+                EtcdClient::embedded(dir.path().join("state.json"), Default::default())
+                    .await
+                    .unwrap();
+            let object_store = ObjectStore::new_memory(
+                MemoryClient::new(),
+                ObjectStoreOptions {
+                    bucket: "test".to_string(),
+                    prefix: None,
+                },
+            );
+            let file_cache = memory_file_cache().await;
+
+            let writer = BlockStoreWriter::new(object_store.clone());
+            for number in 0..=LAST_BLOCK {
+                writer
+                    .put_block(&new_test_cursor(number, 0), &test_block(number, 0))
+                    .await
+                    .unwrap();
+            }
+
+            for first_block in [0, 2] {
+                let first_cursor = new_test_cursor(first_block, 0);
+                let mut builder = SegmentBuilder::default();
+                builder.start_new_segment(first_cursor.clone()).unwrap();
+                for number in first_block..first_block + 2 {
+                    builder
+                        .add_block_data(&new_test_cursor(number, 0), test_block(number, 0))
+                        .unwrap();
+                }
+                for segment in builder.segment_data().unwrap() {
+                    writer.put_segment(&first_cursor, segment).await.unwrap();
+                }
+            }
+
+            let mut chain_builder = CanonicalChainBuilder::new();
+            let mut parent = new_test_cursor(0, 1).hash;
+            for number in 0..=LAST_BLOCK {
+                let hash = new_test_cursor(number, 0).hash;
+                chain_builder
+                    .grow(BlockInfo {
+                        number,
+                        hash: hash.clone(),
+                        parent,
+                    })
+                    .unwrap();
+                parent = hash;
+            }
+            let recent = chain_builder.current_segment().unwrap();
+
+            Self {
+                _dir: dir,
+                etcd_client,
+                object_store,
+                file_cache,
+                recent,
+            }
+        }
+
+        fn repair(&self, ingestion: TestIngestion) -> Repair<TestIngestion> {
+            Repair {
+                ingestion,
+                segment_size: 2,
+                group_size: 2,
+                chain_segment_size: 1_000,
+                starting_block: 0,
+                segmented: Some(3),
+                grouped: Some(3),
+                pruned: None,
+                reader: BlockStoreReader::new(self.object_store.clone(), self.file_cache.clone()),
+                uncached_reader: UncachedBlockStoreReader::new(self.object_store.clone()),
+                writer: BlockStoreWriter::new(self.object_store.clone()),
+                chain_store: ChainStore::new(self.object_store.clone(), self.file_cache.clone()),
+                recent: self.recent.clone(),
+                chain_segment: None,
+                state_client: IngestionStateClient::new(&self.etcd_client),
+                invalidated: Vec::new(),
+            }
+        }
+
+        async fn run(
+            &self,
+            ingestion: TestIngestion,
+            from_block: u64,
+            to_block: u64,
+            ct: &CancellationToken,
+        ) -> Result<(), CompactionError> {
+            let mut lock_client = self.etcd_client.lock_client(LockOptions::default());
+            let mut compaction_lock = lock_client
+                .lock("compaction/lock", ct.clone())
+                .await
+                .unwrap()
+                .unwrap();
+
+            let result = self
+                .repair(ingestion)
+                .run(from_block, to_block, &mut compaction_lock, ct)
+                .await;
+
+            lock_client.unlock(compaction_lock).await.unwrap();
+
+            result
+        }
+
+        async fn stored_block(&self, number: u64) -> Vec<u8> {
+            UncachedBlockStoreReader::new(self.object_store.clone())
+                .get_block(&new_test_cursor(number, 0))
+                .await
+                .unwrap()
+                .to_vec()
+        }
+
+        async fn segment_versions(&self, first_block: u64) -> Vec<u8> {
+            read_segment_blocks(
+                &UncachedBlockStoreReader::new(self.object_store.clone()),
+                first_block,
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|(_, block)| block_version(block))
+            .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repair_overwrites_blocks_and_invalidates_caches() {
+        let context = TestContext::new().await;
+
+        // A server with its own cache, populated before the repair.
+        let server = BlockStoreReader::new(context.object_store.clone(), memory_file_cache().await);
+        let cached = server.get_block(&new_test_cursor(1, 0)).await.unwrap();
+        assert_eq!(cached.value().as_ref(), serialized_block(1, 0).as_slice());
+
+        let ct = CancellationToken::new();
+        let mut state_client = IngestionStateClient::new(&context.etcd_client);
+        let changes = state_client.watch_changes(ct.clone()).await.unwrap();
+        tokio::pin!(changes);
+
+        let ingestion = TestIngestion {
+            reorged: false,
+            cancel_after: None,
+        };
+        context.run(ingestion, 1, 4, &ct).await.unwrap();
+
+        for number in 0..=LAST_BLOCK {
+            let version = if (1..=4).contains(&number) { 1 } else { 0 };
+            assert_eq!(
+                context.stored_block(number).await,
+                serialized_block(number, version),
+                "block {number}"
+            );
+        }
+
+        assert_eq!(context.segment_versions(0).await, vec![0, 1]);
+        assert_eq!(context.segment_versions(2).await, vec![1, 1]);
+
+        // One marker for each segment (including the unsegmented blocks) and one for the group.
+        let mut repaired = Vec::new();
+        for _ in 0..4 {
+            let update = tokio::time::timeout(Duration::from_secs(5), changes.next())
+                .await
+                .expect("repair marker")
+                .unwrap()
+                .unwrap();
+            let IngestionStateUpdate::Repaired(keys) = update else {
+                panic!("expected a repaired update, got {update:?}");
+            };
+            repaired.extend(keys);
+        }
+
+        for number in 1..=4 {
+            assert!(repaired.contains(&format_block_key(&new_test_cursor(number, 0))));
+        }
+        assert!(!repaired.contains(&format_block_key(&new_test_cursor(0, 0))));
+        assert!(repaired.contains(&"segment/0000000000/transaction".to_string()));
+        assert!(repaired.contains(&"segment/0000000002/header".to_string()));
+        assert!(repaired.contains(&"group/0000000000/index".to_string()));
+
+        // The server serves the repaired block once it evicts the published keys.
+        for key in repaired.iter() {
+            server.invalidate(key);
+        }
+        let cached = server.get_block(&new_test_cursor(1, 0)).await.unwrap();
+        assert_eq!(cached.value().as_ref(), serialized_block(1, 1).as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_repair_cancelled_reports_remaining_blocks() {
+        let context = TestContext::new().await;
+
+        let ct = CancellationToken::new();
+        let ingestion = TestIngestion {
+            reorged: false,
+            cancel_after: Some((1, ct.clone())),
+        };
+        let err = context.run(ingestion, 0, 5, &ct).await.unwrap_err();
+
+        let message = format!("{err:?}");
+        assert!(message.contains("repair cancelled before completion"));
+        assert!(message.contains("repaired blocks: 0-1"));
+        assert!(message.contains("remaining blocks: 2-5"));
+
+        // The first segment is repaired, the rest is untouched.
+        assert_eq!(context.segment_versions(0).await, vec![1, 1]);
+        assert_eq!(context.segment_versions(2).await, vec![0, 0]);
+        assert_eq!(context.stored_block(2).await, serialized_block(2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_repair_rejects_non_canonical_blocks() {
+        let context = TestContext::new().await;
+
+        let ct = CancellationToken::new();
+        let ingestion = TestIngestion {
+            reorged: true,
+            cancel_after: None,
+        };
+        let err = context.run(ingestion, 0, 1, &ct).await.unwrap_err();
+
+        assert!(format!("{err:?}").contains("repaired block is not in the canonical chain"));
+        assert_eq!(context.stored_block(0).await, serialized_block(0, 0));
+        assert_eq!(context.segment_versions(0).await, vec![0, 0]);
+    }
+}
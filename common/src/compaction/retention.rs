@@ -0,0 +1,268 @@
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::{
+    block_store::BlockStoreWriter,
+    chain_store::ChainStore,
+    chain_view::{group_start_block, ChainView},
+    ingestion::IngestionStateClient,
+};
+
+use super::{metrics::CompactionMetrics, CompactionError};
+
+/// Expire segment groups that are older than the retention window.
+///
+/// Data is expired one group at a time. The expired watermark is updated before deleting
+/// the objects so that the chain view stops serving the group before its data is removed.
+pub struct RetentionService {
+    retention_blocks: u64,
+    chain_view: ChainView,
+    block_store_writer: BlockStoreWriter,
+    chain_store: ChainStore,
+    state_client: IngestionStateClient,
+    metrics: CompactionMetrics,
+}
+
+impl RetentionService {
+    pub fn new(
+        retention_blocks: u64,
+        chain_view: ChainView,
+        block_store_writer: BlockStoreWriter,
+        chain_store: ChainStore,
+        state_client: IngestionStateClient,
+        metrics: CompactionMetrics,
+    ) -> Self {
+        Self {
+            retention_blocks,
+            chain_view,
+            block_store_writer,
+            chain_store,
+            state_client,
+            metrics,
+        }
+    }
+
+    pub async fn start(mut self, ct: CancellationToken) -> Result<(), CompactionError> {
+        loop {
+            if ct.is_cancelled() {
+                return Ok(());
+            }
+
+            info!("compaction: retention tick");
+
+            self.expire_loop(&ct).await?;
+
+            let Some(_) = ct
+                .run_until_cancelled(self.chain_view.segmented_changed())
+                .await
+            else {
+                return Ok(());
+            };
+        }
+    }
+
+    async fn expire_loop(&mut self, ct: &CancellationToken) -> Result<(), CompactionError> {
+        let Some(grouped) = self
+            .state_client
+            .get_grouped()
+            .await
+            .change_context(CompactionError)?
+        else {
+            debug!("no grouped block, skipping retention");
+            return Ok(());
+        };
+
+        // Only expire groups whose blocks have already been pruned.
+        let Some(pruned) = self
+            .state_client
+            .get_pruned()
+            .await
+            .change_context(CompactionError)?
+        else {
+            debug!("no pruned block, skipping retention");
+            return Ok(());
+        };
+
+        let head = self
+            .chain_view
+            .get_head()
+            .await
+            .change_context(CompactionError)?;
+
+        let Some(retention_start) = head.number.checked_sub(self.retention_blocks) else {
+            debug!(head = %head, "chain is shorter than the retention window");
+            return Ok(());
+        };
+
+        let expired = self
+            .state_client
+            .get_expired()
+            .await
+            .change_context(CompactionError)?;
+
+        let segment_size = self.chain_view.get_segment_size().await;
+        let group_size = self.chain_view.get_group_size().await;
+        let blocks_in_group = self.chain_view.get_blocks_in_group().await;
+        let chain_segment_size = self.chain_view.get_chain_segment_size().await;
+        let starting_block = self.chain_view.get_starting_block().await;
+
+        let mut first_block_in_group =
+            first_unexpired_group_start(expired, starting_block, blocks_in_group);
+
+        debug!(
+            first_block_in_group,
+            retention_start, grouped, pruned, "retention loop starting"
+        );
+
+        loop {
+            if ct.is_cancelled() {
+                return Ok(());
+            }
+
+            let last_block_in_group = first_block_in_group + blocks_in_group - 1;
+
+            if !can_expire_group(last_block_in_group, retention_start, grouped, pruned) {
+                return Ok(());
+            }
+
+            info!(
+                first_block = first_block_in_group,
+                last_block = last_block_in_group,
+                "expiring segment group"
+            );
+
+            self.state_client
+                .put_expired(last_block_in_group)
+                .await
+                .change_context(CompactionError)?;
+
+            self.block_store_writer
+                .delete_group(first_block_in_group)
+                .await
+                .change_context(CompactionError)
+                .attach_printable("failed to delete group")
+                .attach_printable_lazy(|| format!("first block: {first_block_in_group}"))?;
+
+            for segment_index in 0..group_size {
+                let first_block_in_segment = first_block_in_group + segment_index * segment_size;
+
+                self.block_store_writer
+                    .delete_segment(first_block_in_segment)
+                    .await
+                    .change_context(CompactionError)
+                    .attach_printable("failed to delete segment")
+                    .attach_printable_lazy(|| format!("first block: {first_block_in_segment}"))?;
+            }
+
+            // Delete the canonical chain segments that are now fully expired.
+            let chain_segment_start = self
+                .chain_view
+                .get_chain_segment_start_block(first_block_in_group)
+                .await;
+
+            for chain_segment_start in
+                expired_chain_segments(chain_segment_start, chain_segment_size, last_block_in_group)
+            {
+                debug!(first_block = chain_segment_start, "deleting chain segment");
+
+                self.chain_store
+                    .delete(chain_segment_start)
+                    .await
+                    .change_context(CompactionError)
+                    .attach_printable("failed to delete chain segment")
+                    .attach_printable_lazy(|| format!("first block: {chain_segment_start}"))?;
+            }
+
+            self.metrics.expired.record(last_block_in_group, &[]);
+
+            first_block_in_group = last_block_in_group + 1;
+        }
+    }
+}
+
+/// Returns the first block of the oldest group that hasn't expired.
+///
+/// Groups are aligned to the chain's starting block, like when they're built and served.
+fn first_unexpired_group_start(
+    expired: Option<u64>,
+    starting_block: u64,
+    blocks_in_group: u64,
+) -> u64 {
+    let first_available_block = expired.map(|block| block + 1).unwrap_or(starting_block);
+    group_start_block(first_available_block, starting_block, blocks_in_group)
+}
+
+/// Returns whether the group ending at `last_block_in_group` can be expired.
+///
+/// A group is expired only once it's entirely before the retention window, and after it has
+/// been grouped and its blocks pruned.
+fn can_expire_group(
+    last_block_in_group: u64,
+    retention_start: u64,
+    grouped: u64,
+    pruned: u64,
+) -> bool {
+    last_block_in_group < retention_start
+        && last_block_in_group <= grouped
+        && last_block_in_group <= pruned
+}
+
+/// Returns the first block of the chain segments that end at or before `last_expired_block`.
+fn expired_chain_segments(
+    chain_segment_start: u64,
+    chain_segment_size: u64,
+    last_expired_block: u64,
+) -> impl Iterator<Item = u64> {
+    (chain_segment_start..)
+        .step_by(chain_segment_size as usize)
+        .take_while(move |start| start + chain_segment_size - 1 <= last_expired_block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{can_expire_group, expired_chain_segments, first_unexpired_group_start};
+
+    #[test]
+    fn test_first_unexpired_group_start_unaligned_starting_block() {
+        // Groups of 100 blocks starting at block 1_234.
+        assert_eq!(first_unexpired_group_start(None, 1_234, 100), 1_234);
+        assert_eq!(first_unexpired_group_start(Some(1_333), 1_234, 100), 1_334);
+        assert_eq!(first_unexpired_group_start(Some(1_533), 1_234, 100), 1_534);
+    }
+
+    #[test]
+    fn test_first_unexpired_group_start_partially_expired_group() {
+        // A watermark inside a group restarts from the beginning of the group.
+        assert_eq!(first_unexpired_group_start(Some(1_300), 1_234, 100), 1_234);
+        assert_eq!(first_unexpired_group_start(Some(1_400), 1_234, 100), 1_334);
+    }
+
+    #[test]
+    fn test_can_expire_group_retention_boundary() {
+        // The group must end strictly before the first retained block.
+        assert!(can_expire_group(999, 1_000, 2_000, 2_000));
+        assert!(!can_expire_group(1_000, 1_000, 2_000, 2_000));
+        assert!(!can_expire_group(1_001, 1_000, 2_000, 2_000));
+    }
+
+    #[test]
+    fn test_can_expire_group_waits_for_grouped_and_pruned() {
+        assert!(can_expire_group(999, 5_000, 999, 999));
+        assert!(!can_expire_group(999, 5_000, 998, 5_000));
+        assert!(!can_expire_group(999, 5_000, 5_000, 998));
+    }
+
+    #[test]
+    fn test_expired_chain_segments() {
+        let expired = expired_chain_segments(1_000, 100, 1_299).collect::<Vec<_>>();
+        assert_eq!(expired, vec![1_000, 1_100, 1_200]);
+
+        // A chain segment that extends past the expired group is kept.
+        let expired = expired_chain_segments(1_000, 100, 1_249).collect::<Vec<_>>();
+        assert_eq!(expired, vec![1_000, 1_100]);
+
+        let expired = expired_chain_segments(1_000, 100, 1_098).collect::<Vec<_>>();
+        assert!(expired.is_empty());
+    }
+}
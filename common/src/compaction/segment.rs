@@ -1,92 +1,116 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
 use apibara_observability::RecordRequest;
 use error_stack::{Result, ResultExt};
-use futures::{FutureExt, StreamExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use futures_buffered::FuturesOrderedBounded;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
 use crate::{
     block_store::{BlockStoreWriter, UncachedBlockStoreReader},
-    chain_view::{ChainView, NextCursor},
+    chain_view::{CanonicalCursor, ChainView, NextCursor},
     ingestion::IngestionStateClient,
     Cursor,
 };
 
-use super::{metrics::CompactionMetrics, segment_builder::SegmentBuilder, CompactionError};
+use super::{
+    lease::{LeasedSegment, SegmentLeases},
+    metrics::CompactionMetrics,
+    segment_builder::SegmentBuilder,
+    CompactionError,
+};
 
 const MAX_BUFFERED_BLOCKS: usize = 128;
+const BUSY_SEGMENT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
 
 pub struct SegmentService {
+    workers: usize,
+    worker: SegmentWorker,
+    leases: Option<SegmentLeases>,
+    state_client: IngestionStateClient,
+}
+
+/// Builds segments and uploads them to the object store.
+///
+/// The worker doesn't update the segmented watermark so that multiple segments can be
+/// built concurrently.
+#[derive(Clone)]
+pub struct SegmentWorker {
     segment_size: usize,
     chain_view: ChainView,
     block_store_reader: UncachedBlockStoreReader,
     block_store_writer: BlockStoreWriter,
-    state_client: IngestionStateClient,
     metrics: CompactionMetrics,
 }
 
 impl SegmentService {
     pub fn new(
-        segment_size: usize,
-        chain_view: ChainView,
-        block_store_reader: UncachedBlockStoreReader,
-        block_store_writer: BlockStoreWriter,
+        workers: usize,
+        worker: SegmentWorker,
+        leases: Option<SegmentLeases>,
         state_client: IngestionStateClient,
-        metrics: CompactionMetrics,
     ) -> Self {
         Self {
-            segment_size,
-            chain_view,
-            block_store_reader,
-            block_store_writer,
+            workers: usize::max(workers, 1),
+            worker,
+            leases,
             state_client,
-            metrics,
         }
     }
 
     pub async fn start(mut self, ct: CancellationToken) -> Result<(), CompactionError> {
+        let segment_size = self.worker.segment_size as u64;
+        let chain_view = self.worker.chain_view.clone();
+
+        let mut next_to_commit = if let Some(segmented) = chain_view
+            .get_segmented_cursor()
+            .await
+            .change_context(CompactionError)?
+        {
+            segmented.number + 1
+        } else {
+            chain_view
+                .get_starting_cursor()
+                .await
+                .change_context(CompactionError)?
+                .number
+        };
+        let mut next_to_schedule = next_to_commit;
+
+        let mut in_flight = FuturesUnordered::new();
+        // Segments built (by any node) but not yet covered by the watermark.
+        let mut built = BTreeMap::<u64, u64>::new();
+        // Segments leased by other nodes. They are retried until they're built.
+        let mut busy = BTreeSet::<u64>::new();
+        let mut retry_busy = false;
+        let mut busy_retry_interval = tokio::time::interval(BUSY_SEGMENT_RETRY_INTERVAL);
+
         loop {
             if ct.is_cancelled() {
                 return Ok(());
             }
 
-            let first_block_in_segment = if let Some(cursor) = self
-                .chain_view
-                .get_segmented_cursor()
-                .await
-                .change_context(CompactionError)?
-            {
-                let NextCursor::Continue { cursor, .. } = self
-                    .chain_view
-                    .get_next_cursor(&Some(cursor.clone()))
-                    .await
-                    .change_context(CompactionError)?
-                else {
-                    return Err(CompactionError)
-                        .attach_printable("chain view returned invalid cursor")
-                        .attach_printable_lazy(|| format!("cursor: {cursor}"));
-                };
-                cursor
-            } else {
-                self.chain_view
-                    .get_starting_cursor()
-                    .await
-                    .change_context(CompactionError)?
-            };
+            for (first_block, last_block) in take_contiguous(&mut built, next_to_commit) {
+                self.commit_segment(first_block, last_block).await?;
+                next_to_commit = last_block + 1;
+            }
 
-            let head = self
-                .chain_view
+            let head = chain_view
                 .get_head()
                 .await
                 .change_context(CompactionError)?;
-            let finalized = self
-                .chain_view
+            let finalized = chain_view
                 .get_finalized_cursor()
                 .await
                 .change_context(CompactionError)?;
 
             info!(
-                next_cursor = %first_block_in_segment,
+                next_segment = next_to_commit,
+                in_flight = in_flight.len(),
                 head = %head,
                 finalized = %finalized,
                 "compaction: segment tick"
@@ -94,34 +118,162 @@ impl SegmentService {
 
             let latest_available = u64::min(finalized.number, head.number);
 
-            if first_block_in_segment.number + self.segment_size as u64 <= latest_available {
-                let creation_metrics = self.metrics.segment_creation.clone();
-                self.compact_segment(first_block_in_segment)
-                    .record_request(creation_metrics)
+            while in_flight.len() < self.workers {
+                let first_block =
+                    if let Some(first_block) = busy.first().copied().filter(|_| retry_busy) {
+                        busy.remove(&first_block);
+                        first_block
+                    } else if next_to_schedule + segment_size <= latest_available {
+                        let first_block = next_to_schedule;
+                        next_to_schedule += segment_size;
+                        first_block
+                    } else {
+                        break;
+                    };
+
+                let CanonicalCursor::Canonical(first_block_cursor) = chain_view
+                    .get_canonical(first_block)
                     .await
-                    .change_context(CompactionError)?;
-            } else {
-                let state_change = if finalized.number < head.number {
-                    info!("compaction waiting for finalized change");
-                    self.chain_view.finalized_changed().boxed()
+                    .change_context(CompactionError)?
+                else {
+                    return Err(CompactionError)
+                        .attach_printable("chain view returned non canonical segment start")
+                        .attach_printable_lazy(|| format!("block number: {first_block}"));
+                };
+
+                let worker = self.worker.clone();
+                let handle = if let Some(leases) = self.leases.clone() {
+                    tokio::spawn(leases.build(worker, first_block_cursor))
                 } else {
-                    info!("compaction waiting for head change");
-                    self.chain_view.head_changed().boxed()
+                    tokio::spawn(async move {
+                        worker
+                            .build_segment(first_block_cursor)
+                            .await
+                            .map(LeasedSegment::Built)
+                    })
                 };
 
-                tokio::pin!(state_change);
+                in_flight.push(async move { (first_block, handle.await) });
+            }
+
+            retry_busy = false;
 
-                let Some(_) = ct.run_until_cancelled(state_change).await else {
+            // New segments become available as the finalized block (or the head, if it's
+            // behind) moves forward.
+            let idle = in_flight.is_empty() && busy.is_empty();
+            let state_change = if finalized.number < head.number {
+                if idle {
+                    info!("compaction waiting for finalized change");
+                }
+                chain_view.finalized_changed().boxed()
+            } else {
+                if idle {
+                    info!("compaction waiting for head change");
+                }
+                chain_view.head_changed().boxed()
+            };
+
+            tokio::select! {
+                _ = ct.cancelled() => {
                     return Ok(());
-                };
+                }
+                Some((first_block, result)) = in_flight.next(), if !in_flight.is_empty() => {
+                    match result.change_context(CompactionError)?? {
+                        LeasedSegment::Built(last_block) => {
+                            built.insert(first_block, last_block);
+                        }
+                        LeasedSegment::Busy => {
+                            busy.insert(first_block);
+                        }
+                    }
+                }
+                _ = busy_retry_interval.tick(), if !busy.is_empty() => {
+                    retry_busy = true;
+                }
+                _ = state_change, if in_flight.len() < self.workers => {}
             }
         }
     }
 
-    async fn compact_segment(
+    async fn commit_segment(
         &mut self,
-        first_block_in_segment: Cursor,
+        first_block: u64,
+        last_block: u64,
     ) -> Result<(), CompactionError> {
+        if let Some(leases) = self.leases.as_mut() {
+            leases.commit(first_block, last_block).await?;
+        } else {
+            self.state_client
+                .put_segmented(last_block)
+                .await
+                .change_context(CompactionError)
+                .attach_printable("failed to put segmented block")?;
+        }
+
+        self.worker.metrics.segmented.record(last_block, &[]);
+
+        Ok(())
+    }
+}
+
+/// Remove the built segments that extend the watermark without gaps, in commit order.
+///
+/// Segments are built concurrently and finish in any order, but the watermark can only
+/// advance over a contiguous range of segments.
+fn take_contiguous(built: &mut BTreeMap<u64, u64>, next_to_commit: u64) -> Vec<(u64, u64)> {
+    let mut contiguous = Vec::new();
+    let mut next_to_commit = next_to_commit;
+
+    while let Some(last_block) = built.remove(&next_to_commit) {
+        contiguous.push((next_to_commit, last_block));
+        next_to_commit = last_block + 1;
+    }
+
+    contiguous
+}
+
+impl SegmentWorker {
+    pub fn new(
+        segment_size: usize,
+        chain_view: ChainView,
+        block_store_reader: UncachedBlockStoreReader,
+        block_store_writer: BlockStoreWriter,
+        metrics: CompactionMetrics,
+    ) -> Self {
+        Self {
+            segment_size,
+            chain_view,
+            block_store_reader,
+            block_store_writer,
+            metrics,
+        }
+    }
+
+    pub fn segment_size(&self) -> usize {
+        self.segment_size
+    }
+
+    pub fn chain_view(&self) -> &ChainView {
+        &self.chain_view
+    }
+
+    /// Build and upload the segment starting at the given block.
+    ///
+    /// Returns the number of the last block in the segment.
+    pub async fn build_segment(
+        self,
+        first_block_in_segment: Cursor,
+    ) -> Result<u64, CompactionError> {
+        let creation_metrics = self.metrics.segment_creation.clone();
+        self.compact_segment(first_block_in_segment)
+            .record_request(creation_metrics)
+            .await
+    }
+
+    async fn compact_segment(
+        &self,
+        first_block_in_segment: Cursor,
+    ) -> Result<u64, CompactionError> {
         let mut builder = SegmentBuilder::default();
         let chain_view = &self.chain_view;
 
@@ -296,16 +448,54 @@ impl SegmentService {
                 .attach_printable("failed to put segment")?;
         }
 
-        self.state_client
-            .put_segmented(last_block_in_segment.number)
-            .await
-            .change_context(CompactionError)
-            .attach_printable("failed to put segmented block")?;
+        Ok(last_block_in_segment.number)
+    }
+}
 
-        self.metrics
-            .segmented
-            .record(last_block_in_segment.number, &[]);
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
 
-        Ok(())
+    use super::take_contiguous;
+
+    #[test]
+    fn test_take_contiguous_in_order() {
+        let mut built = BTreeMap::from([(0, 9), (10, 19), (20, 29)]);
+
+        assert_eq!(
+            take_contiguous(&mut built, 0),
+            vec![(0, 9), (10, 19), (20, 29)]
+        );
+        assert!(built.is_empty());
+    }
+
+    #[test]
+    fn test_take_contiguous_waits_for_gap() {
+        // The segments after the first finished building first.
+        let mut built = BTreeMap::from([(10, 19), (20, 29)]);
+
+        assert!(take_contiguous(&mut built, 0).is_empty());
+        assert_eq!(built.len(), 2);
+
+        built.insert(0, 9);
+        assert_eq!(
+            take_contiguous(&mut built, 0),
+            vec![(0, 9), (10, 19), (20, 29)]
+        );
+        assert!(built.is_empty());
+    }
+
+    #[test]
+    fn test_take_contiguous_stops_at_gap() {
+        let mut built = BTreeMap::from([(100, 109), (120, 129), (130, 139)]);
+
+        assert_eq!(take_contiguous(&mut built, 100), vec![(100, 109)]);
+        assert_eq!(built, BTreeMap::from([(120, 129), (130, 139)]));
+
+        built.insert(110, 119);
+        assert_eq!(
+            take_contiguous(&mut built, 110),
+            vec![(110, 119), (120, 129), (130, 139)]
+        );
     }
 }
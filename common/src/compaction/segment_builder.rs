@@ -45,6 +45,10 @@ impl SegmentBuilder {
             .change_context(CompactionError)
             .attach_printable("failed to access block")?;
 
+        self.add_block_data(cursor, block)
+    }
+
+    pub fn add_block_data(&mut self, cursor: &Cursor, block: Block) -> Result<(), CompactionError> {
         if let Some(expected_count) = self.expected_fragment_count {
             if block.body.len() != expected_count {
                 return Err(CompactionError)
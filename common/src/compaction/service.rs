@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use apibara_etcd::{EtcdClient, Lock};
 use error_stack::{Result, ResultExt};
 use tokio_util::sync::CancellationToken;
@@ -5,13 +7,26 @@ use tracing::{debug, info};
 
 use crate::{
     block_store::{BlockStoreWriter, UncachedBlockStoreReader},
+    chain_store::ChainStore,
     chain_view::ChainView,
-    compaction::{group::SegmentGroupService, prune::PruneService},
+    compaction::{
+        gc::{GarbageCollectionService, GarbageCollector, GarbageCollectorOptions},
+        group::SegmentGroupService,
+        lease::SegmentLeases,
+        prune::PruneService,
+        retention::RetentionService,
+    },
+    file_cache::FileCache,
     ingestion::IngestionStateClient,
     object_store::ObjectStore,
+    options_store::OptionsStore,
 };
 
-use super::{error::CompactionError, metrics::CompactionMetrics, segment::SegmentService};
+use super::{
+    error::CompactionError,
+    metrics::CompactionMetrics,
+    segment::{SegmentService, SegmentWorker},
+};
 
 #[derive(Debug, Clone)]
 pub struct CompactionServiceOptions {
@@ -19,13 +34,27 @@ pub struct CompactionServiceOptions {
     pub segment_size: usize,
     /// How many segments in a single segment group.
     pub group_size: usize,
+    /// How many blocks of history to keep. Keep everything if `None`.
+    pub retention_blocks: Option<u64>,
+    /// How often to delete orphaned objects. Never delete them if `None`.
+    pub gc_interval: Option<Duration>,
+    /// Options for the garbage collector.
+    pub gc: GarbageCollectorOptions,
+    /// How many segments and groups to build concurrently.
+    pub workers: usize,
+    /// Share segment compaction with other nodes by leasing segments through etcd.
+    pub distributed: bool,
 }
 
 pub struct CompactionService {
     options: CompactionServiceOptions,
     block_store_reader: UncachedBlockStoreReader,
     block_store_writer: BlockStoreWriter,
+    chain_store: ChainStore,
     state_client: IngestionStateClient,
+    options_store: OptionsStore,
+    object_store: ObjectStore,
+    segment_leases: Option<SegmentLeases>,
     chain_view: tokio::sync::watch::Receiver<Option<ChainView>>,
     metrics: CompactionMetrics,
 }
@@ -34,20 +63,32 @@ impl CompactionService {
     pub fn new(
         etcd_client: EtcdClient,
         object_store: ObjectStore,
+        file_cache: FileCache,
         chain_view: tokio::sync::watch::Receiver<Option<ChainView>>,
         options: CompactionServiceOptions,
         metrics: CompactionMetrics,
     ) -> Self {
         let block_store_reader = UncachedBlockStoreReader::new(object_store.clone());
-        let block_store_writer = BlockStoreWriter::new(object_store);
+        let chain_store = ChainStore::new(object_store.clone(), file_cache);
+        let block_store_writer = BlockStoreWriter::new(object_store.clone());
         let state_client = IngestionStateClient::new(&etcd_client);
+        let options_store = OptionsStore::new(&etcd_client);
+        let segment_leases = if options.distributed {
+            SegmentLeases::new(&etcd_client).into()
+        } else {
+            None
+        };
 
         Self {
             options,
             block_store_reader,
             block_store_writer,
+            chain_store,
             chain_view,
             state_client,
+            options_store,
+            object_store,
+            segment_leases,
             metrics,
         }
     }
@@ -72,16 +113,23 @@ impl CompactionService {
             };
         };
 
-        let segment_service = SegmentService::new(
+        let segment_worker = SegmentWorker::new(
             self.options.segment_size,
             chain_view.clone(),
             self.block_store_reader.clone(),
             self.block_store_writer.clone(),
-            self.state_client.clone(),
             self.metrics.clone(),
         );
 
+        let segment_service = SegmentService::new(
+            self.options.workers,
+            segment_worker,
+            self.segment_leases.clone(),
+            self.state_client.clone(),
+        );
+
         let group_service = SegmentGroupService::new(
+            self.options.workers,
             self.options.segment_size,
             self.options.group_size,
             chain_view.clone(),
@@ -103,6 +151,51 @@ impl CompactionService {
         let group_service_handle = tokio::spawn(group_service.start(ct.clone()));
         let prune_service_handle = tokio::spawn(prune_service.start(ct.clone()));
 
+        let retention_service_handle = if let Some(retention_blocks) = self.options.retention_blocks
+        {
+            let retention_service = RetentionService::new(
+                retention_blocks,
+                chain_view.clone(),
+                self.block_store_writer.clone(),
+                self.chain_store.clone(),
+                self.state_client.clone(),
+                self.metrics.clone(),
+            );
+
+            tokio::spawn(retention_service.start(ct.clone()))
+        } else {
+            tokio::spawn({
+                let ct = ct.clone();
+                async move {
+                    ct.cancelled().await;
+                    Ok(())
+                }
+            })
+        };
+
+        let gc_service_handle = if let Some(gc_interval) = self.options.gc_interval {
+            let collector = GarbageCollector::new(
+                self.object_store.clone(),
+                self.chain_store.clone(),
+                self.state_client.clone(),
+                self.options_store,
+                self.options.gc.clone(),
+                self.metrics.clone(),
+            );
+
+            let gc_service = GarbageCollectionService::new(gc_interval, collector);
+
+            tokio::spawn(gc_service.start(ct.clone()))
+        } else {
+            tokio::spawn({
+                let ct = ct.clone();
+                async move {
+                    ct.cancelled().await;
+                    Ok(())
+                }
+            })
+        };
+
         let lock_handle = lock_keep_alive_loop(lock, ct.clone());
 
         tokio::select! {
@@ -125,11 +218,19 @@ impl CompactionService {
                 info!("compaction prune service loop terminated");
                 prune_service.change_context(CompactionError)?.change_context(CompactionError)
             }
+            retention_service = retention_service_handle => {
+                info!("compaction retention service loop terminated");
+                retention_service.change_context(CompactionError)?.change_context(CompactionError)
+            }
+            gc_service = gc_service_handle => {
+                info!("compaction gc service loop terminated");
+                gc_service.change_context(CompactionError)?.change_context(CompactionError)
+            }
         }
     }
 }
 
-async fn lock_keep_alive_loop(
+pub(super) async fn lock_keep_alive_loop(
     lock: &mut Lock,
     ct: CancellationToken,
 ) -> Result<(), CompactionError> {
@@ -153,6 +254,11 @@ impl Default for CompactionServiceOptions {
         Self {
             segment_size: 1_000,
             group_size: 100,
+            retention_blocks: None,
+            gc_interval: None,
+            gc: GarbageCollectorOptions::default(),
+            workers: 1,
+            distributed: false,
         }
     }
 }
@@ -2,7 +2,10 @@ use std::collections::{BTreeMap, HashSet};
 
 use roaring::RoaringBitmap;
 
-use crate::query::{BlockFilter, FilterId};
+use crate::{
+    fragment::FragmentId,
+    query::{BlockFilter, FilterId},
+};
 
 pub trait BlockFilterFactory {
     fn create_block_filter(
@@ -11,7 +14,7 @@ pub trait BlockFilterFactory {
     ) -> tonic::Result<Vec<BlockFilter>, tonic::Status>;
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct FilterMatch(BTreeMap<u32, HashSet<FilterId>>);
 
 #[derive(Debug)]
@@ -43,6 +46,12 @@ impl FilterMatch {
         self.0.entry(index).or_default().insert(filter_id);
     }
 
+    pub fn contains(&self, index: u32, filter_id: FilterId) -> bool {
+        self.0
+            .get(&index)
+            .is_some_and(|filter_ids| filter_ids.contains(&filter_id))
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = Match> + '_ {
         self.0.iter().map(|(index, filter_ids)| Match {
             index: *index,
@@ -50,3 +59,111 @@ impl FilterMatch {
         })
     }
 }
+
+/// Decode the message with the decoders of the filters that matched it.
+///
+/// `direct_match` contains the messages matched by the fragment's own filters, before joins.
+/// Messages joined in by a filter are not decoded, even if the filter has a decoder.
+pub fn decode_match(
+    block_filter: &BlockFilter,
+    fragment_id: FragmentId,
+    direct_match: Option<&FilterMatch>,
+    match_: &Match,
+    message: &[u8],
+    output: &mut Vec<u8>,
+) {
+    let Some(direct_match) = direct_match else {
+        return;
+    };
+
+    for filter_id in match_.filter_ids.iter() {
+        if !direct_match.contains(match_.index, *filter_id) {
+            continue;
+        }
+
+        if let Some(decoder) = block_filter.decoder(fragment_id, *filter_id) {
+            decoder.decode(*filter_id, message, output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::query::{BlockFilter, FilterId, FragmentDecoder};
+
+    use super::{decode_match, FilterMatch};
+
+    const LOG_FRAGMENT_ID: u8 = 3;
+
+    /// Appends the filter id, to check which decoders ran.
+    #[derive(Debug)]
+    struct FilterIdDecoder;
+
+    impl FragmentDecoder for FilterIdDecoder {
+        fn decode(&self, filter_id: FilterId, _message: &[u8], output: &mut Vec<u8>) {
+            output.push(filter_id as u8);
+        }
+    }
+
+    fn decoded(
+        block_filter: &BlockFilter,
+        direct_match: Option<&FilterMatch>,
+        fragment_match: &FilterMatch,
+    ) -> Vec<(u32, Vec<u8>)> {
+        fragment_match
+            .iter()
+            .map(|match_| {
+                let mut output = Vec::new();
+                decode_match(
+                    block_filter,
+                    LOG_FRAGMENT_ID,
+                    direct_match,
+                    &match_,
+                    &[],
+                    &mut output,
+                );
+                (match_.index, output)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_joined_logs() {
+        let mut block_filter = BlockFilter::default();
+        block_filter.add_decoder(LOG_FRAGMENT_ID, 1, Arc::new(FilterIdDecoder));
+        block_filter.add_decoder(LOG_FRAGMENT_ID, 2, Arc::new(FilterIdDecoder));
+
+        // Filter 1 matches log 0 and joins its sibling log 1.
+        // Filter 2 matches log 1 and is joined with log 2 through the transaction.
+        let mut direct_match = FilterMatch::default();
+        direct_match.add_single_match(1, 0);
+        direct_match.add_single_match(2, 1);
+
+        let mut fragment_match = direct_match.clone();
+        fragment_match.add_single_match(1, 1);
+        fragment_match.add_single_match(2, 2);
+
+        assert_eq!(
+            decoded(&block_filter, Some(&direct_match), &fragment_match),
+            vec![(0, vec![1]), (1, vec![2]), (2, vec![])]
+        );
+    }
+
+    #[test]
+    fn test_decode_logs_joined_from_other_fragment() {
+        let mut block_filter = BlockFilter::default();
+        block_filter.add_decoder(LOG_FRAGMENT_ID, 1, Arc::new(FilterIdDecoder));
+
+        // Only a transaction filter with the same id matched, the logs are all joined.
+        let mut fragment_match = FilterMatch::default();
+        fragment_match.add_single_match(1, 0);
+        fragment_match.add_single_match(1, 1);
+
+        assert_eq!(
+            decoded(&block_filter, None, &fragment_match),
+            vec![(0, vec![]), (1, vec![])]
+        );
+    }
+}
@@ -17,10 +17,13 @@ pub enum FragmentAccess<'a> {
 }
 
 impl<'a> FragmentAccess<'a> {
+    /// Returns the index of the fragment, or `None` if the block doesn't have the fragment.
+    ///
+    /// Blocks ingested before a fragment was introduced don't have it.
     pub fn get_index_fragment(
         &'a self,
         fragment_id: &FragmentId,
-    ) -> Result<&'a rkyv::Archived<IndexFragment>, FragmentAccessError> {
+    ) -> Result<Option<&'a rkyv::Archived<IndexFragment>>, FragmentAccessError> {
         match self {
             Self::Segment(access) => access.get_index_fragment(fragment_id),
             Self::Block(access) => access.get_index_fragment(fragment_id),
@@ -46,10 +49,11 @@ impl<'a> FragmentAccess<'a> {
         }
     }
 
+    /// Returns the body of the fragment, or `None` if the block doesn't have the fragment.
     pub fn get_body_fragment(
         &'a self,
         fragment_id: &FragmentId,
-    ) -> Result<&'a rkyv::Archived<BodyFragment>, FragmentAccessError> {
+    ) -> Result<Option<&'a rkyv::Archived<BodyFragment>>, FragmentAccessError> {
         match self {
             Self::Segment(access) => access.get_body_fragment(fragment_id),
             Self::Block(access) => access.get_body_fragment(fragment_id),
@@ -61,21 +65,14 @@ impl BlockAccess {
     pub fn get_index_fragment<'a>(
         &'a self,
         fragment_id: &FragmentId,
-    ) -> Result<&'a rkyv::Archived<IndexFragment>, FragmentAccessError> {
+    ) -> Result<Option<&'a rkyv::Archived<IndexFragment>>, FragmentAccessError> {
         let block = unsafe { rkyv::access_unchecked::<rkyv::Archived<Block>>(self.0.value()) };
 
-        let Some(pos) = block
+        Ok(block
             .index
             .indexes
             .iter()
-            .position(|f| f.fragment_id == *fragment_id)
-        else {
-            return Err(FragmentAccessError)
-                .attach_printable("index for fragment not found")
-                .attach_printable_lazy(|| format!("fragment id: {}", fragment_id));
-        };
-
-        Ok(&block.index.indexes[pos])
+            .find(|f| f.fragment_id == *fragment_id))
     }
 
     pub fn get_join_fragment<'a>(
@@ -108,20 +105,10 @@ impl BlockAccess {
     pub fn get_body_fragment<'a>(
         &'a self,
         fragment_id: &FragmentId,
-    ) -> Result<&'a rkyv::Archived<BodyFragment>, FragmentAccessError> {
+    ) -> Result<Option<&'a rkyv::Archived<BodyFragment>>, FragmentAccessError> {
         let block = unsafe { rkyv::access_unchecked::<rkyv::Archived<Block>>(self.0.value()) };
 
-        let Some(pos) = block
-            .body
-            .iter()
-            .position(|f| f.fragment_id == *fragment_id)
-        else {
-            return Err(FragmentAccessError)
-                .attach_printable("body for fragment not found")
-                .attach_printable_lazy(|| format!("fragment id: {}", fragment_id));
-        };
-
-        Ok(&block.body[pos])
+        Ok(block.body.iter().find(|f| f.fragment_id == *fragment_id))
     }
 }
 
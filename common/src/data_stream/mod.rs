@@ -6,7 +6,7 @@ mod segment_stream;
 mod stream;
 mod stream_group;
 
-pub use self::filter::{BlockFilterFactory, FilterMatch};
+pub use self::filter::{decode_match, BlockFilterFactory, FilterMatch};
 pub use self::fragment_access::FragmentAccess;
 pub use self::metrics::DataStreamMetrics;
 pub use self::segment_access::{SegmentAccess, SegmentAccessFetch};
@@ -109,7 +109,7 @@ impl<'a> SegmentBlockAccess<'a> {
     pub fn get_index_fragment(
         &self,
         fragment_id: &FragmentId,
-    ) -> Result<&'a rkyv::Archived<IndexFragment>, FragmentAccessError> {
+    ) -> Result<Option<&'a rkyv::Archived<IndexFragment>>, FragmentAccessError> {
         let entry = self
             .segment
             .fragments
@@ -123,18 +123,11 @@ impl<'a> SegmentBlockAccess<'a> {
 
         let block_index = &segment.data[self.offset];
 
-        let Some(pos) = block_index
+        Ok(block_index
             .data
             .indexes
             .iter()
-            .position(|f| f.fragment_id == *fragment_id)
-        else {
-            return Err(FragmentAccessError)
-                .attach_printable("index for fragment not found")
-                .attach_printable_lazy(|| format!("fragment id: {}", fragment_id));
-        };
-
-        Ok(&block_index.data.indexes[pos])
+            .find(|f| f.fragment_id == *fragment_id))
     }
 
     pub fn get_join_fragment(
@@ -188,7 +181,7 @@ impl<'a> SegmentBlockAccess<'a> {
     pub fn get_body_fragment(
         &self,
         fragment_id: &FragmentId,
-    ) -> Result<&'a rkyv::Archived<BodyFragment>, FragmentAccessError> {
+    ) -> Result<Option<&'a rkyv::Archived<BodyFragment>>, FragmentAccessError> {
         let entry = self
             .segment
             .fragments
@@ -198,11 +191,16 @@ impl<'a> SegmentBlockAccess<'a> {
             .attach_printable_lazy(|| format!("fragment id: {}", fragment_id))
             .attach_printable_lazy(|| format!("block number: {}", self.block_number()))?;
 
+        // Segments built before the fragment was introduced don't exist.
+        if entry.value().is_empty() {
+            return Ok(None);
+        }
+
         let segment = unsafe {
             rkyv::access_unchecked::<rkyv::Archived<Segment<BodyFragment>>>(entry.value())
         };
 
-        Ok(&segment.data[self.offset].data)
+        Ok(Some(&segment.data[self.offset].data))
     }
 }
 
@@ -13,7 +13,7 @@ use crate::{
     block_store::BlockStoreReader,
     chain_view::ChainView,
     data_stream::SegmentAccessFetch,
-    file_cache::FileCacheError,
+    file_cache::{FileCacheError, FileFetch},
     fragment::{FragmentId, HEADER_FRAGMENT_ID, INDEX_FRAGMENT_ID, JOIN_FRAGMENT_ID},
     query::BlockFilter,
     segment::SegmentGroup,
@@ -141,6 +141,8 @@ impl SegmentStream {
 
                 // Use the group indices to compute which blocks have data for the client-provided filters.
                 for block_filter in self.block_filter.iter() {
+                    fragment_ids_needed.extend(block_filter.header_fragments().iter().copied());
+
                     // If the client requested all headers include all blocks in the group.
                     if block_filter.always_include_header() {
                         let group_start = current_block_number as u32;
@@ -153,21 +155,27 @@ impl SegmentStream {
                     }
 
                     for (fragment_id, filters) in block_filter.iter() {
-                        let Some(pos) = group
+                        let Some(indexes) = group
                             .index
                             .indexes
                             .iter()
-                            .position(|f| f.fragment_id == *fragment_id)
+                            .find(|f| f.fragment_id == *fragment_id)
                         else {
+                            // Groups built before an optional fragment was introduced don't have it.
+                            if block_filter.is_optional_fragment(*fragment_id) {
+                                continue;
+                            }
+
                             return Err(DataStreamError)
                                 .attach_printable("missing index")
                                 .attach_printable_lazy(|| format!("fragment id: {}", fragment_id));
                         };
 
-                        let indexes = &group.index.indexes[pos];
-
+                        let optional_indexes = block_filter.optional_indexes(*fragment_id);
                         for filter in filters {
-                            let rows = filter.filter(indexes).change_context(DataStreamError)?;
+                            let rows = filter
+                                .filter(indexes, optional_indexes)
+                                .change_context(DataStreamError)?;
                             if rows.is_empty() {
                                 continue;
                             }
@@ -210,8 +218,7 @@ impl SegmentStream {
                             .attach_printable("expected fragment id to have a name")
                             .attach_printable_lazy(|| format!("fragment_id: {fragment_id}"))?;
                         let fragment_fetch = self
-                            .store
-                            .get_segment(&segment_cursor, segment_name)
+                            .get_fragment_segment(&segment_cursor, *fragment_id, segment_name)
                             .record_request_with_attributes(
                                 self.metrics.segment_download.clone(),
                                 &[KeyValue::new("name", segment_name.clone())],
@@ -288,8 +295,7 @@ impl SegmentStream {
                         .attach_printable("expected fragment id to have a name")
                         .attach_printable_lazy(|| format!("fragment_id: {fragment_id}"))?;
                     let fragment_fetch = self
-                        .store
-                        .get_segment(&segment_cursor, segment_name)
+                        .get_fragment_segment(&segment_cursor, *fragment_id, segment_name)
                         .record_request_with_attributes(
                             self.metrics.segment_download.clone(),
                             &[KeyValue::new("name", segment_name.clone())],
@@ -309,4 +315,23 @@ impl SegmentStream {
 
         Ok(())
     }
+
+    /// Segments built before an optional fragment was introduced don't exist.
+    fn get_fragment_segment(
+        &self,
+        segment_cursor: &Cursor,
+        fragment_id: FragmentId,
+        segment_name: &str,
+    ) -> FileFetch {
+        let is_optional = self
+            .block_filter
+            .iter()
+            .any(|block_filter| block_filter.is_optional_fragment(fragment_id));
+
+        if is_optional {
+            self.store.get_body_segment(segment_cursor, segment_name)
+        } else {
+            self.store.get_segment(segment_cursor, segment_name)
+        }
+    }
 }
@@ -16,7 +16,9 @@ use tracing::{debug, error, Instrument};
 use crate::{
     block_store::BlockStoreReader,
     chain_view::{ChainView, NextCursor},
-    data_stream::{fragment_access::BlockAccess, FilterMatch, FragmentAccess, SegmentStream},
+    data_stream::{
+        decode_match, fragment_access::BlockAccess, FilterMatch, FragmentAccess, SegmentStream,
+    },
     file_cache::FileCacheError,
     fragment::{FragmentId, HEADER_FRAGMENT_ID},
     join::ArchivedJoinTo,
@@ -39,6 +41,8 @@ pub struct DataStream {
     fragment_id_to_name: HashMap<FragmentId, String>,
     prefetch_segment_count: usize,
     metrics: DataStreamMetrics,
+    /// Set once the stream sent the last block of a bounded chain.
+    ended: bool,
     _permit: tokio::sync::OwnedSemaphorePermit,
 }
 
@@ -70,6 +74,7 @@ impl DataStream {
             prefetch_segment_count,
             store,
             metrics,
+            ended: false,
             _permit: permit,
         }
     }
@@ -81,7 +86,7 @@ impl DataStream {
     ) -> Result<(), DataStreamError> {
         self.metrics.active.add(1, &[]);
 
-        while !ct.is_cancelled() && !tx.is_closed() {
+        while !ct.is_cancelled() && !tx.is_closed() && !self.ended {
             tokio::select! {
                 biased;
 
@@ -132,6 +137,10 @@ impl DataStream {
                 return Ok(());
             }
             NextCursor::AtHead => {
+                if self.chain_view.get_ended_block().await.is_some() {
+                    return self.tick_at_ending_block(tx, ct).await;
+                }
+
                 debug!("head reached. waiting for new head");
 
                 if self.finality == DataFinality::Pending {
@@ -356,6 +365,30 @@ impl DataStream {
         Ok(())
     }
 
+    /// Finalize the last block and terminate the stream.
+    async fn tick_at_ending_block(
+        &mut self,
+        tx: &mpsc::Sender<DataStreamMessage>,
+        ct: &CancellationToken,
+    ) -> Result<(), DataStreamError> {
+        debug!("ending block reached. closing stream");
+
+        let finalized = self
+            .chain_view
+            .get_finalized_cursor()
+            .await
+            .change_context(DataStreamError)?;
+
+        if finalized != self.finalized {
+            self.finalized = finalized;
+            self.send_finalize_message(tx, ct).await?;
+        }
+
+        self.ended = true;
+
+        Ok(())
+    }
+
     async fn tick_at_head(
         &mut self,
         tx: &mpsc::Sender<DataStreamMessage>,
@@ -507,19 +540,34 @@ impl DataStream {
 
             let mut data_buffer = BytesMut::with_capacity(DEFAULT_BLOCKS_BUFFER_SIZE);
             let mut fragment_matches = BTreeMap::default();
+            // Matches before joins, decoders only apply to these.
+            let mut direct_matches = BTreeMap::<FragmentId, FilterMatch>::default();
 
             let mut joins = BTreeMap::<(FragmentId, FragmentId), FilterMatch>::default();
 
             for (fragment_id, filters) in block_filter.iter() {
                 let mut filter_match = FilterMatch::default();
 
-                let indexes = fragment_access
+                let Some(indexes) = fragment_access
                     .get_index_fragment(fragment_id)
                     .change_context(DataStreamError)
-                    .attach_printable("failed to get fragment indexes")?;
+                    .attach_printable("failed to get fragment indexes")?
+                else {
+                    // Blocks ingested before an optional fragment was introduced don't have it.
+                    if block_filter.is_optional_fragment(*fragment_id) {
+                        continue;
+                    }
 
+                    return Err(DataStreamError)
+                        .attach_printable("index for fragment not found")
+                        .attach_printable_lazy(|| format!("fragment id: {}", fragment_id));
+                };
+
+                let optional_indexes = block_filter.optional_indexes(*fragment_id);
                 for filter in filters {
-                    let rows = filter.filter(indexes).change_context(DataStreamError)?;
+                    let rows = filter
+                        .filter(indexes, optional_indexes)
+                        .change_context(DataStreamError)?;
                     filter_match.add_match(filter.filter_id, &rows);
 
                     if rows.is_empty() {
@@ -538,6 +586,10 @@ impl DataStream {
                     continue;
                 }
 
+                if block_filter.has_decoders(*fragment_id) {
+                    direct_matches.insert(*fragment_id, filter_match.clone());
+                }
+
                 fragment_matches.insert(*fragment_id, filter_match);
             }
 
@@ -549,11 +601,16 @@ impl DataStream {
                     .change_context(DataStreamError)
                     .attach_printable("failed to get join fragment")?;
 
-                let Some(target_pos) = join_fragment
+                let Some(join) = join_fragment
                     .joins
                     .iter()
-                    .position(|f| f.to_fragment_id == target_fragment_id)
+                    .find(|f| f.to_fragment_id == target_fragment_id)
                 else {
+                    // Blocks ingested before an optional fragment was introduced have no join to it.
+                    if block_filter.is_optional_fragment(target_fragment_id) {
+                        continue;
+                    }
+
                     return Err(DataStreamError)
                         .attach_printable("join fragment not found")
                         .attach_printable_lazy(|| {
@@ -563,7 +620,6 @@ impl DataStream {
                             format!("target fragment id: {}", target_fragment_id)
                         });
                 };
-                let join = &join_fragment.joins[target_pos];
 
                 let target_fragment_matches =
                     fragment_matches.entry(target_fragment_id).or_default();
@@ -602,13 +658,39 @@ impl DataStream {
                     .change_context(DataStreamError)
                     .attach_printable("failed to get header fragment")?;
 
+                // Header fragments are appended to the header, the client merges them.
+                let mut header_extension = Vec::new();
+                for fragment_id in block_filter.header_fragments() {
+                    let Some(body) = fragment_access
+                        .get_body_fragment(fragment_id)
+                        .change_context(DataStreamError)
+                        .attach_printable("failed to get header body fragment")?
+                    else {
+                        if block_filter.is_optional_fragment(*fragment_id) {
+                            continue;
+                        }
+
+                        return Err(DataStreamError)
+                            .attach_printable("body for header fragment not found")
+                            .attach_printable_lazy(|| format!("fragment id: {}", fragment_id));
+                    };
+
+                    for message_bytes in body.data.iter() {
+                        header_extension.extend_from_slice(message_bytes.as_slice());
+                    }
+                }
+
                 prost::encoding::encode_key(
                     HEADER_FRAGMENT_ID as u32,
                     prost::encoding::WireType::LengthDelimited,
                     &mut data_buffer,
                 );
-                prost::encoding::encode_varint(header.data.len() as u64, &mut data_buffer);
+                prost::encoding::encode_varint(
+                    (header.data.len() + header_extension.len()) as u64,
+                    &mut data_buffer,
+                );
                 data_buffer.put(header.data.as_slice());
+                data_buffer.put(header_extension.as_slice());
             }
 
             for (fragment_id, filter_match) in fragment_matches.into_iter() {
@@ -622,9 +704,13 @@ impl DataStream {
                 let body = fragment_access
                     .get_body_fragment(&fragment_id)
                     .change_context(DataStreamError)
-                    .attach_printable("failed to get body fragment")?;
+                    .attach_printable("failed to get body fragment")?
+                    .ok_or(DataStreamError)
+                    .attach_printable("body for fragment not found")
+                    .attach_printable_lazy(|| format!("fragment id: {}", fragment_id))?;
 
                 let starting_size = data_buffer.len();
+                let mut decoded_buffer = Vec::new();
                 for match_ in filter_match.iter() {
                     const FILTER_IDS_TAG: u32 = 1;
 
@@ -634,6 +720,17 @@ impl DataStream {
                         &match_.filter_ids,
                     );
 
+                    // Decoded fields are appended to the message, the client merges them.
+                    decoded_buffer.clear();
+                    decode_match(
+                        block_filter,
+                        fragment_id,
+                        direct_matches.get(&fragment_id),
+                        &match_,
+                        message_bytes.as_slice(),
+                        &mut decoded_buffer,
+                    );
+
                     prost::encoding::encode_key(
                         fragment_id as u32,
                         prost::encoding::WireType::LengthDelimited,
@@ -641,7 +738,7 @@ impl DataStream {
                     );
 
                     prost::encoding::encode_varint(
-                        (filter_ids_len + message_bytes.len()) as u64,
+                        (filter_ids_len + message_bytes.len() + decoded_buffer.len()) as u64,
                         &mut data_buffer,
                     );
 
@@ -651,6 +748,7 @@ impl DataStream {
                         &mut data_buffer,
                     );
                     data_buffer.put(message_bytes.as_slice());
+                    data_buffer.put(decoded_buffer.as_slice());
                 }
 
                 let fragment_size = data_buffer.len() - starting_size;
@@ -1,7 +1,9 @@
 mod error;
 mod index;
 mod prefetch;
+mod reorgs;
 
 pub use self::error::DebugCommandError;
 pub use self::index::DebugIndexCommand;
 pub use self::prefetch::run_debug_prefetch_stream;
+pub use self::reorgs::DebugReorgsCommand;
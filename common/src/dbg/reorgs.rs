@@ -0,0 +1,116 @@
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    chain_view::{chain_view_sync_loop, CanonicalCursor},
+    cli::{EtcdArgs, ObjectStoreArgs},
+    file_cache::FileCacheArgs,
+};
+
+use super::DebugCommandError;
+
+/// List the chain reorganizations recorded in the canonical chain.
+#[derive(Args, Debug)]
+pub struct DebugReorgsCommand {
+    /// First block number (inclusive) where to look for orphaned blocks.
+    #[arg(long, default_value = "0")]
+    from_block: u64,
+    /// Last block number (inclusive) where to look for orphaned blocks.
+    ///
+    /// Defaults to the current head.
+    #[arg(long)]
+    to_block: Option<u64>,
+    #[clap(flatten)]
+    object_store: ObjectStoreArgs,
+    #[clap(flatten)]
+    etcd: EtcdArgs,
+    #[clap(flatten)]
+    cache: FileCacheArgs,
+}
+
+impl DebugReorgsCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), DebugCommandError> {
+        let object_store = self
+            .object_store
+            .into_object_store_client()
+            .await
+            .change_context(DebugCommandError)?;
+        let file_cache = self
+            .cache
+            .to_file_cache()
+            .await
+            .change_context(DebugCommandError)?;
+        let etcd_client = self
+            .etcd
+            .into_etcd_client()
+            .await
+            .change_context(DebugCommandError)?;
+
+        let (chain_view, chain_view_sync) =
+            chain_view_sync_loop(file_cache, etcd_client, object_store)
+                .await
+                .change_context(DebugCommandError)?;
+
+        let sync_ct = ct.child_token();
+        let mut sync_handle = tokio::spawn(chain_view_sync.start(sync_ct.clone()));
+
+        let chain_view = loop {
+            if let Some(chain_view) = chain_view.borrow().clone() {
+                break chain_view;
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {},
+                _ = ct.cancelled() => {
+                    return Ok(())
+                }
+                sync = &mut sync_handle => {
+                    info!("sync loop terminated");
+                    sync.change_context(DebugCommandError)?.change_context(DebugCommandError)?;
+                    return Ok(());
+                }
+            };
+        };
+
+        let head = chain_view
+            .get_head()
+            .await
+            .change_context(DebugCommandError)?;
+        let to_block = u64::min(self.to_block.unwrap_or(head.number), head.number);
+
+        let reorgs = chain_view
+            .get_reorgs(self.from_block, to_block)
+            .await
+            .change_context(DebugCommandError)?;
+
+        info!(
+            from_block = self.from_block,
+            to_block,
+            count = reorgs.len(),
+            "found reorgs"
+        );
+
+        for reorg in reorgs {
+            info!(target = %reorg.target, depth = reorg.depth(), "reorg");
+
+            for orphaned in reorg.orphaned.iter() {
+                let canonical = match chain_view
+                    .get_canonical(orphaned.number)
+                    .await
+                    .change_context(DebugCommandError)?
+                {
+                    CanonicalCursor::Canonical(cursor) => cursor.to_string(),
+                    _ => "unavailable".to_string(),
+                };
+
+                info!(orphaned = %orphaned, canonical, "  orphaned block");
+            }
+        }
+
+        sync_ct.cancel();
+
+        Ok(())
+    }
+}
@@ -252,6 +252,30 @@ impl FileCacheArgs {
     }
 }
 
+pub mod testing {
+    use bytes::Bytes;
+    use foyer::HybridCacheBuilder;
+
+    use super::FileCache;
+
+    /// Returns a small file cache that only keeps files in memory.
+    pub async fn memory_file_cache() -> FileCache {
+        let general = HybridCacheBuilder::<String, Bytes>::new()
+            .memory(1024 * 1024)
+            .storage()
+            .build()
+            .await
+            .expect("failed to create file cache");
+        let index = HybridCacheBuilder::<String, Bytes>::new()
+            .memory(1024 * 1024)
+            .storage()
+            .build()
+            .await
+            .expect("failed to create file cache");
+        FileCache { general, index }
+    }
+}
+
 impl error_stack::Context for FileCacheError {}
 
 impl std::fmt::Display for FileCacheError {
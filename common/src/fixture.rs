@@ -0,0 +1,184 @@
+//! Record and replay provider responses.
+//!
+//! Responses are stored as JSON files in `<dir>/<method>/<key>.json`, where the key is derived
+//! from the request parameters. Chain providers use this to record responses from a live node
+//! and serve them later, without network access, in tests.
+use std::path::{Path, PathBuf};
+
+use error_stack::{Result, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Environment variable used to (re)generate golden files.
+pub const UPDATE_GOLDEN_ENV: &str = "DNA_UPDATE_GOLDEN";
+
+#[derive(Debug)]
+pub struct FixtureError;
+
+#[derive(Debug, Clone)]
+pub struct FixtureStore {
+    dir: PathBuf,
+}
+
+impl FixtureStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the recorded response, if any.
+    pub async fn get<T>(&self, method: &str, key: &str) -> Result<Option<T>, FixtureError>
+    where
+        T: DeserializeOwned,
+    {
+        let path = self.path(method, key);
+
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .change_context(FixtureError)
+                    .attach_printable("failed to read fixture")
+                    .attach_printable_lazy(|| format!("path: {}", path.display()));
+            }
+        };
+
+        let value = serde_json::from_slice(&data)
+            .change_context(FixtureError)
+            .attach_printable("failed to decode fixture")
+            .attach_printable_lazy(|| format!("path: {}", path.display()))?;
+
+        Ok(Some(value))
+    }
+
+    /// Record a response.
+    pub async fn put<T>(&self, method: &str, key: &str, value: &T) -> Result<(), FixtureError>
+    where
+        T: Serialize,
+    {
+        let path = self.path(method, key);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .change_context(FixtureError)
+                .attach_printable("failed to create fixture directory")
+                .attach_printable_lazy(|| format!("path: {}", parent.display()))?;
+        }
+
+        let data = serde_json::to_vec_pretty(value)
+            .change_context(FixtureError)
+            .attach_printable("failed to encode fixture")?;
+
+        tokio::fs::write(&path, data)
+            .await
+            .change_context(FixtureError)
+            .attach_printable("failed to write fixture")
+            .attach_printable_lazy(|| format!("path: {}", path.display()))
+    }
+
+    /// Returns the keys recorded for the given method.
+    pub async fn keys(&self, method: &str) -> Result<Vec<String>, FixtureError> {
+        let dir = self.dir.join(method);
+
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err)
+                    .change_context(FixtureError)
+                    .attach_printable("failed to list fixtures")
+                    .attach_printable_lazy(|| format!("path: {}", dir.display()));
+            }
+        };
+
+        let mut keys = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .change_context(FixtureError)
+            .attach_printable("failed to list fixtures")?
+        {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    fn path(&self, method: &str, key: &str) -> PathBuf {
+        self.dir.join(method).join(format!("{key}.json"))
+    }
+}
+
+/// Compare `data` with the content of the golden file at `path`.
+///
+/// If the `DNA_UPDATE_GOLDEN` environment variable is set, the golden file is overwritten
+/// instead.
+pub fn assert_golden(path: impl AsRef<Path>, data: &[u8]) {
+    let path = path.as_ref();
+
+    if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("failed to create golden directory");
+        }
+        std::fs::write(path, data).expect("failed to write golden file");
+        return;
+    }
+
+    let expected = std::fs::read(path).unwrap_or_else(|err| {
+        panic!(
+            "failed to read golden file {}: {err}. Run with {UPDATE_GOLDEN_ENV}=1 to create it",
+            path.display()
+        )
+    });
+
+    assert!(
+        expected == data,
+        "output differs from golden file {}. Run with {UPDATE_GOLDEN_ENV}=1 to update it",
+        path.display()
+    );
+}
+
+impl error_stack::Context for FixtureError {}
+
+impl std::fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fixture error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixtureStore;
+
+    #[tokio::test]
+    async fn test_fixture_store_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FixtureStore::new(dir.path());
+
+        let missing = store.get::<Vec<u64>>("get_block", "1").await.unwrap();
+        assert!(missing.is_none());
+
+        store
+            .put("get_block", "1", &vec![1u64, 2, 3])
+            .await
+            .unwrap();
+        store.put("get_block", "0x2", &vec![4u64]).await.unwrap();
+
+        let value = store.get::<Vec<u64>>("get_block", "1").await.unwrap();
+        assert_eq!(value, Some(vec![1, 2, 3]));
+
+        let keys = store.keys("get_block").await.unwrap();
+        assert_eq!(keys, vec!["0x2".to_string(), "1".to_string()]);
+    }
+}
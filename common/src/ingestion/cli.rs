@@ -1,7 +1,7 @@
 use clap::Args;
 use error_stack::{Result, ResultExt};
 
-use super::IngestionError;
+use super::{FinalityPolicy, FinalityPolicyKind, IngestionError};
 
 #[derive(Args, Debug)]
 pub struct IngestionArgs {
@@ -28,6 +28,15 @@ pub struct IngestionArgs {
         env = "DNA_INGESTION_DANGEROUSLY_OVERRIDE_STARTING_BLOCK"
     )]
     pub ingestion_dangerously_override_starting_block: Option<u64>,
+    /// Stop ingestion at this block (inclusive).
+    ///
+    /// Once the block is ingested and finalized, the node keeps compacting and serving the
+    /// ingested range without querying the RPC. Streams terminate after the last block.
+    ///
+    /// Restart with a later ending block, or without one, to resume ingestion. The ending block
+    /// can't be lowered once reached.
+    #[clap(long = "ingestion.ending-block", env = "DNA_INGESTION_ENDING_BLOCK")]
+    pub ingestion_ending_block: Option<u64>,
     /// How often to refresh the pending block, for example "3s" or "500ms".
     #[clap(
         long = "ingestion.pending-refresh-interval",
@@ -49,6 +58,22 @@ pub struct IngestionArgs {
         default_value = "30s"
     )]
     pub ingestion_finalized_refresh_interval: String,
+    /// How to decide which blocks are finalized.
+    #[clap(
+        long = "ingestion.finality",
+        env = "DNA_INGESTION_FINALITY",
+        value_enum,
+        default_value_t = FinalityPolicyKind::Provider
+    )]
+    pub ingestion_finality: FinalityPolicyKind,
+    /// How many confirmations a block needs to be considered finalized.
+    ///
+    /// Required by all finality policies other than "provider".
+    #[clap(
+        long = "ingestion.finality-confirmations",
+        env = "DNA_INGESTION_FINALITY_CONFIRMATIONS"
+    )]
+    pub ingestion_finality_confirmations: Option<u64>,
 }
 
 impl IngestionArgs {
@@ -74,14 +99,49 @@ impl IngestionArgs {
                     .attach_printable(format!("error: {}", err))
             })?;
 
+        let finality = self.finality_policy()?;
+
+        if let (Some(starting_block), Some(ending_block)) = (
+            self.ingestion_dangerously_override_starting_block,
+            self.ingestion_ending_block,
+        ) {
+            if ending_block < starting_block {
+                return Err(IngestionError::Options)
+                    .attach_printable("ending block is before the starting block")
+                    .attach_printable(format!("starting block: {starting_block}"))
+                    .attach_printable(format!("ending block: {ending_block}"));
+            }
+        }
+
         Ok(super::IngestionServiceOptions {
             max_concurrent_tasks: self.ingestion_max_concurrent_tasks,
             chain_segment_size: self.ingestion_chain_segment_size,
             chain_segment_upload_offset_size: 100,
             override_starting_block: self.ingestion_dangerously_override_starting_block,
+            ending_block: self.ingestion_ending_block,
             pending_refresh_interval,
             head_refresh_interval,
             finalized_refresh_interval,
+            finality,
+        })
+    }
+
+    fn finality_policy(&self) -> Result<FinalityPolicy, IngestionError> {
+        if self.ingestion_finality == FinalityPolicyKind::Provider {
+            return Ok(FinalityPolicy::Provider);
+        }
+
+        let Some(confirmations) = self.ingestion_finality_confirmations else {
+            return Err(IngestionError::Options)
+                .attach_printable("missing finality confirmations")
+                .attach_printable("hint: set --ingestion.finality-confirmations");
+        };
+
+        Ok(match self.ingestion_finality {
+            FinalityPolicyKind::Provider => FinalityPolicy::Provider,
+            FinalityPolicyKind::Confirmations => FinalityPolicy::Confirmations(confirmations),
+            FinalityPolicyKind::Min => FinalityPolicy::Min(confirmations),
+            FinalityPolicyKind::Max => FinalityPolicy::Max(confirmations),
         })
     }
 }
@@ -12,6 +12,8 @@ pub enum IngestionError {
     BadHash,
     Model,
     Indexing,
+    /// The block data doesn't match the block header.
+    Verification,
 }
 
 pub trait IngestionErrorExt {
@@ -51,6 +53,9 @@ impl std::fmt::Display for IngestionError {
             IngestionError::Indexing => {
                 write!(f, "ingestion error: indexing error")
             }
+            IngestionError::Verification => {
+                write!(f, "ingestion error: block data verification failed")
+            }
         }
     }
 }
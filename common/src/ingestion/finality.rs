@@ -0,0 +1,193 @@
+use error_stack::Result;
+
+use crate::Cursor;
+
+use super::{BlockIngestion, IngestionError};
+
+/// How the ingestion service decides which blocks are finalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FinalityPolicy {
+    /// Use the finalized block reported by the provider.
+    #[default]
+    Provider,
+    /// Consider finalized all blocks with at least the given number of confirmations.
+    Confirmations(u64),
+    /// Use the earliest of the provider-reported and confirmation-based finalized blocks.
+    Min(u64),
+    /// Use the latest of the provider-reported and confirmation-based finalized blocks.
+    Max(u64),
+}
+
+/// The finality policy selected on the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FinalityPolicyKind {
+    /// The finalized block reported by the node.
+    #[default]
+    Provider,
+    /// The head minus the number of confirmations.
+    Confirmations,
+    /// The earliest of the provider-reported and confirmation-based finalized blocks.
+    Min,
+    /// The latest of the provider-reported and confirmation-based finalized blocks.
+    Max,
+}
+
+impl FinalityPolicy {
+    /// Returns true if the finalized block comes from the provider only.
+    pub fn is_provider(&self) -> bool {
+        matches!(self, FinalityPolicy::Provider)
+    }
+
+    pub async fn get_finalized_cursor<I>(&self, ingestion: &I) -> Result<Cursor, IngestionError>
+    where
+        I: BlockIngestion,
+    {
+        match *self {
+            FinalityPolicy::Provider => ingestion.get_finalized_cursor().await,
+            FinalityPolicy::Confirmations(confirmations) => {
+                get_confirmed_cursor(ingestion, confirmations).await
+            }
+            FinalityPolicy::Min(confirmations) => {
+                let provider = ingestion.get_finalized_cursor().await?;
+                let confirmed = get_confirmed_cursor(ingestion, confirmations).await?;
+
+                if provider.number <= confirmed.number {
+                    Ok(provider)
+                } else {
+                    Ok(confirmed)
+                }
+            }
+            FinalityPolicy::Max(confirmations) => {
+                let provider = ingestion.get_finalized_cursor().await?;
+                let confirmed = get_confirmed_cursor(ingestion, confirmations).await?;
+
+                if provider.number >= confirmed.number {
+                    Ok(provider)
+                } else {
+                    Ok(confirmed)
+                }
+            }
+        }
+    }
+}
+
+/// Returns the most recent block with at least `confirmations` blocks on top of it.
+async fn get_confirmed_cursor<I>(
+    ingestion: &I,
+    confirmations: u64,
+) -> Result<Cursor, IngestionError>
+where
+    I: BlockIngestion,
+{
+    let head = ingestion.get_head_cursor().await?;
+    let block_number = head.number.saturating_sub(confirmations);
+
+    if block_number == head.number {
+        return Ok(head);
+    }
+
+    let block_info = ingestion.get_block_info_by_number(block_number).await?;
+
+    Ok(block_info.cursor())
+}
+
+#[cfg(test)]
+mod tests {
+    use error_stack::Result;
+
+    use crate::{
+        chain::BlockInfo,
+        fragment::Block,
+        ingestion::{BlockIngestion, IngestionError},
+        new_test_cursor, Cursor,
+    };
+
+    use super::FinalityPolicy;
+
+    #[derive(Clone)]
+    struct TestIngestion {
+        head: u64,
+        finalized: u64,
+    }
+
+    impl BlockIngestion for TestIngestion {
+        async fn get_head_cursor(&self) -> Result<Cursor, IngestionError> {
+            Ok(new_test_cursor(self.head, 0))
+        }
+
+        async fn get_finalized_cursor(&self) -> Result<Cursor, IngestionError> {
+            Ok(new_test_cursor(self.finalized, 0))
+        }
+
+        async fn get_block_info_by_number(
+            &self,
+            block_number: u64,
+        ) -> Result<BlockInfo, IngestionError> {
+            assert!(block_number <= self.head);
+            Ok(BlockInfo {
+                number: block_number,
+                hash: new_test_cursor(block_number, 0).hash,
+                parent: new_test_cursor(block_number.saturating_sub(1), 0).hash,
+            })
+        }
+
+        async fn ingest_block_by_number(
+            &self,
+            _block_number: u64,
+        ) -> Result<(BlockInfo, Block), IngestionError> {
+            unimplemented!()
+        }
+    }
+
+    async fn finalized(policy: FinalityPolicy, head: u64, finalized: u64) -> Cursor {
+        policy
+            .get_finalized_cursor(&TestIngestion { head, finalized })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_provider() {
+        let policy = FinalityPolicy::Provider;
+        assert!(policy.is_provider());
+        assert_eq!(finalized(policy, 1_000, 900).await, new_test_cursor(900, 0));
+        assert_eq!(
+            finalized(policy, 1_000, 1_000).await,
+            new_test_cursor(1_000, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_confirmations() {
+        let policy = FinalityPolicy::Confirmations(64);
+        assert!(!policy.is_provider());
+        assert_eq!(finalized(policy, 1_000, 900).await, new_test_cursor(936, 0));
+        // The provider's finalized block is ignored.
+        assert_eq!(finalized(policy, 1_000, 990).await, new_test_cursor(936, 0));
+        // Never goes below genesis.
+        assert_eq!(finalized(policy, 10, 0).await, new_test_cursor(0, 0));
+        // Zero confirmations finalize the head.
+        assert_eq!(
+            finalized(FinalityPolicy::Confirmations(0), 1_000, 900).await,
+            new_test_cursor(1_000, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_min() {
+        let policy = FinalityPolicy::Min(64);
+        assert!(!policy.is_provider());
+        assert_eq!(finalized(policy, 1_000, 900).await, new_test_cursor(900, 0));
+        assert_eq!(finalized(policy, 1_000, 990).await, new_test_cursor(936, 0));
+        assert_eq!(finalized(policy, 1_000, 936).await, new_test_cursor(936, 0));
+    }
+
+    #[tokio::test]
+    async fn test_max() {
+        let policy = FinalityPolicy::Max(64);
+        assert!(!policy.is_provider());
+        assert_eq!(finalized(policy, 1_000, 900).await, new_test_cursor(936, 0));
+        assert_eq!(finalized(policy, 1_000, 990).await, new_test_cursor(990, 0));
+        assert_eq!(finalized(policy, 1_000, 936).await, new_test_cursor(936, 0));
+    }
+}
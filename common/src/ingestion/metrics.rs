@@ -1,4 +1,4 @@
-use apibara_observability::{Gauge, Histogram, RequestMetrics};
+use apibara_observability::{Counter, Gauge, Histogram, RequestMetrics};
 
 #[derive(Debug, Clone)]
 pub struct IngestionMetrics {
@@ -11,6 +11,8 @@ pub struct IngestionMetrics {
     pub rpc: RequestMetrics,
     pub block_upload: RequestMetrics,
     pub ingestion_latency: Histogram<f64>,
+    pub reorg: Counter<u64>,
+    pub reorg_depth: Histogram<u64>,
 }
 
 impl Default for IngestionMetrics {
@@ -75,6 +77,17 @@ impl Default for IngestionMetrics {
                     10.0, 20.0, 30.0, 60.0, 120.0,
                 ])
                 .build(),
+            reorg: meter
+                .u64_counter("dna.ingestion.reorg")
+                .with_description("number of chain reorganizations")
+                .with_unit("{reorg}")
+                .build(),
+            reorg_depth: meter
+                .u64_histogram("dna.ingestion.reorg_depth")
+                .with_description("number of blocks removed by a chain reorganization")
+                .with_unit("{block}")
+                .with_boundaries(vec![1.0, 2.0, 3.0, 5.0, 10.0, 25.0, 50.0, 100.0])
+                .build(),
         }
     }
 }
@@ -1,8 +1,10 @@
 mod cli;
 mod error;
+mod finality;
 mod metrics;
 mod service;
 pub mod state_client;
+mod subscription;
 
 use apibara_etcd::{EtcdClient, LockOptions};
 use error_stack::{Result, ResultExt};
@@ -15,12 +17,16 @@ use crate::options_store::OptionsStore;
 
 pub use self::cli::IngestionArgs;
 pub use self::error::{IngestionError, IngestionErrorExt};
+pub use self::finality::{FinalityPolicy, FinalityPolicyKind};
 pub use self::metrics::IngestionMetrics;
 pub use self::service::{BlockIngestion, IngestionService, IngestionServiceOptions};
 pub use self::state_client::{
     IngestionStateClient, IngestionStateClientError, IngestionStateUpdate, FINALIZED_KEY,
     INGESTED_KEY, INGESTION_PREFIX_KEY, STARTING_BLOCK_KEY,
 };
+pub use self::subscription::{
+    HeadEvent, HeadEventStream, HeadNotifications, HeadSubscription, JsonRpcWsSubscription,
+};
 
 pub async fn ingestion_service_loop<I>(
     ingestion: I,
@@ -99,6 +105,14 @@ where
                     .await
                     .change_context(IngestionError::LockKeepAlive)?;
                 info!("ingestion lock released");
+
+                // Ingestion reached the ending block. Keep running so that the other
+                // services continue serving the ingested range.
+                if !ct.is_cancelled() {
+                    info!("ingestion ended. waiting for shutdown");
+                    ct.cancelled().await;
+                }
+
                 break;
             }
             Err(err) => {
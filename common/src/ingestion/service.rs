@@ -26,7 +26,13 @@ use crate::{
     Cursor,
 };
 
-use super::{error::IngestionError, metrics::IngestionMetrics, state_client::IngestionStateClient};
+use super::{
+    error::IngestionError,
+    finality::FinalityPolicy,
+    metrics::IngestionMetrics,
+    state_client::IngestionStateClient,
+    subscription::{HeadEvent, HeadNotifications},
+};
 
 pub trait BlockIngestion: Clone {
     fn supports_pending(&self) -> bool {
@@ -55,6 +61,14 @@ pub trait BlockIngestion: Clone {
     {
         async { Ok(None) }
     }
+
+    /// Subscribe to head and finality notifications pushed by the node.
+    ///
+    /// Returns `None` if the chain doesn't support notifications or they're not configured, in
+    /// which case the service only polls.
+    fn head_notifications(&self, _ct: CancellationToken) -> Option<HeadNotifications> {
+        None
+    }
 }
 
 pub enum IngestionTask {
@@ -74,12 +88,16 @@ pub struct IngestionServiceOptions {
     pub chain_segment_upload_offset_size: usize,
     /// Override the ingestion starting block.
     pub override_starting_block: Option<u64>,
+    /// Stop ingestion once this block is ingested and finalized.
+    pub ending_block: Option<u64>,
     /// How often to refresh the pending block.
     pub pending_refresh_interval: Duration,
     /// How often to refresh the head block.
     pub head_refresh_interval: Duration,
     /// How often to refresh the finalized block.
     pub finalized_refresh_interval: Duration,
+    /// How to decide which blocks are finalized.
+    pub finality: FinalityPolicy,
 }
 
 pub struct IngestionService<I>
@@ -92,6 +110,7 @@ where
     chain_store: ChainStore,
     chain_builder: CanonicalChainBuilder,
     task_queue: FuturesOrdered<IngestionTaskHandle>,
+    head_notifications: Option<HeadNotifications>,
     metrics: IngestionMetrics,
 }
 
@@ -106,6 +125,7 @@ where
 {
     block_store: BlockStoreWriter,
     ingestion: Arc<I>,
+    finality: FinalityPolicy,
     metrics: IngestionMetrics,
 }
 
@@ -165,18 +185,21 @@ where
         let chain_store = ChainStore::new(object_store.clone(), file_cache);
         let block_store = BlockStoreWriter::new(object_store);
         let state_client = IngestionStateClient::new(&etcd_client);
+        let finality = options.finality;
 
         Self {
             options,
             ingestion: IngestionInner {
                 ingestion: ingestion.into(),
                 block_store,
+                finality,
                 metrics: metrics.clone(),
             },
             state_client,
             chain_store,
             chain_builder: CanonicalChainBuilder::new(),
             task_queue: FuturesOrdered::new(),
+            head_notifications: None,
             metrics,
         }
     }
@@ -186,8 +209,48 @@ where
         lock: &mut Lock,
         ct: CancellationToken,
     ) -> Result<(), IngestionError> {
+        if let Some(ended) = self
+            .state_client
+            .get_ended()
+            .await
+            .change_context(IngestionError::StateClientRequest)?
+        {
+            match self.options.ending_block {
+                Some(ending_block) if ending_block == ended => {
+                    info!(
+                        ending_block = ended,
+                        "ingestion already reached the ending block"
+                    );
+                    return Ok(());
+                }
+                Some(ending_block) if ending_block < ended => {
+                    return Err(IngestionError::Options)
+                        .attach_printable("ending block is before the block ingestion ended at")
+                        .attach_printable("the ending block can only be raised or cleared")
+                        .attach_printable_lazy(|| format!("ended block: {ended}"))
+                        .attach_printable_lazy(|| format!("ending block: {ending_block}"));
+                }
+                ending_block => {
+                    info!(
+                        ended_block = ended,
+                        ending_block = ?ending_block,
+                        "resuming ingestion after the previous ending block"
+                    );
+                    self.state_client
+                        .delete_ended()
+                        .await
+                        .change_context(IngestionError::StateClientRequest)?;
+                }
+            }
+        }
+
         let mut state = self.initialize().await?;
 
+        self.head_notifications = self
+            .ingestion
+            .ingestion
+            .head_notifications(ct.child_token());
+
         loop {
             if ct.is_cancelled() {
                 return Ok(());
@@ -206,6 +269,12 @@ where
                 .await
                 .change_context(IngestionError::LockKeepAlive)?;
 
+            if let IngestionState::Ingest(inner_state) = &state {
+                if let Some(ending_block) = self.reached_ending_block(inner_state) {
+                    return self.finish_ingestion(ending_block).await;
+                }
+            }
+
             state = async {
                 match state {
                     IngestionState::Ingest(inner_state) => {
@@ -232,7 +301,9 @@ where
     pub async fn initialize(&mut self) -> Result<IngestionState, IngestionError> {
         debug!("initializing ingestion");
         let head = self.ingestion.get_head_cursor().await?;
+        let head = self.clamp_to_ending_block(head).await?;
         let finalized = self.ingestion.get_finalized_cursor().await?;
+        let finalized = self.clamp_to_ending_block(finalized).await?;
 
         let current_span = tracing::Span::current();
 
@@ -330,27 +401,53 @@ where
         current_span.record("finalized", state.finalized.number);
         current_span.record("task_queue_size", self.task_queue.len());
 
-        tokio::select! {
+        // Take the notifications out so that the handlers below can borrow `self`.
+        let mut head_notifications = self.head_notifications.take();
+        let head_subscription_live = head_notifications
+            .as_ref()
+            .is_some_and(HeadNotifications::is_live);
+
+        let result = tokio::select! {
             biased;
 
             _ = ct.cancelled() => Ok(IngestionState::Ingest(state)),
 
+            Some(event) = next_head_event(&mut head_notifications) => {
+                match event {
+                    HeadEvent::Head => {
+                        current_span.record("action", "notify_head");
+
+                        self.tick_refresh_head(state).await
+                    }
+                    HeadEvent::Finalized => {
+                        current_span.record("action", "notify_finalized");
+
+                        self.tick_refresh_finalized(state).await
+                    }
+                }
+            }
+
             _ = state.finalized_refresh_interval.tick() => {
                 current_span.record("action", "refresh_finalized");
 
                 self.tick_refresh_finalized(state).await
             }
 
-            _ = state.pending_refresh_interval.tick(), if state.head == state.last_ingested && self.ingestion.supports_pending() => {
+            _ = state.pending_refresh_interval.tick(), if state.head == state.last_ingested && self.ingestion.supports_pending() && self.options.ending_block.is_none() => {
                 current_span.record("action", "refresh_pending");
 
                 self.tick_refresh_pending(state).await
             }
 
             _ = state.head_refresh_interval.tick() => {
-                current_span.record("action", "refresh_head");
+                // Only poll if the subscription is not delivering events.
+                if head_subscription_live {
+                    Ok(IngestionState::Ingest(state))
+                } else {
+                    current_span.record("action", "refresh_head");
 
-                self.tick_refresh_head(state).await
+                    self.tick_refresh_head(state).await
+                }
             }
 
             join_result = self.task_queue_next(), if !self.task_queue_is_empty() => {
@@ -358,7 +455,11 @@ where
 
                 self.tick_with_task_result(state, join_result).await
             }
-        }
+        };
+
+        self.head_notifications = head_notifications;
+
+        result
     }
 
     pub async fn tick_refresh_finalized(
@@ -371,8 +472,15 @@ where
             .await
             .change_context(IngestionError::RpcRequest)
             .attach_printable("failed to refresh finalized cursor")?;
+        let finalized = self.clamp_to_ending_block(finalized).await?;
 
         if state.finalized.number > finalized.number {
+            // Confirmation-based finality moves back when the head is reorged.
+            if !self.options.finality.is_provider() {
+                debug!(cursor = %finalized, "ignoring finalized cursor behind the current one");
+                return Ok(IngestionState::Ingest(state));
+            }
+
             return Err(IngestionError::Model)
                 .attach_printable("the new finalized cursor is behind the old one")
                 .attach_printable("this should never happen");
@@ -402,6 +510,7 @@ where
             .await
             .change_context(IngestionError::RpcRequest)
             .attach_printable("failed to refresh head cursor")?;
+        let head = self.clamp_to_ending_block(head).await?;
 
         if state.head == head {
             return Ok(IngestionState::Ingest(state));
@@ -684,13 +793,19 @@ where
         }
 
         self.task_queue_clear();
-        self.chain_builder
+        let removed = self
+            .chain_builder
             .shrink(new_head_candidate.clone())
             .change_context(IngestionError::Model)
             .attach_printable("failed to shrink canonical chain after reorg recovery")
             .attach_printable_lazy(|| format!("new head: {}", new_head_candidate))?;
 
-        info!(new_head = %new_head_candidate, "recovered from a chain reorganization");
+        if !removed.is_empty() {
+            self.metrics.reorg.add(1, &[]);
+            self.metrics.reorg_depth.record(removed.len() as u64, &[]);
+        }
+
+        info!(new_head = %new_head_candidate, depth = removed.len(), "recovered from a chain reorganization");
 
         Ok(IngestionState::Ingest(IngestState {
             finalized: state.finalized,
@@ -706,6 +821,61 @@ where
         }))
     }
 
+    /// Returns the cursor of the ending block if the given cursor is after it.
+    async fn clamp_to_ending_block(&self, cursor: Cursor) -> Result<Cursor, IngestionError> {
+        match self.options.ending_block {
+            Some(ending_block) if cursor.number > ending_block => {
+                let block_info = self
+                    .ingestion
+                    .get_block_info_by_number(ending_block)
+                    .await
+                    .change_context(IngestionError::RpcRequest)
+                    .attach_printable("failed to get ending block info")?;
+                Ok(block_info.cursor())
+            }
+            _ => Ok(cursor),
+        }
+    }
+
+    /// Returns the ending block if it was ingested and finalized.
+    fn reached_ending_block(&self, state: &IngestState) -> Option<u64> {
+        let ending_block = self.options.ending_block?;
+
+        let reached = state.last_ingested.number >= ending_block
+            && state.finalized.number >= ending_block
+            && self.task_queue_is_empty();
+
+        reached.then_some(ending_block)
+    }
+
+    /// Upload the canonical chain up to the ending block and mark ingestion as ended.
+    async fn finish_ingestion(&mut self, ending_block: u64) -> Result<(), IngestionError> {
+        let current_segment = self
+            .chain_builder
+            .current_segment()
+            .change_context(IngestionError::Model)?;
+
+        info!(first_block = %current_segment.info.first_block, last_block = %current_segment.info.last_block, "uploading recent chain segment");
+
+        let recent_etag = self
+            .chain_store
+            .put_recent(&current_segment)
+            .await
+            .change_context(IngestionError::CanonicalChainStoreRequest)?;
+        self.state_client
+            .put_ingested(recent_etag)
+            .await
+            .change_context(IngestionError::StateClientRequest)?;
+        self.state_client
+            .put_ended(ending_block)
+            .await
+            .change_context(IngestionError::StateClientRequest)?;
+
+        info!(ending_block, "ingestion reached the ending block");
+
+        Ok(())
+    }
+
     pub fn task_queue_clear(&mut self) {
         self.task_queue = FuturesOrdered::new();
     }
@@ -912,7 +1082,9 @@ where
     }
 
     async fn get_finalized_cursor(&self) -> Result<Cursor, IngestionError> {
-        self.ingestion.get_finalized_cursor().await
+        self.finality
+            .get_finalized_cursor(self.ingestion.as_ref())
+            .await
     }
 
     async fn get_block_info_by_number(
@@ -923,6 +1095,14 @@ where
     }
 }
 
+/// Wait for the next head event, or forever if there's no subscription.
+async fn next_head_event(notifications: &mut Option<HeadNotifications>) -> Option<HeadEvent> {
+    match notifications {
+        Some(notifications) => notifications.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 impl Default for IngestionServiceOptions {
     fn default() -> Self {
         Self {
@@ -930,9 +1110,11 @@ impl Default for IngestionServiceOptions {
             chain_segment_size: 10_000,
             chain_segment_upload_offset_size: 100,
             override_starting_block: None,
+            ending_block: None,
             pending_refresh_interval: Duration::from_secs(3),
             head_refresh_interval: Duration::from_secs(3),
             finalized_refresh_interval: Duration::from_secs(30),
+            finality: FinalityPolicy::default(),
         }
     }
 }
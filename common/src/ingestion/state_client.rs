@@ -1,4 +1,4 @@
-use apibara_etcd::{EtcdClient, KvClient, WatchClient};
+use apibara_etcd::{EtcdClient, KeyValue, KvClient, WatchClient};
 use error_stack::{Result, ResultExt};
 use futures::{Stream, StreamExt};
 use tokio_util::sync::CancellationToken;
@@ -12,6 +12,9 @@ pub static STARTING_BLOCK_KEY: &str = "ingestion/starting_block";
 pub static FINALIZED_KEY: &str = "ingestion/finalized";
 pub static SEGMENTED_KEY: &str = "ingestion/segmented";
 pub static GROUPED_KEY: &str = "ingestion/grouped";
+pub static EXPIRED_KEY: &str = "ingestion/expired";
+pub static ENDED_KEY: &str = "ingestion/ended";
+pub static REPAIRED_KEY: &str = "ingestion/repaired";
 
 // Use a different prefix for pruned blocks to avoid overwhelming the compaction state store
 pub static PRUNED_KEY: &str = "compaction/pruned";
@@ -32,7 +35,12 @@ pub enum IngestionStateUpdate {
     Pending(Option<u64>),
     Segmented(u64),
     Grouped(u64),
+    Expired(u64),
     Ingested(String),
+    /// The ending block, or `None` if ingestion resumed after it.
+    Ended(Option<u64>),
+    /// Cache keys of the objects overwritten by a repair.
+    Repaired(Vec<String>),
 }
 
 impl IngestionStateClient {
@@ -206,6 +214,34 @@ impl IngestionStateClient {
         Ok(())
     }
 
+    pub async fn get_pending(&mut self) -> Result<Option<u64>, IngestionStateClientError> {
+        let response = self
+            .kv_client
+            .get(PENDING_KEY)
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to get pending block generation")?;
+
+        let Some(kv) = response.kvs().first() else {
+            return Ok(None);
+        };
+
+        let value = String::from_utf8(kv.value().to_vec())
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to decode pending block generation")?;
+
+        if value.is_empty() {
+            return Ok(None);
+        }
+
+        let generation = value
+            .parse::<u64>()
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to parse pending block generation")?;
+
+        Ok(Some(generation))
+    }
+
     pub async fn get_segmented(&mut self) -> Result<Option<u64>, IngestionStateClientError> {
         let response = self
             .kv_client
@@ -276,6 +312,102 @@ impl IngestionStateClient {
         Ok(())
     }
 
+    pub async fn get_expired(&mut self) -> Result<Option<u64>, IngestionStateClientError> {
+        let response = self
+            .kv_client
+            .get(EXPIRED_KEY)
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to get expired block")?;
+
+        let Some(kv) = response.kvs().first() else {
+            return Ok(None);
+        };
+
+        let value = String::from_utf8(kv.value().to_vec())
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to decode expired block")?;
+
+        let block = value
+            .parse::<u64>()
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to parse expired block")?;
+
+        Ok(Some(block))
+    }
+
+    pub async fn put_expired(&mut self, block: u64) -> Result<(), IngestionStateClientError> {
+        let value = block.to_string();
+        self.kv_client
+            .put(EXPIRED_KEY, value.as_bytes())
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to put expired block")?;
+
+        Ok(())
+    }
+
+    /// Returns the ending block, if ingestion reached it.
+    pub async fn get_ended(&mut self) -> Result<Option<u64>, IngestionStateClientError> {
+        let response = self
+            .kv_client
+            .get(ENDED_KEY)
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to get ended block")?;
+
+        let Some(kv) = response.kvs().first() else {
+            return Ok(None);
+        };
+
+        let value = String::from_utf8(kv.value().to_vec())
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to decode ended block")?;
+
+        let block = value
+            .parse::<u64>()
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to parse ended block")?;
+
+        Ok(Some(block))
+    }
+
+    pub async fn put_ended(&mut self, block: u64) -> Result<(), IngestionStateClientError> {
+        let value = block.to_string();
+        self.kv_client
+            .put(ENDED_KEY, value.as_bytes())
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to put ended block")?;
+
+        Ok(())
+    }
+
+    /// Clear the ending block so that ingestion can continue after it.
+    pub async fn delete_ended(&mut self) -> Result<(), IngestionStateClientError> {
+        self.kv_client
+            .delete(ENDED_KEY)
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to delete ended block")?;
+
+        Ok(())
+    }
+
+    /// Publish the cache keys of the objects overwritten by a repair.
+    ///
+    /// Running servers watch this key and remove the objects from their cache.
+    pub async fn put_repaired(&mut self, keys: &[String]) -> Result<(), IngestionStateClientError> {
+        let value = keys.join("\n");
+        self.kv_client
+            .put(REPAIRED_KEY, value.as_bytes())
+            .await
+            .change_context(IngestionStateClientError)
+            .attach_printable("failed to put repaired objects")?;
+
+        Ok(())
+    }
+
     pub async fn get_pruned(&mut self) -> Result<Option<u64>, IngestionStateClientError> {
         let response = self
             .kv_client
@@ -325,7 +457,7 @@ impl IngestionStateUpdate {
         matches!(self, IngestionStateUpdate::Pending(_))
     }
 
-    pub fn from_kv(kv: &etcd_client::KeyValue) -> Result<Option<Self>, IngestionStateClientError> {
+    pub fn from_kv(kv: &KeyValue) -> Result<Option<Self>, IngestionStateClientError> {
         let key = String::from_utf8(kv.key().to_vec())
             .change_context(IngestionStateClientError)
             .attach_printable("failed to decode key")?;
@@ -370,6 +502,25 @@ impl IngestionStateUpdate {
                 .change_context(IngestionStateClientError)
                 .attach_printable("failed to parse grouped block")?;
             Ok(Some(IngestionStateUpdate::Grouped(block)))
+        } else if key.ends_with(EXPIRED_KEY) {
+            let block = value
+                .parse::<u64>()
+                .change_context(IngestionStateClientError)
+                .attach_printable("failed to parse expired block")?;
+            Ok(Some(IngestionStateUpdate::Expired(block)))
+        } else if key.ends_with(ENDED_KEY) {
+            if value.is_empty() {
+                Ok(Some(IngestionStateUpdate::Ended(None)))
+            } else {
+                let block = value
+                    .parse::<u64>()
+                    .change_context(IngestionStateClientError)
+                    .attach_printable("failed to parse ended block")?;
+                Ok(Some(IngestionStateUpdate::Ended(Some(block))))
+            }
+        } else if key.ends_with(REPAIRED_KEY) {
+            let keys = value.lines().map(String::from).collect();
+            Ok(Some(IngestionStateUpdate::Repaired(keys)))
         } else {
             Ok(None)
         }
@@ -0,0 +1,269 @@
+//! Head and finality notifications pushed by the node.
+//!
+//! Notifications only wake up the ingestion service: the head and finalized cursors are still
+//! fetched with the regular provider methods. If the subscription disconnects, the service falls
+//! back to polling until it reconnects.
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use error_stack::{Result, ResultExt};
+use futures::{stream::BoxStream, SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::IngestionError;
+
+/// Consider the subscription stale if no event is received for this long.
+const STALE_AFTER: Duration = Duration::from_secs(60);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadEvent {
+    /// The chain has a new head.
+    Head,
+    /// The chain has a new finalized block.
+    Finalized,
+}
+
+pub type HeadEventStream = BoxStream<'static, Result<HeadEvent, IngestionError>>;
+
+/// A source of head events.
+pub trait HeadSubscription: Send + Sync + 'static {
+    /// Connect to the node and return the stream of events.
+    ///
+    /// The stream ends when the connection is closed.
+    fn connect(&self) -> impl Future<Output = Result<HeadEventStream, IngestionError>> + Send;
+}
+
+/// Receive head events from a subscription, reconnecting in the background.
+#[derive(Debug)]
+pub struct HeadNotifications {
+    rx: mpsc::Receiver<HeadEvent>,
+    connected: Arc<AtomicBool>,
+    last_event: Option<Instant>,
+}
+
+impl HeadNotifications {
+    pub fn spawn<S: HeadSubscription>(subscription: S, ct: CancellationToken) -> Self {
+        let (tx, rx) = mpsc::channel(64);
+        let connected = Arc::new(AtomicBool::new(false));
+
+        tokio::spawn(subscription_loop(subscription, tx, connected.clone(), ct));
+
+        Self {
+            rx,
+            connected,
+            last_event: None,
+        }
+    }
+
+    pub async fn recv(&mut self) -> Option<HeadEvent> {
+        let event = self.rx.recv().await?;
+        self.last_event = Some(Instant::now());
+        Some(event)
+    }
+
+    /// Returns true if the subscription is connected and delivering events.
+    ///
+    /// Polling the node is not needed while the subscription is live.
+    pub fn is_live(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+            && self
+                .last_event
+                .is_some_and(|last_event| last_event.elapsed() < STALE_AFTER)
+    }
+}
+
+async fn subscription_loop<S: HeadSubscription>(
+    subscription: S,
+    tx: mpsc::Sender<HeadEvent>,
+    connected: Arc<AtomicBool>,
+    ct: CancellationToken,
+) {
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
+
+    while !ct.is_cancelled() {
+        match subscription.connect().await {
+            Ok(mut stream) => {
+                info!("head subscription connected");
+                connected.store(true, Ordering::Relaxed);
+                reconnect_delay = MIN_RECONNECT_DELAY;
+
+                loop {
+                    let event = tokio::select! {
+                        _ = ct.cancelled() => return,
+                        event = stream.next() => event,
+                    };
+
+                    match event {
+                        Some(Ok(event)) => {
+                            debug!(event = ?event, "received head event");
+                            // Events are only used to wake up the service, so it's fine to
+                            // drop them if the service is busy.
+                            if let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(event) {
+                                return;
+                            }
+                        }
+                        Some(Err(err)) => {
+                            warn!(error = ?err, "head subscription error");
+                            break;
+                        }
+                        None => {
+                            warn!("head subscription closed");
+                            break;
+                        }
+                    }
+                }
+
+                connected.store(false, Ordering::Relaxed);
+            }
+            Err(err) => {
+                warn!(error = ?err, "failed to connect head subscription");
+            }
+        }
+
+        tokio::select! {
+            _ = ct.cancelled() => return,
+            _ = tokio::time::sleep(reconnect_delay) => {}
+        }
+
+        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// A JSON-RPC subscription over WebSocket, for example `eth_subscribe("newHeads")`.
+///
+/// Every notification received is turned into the same event.
+#[derive(Debug, Clone)]
+pub struct JsonRpcWsSubscription {
+    url: String,
+    method: String,
+    params: serde_json::Value,
+    event: HeadEvent,
+}
+
+impl JsonRpcWsSubscription {
+    pub fn new(
+        url: impl Into<String>,
+        method: impl Into<String>,
+        params: serde_json::Value,
+        event: HeadEvent,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            method: method.into(),
+            params,
+            event,
+        }
+    }
+}
+
+impl HeadSubscription for JsonRpcWsSubscription {
+    async fn connect(&self) -> Result<HeadEventStream, IngestionError> {
+        let (mut ws, _) = tokio_tungstenite::connect_async(self.url.as_str())
+            .await
+            .change_context(IngestionError::RpcRequest)
+            .attach_printable("failed to connect to websocket")
+            .attach_printable_lazy(|| format!("url: {}", self.url))?;
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": self.method,
+            "params": self.params,
+        });
+
+        ws.send(Message::text(request.to_string()))
+            .await
+            .change_context(IngestionError::RpcRequest)
+            .attach_printable("failed to send subscription request")
+            .attach_printable_lazy(|| format!("method: {}", self.method))?;
+
+        let event = self.event;
+        let stream = ws.filter_map(move |message| async move {
+            let text = match message {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => {
+                    return Some(
+                        Err(IngestionError::RpcRequest)
+                            .attach_printable("websocket closed by the server"),
+                    )
+                }
+                Ok(_) => return None,
+                Err(err) => {
+                    return Some(
+                        Err(err)
+                            .change_context(IngestionError::RpcRequest)
+                            .attach_printable("websocket error"),
+                    )
+                }
+            };
+
+            let response = match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(response) => response,
+                Err(err) => {
+                    return Some(
+                        Err(err)
+                            .change_context(IngestionError::RpcRequest)
+                            .attach_printable("failed to decode subscription message"),
+                    )
+                }
+            };
+
+            if let Some(error) = response.get("error") {
+                return Some(
+                    Err(IngestionError::RpcRequest)
+                        .attach_printable("subscription error")
+                        .attach_printable(format!("error: {error}")),
+                );
+            }
+
+            // Only notifications have a method, the subscription response only has the id.
+            response.get("method").map(|_| Ok(event))
+        });
+
+        Ok(stream.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{HeadEvent, HeadEventStream, HeadNotifications, HeadSubscription};
+    use crate::ingestion::IngestionError;
+
+    struct TestSubscription;
+
+    impl HeadSubscription for TestSubscription {
+        async fn connect(&self) -> error_stack::Result<HeadEventStream, IngestionError> {
+            let events = vec![Ok(HeadEvent::Head), Ok(HeadEvent::Finalized)];
+            Ok(futures::stream::iter(events)
+                .chain(futures::stream::pending())
+                .boxed())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_head_notifications() {
+        let ct = CancellationToken::new();
+        let mut notifications = HeadNotifications::spawn(TestSubscription, ct.clone());
+
+        assert!(!notifications.is_live());
+        assert_eq!(notifications.recv().await, Some(HeadEvent::Head));
+        assert_eq!(notifications.recv().await, Some(HeadEvent::Finalized));
+        assert!(notifications.is_live());
+
+        ct.cancel();
+    }
+}
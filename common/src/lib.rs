@@ -8,16 +8,19 @@ mod core;
 pub mod data_stream;
 pub mod dbg;
 pub mod file_cache;
+pub mod fixture;
 pub mod fragment;
 pub mod index;
 pub mod ingestion;
 pub mod join;
 pub mod object_store;
 pub mod options_store;
+pub mod provider_pool;
 pub mod query;
 pub mod rkyv;
 pub mod segment;
 pub mod server;
+pub mod snapshot;
 
 pub use apibara_etcd as etcd;
 use data_stream::BlockFilterFactory;
@@ -141,11 +144,15 @@ mod server_impl {
         let sync_handle = tokio::spawn(chain_view_sync.start(ct.clone()));
 
         let compaction_handle = if args.compaction.compaction_enabled {
-            let options = args.compaction.to_compaction_options();
+            let options = args
+                .compaction
+                .to_compaction_options()
+                .change_context(ServerError)?;
 
             tokio::spawn(compaction_service_loop(
                 etcd_client.clone(),
                 object_store.clone(),
+                file_cache.clone(),
                 chain_view.clone(),
                 options,
                 ct.clone(),
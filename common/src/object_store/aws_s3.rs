@@ -1,9 +1,11 @@
+use std::time::SystemTime;
+
 use bytes::Bytes;
 use error_stack::{Result, ResultExt};
 
 use super::{
-    error::ObjectStoreError, metrics::ObjectStoreMetrics, DeleteOptions, GetOptions, ObjectETag,
-    ObjectStoreResultExt, PutMode, PutOptions, ToObjectStoreResult,
+    error::ObjectStoreError, metrics::ObjectStoreMetrics, DeleteOptions, GetOptions, ListOptions,
+    ObjectETag, ObjectMetadata, ObjectStoreResultExt, PutMode, PutOptions, ToObjectStoreResult,
 };
 
 #[derive(Clone)]
@@ -138,28 +140,44 @@ impl AwsS3Client {
         &self,
         bucket: &str,
         prefix: &str,
-    ) -> Result<Vec<String>, ObjectStoreError> {
+        options: ListOptions,
+    ) -> Result<(Vec<ObjectMetadata>, Option<String>), ObjectStoreError> {
         self.metrics.list.add(1, &[]);
 
         let response = self
             .client
-            .list_objects()
+            .list_objects_v2()
             .bucket(bucket)
             .prefix(prefix)
+            .set_continuation_token(options.continuation_token)
+            .set_max_keys(options.max_keys.map(|max_keys| max_keys as i32))
             .send()
             .await
             .change_to_object_store_context()?;
 
-        let mut object_ids = Vec::default();
+        let mut objects = Vec::default();
         for object in response.contents() {
             let object_id = object
                 .key()
                 .ok_or(ObjectStoreError::Metadata)
                 .attach_printable("missing key")?;
-            object_ids.push(object_id.to_string());
+            let last_modified = object
+                .last_modified()
+                .and_then(|last_modified| SystemTime::try_from(*last_modified).ok());
+            objects.push(ObjectMetadata {
+                object_id: object_id.to_string(),
+                size: object.size().unwrap_or_default() as u64,
+                last_modified,
+            });
         }
 
-        Ok(object_ids)
+        let continuation_token = if response.is_truncated().unwrap_or(false) {
+            response.next_continuation_token().map(ToString::to_string)
+        } else {
+            None
+        };
+
+        Ok((objects, continuation_token))
     }
 
     pub async fn delete_object(
@@ -1,4 +1,9 @@
-use azure_core::{self, prelude::IfMatchCondition};
+use std::num::NonZeroU32;
+
+use azure_core::{
+    self,
+    prelude::{IfMatchCondition, NextMarker},
+};
 use azure_storage::{CloudLocation, StorageCredentials};
 use azure_storage_blobs::prelude::ClientBuilder;
 use bytes::{Bytes, BytesMut};
@@ -9,8 +14,8 @@ use tracing::debug;
 use crate::object_store::ObjectStoreResultExt;
 
 use super::{
-    error::ToObjectStoreResult, metrics::ObjectStoreMetrics, DeleteOptions, GetOptions, ObjectETag,
-    ObjectStoreError, PutMode, PutOptions,
+    error::ToObjectStoreResult, metrics::ObjectStoreMetrics, DeleteOptions, GetOptions,
+    ListOptions, ObjectETag, ObjectMetadata, ObjectStoreError, PutMode, PutOptions,
 };
 
 #[derive(Clone)]
@@ -134,25 +139,49 @@ impl AzureBlobClient {
         &self,
         bucket: &str,
         prefix: &str,
-    ) -> Result<Vec<String>, ObjectStoreError> {
+        options: ListOptions,
+    ) -> Result<(Vec<ObjectMetadata>, Option<String>), ObjectStoreError> {
         self.metrics.list.add(1, &[]);
 
-        let mut response = self
+        let mut request = self
             .client
             .clone()
             .container_client(bucket)
             .list_blobs()
-            .prefix(prefix.to_string())
-            .into_stream();
+            .prefix(prefix.to_string());
 
-        let mut object_ids = Vec::new();
-        while let Some(response) = response.try_next().await.change_to_object_store_context()? {
-            for blob in response.blobs.blobs() {
-                object_ids.push(blob.name.to_string());
-            }
+        if let Some(token) = options.continuation_token {
+            request = request.marker(NextMarker::new(token));
+        }
+
+        if let Some(max_results) = options.max_keys.and_then(NonZeroU32::new) {
+            request = request.max_results(max_results);
         }
 
-        Ok(object_ids)
+        let Some(response) = request
+            .into_stream()
+            .try_next()
+            .await
+            .change_to_object_store_context()?
+        else {
+            return Ok((Vec::new(), None));
+        };
+
+        let objects = response
+            .blobs
+            .blobs()
+            .map(|blob| ObjectMetadata {
+                object_id: blob.name.to_string(),
+                size: blob.properties.content_length,
+                last_modified: Some(blob.properties.last_modified.into()),
+            })
+            .collect();
+
+        let continuation_token = response
+            .next_marker
+            .map(|marker| marker.as_str().to_string());
+
+        Ok((objects, continuation_token))
     }
 
     pub async fn delete_object(
@@ -2,14 +2,15 @@ use bytes::Bytes;
 use error_stack::Result;
 
 use super::{
-    azure_blob::AzureBlobClient, AwsS3Client, DeleteOptions, GetOptions, ObjectETag,
-    ObjectStoreError, PutOptions,
+    azure_blob::AzureBlobClient, memory::MemoryClient, AwsS3Client, DeleteOptions, GetOptions,
+    ListOptions, ObjectETag, ObjectMetadata, ObjectStoreError, PutOptions,
 };
 
 #[derive(Clone)]
 pub enum ObjectStoreClient {
     AwsS3(AwsS3Client),
     AzureBlob(Box<AzureBlobClient>),
+    Memory(MemoryClient),
 }
 
 impl ObjectStoreClient {
@@ -17,6 +18,7 @@ impl ObjectStoreClient {
         match self {
             Self::AwsS3(client) => client.has_bucket(name).await,
             Self::AzureBlob(client) => client.has_bucket(name).await,
+            Self::Memory(client) => client.has_bucket(name).await,
         }
     }
 
@@ -24,6 +26,7 @@ impl ObjectStoreClient {
         match self {
             Self::AwsS3(client) => client.create_bucket(name).await,
             Self::AzureBlob(client) => client.create_bucket(name).await,
+            Self::Memory(client) => client.create_bucket(name).await,
         }
     }
 
@@ -36,6 +39,7 @@ impl ObjectStoreClient {
         match self {
             Self::AwsS3(client) => client.get_object(bucket, key, options).await,
             Self::AzureBlob(client) => client.get_object(bucket, key, options).await,
+            Self::Memory(client) => client.get_object(bucket, key, options).await,
         }
     }
 
@@ -49,6 +53,7 @@ impl ObjectStoreClient {
         match self {
             Self::AwsS3(client) => client.put_object(bucket, key, body, options).await,
             Self::AzureBlob(client) => client.put_object(bucket, key, body, options).await,
+            Self::Memory(client) => client.put_object(bucket, key, body, options).await,
         }
     }
 
@@ -56,10 +61,12 @@ impl ObjectStoreClient {
         &self,
         bucket: &str,
         prefix: &str,
-    ) -> Result<Vec<String>, ObjectStoreError> {
+        options: ListOptions,
+    ) -> Result<(Vec<ObjectMetadata>, Option<String>), ObjectStoreError> {
         match self {
-            Self::AwsS3(client) => client.list_objects(bucket, prefix).await,
-            Self::AzureBlob(client) => client.list_objects(bucket, prefix).await,
+            Self::AwsS3(client) => client.list_objects(bucket, prefix, options).await,
+            Self::AzureBlob(client) => client.list_objects(bucket, prefix, options).await,
+            Self::Memory(client) => client.list_objects(bucket, prefix, options).await,
         }
     }
 
@@ -72,6 +79,7 @@ impl ObjectStoreClient {
         match self {
             Self::AwsS3(client) => client.delete_object(bucket, key, _options).await,
             Self::AzureBlob(client) => client.delete_object(bucket, key, _options).await,
+            Self::Memory(client) => client.delete_object(bucket, key, _options).await,
         }
     }
 }
@@ -87,3 +95,9 @@ impl From<AzureBlobClient> for ObjectStoreClient {
         Self::AzureBlob(client.into())
     }
 }
+
+impl From<MemoryClient> for ObjectStoreClient {
+    fn from(client: MemoryClient) -> Self {
+        Self::Memory(client)
+    }
+}
@@ -0,0 +1,159 @@
+//! An object store that keeps all objects in memory.
+//!
+//! This client is meant for tests and local development. It mirrors the S3 semantics used by
+//! DNA: conditional puts, etags and paginated listings with at most 1000 keys per page.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use error_stack::Result;
+
+use super::{
+    error::ObjectStoreError, DeleteOptions, GetOptions, ListOptions, ObjectETag, ObjectMetadata,
+    PutMode, PutOptions,
+};
+
+/// The maximum number of keys returned by a single list request, like S3.
+const MAX_KEYS_PER_PAGE: u32 = 1_000;
+
+#[derive(Clone, Default)]
+pub struct MemoryClient {
+    inner: Arc<Mutex<MemoryClientInner>>,
+}
+
+#[derive(Default)]
+struct MemoryClientInner {
+    buckets: BTreeSet<String>,
+    objects: BTreeMap<(String, String), MemoryObject>,
+    version: u64,
+}
+
+struct MemoryObject {
+    body: Bytes,
+    etag: ObjectETag,
+    last_modified: SystemTime,
+}
+
+impl MemoryClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn has_bucket(&self, name: &str) -> Result<bool, ObjectStoreError> {
+        let inner = self.inner.lock().expect("memory client lock poisoned");
+        Ok(inner.buckets.contains(name))
+    }
+
+    pub async fn create_bucket(&self, name: &str) -> Result<(), ObjectStoreError> {
+        let mut inner = self.inner.lock().expect("memory client lock poisoned");
+        inner.buckets.insert(name.to_string());
+        Ok(())
+    }
+
+    pub async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        options: GetOptions,
+    ) -> Result<(ObjectETag, Bytes), ObjectStoreError> {
+        let inner = self.inner.lock().expect("memory client lock poisoned");
+
+        let Some(object) = inner.objects.get(&(bucket.to_string(), key.to_string())) else {
+            return Err(ObjectStoreError::NotFound.into());
+        };
+
+        if let Some(etag) = options.etag {
+            if etag != object.etag {
+                return Err(ObjectStoreError::Precondition.into());
+            }
+        }
+
+        Ok((object.etag.clone(), object.body.clone()))
+    }
+
+    pub async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: Bytes,
+        options: PutOptions,
+    ) -> Result<ObjectETag, ObjectStoreError> {
+        let mut inner = self.inner.lock().expect("memory client lock poisoned");
+        let object_key = (bucket.to_string(), key.to_string());
+
+        match (&options.mode, inner.objects.get(&object_key)) {
+            (PutMode::Overwrite, _) => {}
+            (PutMode::Create, None) => {}
+            (PutMode::Update(etag), Some(existing)) if *etag == existing.etag => {}
+            _ => return Err(ObjectStoreError::Precondition.into()),
+        }
+
+        inner.version += 1;
+        let etag = ObjectETag(format!("\"{}\"", inner.version));
+
+        inner.objects.insert(
+            object_key,
+            MemoryObject {
+                body,
+                etag: etag.clone(),
+                last_modified: SystemTime::now(),
+            },
+        );
+
+        Ok(etag)
+    }
+
+    pub async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        options: ListOptions,
+    ) -> Result<(Vec<ObjectMetadata>, Option<String>), ObjectStoreError> {
+        let inner = self.inner.lock().expect("memory client lock poisoned");
+
+        let max_keys = options
+            .max_keys
+            .unwrap_or(MAX_KEYS_PER_PAGE)
+            .clamp(1, MAX_KEYS_PER_PAGE) as usize;
+
+        // The continuation token is the last key of the previous page.
+        let start = options.continuation_token.unwrap_or_default();
+
+        let mut matching = inner
+            .objects
+            .range((bucket.to_string(), prefix.to_string())..)
+            .take_while(|((object_bucket, key), _)| {
+                object_bucket == bucket && key.starts_with(prefix)
+            })
+            .filter(|((_, key), _)| key.as_str() > start.as_str())
+            .map(|((_, key), object)| ObjectMetadata {
+                object_id: key.clone(),
+                size: object.body.len() as u64,
+                last_modified: Some(object.last_modified),
+            });
+
+        let objects = matching.by_ref().take(max_keys).collect::<Vec<_>>();
+
+        let continuation_token = if matching.next().is_some() {
+            objects.last().map(|object| object.object_id.clone())
+        } else {
+            None
+        };
+
+        Ok((objects, continuation_token))
+    }
+
+    pub async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        _options: DeleteOptions,
+    ) -> Result<(), ObjectStoreError> {
+        let mut inner = self.inner.lock().expect("memory client lock poisoned");
+        inner.objects.remove(&(bucket.to_string(), key.to_string()));
+        Ok(())
+    }
+}
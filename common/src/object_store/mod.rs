@@ -1,3 +1,5 @@
+use std::time::SystemTime;
+
 use apibara_etcd::normalize_prefix;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use error_stack::{Result, ResultExt};
@@ -7,6 +9,7 @@ mod aws_s3;
 mod azure_blob;
 mod client;
 mod error;
+mod memory;
 mod metrics;
 pub mod testing;
 
@@ -14,6 +17,7 @@ pub use self::aws_s3::AwsS3Client;
 pub use self::azure_blob::AzureBlobClient;
 pub use self::client::ObjectStoreClient;
 pub use self::error::{ObjectStoreError, ObjectStoreResultExt, ToObjectStoreResult};
+pub use self::memory::MemoryClient;
 
 /// Options for the object store.
 #[derive(Default, Clone, Debug)]
@@ -65,7 +69,12 @@ pub struct DeleteOptions {
 }
 
 #[derive(Default, Clone, Debug)]
-pub struct ListOptions {}
+pub struct ListOptions {
+    /// Continue listing from the page returned by a previous request.
+    pub continuation_token: Option<String>,
+    /// The maximum number of objects returned by a single request.
+    pub max_keys: Option<u32>,
+}
 
 #[derive(Debug)]
 pub struct GetResult {
@@ -84,6 +93,18 @@ pub struct DeleteResult;
 #[derive(Debug)]
 pub struct ListResult {
     pub object_ids: Vec<String>,
+    pub objects: Vec<ObjectMetadata>,
+    /// Token to fetch the next page, if there are more objects.
+    pub continuation_token: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectMetadata {
+    /// The object id, including the store prefix.
+    pub object_id: String,
+    /// The size of the stored (compressed) object.
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
 }
 
 impl ObjectStore {
@@ -105,6 +126,11 @@ impl ObjectStore {
         Self::new(client.into(), options)
     }
 
+    /// Create an object store that keeps all objects in memory.
+    pub fn new_memory(client: MemoryClient, options: ObjectStoreOptions) -> Self {
+        Self::new(client.into(), options)
+    }
+
     /// Ensure the currently configured bucket exists.
     pub async fn ensure_bucket(&self) -> Result<(), ObjectStoreError> {
         if self.client.has_bucket(&self.bucket).await? {
@@ -218,21 +244,73 @@ impl ObjectStore {
         Ok(DeleteResult)
     }
 
+    /// List all objects with the given prefix, following continuation tokens.
     #[tracing::instrument(name = "object_store_list", skip(self), level = "debug")]
     pub async fn list(
         &self,
         prefix: &str,
-        _options: ListOptions,
+        options: ListOptions,
+    ) -> Result<ListResult, ObjectStoreError> {
+        let mut options = options;
+        let mut objects = Vec::new();
+
+        loop {
+            let page = self.list_page(prefix, options.clone()).await?;
+            objects.extend(page.objects);
+
+            match page.continuation_token {
+                None => break,
+                token => options.continuation_token = token,
+            }
+        }
+
+        let object_ids = objects
+            .iter()
+            .map(|object| object.object_id.clone())
+            .collect();
+
+        Ok(ListResult {
+            object_ids,
+            objects,
+            continuation_token: None,
+        })
+    }
+
+    /// List a single page of objects with the given prefix.
+    #[tracing::instrument(name = "object_store_list_page", skip(self), level = "debug")]
+    pub async fn list_page(
+        &self,
+        prefix: &str,
+        options: ListOptions,
     ) -> Result<ListResult, ObjectStoreError> {
         let key = self.full_key(prefix);
-        let object_ids = self
+        let (objects, continuation_token) = self
             .client
-            .list_objects(&self.bucket, &key)
+            .list_objects(&self.bucket, &key, options)
             .await
             .attach_printable("failed to list objects")
             .attach_printable_lazy(|| format!("prefix: {key}"))?;
 
-        Ok(ListResult { object_ids })
+        let object_ids = objects
+            .iter()
+            .map(|object| object.object_id.clone())
+            .collect();
+
+        Ok(ListResult {
+            object_ids,
+            objects,
+            continuation_token,
+        })
+    }
+
+    /// Returns the prefix under which objects are stored, ending with `/` if not empty.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Returns the path of an object listed with [ObjectStore::list], without the store prefix.
+    pub fn relative_path<'a>(&self, object_id: &'a str) -> &'a str {
+        object_id.strip_prefix(&self.prefix).unwrap_or(object_id)
     }
 
     fn full_key(&self, path: &str) -> String {
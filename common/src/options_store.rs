@@ -5,6 +5,10 @@ pub static OPTIONS_PREFIX_KEY: &str = "options/";
 pub static CHAIN_SEGMENT_SIZE_KEY: &str = "options/chain_segment_size";
 pub static SEGMENT_SIZE_KEY: &str = "options/segment_size";
 pub static GROUP_SIZE_KEY: &str = "options/group_size";
+/// The object store prefix that holds the data referenced by the ingestion state.
+///
+/// Only set once the data was moved to a different prefix, for example by re-compaction.
+pub static OBJECT_STORE_PREFIX_KEY: &str = "options/object_store_prefix";
 
 #[derive(Debug)]
 pub struct OptionsStoreError;
@@ -56,6 +60,35 @@ impl OptionsStore {
             .attach_printable("failed to get group size")
     }
 
+    pub async fn set_object_store_prefix(&mut self, prefix: &str) -> Result<(), OptionsStoreError> {
+        self.client
+            .put(OBJECT_STORE_PREFIX_KEY, prefix.as_bytes())
+            .await
+            .change_context(OptionsStoreError)
+            .attach_printable("failed to set object store prefix")?;
+
+        Ok(())
+    }
+
+    pub async fn get_object_store_prefix(&mut self) -> Result<Option<String>, OptionsStoreError> {
+        let response = self
+            .client
+            .get(OBJECT_STORE_PREFIX_KEY)
+            .await
+            .change_context(OptionsStoreError)
+            .attach_printable("failed to get object store prefix")?;
+
+        let Some(kv) = response.kvs().first() else {
+            return Ok(None);
+        };
+
+        let prefix = String::from_utf8(kv.value().to_vec())
+            .change_context(OptionsStoreError)
+            .attach_printable("failed to decode object store prefix")?;
+
+        Ok(prefix.into())
+    }
+
     async fn set_usize(&mut self, key: &str, size: usize) -> Result<(), OptionsStoreError> {
         let size = size.to_string();
         self.client
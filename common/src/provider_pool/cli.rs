@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use url::Url;
+
+use super::{ProviderPoolError, ProviderPoolOptions, UpstreamConfig};
+
+#[derive(Args, Clone, Debug)]
+pub struct ProviderPoolArgs {
+    /// Weight of each RPC upstream, in the same order as the RPC URLs.
+    ///
+    /// Requests are spread over the healthy upstreams in proportion to their weight.
+    /// Upstreams without a weight default to 1.
+    #[arg(long = "rpc.weight", env = "DNA_RPC_WEIGHT", value_delimiter = ',')]
+    pub rpc_weight: Vec<u32>,
+
+    /// Send a second request to another upstream if the first one doesn't respond within this
+    /// delay, for example "500ms". Disabled by default.
+    #[arg(long = "rpc.hedge-delay", env = "DNA_RPC_HEDGE_DELAY")]
+    pub rpc_hedge_delay: Option<String>,
+
+    /// How many blocks an upstream can lag behind the others before it's considered unhealthy.
+    #[arg(
+        long = "rpc.max-head-lag",
+        env = "DNA_RPC_MAX_HEAD_LAG",
+        default_value = "10"
+    )]
+    pub rpc_max_head_lag: u64,
+
+    /// Fraction of failed requests after which an upstream is considered unhealthy.
+    #[arg(
+        long = "rpc.max-error-rate",
+        env = "DNA_RPC_MAX_ERROR_RATE",
+        default_value = "0.5"
+    )]
+    pub rpc_max_error_rate: f64,
+
+    /// How long an unhealthy upstream is avoided, for example "30s".
+    #[arg(
+        long = "rpc.unhealthy-cooldown",
+        env = "DNA_RPC_UNHEALTHY_COOLDOWN",
+        default_value = "30s"
+    )]
+    pub rpc_unhealthy_cooldown: String,
+}
+
+impl ProviderPoolArgs {
+    /// Parse the RPC URLs and pair them with their weight.
+    pub fn to_upstreams(&self, urls: &[String]) -> Result<Vec<UpstreamConfig>, ProviderPoolError> {
+        if self.rpc_weight.len() > urls.len() {
+            return Err(ProviderPoolError)
+                .attach_printable("more rpc weights than rpc urls")
+                .attach_printable_lazy(|| format!("weights: {}", self.rpc_weight.len()))
+                .attach_printable_lazy(|| format!("urls: {}", urls.len()));
+        }
+
+        let mut upstreams = Vec::with_capacity(urls.len());
+        let mut name_count = HashMap::<String, usize>::new();
+
+        for (index, url) in urls.iter().enumerate() {
+            let parsed = url
+                .parse::<Url>()
+                .change_context(ProviderPoolError)
+                .attach_printable("failed to parse RPC URL")
+                .attach_printable_lazy(|| format!("url: {}", url))?;
+
+            let weight = self.rpc_weight.get(index).copied().unwrap_or(1);
+            let mut upstream = UpstreamConfig::new(parsed, weight);
+
+            // Keep names unique when using multiple endpoints from the same host.
+            let count = name_count.entry(upstream.name.clone()).or_default();
+            if *count > 0 {
+                upstream.name = format!("{}#{}", upstream.name, count);
+            }
+            *count += 1;
+
+            upstreams.push(upstream);
+        }
+
+        Ok(upstreams)
+    }
+
+    pub fn to_provider_pool_options(&self) -> Result<ProviderPoolOptions, ProviderPoolError> {
+        let hedge_delay = self
+            .rpc_hedge_delay
+            .as_ref()
+            .map(|delay| {
+                duration_str::parse_std(delay).or_else(|err| {
+                    Err(ProviderPoolError)
+                        .attach_printable("failed to parse rpc hedge delay")
+                        .attach_printable(format!("error: {}", err))
+                })
+            })
+            .transpose()?;
+
+        let unhealthy_cooldown =
+            duration_str::parse_std(&self.rpc_unhealthy_cooldown).or_else(|err| {
+                Err(ProviderPoolError)
+                    .attach_printable("failed to parse rpc unhealthy cooldown")
+                    .attach_printable(format!("error: {}", err))
+            })?;
+
+        Ok(ProviderPoolOptions {
+            hedge_delay,
+            max_head_lag: self.rpc_max_head_lag,
+            max_error_rate: self.rpc_max_error_rate,
+            unhealthy_cooldown,
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Weight of the most recent sample in the moving averages.
+const EWMA_ALPHA: f64 = 0.2;
+
+/// Tracks the health of one upstream.
+#[derive(Debug, Default)]
+pub struct UpstreamHealth(Mutex<HealthState>);
+
+#[derive(Debug, Default)]
+struct HealthState {
+    /// Moving average of the request latency, in seconds.
+    latency: Option<f64>,
+    /// Moving average of the fraction of failed requests.
+    error_rate: f64,
+    /// Most recent head reported by the upstream.
+    head: Option<u64>,
+    /// The upstream is not used until this instant.
+    unhealthy_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HealthSnapshot {
+    pub latency: Option<f64>,
+    pub error_rate: f64,
+    pub head: Option<u64>,
+    pub cooling_down: bool,
+}
+
+impl UpstreamHealth {
+    pub fn record_success(&self, latency: Duration) {
+        let mut state = self.0.lock().expect("upstream health lock poisoned");
+        state.record_latency(latency);
+        state.error_rate *= 1.0 - EWMA_ALPHA;
+    }
+
+    /// Record a failed request.
+    ///
+    /// Returns true if the upstream just became unhealthy.
+    pub fn record_failure(
+        &self,
+        latency: Duration,
+        max_error_rate: f64,
+        cooldown: Duration,
+    ) -> bool {
+        let mut state = self.0.lock().expect("upstream health lock poisoned");
+        state.record_latency(latency);
+        state.error_rate = state.error_rate * (1.0 - EWMA_ALPHA) + EWMA_ALPHA;
+
+        let now = Instant::now();
+        let cooling_down = state.unhealthy_until.is_some_and(|until| until > now);
+
+        if state.error_rate > max_error_rate && !cooling_down {
+            state.unhealthy_until = Some(now + cooldown);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn record_head(&self, head: u64) {
+        let mut state = self.0.lock().expect("upstream health lock poisoned");
+        state.head = Some(head);
+    }
+
+    pub fn snapshot(&self) -> HealthSnapshot {
+        let state = self.0.lock().expect("upstream health lock poisoned");
+        HealthSnapshot {
+            latency: state.latency,
+            error_rate: state.error_rate,
+            head: state.head,
+            cooling_down: state
+                .unhealthy_until
+                .is_some_and(|until| until > Instant::now()),
+        }
+    }
+}
+
+impl HealthState {
+    fn record_latency(&mut self, latency: Duration) {
+        let latency = latency.as_secs_f64();
+        self.latency = Some(match self.latency {
+            None => latency,
+            Some(average) => average * (1.0 - EWMA_ALPHA) + latency * EWMA_ALPHA,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::UpstreamHealth;
+
+    #[test]
+    fn test_upstream_becomes_unhealthy_after_failures() {
+        let health = UpstreamHealth::default();
+        let latency = Duration::from_millis(10);
+        let cooldown = Duration::from_secs(60);
+
+        health.record_success(latency);
+        assert!(!health.record_failure(latency, 0.5, cooldown));
+        assert!(!health.record_failure(latency, 0.5, cooldown));
+        assert!(!health.record_failure(latency, 0.5, cooldown));
+        assert!(health.record_failure(latency, 0.5, cooldown));
+        assert!(health.snapshot().cooling_down);
+
+        // Already cooling down.
+        assert!(!health.record_failure(latency, 0.5, cooldown));
+    }
+}
@@ -0,0 +1,53 @@
+use apibara_observability::{Counter, Gauge, Histogram};
+
+#[derive(Debug, Clone)]
+pub struct ProviderPoolMetrics {
+    pub requests: Counter<u64>,
+    pub latency: Histogram<f64>,
+    pub failover: Counter<u64>,
+    pub hedged: Counter<u64>,
+    pub healthy: Gauge<u64>,
+    pub head: Gauge<u64>,
+}
+
+impl Default for ProviderPoolMetrics {
+    fn default() -> Self {
+        let meter = apibara_observability::meter("dna_rpc");
+
+        Self {
+            requests: meter
+                .u64_counter("dna.rpc.upstream.requests")
+                .with_description("number of requests sent to the upstream")
+                .with_unit("{request}")
+                .build(),
+            latency: meter
+                .f64_histogram("dna.rpc.upstream.latency")
+                .with_description("upstream request latency")
+                .with_unit("s")
+                .with_boundaries(vec![
+                    0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 7.5,
+                    10.0, 20.0, 30.0,
+                ])
+                .build(),
+            failover: meter
+                .u64_counter("dna.rpc.failover")
+                .with_description("number of requests retried on another upstream")
+                .with_unit("{request}")
+                .build(),
+            hedged: meter
+                .u64_counter("dna.rpc.hedged")
+                .with_description("number of hedged requests sent to another upstream")
+                .with_unit("{request}")
+                .build(),
+            healthy: meter
+                .u64_gauge("dna.rpc.upstream.healthy")
+                .with_description("upstream is healthy. 1 = healthy, 0 = unhealthy")
+                .build(),
+            head: meter
+                .u64_gauge("dna.rpc.upstream.head")
+                .with_description("head block reported by the upstream")
+                .with_unit("{block}")
+                .build(),
+        }
+    }
+}
@@ -0,0 +1,954 @@
+//! Spread RPC requests over multiple upstreams.
+//!
+//! Requests go to a healthy upstream picked at random, weighted by its configured weight, latency
+//! and error rate, and fail over to the next one on error. Upstreams that fail too often, or whose
+//! head lags behind the others, are moved to the back of the queue for a while.
+mod cli;
+mod health;
+mod metrics;
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use apibara_observability::KeyValue;
+use error_stack::{Result, ResultExt};
+use futures::{stream::FuturesUnordered, StreamExt};
+use rand::{Rng, RngExt};
+use tracing::{debug, warn};
+use url::Url;
+
+pub use self::cli::ProviderPoolArgs;
+pub use self::health::HealthSnapshot;
+pub use self::metrics::ProviderPoolMetrics;
+
+use self::health::UpstreamHealth;
+
+#[derive(Debug)]
+pub struct ProviderPoolError;
+
+/// Classify provider errors.
+pub trait UpstreamError: error_stack::Context {
+    /// Returns true if the error is caused by the upstream, for example a timeout.
+    ///
+    /// Other errors, like a block not being found, don't count against the upstream health.
+    fn is_upstream_failure(&self) -> bool;
+}
+
+#[derive(Debug, Clone)]
+pub struct ProviderPoolOptions {
+    /// Send the request to a second upstream if the first doesn't respond within this delay.
+    pub hedge_delay: Option<Duration>,
+    /// How many blocks an upstream can lag behind the others.
+    pub max_head_lag: u64,
+    /// Fraction of failed requests after which an upstream is considered unhealthy.
+    pub max_error_rate: f64,
+    /// How long an unhealthy upstream is moved to the back of the queue.
+    pub unhealthy_cooldown: Duration,
+}
+
+/// An upstream URL, as configured by the user.
+#[derive(Debug, Clone)]
+pub struct UpstreamConfig {
+    /// Name used in logs and metrics. Doesn't contain the URL path, which may contain secrets.
+    pub name: String,
+    pub url: Url,
+    pub weight: u32,
+}
+
+/// The kind of chain-wide read to check for consistency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsistentRead {
+    Head,
+    Finalized,
+}
+
+struct Upstream<P> {
+    name: String,
+    weight: u32,
+    provider: P,
+    health: UpstreamHealth,
+    /// Last health reported to the metrics.
+    healthy: AtomicBool,
+}
+
+#[derive(Clone)]
+pub struct ProviderPool<P> {
+    upstreams: Arc<Vec<Upstream<P>>>,
+    options: ProviderPoolOptions,
+    metrics: ProviderPoolMetrics,
+}
+
+impl<P> ProviderPool<P>
+where
+    P: Clone,
+{
+    /// Create a new pool from the given `(config, provider)` pairs.
+    pub fn new(
+        upstreams: Vec<(UpstreamConfig, P)>,
+        options: ProviderPoolOptions,
+    ) -> Result<Self, ProviderPoolError> {
+        if upstreams.is_empty() {
+            return Err(ProviderPoolError).attach_printable("no rpc upstream configured");
+        }
+
+        let metrics = ProviderPoolMetrics::default();
+
+        let upstreams = upstreams
+            .into_iter()
+            .map(|(config, provider)| {
+                metrics
+                    .healthy
+                    .record(1, &[KeyValue::new("upstream", config.name.clone())]);
+
+                Upstream {
+                    name: config.name,
+                    weight: u32::max(config.weight, 1),
+                    provider,
+                    health: UpstreamHealth::default(),
+                    healthy: AtomicBool::new(true),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            upstreams: Arc::new(upstreams),
+            options,
+            metrics,
+        })
+    }
+
+    /// Returns the health of each upstream, by name.
+    pub fn health(&self) -> Vec<(String, HealthSnapshot)> {
+        self.upstreams
+            .iter()
+            .map(|upstream| (upstream.name.clone(), upstream.health.snapshot()))
+            .collect()
+    }
+
+    /// Send the request to a weighted random healthy upstream, failing over to the others on error.
+    pub async fn request<T, E, F, Fut>(&self, method: &'static str, f: F) -> Result<T, E>
+    where
+        E: UpstreamError,
+        F: Fn(P) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut last_error = None;
+
+        for (attempt, index) in self.ranked().into_iter().enumerate() {
+            if attempt > 0 {
+                self.metrics
+                    .failover
+                    .add(1, &[KeyValue::new("method", method)]);
+            }
+
+            match self.call(index, method, &f).await {
+                Ok(response) => return Ok(response),
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        Err(last_error.expect("pool has at least one upstream"))
+    }
+
+    /// Like `request`, but also send the request to the next upstream if the first one is slow.
+    pub async fn hedged_request<T, E, F, Fut>(&self, method: &'static str, f: F) -> Result<T, E>
+    where
+        E: UpstreamError,
+        F: Fn(P) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let Some(hedge_delay) = self.options.hedge_delay else {
+            return self.request(method, f).await;
+        };
+
+        let mut ranked = self.ranked().into_iter().peekable();
+        let mut in_flight = FuturesUnordered::new();
+        let mut last_error = None;
+
+        if let Some(index) = ranked.next() {
+            in_flight.push(self.call(index, method, &f));
+        }
+
+        while !in_flight.is_empty() {
+            let can_hedge = ranked.peek().is_some();
+
+            tokio::select! {
+                Some(result) = in_flight.next() => {
+                    match result {
+                        Ok(response) => return Ok(response),
+                        Err(err) => {
+                            last_error = Some(err);
+
+                            if let Some(index) = ranked.next() {
+                                self.metrics
+                                    .failover
+                                    .add(1, &[KeyValue::new("method", method)]);
+                                in_flight.push(self.call(index, method, &f));
+                            }
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(hedge_delay), if can_hedge => {
+                    if let Some(index) = ranked.next() {
+                        self.metrics
+                            .hedged
+                            .add(1, &[KeyValue::new("method", method)]);
+                        in_flight.push(self.call(index, method, &f));
+                    }
+                }
+            }
+        }
+
+        Err(last_error.expect("pool has at least one upstream"))
+    }
+
+    /// Send the request to all healthy upstreams and return the most conservative response.
+    ///
+    /// Heads reported by the upstreams are used to detect upstreams lagging behind. The
+    /// response with the lowest block number among the up-to-date upstreams is returned, so
+    /// that all of them can serve the blocks up to it.
+    pub async fn request_consistent<T, E, F, Fut>(
+        &self,
+        read: ConsistentRead,
+        method: &'static str,
+        f: F,
+        block_number: impl Fn(&T) -> u64,
+    ) -> Result<T, E>
+    where
+        E: UpstreamError,
+        F: Fn(P) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if self.upstreams.len() == 1 {
+            let response = self.call(0, method, &f).await?;
+            if read == ConsistentRead::Head {
+                self.record_head(0, block_number(&response));
+            }
+            return Ok(response);
+        }
+
+        let mut candidates = self.ranked_healthy();
+        if candidates.is_empty() {
+            candidates = self.ranked();
+        }
+
+        let results = futures::future::join_all(
+            candidates
+                .iter()
+                .map(|index| async { (*index, self.call(*index, method, &f).await) }),
+        )
+        .await;
+
+        let mut responses = Vec::with_capacity(results.len());
+        let mut last_error = None;
+
+        for (index, result) in results {
+            match result {
+                Ok(response) => {
+                    let number = block_number(&response);
+                    if read == ConsistentRead::Head {
+                        self.record_head(index, number);
+                    }
+                    responses.push((index, number, response));
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        let Some(max_number) = responses.iter().map(|(_, number, _)| *number).max() else {
+            return Err(last_error.expect("pool has at least one upstream"));
+        };
+
+        if read == ConsistentRead::Head {
+            responses.retain(|(index, number, _)| {
+                let lagging = max_number - number > self.options.max_head_lag;
+                if lagging {
+                    warn!(
+                        upstream = self.upstreams[*index].name,
+                        head = number,
+                        max_head = max_number,
+                        "rpc upstream is lagging behind"
+                    );
+                }
+                !lagging
+            });
+        }
+
+        let (index, number, response) = responses
+            .into_iter()
+            .min_by_key(|(_, number, _)| *number)
+            .expect("the upstream with the highest block is never lagging");
+
+        if number != max_number {
+            debug!(
+                upstream = self.upstreams[index].name,
+                block_number = number,
+                max_block_number = max_number,
+                read = ?read,
+                "rpc upstreams disagree"
+            );
+        }
+
+        Ok(response)
+    }
+
+    async fn call<T, E, F, Fut>(&self, index: usize, method: &'static str, f: &F) -> Result<T, E>
+    where
+        E: UpstreamError,
+        F: Fn(P) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let upstream = &self.upstreams[index];
+
+        let start = Instant::now();
+        let result = f(upstream.provider.clone()).await;
+        let elapsed = start.elapsed();
+
+        let status = match &result {
+            Err(err) if err.current_context().is_upstream_failure() => {
+                let became_unhealthy = upstream.health.record_failure(
+                    elapsed,
+                    self.options.max_error_rate,
+                    self.options.unhealthy_cooldown,
+                );
+
+                if became_unhealthy {
+                    warn!(
+                        upstream = upstream.name,
+                        cooldown = ?self.options.unhealthy_cooldown,
+                        "rpc upstream is unhealthy"
+                    );
+                    self.update_healthy(index, false);
+                }
+
+                "error"
+            }
+            _ => {
+                upstream.health.record_success(elapsed);
+                "ok"
+            }
+        };
+
+        let attributes = [
+            KeyValue::new("upstream", upstream.name.clone()),
+            KeyValue::new("method", method),
+            KeyValue::new("status", status),
+        ];
+
+        self.metrics.requests.add(1, &attributes);
+        self.metrics
+            .latency
+            .record(elapsed.as_secs_f64(), &attributes);
+
+        result
+    }
+
+    fn record_head(&self, index: usize, head: u64) {
+        let upstream = &self.upstreams[index];
+        upstream.health.record_head(head);
+        self.metrics
+            .head
+            .record(head, &[KeyValue::new("upstream", upstream.name.clone())]);
+    }
+
+    /// Returns the upstreams indices, in the order they should be tried.
+    ///
+    /// Healthy upstreams come first. Within each group, upstreams are shuffled so that each one
+    /// is picked first with probability proportional to its weight, divided by its relative
+    /// latency and error rate.
+    fn ranked(&self) -> Vec<usize> {
+        self.ranked_with(&mut rand::rng())
+    }
+
+    fn ranked_with(&self, rng: &mut impl Rng) -> Vec<usize> {
+        let snapshots = self
+            .upstreams
+            .iter()
+            .map(|upstream| upstream.health.snapshot())
+            .collect::<Vec<_>>();
+
+        let max_head = snapshots.iter().filter_map(|snapshot| snapshot.head).max();
+
+        let healthy = snapshots
+            .iter()
+            .map(|snapshot| self.is_healthy(snapshot, max_head))
+            .collect::<Vec<_>>();
+
+        for (index, healthy) in healthy.iter().enumerate() {
+            self.update_healthy(index, *healthy);
+        }
+
+        let min_latency = snapshots
+            .iter()
+            .filter_map(|snapshot| snapshot.latency)
+            .filter(|latency| *latency > 0.0)
+            .reduce(f64::min);
+
+        let selection_weight = |index: usize| {
+            let snapshot = &snapshots[index];
+            // Upstreams without samples are treated like the fastest one.
+            let relative_latency = match (snapshot.latency, min_latency) {
+                (Some(latency), Some(min_latency)) => f64::max(latency / min_latency, 1.0),
+                _ => 1.0,
+            };
+            self.upstreams[index].weight as f64 / (relative_latency * (1.0 + snapshot.error_rate))
+        };
+
+        // Weighted sampling without replacement: sorting by `u^(1/w)`, with `u` uniform in
+        // `[0, 1)`, puts each upstream first with probability proportional to its weight `w`.
+        let keys = (0..self.upstreams.len())
+            .map(|index| rng.random::<f64>().powf(1.0 / selection_weight(index)))
+            .collect::<Vec<_>>();
+
+        let mut indices = (0..self.upstreams.len()).collect::<Vec<_>>();
+        indices.sort_by(|a, b| {
+            healthy[*b]
+                .cmp(&healthy[*a])
+                .then_with(|| keys[*b].total_cmp(&keys[*a]))
+        });
+
+        indices
+    }
+
+    /// Update the upstream health gauge if the upstream health changed.
+    ///
+    /// Returns true if the health changed.
+    fn update_healthy(&self, index: usize, healthy: bool) -> bool {
+        let upstream = &self.upstreams[index];
+        if upstream.healthy.swap(healthy, Ordering::Relaxed) == healthy {
+            return false;
+        }
+
+        self.metrics.healthy.record(
+            healthy as u64,
+            &[KeyValue::new("upstream", upstream.name.clone())],
+        );
+
+        true
+    }
+
+    fn ranked_healthy(&self) -> Vec<usize> {
+        let max_head = self
+            .upstreams
+            .iter()
+            .filter_map(|upstream| upstream.health.snapshot().head)
+            .max();
+
+        self.ranked()
+            .into_iter()
+            .filter(|index| {
+                let snapshot = self.upstreams[*index].health.snapshot();
+                self.is_healthy(&snapshot, max_head)
+            })
+            .collect()
+    }
+
+    fn is_healthy(&self, snapshot: &HealthSnapshot, max_head: Option<u64>) -> bool {
+        if snapshot.cooling_down {
+            return false;
+        }
+
+        match (max_head, snapshot.head) {
+            (Some(max_head), Some(head)) => max_head - head <= self.options.max_head_lag,
+            _ => true,
+        }
+    }
+}
+
+impl UpstreamConfig {
+    /// Create a new upstream config, naming it after the URL host.
+    pub fn new(url: Url, weight: u32) -> Self {
+        let name = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            (None, _) => url.scheme().to_string(),
+        };
+
+        Self { name, url, weight }
+    }
+}
+
+impl Default for ProviderPoolOptions {
+    fn default() -> Self {
+        Self {
+            hedge_delay: None,
+            max_head_lag: 10,
+            max_error_rate: 0.5,
+            unhealthy_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl error_stack::Context for ProviderPoolError {}
+
+impl std::fmt::Display for ProviderPoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rpc provider pool error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use error_stack::{Result, ResultExt};
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use url::Url;
+
+    use super::{ConsistentRead, ProviderPool, ProviderPoolOptions, UpstreamConfig, UpstreamError};
+
+    #[derive(Debug)]
+    enum TestError {
+        /// The request timed out or the server returned a 5xx status.
+        Upstream,
+        /// The server returned a 4xx status, for example for a missing block.
+        Request,
+    }
+
+    impl error_stack::Context for TestError {}
+
+    impl std::fmt::Display for TestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                TestError::Upstream => write!(f, "upstream error"),
+                TestError::Request => write!(f, "request error"),
+            }
+        }
+    }
+
+    impl UpstreamError for TestError {
+        fn is_upstream_failure(&self) -> bool {
+            matches!(self, TestError::Upstream)
+        }
+    }
+
+    /// How a mock server answers requests.
+    #[derive(Debug, Clone, Copy)]
+    struct MockResponse {
+        head: u64,
+        delay: Duration,
+        status: u16,
+    }
+
+    /// An HTTP server that answers every request with the configured head.
+    struct MockServer {
+        url: Url,
+        response: Arc<Mutex<MockResponse>>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    /// Fetches the head from a mock server over HTTP.
+    #[derive(Clone)]
+    struct HttpUpstream {
+        client: reqwest::Client,
+        url: Url,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct HeadResponse {
+        id: u64,
+        head: u64,
+    }
+
+    impl MockServer {
+        async fn start(id: u64) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap())
+                .parse()
+                .unwrap();
+
+            let response = Arc::new(Mutex::new(MockResponse {
+                head: 100,
+                delay: Duration::ZERO,
+                status: 200,
+            }));
+            let requests = Arc::new(AtomicUsize::new(0));
+
+            tokio::spawn({
+                let response = response.clone();
+                let requests = requests.clone();
+                async move {
+                    loop {
+                        let Ok((stream, _)) = listener.accept().await else {
+                            return;
+                        };
+                        let response = *response.lock().unwrap();
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        tokio::spawn(Self::respond(stream, id, response));
+                    }
+                }
+            });
+
+            Self {
+                url,
+                response,
+                requests,
+            }
+        }
+
+        async fn respond(mut stream: TcpStream, id: u64, response: MockResponse) {
+            // Read the request headers. Requests from the tests have no body.
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            }
+
+            tokio::time::sleep(response.delay).await;
+
+            let body = format!(r#"{{"id":{id},"head":{}}}"#, response.head);
+            let message = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                response.status,
+                body.len()
+            );
+            let _ = stream.write_all(message.as_bytes()).await;
+        }
+
+        fn update(&self, f: impl FnOnce(&mut MockResponse)) {
+            f(&mut self.response.lock().unwrap());
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    impl HttpUpstream {
+        async fn get_head(self) -> Result<(u64, u64), TestError> {
+            let response = self
+                .client
+                .get(self.url.clone())
+                .send()
+                .await
+                .change_context(TestError::Upstream)
+                .attach_printable("failed to send request")?;
+
+            let status = response.status();
+            if status.is_server_error() {
+                return Err(TestError::Upstream).attach_printable(format!("status: {status}"));
+            }
+            if status.is_client_error() {
+                return Err(TestError::Request).attach_printable(format!("status: {status}"));
+            }
+
+            let response = response
+                .json::<HeadResponse>()
+                .await
+                .change_context(TestError::Upstream)
+                .attach_printable("failed to decode response")?;
+
+            Ok((response.id, response.head))
+        }
+    }
+
+    fn new_pool(
+        servers: &[&MockServer],
+        timeout: Duration,
+        options: ProviderPoolOptions,
+    ) -> ProviderPool<HttpUpstream> {
+        let weighted = servers
+            .iter()
+            .map(|server| (*server, 1))
+            .collect::<Vec<_>>();
+        new_weighted_pool(&weighted, timeout, options)
+    }
+
+    fn new_weighted_pool(
+        servers: &[(&MockServer, u32)],
+        timeout: Duration,
+        options: ProviderPoolOptions,
+    ) -> ProviderPool<HttpUpstream> {
+        let client = reqwest::Client::builder().timeout(timeout).build().unwrap();
+        let upstreams = servers
+            .iter()
+            .map(|(server, weight)| {
+                let upstream = HttpUpstream {
+                    client: client.clone(),
+                    url: server.url.clone(),
+                };
+                (UpstreamConfig::new(server.url.clone(), *weight), upstream)
+            })
+            .collect();
+        ProviderPool::new(upstreams, options).unwrap()
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn new_unconnected_pool(weights: &[u32]) -> ProviderPool<()> {
+        let upstreams = weights
+            .iter()
+            .enumerate()
+            .map(|(index, weight)| {
+                let url = format!("http://upstream-{index}/").parse().unwrap();
+                (UpstreamConfig::new(url, *weight), ())
+            })
+            .collect();
+        ProviderPool::new(upstreams, Default::default()).unwrap()
+    }
+
+    #[test]
+    fn test_ranked_is_weighted() {
+        let pool = new_unconnected_pool(&[3, 1]);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let first_picks = (0..10_000)
+            .filter(|_| pool.ranked_with(&mut rng)[0] == 0)
+            .count();
+        assert!(
+            (7_000..8_000).contains(&first_picks),
+            "first upstream picked {first_picks} times"
+        );
+    }
+
+    #[test]
+    fn test_healthy_gauge_only_updated_on_change() {
+        let pool = new_unconnected_pool(&[1, 1]);
+        pool.record_head(0, 50);
+        pool.record_head(1, 100);
+
+        assert_eq!(pool.ranked(), vec![1, 0]);
+        assert!(!pool.update_healthy(0, false));
+        assert!(!pool.update_healthy(1, true));
+
+        pool.record_head(0, 100);
+        pool.ranked();
+        assert!(!pool.update_healthy(0, true));
+        assert!(pool.update_healthy(0, false));
+    }
+
+    #[tokio::test]
+    async fn test_request_fails_over() {
+        let first = MockServer::start(0).await;
+        first.update(|response| response.status = 500);
+        let second = MockServer::start(1).await;
+        let pool = new_weighted_pool(
+            &[(&first, 1_000_000), (&second, 1)],
+            TIMEOUT,
+            Default::default(),
+        );
+
+        let (id, _) = pool
+            .request("get_head", HttpUpstream::get_head)
+            .await
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(first.requests(), 1);
+        assert_eq!(second.requests(), 1);
+        assert!(pool.health()[0].1.error_rate > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_request_fails_over_on_timeout() {
+        let first = MockServer::start(0).await;
+        first.update(|response| response.delay = Duration::from_secs(10));
+        let second = MockServer::start(1).await;
+        let pool = new_weighted_pool(
+            &[(&first, 1_000_000), (&second, 1)],
+            Duration::from_millis(200),
+            Default::default(),
+        );
+
+        let (id, _) = pool
+            .request("get_head", HttpUpstream::get_head)
+            .await
+            .unwrap();
+        assert_eq!(id, 1);
+        assert!(pool.health()[0].1.error_rate > 0.0);
+        assert_eq!(pool.health()[1].1.error_rate, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_request_returns_last_error() {
+        let first = MockServer::start(0).await;
+        first.update(|response| response.status = 500);
+        let second = MockServer::start(1).await;
+        second.update(|response| response.status = 503);
+        let pool = new_pool(&[&first, &second], TIMEOUT, Default::default());
+
+        assert!(pool
+            .request("get_head", HttpUpstream::get_head)
+            .await
+            .is_err());
+        assert_eq!(first.requests(), 1);
+        assert_eq!(second.requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_dont_affect_health() {
+        let server = MockServer::start(0).await;
+        server.update(|response| response.status = 404);
+        let pool = new_pool(&[&server], TIMEOUT, Default::default());
+
+        let err = pool
+            .request("get_head", HttpUpstream::get_head)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), TestError::Request));
+        assert_eq!(pool.health()[0].1.error_rate, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_upstream_is_skipped() {
+        let first = MockServer::start(0).await;
+        first.update(|response| response.status = 500);
+        let second = MockServer::start(1).await;
+        // The first upstream is preferred until it's moved to the back of the queue.
+        let pool = new_weighted_pool(
+            &[(&first, 1_000_000), (&second, 1)],
+            TIMEOUT,
+            Default::default(),
+        );
+
+        for _ in 0..4 {
+            let (id, _) = pool
+                .request("get_head", HttpUpstream::get_head)
+                .await
+                .unwrap();
+            assert_eq!(id, 1);
+        }
+
+        let first_requests = first.requests();
+        assert_eq!(first_requests, 4);
+        assert!(pool.health()[0].1.cooling_down);
+        assert_eq!(pool.ranked(), vec![1, 0]);
+
+        pool.request("get_head", HttpUpstream::get_head)
+            .await
+            .unwrap();
+        assert_eq!(first.requests(), first_requests);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request_returns_fastest() {
+        let slow = MockServer::start(0).await;
+        slow.update(|response| response.delay = Duration::from_secs(10));
+        let fast = MockServer::start(1).await;
+        let options = ProviderPoolOptions {
+            hedge_delay: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let pool = new_weighted_pool(
+            &[(&slow, 1_000_000), (&fast, 1)],
+            Duration::from_secs(30),
+            options,
+        );
+
+        let (id, _) = tokio::time::timeout(
+            Duration::from_secs(5),
+            pool.hedged_request("get_head", HttpUpstream::get_head),
+        )
+        .await
+        .expect("hedged request doesn't wait for the slow upstream")
+        .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(slow.requests(), 1);
+        assert_eq!(fast.requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request_not_sent_if_fast() {
+        let first = MockServer::start(0).await;
+        let second = MockServer::start(1).await;
+        let options = ProviderPoolOptions {
+            hedge_delay: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let pool = new_weighted_pool(&[(&first, 1_000_000), (&second, 1)], TIMEOUT, options);
+
+        let (id, _) = pool
+            .hedged_request("get_head", HttpUpstream::get_head)
+            .await
+            .unwrap();
+        assert_eq!(id, 0);
+        assert_eq!(second.requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request_fails_over_before_delay() {
+        let first = MockServer::start(0).await;
+        first.update(|response| response.status = 500);
+        let second = MockServer::start(1).await;
+        let options = ProviderPoolOptions {
+            hedge_delay: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let pool = new_pool(&[&first, &second], TIMEOUT, options);
+
+        let (id, _) = tokio::time::timeout(
+            Duration::from_secs(5),
+            pool.hedged_request("get_head", HttpUpstream::get_head),
+        )
+        .await
+        .expect("failover doesn't wait for the hedge delay")
+        .unwrap();
+        assert_eq!(id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_consistent_head_ignores_lagging_upstreams() {
+        let lagging = MockServer::start(0).await;
+        lagging.update(|response| response.head = 50);
+        let behind = MockServer::start(1).await;
+        behind.update(|response| response.head = 98);
+        let latest = MockServer::start(2).await;
+        let pool = new_pool(&[&lagging, &behind, &latest], TIMEOUT, Default::default());
+
+        let (id, head) = pool
+            .request_consistent(
+                ConsistentRead::Head,
+                "get_head",
+                HttpUpstream::get_head,
+                |(_, head)| *head,
+            )
+            .await
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(head, 98);
+
+        // The lagging upstream is moved to the back of the queue.
+        let health = pool.health();
+        assert_eq!(health[0].1.head, Some(50));
+        assert_eq!(pool.ranked().last(), Some(&0));
+    }
+
+    #[tokio::test]
+    async fn test_consistent_head_tolerates_failed_upstream() {
+        let failing = MockServer::start(0).await;
+        failing.update(|response| response.status = 500);
+        let latest = MockServer::start(1).await;
+        let pool = new_pool(&[&failing, &latest], TIMEOUT, Default::default());
+
+        let (id, head) = pool
+            .request_consistent(
+                ConsistentRead::Head,
+                "get_head",
+                HttpUpstream::get_head,
+                |(_, head)| *head,
+            )
+            .await
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(head, 100);
+        assert_eq!(failing.requests(), 1);
+    }
+}
@@ -1,6 +1,9 @@
-use std::collections::{BTreeMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
 
-use error_stack::Result;
+use error_stack::{Result, ResultExt};
 use roaring::RoaringBitmap;
 use tracing::trace;
 
@@ -43,11 +46,26 @@ pub struct Filter {
     pub joins: Vec<FragmentId>,
 }
 
+/// Decode the data matched by a filter, for example with an ABI provided by the user.
+///
+/// Decoders run in the data stream, after filtering. They only decode the data matched
+/// directly by their filter, not the data joined in by it.
+pub trait FragmentDecoder: std::fmt::Debug + Send + Sync {
+    /// Decode the encoded message matched by the filter.
+    ///
+    /// The decoded fields are appended to `output` and merged into the message by the client.
+    fn decode(&self, filter_id: FilterId, message: &[u8], output: &mut Vec<u8>);
+}
+
 /// A collection of filters.
 #[derive(Debug, Clone, Default)]
 pub struct BlockFilter {
     pub header_filter: HeaderFilter,
+    header_fragments: Vec<FragmentId>,
+    optional_fragments: HashSet<FragmentId>,
+    optional_indexes: BTreeMap<FragmentId, Vec<IndexId>>,
     filters: BTreeMap<FragmentId, Vec<Filter>>,
+    decoders: BTreeMap<(FragmentId, FilterId), Arc<dyn FragmentDecoder>>,
 }
 
 impl BlockFilter {
@@ -63,6 +81,51 @@ impl BlockFilter {
         self.header_filter = value;
     }
 
+    /// Merge the fragment into the block header.
+    ///
+    /// The fragment contains the encoded header fields, sent only when the header is sent.
+    pub fn add_header_fragment(&mut self, fragment_id: FragmentId) {
+        if !self.header_fragments.contains(&fragment_id) {
+            self.header_fragments.push(fragment_id);
+        }
+    }
+
+    /// Returns the fragments merged into the block header.
+    pub fn header_fragments(&self) -> &[FragmentId] {
+        &self.header_fragments
+    }
+
+    /// Mark the fragment as optional.
+    ///
+    /// Blocks ingested before the fragment was introduced don't have it. Filters on the
+    /// fragment match nothing on these blocks, instead of failing the stream.
+    pub fn add_optional_fragment(&mut self, fragment_id: FragmentId) {
+        self.optional_fragments.insert(fragment_id);
+    }
+
+    /// Returns true if the fragment may be missing from a block.
+    pub fn is_optional_fragment(&self, fragment_id: FragmentId) -> bool {
+        self.optional_fragments.contains(&fragment_id)
+    }
+
+    /// Mark the index of the fragment as optional.
+    ///
+    /// Like `add_optional_fragment`, but for an index added to an existing fragment.
+    pub fn add_optional_index(&mut self, fragment_id: FragmentId, index_id: IndexId) {
+        let indexes = self.optional_indexes.entry(fragment_id).or_default();
+        if !indexes.contains(&index_id) {
+            indexes.push(index_id);
+        }
+    }
+
+    /// Returns the indexes of the fragment that may be missing from a block.
+    pub fn optional_indexes(&self, fragment_id: FragmentId) -> &[IndexId] {
+        self.optional_indexes
+            .get(&fragment_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
     /// Add a filter to the block filter.
     pub fn add_filter(&mut self, filter: Filter) {
         self.filters
@@ -71,6 +134,33 @@ impl BlockFilter {
             .push(filter);
     }
 
+    /// Decode the data matched directly by the filter with the given decoder.
+    pub fn add_decoder(
+        &mut self,
+        fragment_id: FragmentId,
+        filter_id: FilterId,
+        decoder: Arc<dyn FragmentDecoder>,
+    ) {
+        self.decoders.insert((fragment_id, filter_id), decoder);
+    }
+
+    /// Returns the decoder for the data matched by the filter, if any.
+    pub fn decoder(
+        &self,
+        fragment_id: FragmentId,
+        filter_id: FilterId,
+    ) -> Option<&Arc<dyn FragmentDecoder>> {
+        self.decoders.get(&(fragment_id, filter_id))
+    }
+
+    /// Returns true if any filter on the fragment has a decoder.
+    pub fn has_decoders(&self, fragment_id: FragmentId) -> bool {
+        self.decoders
+            .range((fragment_id, FilterId::MIN)..=(fragment_id, FilterId::MAX))
+            .next()
+            .is_some()
+    }
+
     /// Returns an iterator over the filters, grouped by fragment.
     pub fn iter(&self) -> impl Iterator<Item = (&FragmentId, &Vec<Filter>)> {
         self.filters.iter()
@@ -86,7 +176,7 @@ impl BlockFilter {
 
     /// Returns all fragment id needed by this filter.
     pub fn all_fragment_ids(&self) -> HashSet<FragmentId> {
-        let mut out = HashSet::default();
+        let mut out = HashSet::from_iter(self.header_fragments.iter().copied());
 
         for (filter_id, filters) in self.iter() {
             out.insert(*filter_id);
@@ -103,17 +193,37 @@ impl BlockFilter {
 pub struct FilterError;
 
 impl Filter {
-    pub fn filter(&self, indexes: &ArchivedIndexFragment) -> Result<RoaringBitmap, FilterError> {
+    /// Returns the rows matching all conditions.
+    ///
+    /// Indexes are looked up by id. A missing index in `optional_indexes` matches nothing,
+    /// any other missing index is an error.
+    pub fn filter(
+        &self,
+        indexes: &ArchivedIndexFragment,
+        optional_indexes: &[IndexId],
+    ) -> Result<RoaringBitmap, FilterError> {
         let range_start = indexes.range_start.to_native();
         let range_len = indexes.range_len.to_native();
         let mut result = RoaringBitmap::from_iter(range_start..(range_start + range_len));
         trace!(starting = ?result, "starting bitmap");
 
         for cond in self.conditions.iter() {
-            let cond_index = indexes
+            let Some(cond_index) = indexes
                 .indexes
-                .get(cond.index_id as usize)
-                .ok_or(FilterError)?;
+                .iter()
+                .find(|index| index.index_id == cond.index_id)
+            else {
+                if optional_indexes.contains(&cond.index_id) {
+                    trace!(index_id = cond.index_id, "missing optional index");
+                    result.clear();
+                    break;
+                }
+
+                return Err(FilterError)
+                    .attach_printable("index not found")
+                    .attach_printable_lazy(|| format!("fragment id: {}", self.fragment_id))
+                    .attach_printable_lazy(|| format!("index id: {}", cond.index_id));
+            };
 
             match &cond_index.index {
                 index::ArchivedIndex::Empty => {}
@@ -141,3 +251,93 @@ impl std::fmt::Display for FilterError {
         write!(f, "failed to filter block")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        fragment::{ArchivedIndexFragment, Index, IndexFragment},
+        index::{BitmapIndexBuilder, ScalarValue},
+    };
+
+    use super::{BlockFilter, Condition, Filter, FilterError};
+
+    /// Blocks 10 to 13 with only index 3, which is not at position 3. Index 7 is optional.
+    fn try_filter_rows(conditions: &[(u8, u32)]) -> error_stack::Result<Vec<u32>, FilterError> {
+        let mut builder = BitmapIndexBuilder::default();
+        builder.insert(ScalarValue::Uint32(1), 11);
+        builder.insert(ScalarValue::Uint32(1), 13);
+        builder.insert(ScalarValue::Uint32(2), 12);
+
+        let fragment = IndexFragment {
+            fragment_id: 1,
+            range_start: 10,
+            range_len: 4,
+            indexes: vec![Index {
+                index_id: 3,
+                index: builder.build().unwrap().into(),
+            }],
+        };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&fragment).unwrap();
+        let indexes = unsafe { rkyv::access_unchecked::<ArchivedIndexFragment>(&bytes) };
+
+        let filter = Filter {
+            filter_id: 1,
+            fragment_id: 1,
+            conditions: conditions
+                .iter()
+                .map(|(index_id, key)| Condition {
+                    index_id: *index_id,
+                    key: ScalarValue::Uint32(*key),
+                })
+                .collect(),
+            joins: Vec::new(),
+        };
+
+        let mut block_filter = BlockFilter::default();
+        block_filter.add_optional_index(1, 7);
+
+        let rows = filter.filter(indexes, block_filter.optional_indexes(1))?;
+        Ok(rows.iter().collect())
+    }
+
+    fn filter_rows(conditions: &[(u8, u32)]) -> Vec<u32> {
+        try_filter_rows(conditions).unwrap()
+    }
+
+    #[test]
+    fn test_filter_without_conditions() {
+        assert_eq!(filter_rows(&[]), vec![10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn test_filter_looks_up_index_by_id() {
+        assert_eq!(filter_rows(&[(3, 1)]), vec![11, 13]);
+        assert_eq!(filter_rows(&[(3, 2)]), vec![12]);
+        assert!(filter_rows(&[(3, 3)]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_missing_optional_index_matches_nothing() {
+        assert!(filter_rows(&[(7, 1)]).is_empty());
+        assert!(filter_rows(&[(3, 1), (7, 1)]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_missing_index_is_an_error() {
+        assert!(try_filter_rows(&[(0, 1)]).is_err());
+        assert!(try_filter_rows(&[(3, 1), (4, 1)]).is_err());
+    }
+
+    #[test]
+    fn test_optional_fragments_and_indexes() {
+        let mut block_filter = BlockFilter::default();
+        block_filter.add_optional_fragment(7);
+        block_filter.add_optional_index(1, 4);
+        block_filter.add_optional_index(1, 4);
+
+        assert!(block_filter.is_optional_fragment(7));
+        assert!(!block_filter.is_optional_fragment(1));
+        assert_eq!(block_filter.optional_indexes(1), &[4]);
+        assert!(block_filter.optional_indexes(7).is_empty());
+    }
+}
@@ -2,7 +2,8 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use apibara_dna_protocol::dna::stream::{
     dna_stream_server::{self, DnaStream},
-    DataFinality, StatusRequest, StatusResponse, StreamDataRequest,
+    DataFinality, ListReorgsRequest, ListReorgsResponse, StatusRequest, StatusResponse,
+    StreamDataRequest,
 };
 use error_stack::Result;
 use futures::{Future, TryFutureExt};
@@ -21,6 +22,9 @@ use crate::{
 
 static STREAM_SEMAPHORE_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(1);
 
+/// Maximum number of blocks scanned by a single `ListReorgs` request.
+const LIST_REORGS_MAX_RANGE: u64 = 100_000;
+
 #[derive(Debug, Clone)]
 pub struct StreamServiceOptions {
     /// Maximum number of concurrent streams.
@@ -107,6 +111,22 @@ where
         Ok(tonic::Response::new(response))
     }
 
+    #[tracing::instrument(name = "stream::list_reorgs", skip_all)]
+    async fn list_reorgs(
+        &self,
+        request: tonic::Request<ListReorgsRequest>,
+    ) -> tonic::Result<tonic::Response<ListReorgsResponse>, tonic::Status> {
+        let request = request.into_inner();
+
+        let Some(chain_view) = self.chain_view.borrow().clone() else {
+            return Err(tonic::Status::unavailable("chain view not initialized yet"));
+        };
+
+        let response = chain_view.list_reorgs(request).await?;
+
+        Ok(tonic::Response::new(response))
+    }
+
     #[tracing::instrument(
         name = "stream::stream_data",
         skip_all,
@@ -225,6 +245,10 @@ trait ChainViewExt {
         &self,
         cursor: &Cursor,
     ) -> impl Future<Output = tonic::Result<(), tonic::Status>> + Send;
+    fn list_reorgs(
+        &self,
+        request: ListReorgsRequest,
+    ) -> impl Future<Output = tonic::Result<ListReorgsResponse, tonic::Status>> + Send;
 }
 
 impl ChainViewExt for ChainView {
@@ -262,6 +286,69 @@ impl ChainViewExt for ChainView {
             CanonicalCursor::Canonical(_) => Ok(()),
         }
     }
+
+    async fn list_reorgs(
+        &self,
+        request: ListReorgsRequest,
+    ) -> tonic::Result<ListReorgsResponse, tonic::Status> {
+        let head = self
+            .get_head()
+            .await
+            .map_err(|_| tonic::Status::internal("internal server error"))?;
+
+        let from_block = request.from_block;
+        let to_block = u64::min(request.to_block.unwrap_or(head.number), head.number);
+
+        if from_block > to_block {
+            return Err(tonic::Status::invalid_argument(format!(
+                "from block {from_block} is after to block {to_block}"
+            )));
+        }
+
+        if to_block - from_block >= LIST_REORGS_MAX_RANGE {
+            return Err(tonic::Status::invalid_argument(format!(
+                "block range must be at most {LIST_REORGS_MAX_RANGE} blocks"
+            )));
+        }
+
+        let reorgs = self.get_reorgs(from_block, to_block).await.map_err(|err| {
+            error!(error = ?err, "DnaStream::list_reorgs error");
+            tonic::Status::internal("internal server error")
+        })?;
+
+        let mut response = ListReorgsResponse::default();
+
+        for reorg in reorgs {
+            let mut canonical = Vec::with_capacity(reorg.orphaned.len());
+            let mut block_numbers = reorg
+                .orphaned
+                .iter()
+                .map(|cursor| cursor.number)
+                .collect::<Vec<_>>();
+            block_numbers.dedup();
+
+            for block_number in block_numbers {
+                if let CanonicalCursor::Canonical(cursor) =
+                    self.get_canonical(block_number)
+                        .await
+                        .map_err(|_| tonic::Status::internal("internal server error"))?
+                {
+                    canonical.push(cursor.into());
+                }
+            }
+
+            response
+                .reorgs
+                .push(apibara_dna_protocol::dna::stream::Reorg {
+                    depth: reorg.depth(),
+                    target: Some(reorg.target.into()),
+                    orphaned: reorg.orphaned.into_iter().map(Into::into).collect(),
+                    canonical,
+                });
+        }
+
+        Ok(response)
+    }
 }
 
 fn validate_heartbeat_interval(
@@ -0,0 +1,570 @@
+use std::path::PathBuf;
+
+use apibara_etcd::{EtcdClient, Lock, LockOptions};
+use bytes::Bytes;
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    block_store::{BLOCK_PREFIX, GROUP_PREFIX, SEGMENT_PREFIX},
+    chain::CanonicalChainSegment,
+    chain_store::{CANONICAL_PREFIX, RECENT_CHAIN_SEGMENT_NAME},
+    cli::{EtcdArgs, ObjectStoreArgs},
+};
+
+use super::{
+    manifest::{
+        snapshot_prefixes, SnapshotManifest, SnapshotObject, SnapshotState, MANIFEST_FILENAME,
+    },
+    storage::SnapshotStorage,
+    tarball,
+    transfer::{Transfer, TransferArgs},
+    SnapshotError,
+};
+
+#[derive(Args, Debug)]
+pub struct ExportCommand {
+    #[clap(flatten)]
+    pub object_store: ObjectStoreArgs,
+    #[clap(flatten)]
+    pub etcd: EtcdArgs,
+    #[clap(flatten)]
+    pub transfer: TransferArgs,
+    /// Write the snapshot to this tarball.
+    #[arg(long = "snapshot.output", env = "DNA_SNAPSHOT_OUTPUT")]
+    pub output: Option<PathBuf>,
+    /// Copy the snapshot to this bucket instead of a tarball.
+    ///
+    /// Defaults to the source bucket.
+    #[arg(
+        long = "snapshot.target-s3-bucket",
+        env = "DNA_SNAPSHOT_TARGET_S3_BUCKET"
+    )]
+    pub target_s3_bucket: Option<String>,
+    /// Copy the snapshot under this prefix instead of a tarball.
+    #[arg(
+        long = "snapshot.target-s3-prefix",
+        env = "DNA_SNAPSHOT_TARGET_S3_PREFIX"
+    )]
+    pub target_s3_prefix: Option<String>,
+    /// The etcd endpoints where to write the ingestion state.
+    ///
+    /// Defaults to the source etcd endpoints.
+    #[arg(
+        long = "snapshot.target-etcd-endpoints",
+        env = "DNA_SNAPSHOT_TARGET_ETCD_ENDPOINTS",
+        value_delimiter = ','
+    )]
+    pub target_etcd_endpoints: Vec<String>,
+    /// The etcd prefix where to write the ingestion state.
+    #[arg(
+        long = "snapshot.target-etcd-prefix",
+        env = "DNA_SNAPSHOT_TARGET_ETCD_PREFIX"
+    )]
+    pub target_etcd_prefix: Option<String>,
+}
+
+enum ExportDestination {
+    Tarball(PathBuf),
+    Bucket {
+        object_store: Box<ObjectStoreArgs>,
+        etcd: Box<EtcdArgs>,
+    },
+}
+
+impl ExportCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), SnapshotError> {
+        let destination = self.destination()?;
+
+        let source_store = self
+            .object_store
+            .into_object_store_client()
+            .await
+            .change_context(SnapshotError)?;
+
+        let etcd_client = self
+            .etcd
+            .into_etcd_client()
+            .await
+            .change_context(SnapshotError)?;
+
+        // Hold the compaction lock so that segments, groups and blocks are not created,
+        // pruned or expired while they are copied. Ingestion keeps running.
+        let mut lock_client = etcd_client.lock_client(LockOptions::default());
+
+        info!("acquiring compaction lock");
+        let Some(mut lock) = lock_client
+            .lock("compaction/lock", ct.clone())
+            .await
+            .change_context(SnapshotError)
+            .attach_printable("failed to acquire compaction lock")?
+        else {
+            return Ok(());
+        };
+
+        let result = export(
+            SnapshotStorage::ObjectStore(source_store),
+            &etcd_client,
+            destination,
+            self.transfer,
+            &mut lock,
+            &ct,
+        )
+        .await;
+
+        lock_client
+            .unlock(lock)
+            .await
+            .change_context(SnapshotError)?;
+
+        result
+    }
+
+    fn destination(&self) -> Result<ExportDestination, SnapshotError> {
+        match (&self.output, &self.target_s3_prefix) {
+            (Some(output), None) => Ok(ExportDestination::Tarball(output.clone())),
+            (None, Some(target_prefix)) => {
+                let object_store = ObjectStoreArgs {
+                    s3_bucket: self
+                        .target_s3_bucket
+                        .clone()
+                        .unwrap_or_else(|| self.object_store.s3_bucket.clone()),
+                    s3_prefix: Some(target_prefix.clone()),
+                    ..self.object_store.clone()
+                };
+
+                let mut etcd = EtcdArgs {
+                    etcd_prefix: self.target_etcd_prefix.clone(),
+                    ..self.etcd.clone()
+                };
+
+                if !self.target_etcd_endpoints.is_empty() {
+                    etcd.etcd_endpoints = self.target_etcd_endpoints.clone();
+                }
+
+                if object_store.s3_bucket == self.object_store.s3_bucket
+                    && object_store.s3_prefix == self.object_store.s3_prefix
+                {
+                    return Err(SnapshotError)
+                        .attach_printable("target bucket and prefix must differ from the source");
+                }
+
+                if etcd.etcd_endpoints == self.etcd.etcd_endpoints
+                    && etcd.etcd_prefix == self.etcd.etcd_prefix
+                {
+                    return Err(SnapshotError).attach_printable(
+                        "target etcd endpoints or prefix must differ from the source",
+                    );
+                }
+
+                Ok(ExportDestination::Bucket {
+                    object_store: object_store.into(),
+                    etcd: etcd.into(),
+                })
+            }
+            _ => Err(SnapshotError).attach_printable(
+                "exactly one of --snapshot.output or --snapshot.target-s3-prefix is required",
+            ),
+        }
+    }
+}
+
+async fn export(
+    source: SnapshotStorage,
+    etcd_client: &EtcdClient,
+    destination: ExportDestination,
+    transfer_args: TransferArgs,
+    lock: &mut Lock,
+    ct: &CancellationToken,
+) -> Result<(), SnapshotError> {
+    let state = SnapshotState::read(etcd_client).await?;
+
+    let recent_path = format!("{CANONICAL_PREFIX}/{RECENT_CHAIN_SEGMENT_NAME}");
+    let recent_data = source.get(&recent_path).await?;
+    let recent = rkyv::from_bytes::<CanonicalChainSegment, rkyv::rancor::Error>(&recent_data)
+        .change_context(SnapshotError)
+        .attach_printable("failed to decode recent canonical chain segment")?;
+
+    info!(
+        starting_block = state.starting_block,
+        segmented = ?state.segmented,
+        grouped = ?state.grouped,
+        last_block = %recent.info.last_block,
+        "exporting snapshot"
+    );
+
+    let paths = snapshot_objects(&source, &state, &recent).await?;
+
+    match destination {
+        ExportDestination::Tarball(output) => {
+            let staging = tarball::staging_dir(&output);
+            let transfer = Transfer::new(
+                source,
+                SnapshotStorage::Directory(staging.clone()),
+                transfer_args,
+            );
+
+            let Some(mut objects) = transfer.copy_objects(paths, None, Some(lock), ct).await?
+            else {
+                return Ok(());
+            };
+
+            transfer
+                .destination()
+                .put(&recent_path, recent_data.clone())
+                .await?;
+            objects.push(SnapshotObject {
+                path: recent_path,
+                size: recent_data.len() as u64,
+                crc32: crc32fast::hash(&recent_data),
+            });
+
+            let manifest = SnapshotManifest::new(state, objects);
+            transfer
+                .destination()
+                .put(MANIFEST_FILENAME, Bytes::from(manifest.to_json()?))
+                .await?;
+
+            tarball::pack(&staging, &output).await?;
+
+            tokio::fs::remove_dir_all(&staging)
+                .await
+                .change_context(SnapshotError)
+                .attach_printable("failed to remove staging directory")?;
+
+            info!(path = %output.display(), "snapshot exported");
+        }
+        ExportDestination::Bucket { object_store, etcd } => {
+            let target_store = object_store
+                .into_object_store_client()
+                .await
+                .change_context(SnapshotError)?;
+            let target_etcd_client = etcd
+                .into_etcd_client()
+                .await
+                .change_context(SnapshotError)?;
+
+            let transfer = Transfer::new(
+                source,
+                SnapshotStorage::ObjectStore(target_store),
+                transfer_args,
+            );
+
+            if transfer
+                .copy_objects(paths, None, Some(lock), ct)
+                .await?
+                .is_none()
+            {
+                return Ok(());
+            }
+
+            let recent_etag = transfer
+                .destination()
+                .put(&recent_path, recent_data)
+                .await?
+                .ok_or(SnapshotError)
+                .attach_printable("missing recent canonical chain segment etag")?;
+
+            state.write(&target_etcd_client, recent_etag).await?;
+
+            info!("snapshot copied");
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path of all objects that are part of the snapshot.
+///
+/// The recent canonical chain segment is not included since it must be written last.
+async fn snapshot_objects(
+    source: &SnapshotStorage,
+    state: &SnapshotState,
+    recent: &CanonicalChainSegment,
+) -> Result<Vec<String>, SnapshotError> {
+    let first_unsegmented = state.first_unsegmented_block();
+    let last_block = recent.info.last_block.number;
+
+    let mut paths = source
+        .list(&snapshot_prefixes())
+        .await?
+        .into_iter()
+        .filter(|path| {
+            let mut parts = path.split('/');
+            let (Some(prefix), Some(name)) = (parts.next(), parts.next()) else {
+                return false;
+            };
+
+            if prefix == CANONICAL_PREFIX {
+                return name
+                    .strip_prefix("z-")
+                    .and_then(|number| number.parse::<u64>().ok())
+                    .is_some_and(|number| number < recent.info.first_block.number);
+            }
+
+            let Ok(number) = name.parse::<u64>() else {
+                return false;
+            };
+
+            if prefix == SEGMENT_PREFIX {
+                state.segmented.is_some_and(|segmented| number <= segmented)
+            } else if prefix == GROUP_PREFIX {
+                state.grouped.is_some_and(|grouped| number <= grouped)
+            } else if prefix == BLOCK_PREFIX {
+                let is_pending = parts
+                    .next()
+                    .is_some_and(|name| name.starts_with("pending-"));
+                !is_pending && number >= first_unsegmented && number <= last_block
+            } else {
+                false
+            }
+        })
+        .collect::<Vec<_>>();
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_etcd::{EtcdClient, LockOptions};
+    use bytes::Bytes;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        chain::{BlockInfo, CanonicalChainBuilder, CanonicalChainSegment},
+        chain_store::{CANONICAL_PREFIX, RECENT_CHAIN_SEGMENT_NAME},
+        ingestion::IngestionStateClient,
+        new_test_cursor,
+        object_store::{MemoryClient, ObjectETag, ObjectStore, ObjectStoreOptions},
+        snapshot::{
+            import::import,
+            storage::SnapshotStorage,
+            tarball,
+            transfer::{Transfer, TransferArgs},
+            SnapshotState,
+        },
+    };
+
+    use super::{export, snapshot_objects, ExportDestination};
+
+    fn memory_storage() -> SnapshotStorage {
+        SnapshotStorage::ObjectStore(ObjectStore::new_memory(
+            MemoryClient::new(),
+            ObjectStoreOptions {
+                bucket: "test".to_string(),
+                prefix: None,
+            },
+        ))
+    }
+
+    fn transfer_args() -> TransferArgs {
+        TransferArgs {
+            parallelism: 4,
+            verify: true,
+            resume: false,
+        }
+    }
+
+    fn test_state() -> SnapshotState {
+        SnapshotState {
+            starting_block: 0,
+            finalized: Some(990),
+            segmented: Some(999),
+            grouped: Some(799),
+            pruned: Some(799),
+            expired: None,
+            chain_segment_size: 1_000,
+            segment_size: 100,
+            group_size: 2,
+        }
+    }
+
+    fn recent_segment() -> CanonicalChainSegment {
+        let mut builder = CanonicalChainBuilder::new();
+        let mut parent = new_test_cursor(999, 0).hash;
+        for number in 1_000..=1_005 {
+            let hash = new_test_cursor(number, 0).hash;
+            builder
+                .grow(BlockInfo {
+                    number,
+                    hash: hash.clone(),
+                    parent,
+                })
+                .unwrap();
+            parent = hash;
+        }
+        builder.current_segment().unwrap()
+    }
+
+    /// The objects that belong to the snapshot.
+    fn snapshot_paths() -> Vec<String> {
+        vec![
+            "block/0000001000/0x01".to_string(),
+            "block/0000001005/0x02".to_string(),
+            "canon/z-0000000000".to_string(),
+            "group/0000000600/index".to_string(),
+            "segment/0000000900/index".to_string(),
+            "segment/0000000900/log".to_string(),
+        ]
+    }
+
+    async fn put_source_objects(source: &SnapshotStorage) -> Bytes {
+        let ignored = [
+            // Already in a segment.
+            "block/0000000999/0x03",
+            // Pending blocks are never part of the snapshot.
+            "block/0000001003/pending-0001",
+            // After the recent canonical chain segment.
+            "block/0000001006/0x04",
+            // Not covered by the segmented and grouped watermarks.
+            "canon/z-0000001000",
+            "group/0000000800/index",
+            "segment/0000001000/index",
+        ];
+
+        for path in snapshot_paths().iter().map(String::as_str).chain(ignored) {
+            source
+                .put(path, Bytes::from(path.as_bytes().to_vec()))
+                .await
+                .unwrap();
+        }
+
+        let recent = rkyv::to_bytes::<rkyv::rancor::Error>(&recent_segment()).unwrap();
+        let recent = Bytes::copy_from_slice(recent.as_slice());
+        source
+            .put(
+                &format!("{CANONICAL_PREFIX}/{RECENT_CHAIN_SEGMENT_NAME}"),
+                recent.clone(),
+            )
+            .await
+            .unwrap();
+
+        recent
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_objects() {
+        let source = memory_storage();
+        put_source_objects(&source).await;
+
+        let paths = snapshot_objects(&source, &test_state(), &recent_segment())
+            .await
+            .unwrap();
+
+        assert_eq!(paths, snapshot_paths());
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import_tarball() {
+        let dir = tempfile::tempdir().unwrap();
+        let ct = CancellationToken::new();
+
+        let source = memory_storage();
+        let recent = put_source_objects(&source).await;
+
+        let source_etcd = EtcdClient::embedded(dir.path().join("source.json"), Default::default())
+            .await
+            .unwrap();
+        test_state()
+            .write(&source_etcd, ObjectETag("\"source\"".to_string()))
+            .await
+            .unwrap();
+
+        let output = dir.path().join("snapshot.tar");
+        let mut lock = source_etcd
+            .lock_client(LockOptions::default())
+            .lock("compaction/lock", ct.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        export(
+            source.clone(),
+            &source_etcd,
+            ExportDestination::Tarball(output.clone()),
+            transfer_args(),
+            &mut lock,
+            &ct,
+        )
+        .await
+        .unwrap();
+
+        assert!(output.exists());
+        assert!(!tarball::staging_dir(&output).exists());
+
+        let target = memory_storage();
+        let target_etcd = EtcdClient::embedded(dir.path().join("target.json"), Default::default())
+            .await
+            .unwrap();
+        let mut lock = target_etcd
+            .lock_client(LockOptions::default())
+            .lock("ingestion/lock", ct.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let transfer = Transfer::new(
+            SnapshotStorage::Directory(tarball::staging_dir(&output)),
+            target.clone(),
+            transfer_args(),
+        );
+
+        import(&output, transfer, false, &target_etcd, &mut lock, &ct)
+            .await
+            .unwrap();
+
+        // All snapshot objects, and only them, are copied.
+        let mut imported = target
+            .list(&["block", "canon", "group", "segment"])
+            .await
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+        imported.sort();
+
+        let recent_path = format!("{CANONICAL_PREFIX}/{RECENT_CHAIN_SEGMENT_NAME}");
+        let mut expected = snapshot_paths();
+        expected.push(recent_path.clone());
+        expected.sort();
+        assert_eq!(imported, expected);
+
+        for path in snapshot_paths() {
+            assert_eq!(
+                target.get(&path).await.unwrap(),
+                Bytes::from(path.into_bytes())
+            );
+        }
+        assert_eq!(target.get(&recent_path).await.unwrap(), recent);
+
+        // The ingestion state points to the imported recent chain segment.
+        let state = SnapshotState::read(&target_etcd).await.unwrap();
+        assert_eq!(state.segmented, Some(999));
+        assert_eq!(state.grouped, Some(799));
+        assert_eq!(state.pruned, Some(799));
+        assert_eq!(state.expired, None);
+        assert_eq!(state.segment_size, 100);
+
+        let ingested = IngestionStateClient::new(&target_etcd)
+            .get_ingested()
+            .await
+            .unwrap();
+        assert_ne!(ingested, Some(ObjectETag("\"source\"".to_string())));
+        assert!(ingested.is_some());
+
+        // Importing again requires resuming.
+        let transfer = Transfer::new(
+            SnapshotStorage::Directory(tarball::staging_dir(&output)),
+            target,
+            transfer_args(),
+        );
+        assert!(
+            import(&output, transfer, false, &target_etcd, &mut lock, &ct)
+                .await
+                .is_err()
+        );
+    }
+}
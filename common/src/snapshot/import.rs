@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use apibara_etcd::{EtcdClient, Lock, LockOptions};
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    chain_store::{CANONICAL_PREFIX, RECENT_CHAIN_SEGMENT_NAME},
+    cli::{EtcdArgs, ObjectStoreArgs},
+    ingestion::IngestionStateClient,
+};
+
+use super::{
+    manifest::{SnapshotManifest, MANIFEST_FILENAME},
+    storage::SnapshotStorage,
+    tarball,
+    transfer::{Transfer, TransferArgs},
+    SnapshotError,
+};
+
+#[derive(Args, Debug)]
+pub struct ImportCommand {
+    #[clap(flatten)]
+    pub object_store: ObjectStoreArgs,
+    #[clap(flatten)]
+    pub etcd: EtcdArgs,
+    #[clap(flatten)]
+    pub transfer: TransferArgs,
+    /// The snapshot tarball to import.
+    #[arg(long = "snapshot.input", env = "DNA_SNAPSHOT_INPUT")]
+    pub input: PathBuf,
+}
+
+impl ImportCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), SnapshotError> {
+        let target_store = self
+            .object_store
+            .into_object_store_client()
+            .await
+            .change_context(SnapshotError)?;
+
+        let etcd_client = self
+            .etcd
+            .into_etcd_client()
+            .await
+            .change_context(SnapshotError)?;
+
+        // Hold the ingestion lock so that no DNA instance starts ingesting while the
+        // snapshot is imported.
+        let mut lock_client = etcd_client.lock_client(LockOptions::default());
+
+        info!("acquiring ingestion lock");
+        let Some(mut lock) = lock_client
+            .lock("ingestion/lock", ct.clone())
+            .await
+            .change_context(SnapshotError)
+            .attach_printable("failed to acquire ingestion lock")?
+        else {
+            return Ok(());
+        };
+
+        let transfer = Transfer::new(
+            SnapshotStorage::Directory(tarball::staging_dir(&self.input)),
+            SnapshotStorage::ObjectStore(target_store),
+            self.transfer.clone(),
+        );
+
+        let result = import(
+            &self.input,
+            transfer,
+            self.transfer.resume,
+            &etcd_client,
+            &mut lock,
+            &ct,
+        )
+        .await;
+
+        lock_client
+            .unlock(lock)
+            .await
+            .change_context(SnapshotError)?;
+
+        result
+    }
+}
+
+pub(super) async fn import(
+    input: &Path,
+    transfer: Transfer,
+    resume: bool,
+    etcd_client: &EtcdClient,
+    lock: &mut Lock,
+    ct: &CancellationToken,
+) -> Result<(), SnapshotError> {
+    let existing_state = IngestionStateClient::new(etcd_client)
+        .get_starting_block()
+        .await
+        .change_context(SnapshotError)?;
+
+    if existing_state.is_some() && !resume {
+        return Err(SnapshotError)
+            .attach_printable("destination etcd already contains ingestion state")
+            .attach_printable("use --snapshot.resume to continue a previous import");
+    }
+
+    let staging = tarball::staging_dir(input);
+    tarball::unpack(input, &staging).await?;
+
+    let manifest = transfer.source().get(MANIFEST_FILENAME).await?;
+    let manifest = SnapshotManifest::from_json(&manifest)?;
+
+    info!(
+        starting_block = manifest.state.starting_block,
+        segmented = ?manifest.state.segmented,
+        grouped = ?manifest.state.grouped,
+        objects = manifest.objects.len(),
+        "importing snapshot"
+    );
+
+    let recent_path = format!("{CANONICAL_PREFIX}/{RECENT_CHAIN_SEGMENT_NAME}");
+
+    let expected = manifest
+        .objects
+        .iter()
+        .map(|object| (object.path.clone(), object.crc32))
+        .collect::<HashMap<_, _>>();
+
+    let paths = manifest
+        .objects
+        .iter()
+        .filter(|object| object.path != recent_path)
+        .map(|object| object.path.clone())
+        .collect::<Vec<_>>();
+
+    if transfer
+        .copy_objects(paths, Some(&expected), Some(lock), ct)
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    let recent_data = transfer.source().get(&recent_path).await?;
+    if expected.get(&recent_path) != Some(&crc32fast::hash(&recent_data)) {
+        return Err(SnapshotError)
+            .attach_printable("recent canonical chain segment does not match the manifest");
+    }
+
+    let recent_etag = transfer
+        .destination()
+        .put(&recent_path, recent_data)
+        .await?
+        .ok_or(SnapshotError)
+        .attach_printable("missing recent canonical chain segment etag")?;
+
+    manifest.state.write(etcd_client, recent_etag).await?;
+
+    tokio::fs::remove_dir_all(&staging)
+        .await
+        .change_context(SnapshotError)
+        .attach_printable("failed to remove staging directory")?;
+
+    info!("snapshot imported");
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use apibara_etcd::EtcdClient;
+use error_stack::{Result, ResultExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_store::{BLOCK_PREFIX, GROUP_PREFIX, SEGMENT_PREFIX},
+    chain_store::CANONICAL_PREFIX,
+    ingestion::{
+        state_client::{EXPIRED_KEY, GROUPED_KEY, PRUNED_KEY, SEGMENTED_KEY},
+        IngestionStateClient, FINALIZED_KEY, INGESTED_KEY, STARTING_BLOCK_KEY,
+    },
+    object_store::ObjectETag,
+    options_store::{OptionsStore, CHAIN_SEGMENT_SIZE_KEY, GROUP_SIZE_KEY, SEGMENT_SIZE_KEY},
+};
+
+use super::SnapshotError;
+
+pub static MANIFEST_FILENAME: &str = "manifest.json";
+
+static MANIFEST_VERSION: u32 = 1;
+
+/// The object store prefixes included in a snapshot.
+pub fn snapshot_prefixes() -> [&'static str; 4] {
+    [CANONICAL_PREFIX, SEGMENT_PREFIX, GROUP_PREFIX, BLOCK_PREFIX]
+}
+
+/// The content of a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub state: SnapshotState,
+    pub objects: Vec<SnapshotObject>,
+}
+
+/// An object stored in the snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotObject {
+    /// The object path, relative to the bucket prefix.
+    pub path: String,
+    /// The uncompressed object size.
+    pub size: u64,
+    /// The crc32 checksum of the uncompressed object.
+    pub crc32: u32,
+}
+
+/// The ingestion state and options stored in etcd.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotState {
+    pub starting_block: u64,
+    pub finalized: Option<u64>,
+    pub segmented: Option<u64>,
+    pub grouped: Option<u64>,
+    pub pruned: Option<u64>,
+    pub expired: Option<u64>,
+    pub chain_segment_size: usize,
+    pub segment_size: usize,
+    pub group_size: usize,
+}
+
+impl SnapshotManifest {
+    pub fn new(state: SnapshotState, objects: Vec<SnapshotObject>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            state,
+            objects,
+        }
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let manifest: Self = serde_json::from_slice(bytes)
+            .change_context(SnapshotError)
+            .attach_printable("failed to decode snapshot manifest")?;
+
+        if manifest.version != MANIFEST_VERSION {
+            return Err(SnapshotError)
+                .attach_printable("unsupported snapshot manifest version")
+                .attach_printable_lazy(|| format!("version: {}", manifest.version));
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, SnapshotError> {
+        serde_json::to_vec_pretty(self)
+            .change_context(SnapshotError)
+            .attach_printable("failed to encode snapshot manifest")
+    }
+}
+
+impl SnapshotState {
+    /// Read the current ingestion state from etcd.
+    pub async fn read(etcd_client: &EtcdClient) -> Result<Self, SnapshotError> {
+        let mut state_client = IngestionStateClient::new(etcd_client);
+        let mut options_store = OptionsStore::new(etcd_client);
+
+        let starting_block = state_client
+            .get_starting_block()
+            .await
+            .change_context(SnapshotError)?
+            .ok_or(SnapshotError)
+            .attach_printable("ingestion starting block not found")?;
+
+        let finalized = state_client
+            .get_finalized()
+            .await
+            .change_context(SnapshotError)?;
+        let segmented = state_client
+            .get_segmented()
+            .await
+            .change_context(SnapshotError)?;
+        let grouped = state_client
+            .get_grouped()
+            .await
+            .change_context(SnapshotError)?;
+        let pruned = state_client
+            .get_pruned()
+            .await
+            .change_context(SnapshotError)?;
+        let expired = state_client
+            .get_expired()
+            .await
+            .change_context(SnapshotError)?;
+
+        let chain_segment_size = options_store
+            .get_chain_segment_size()
+            .await
+            .change_context(SnapshotError)?
+            .ok_or(SnapshotError)
+            .attach_printable("chain segment size option not found")?;
+        let segment_size = options_store
+            .get_segment_size()
+            .await
+            .change_context(SnapshotError)?
+            .ok_or(SnapshotError)
+            .attach_printable("segment size option not found")?;
+        let group_size = options_store
+            .get_group_size()
+            .await
+            .change_context(SnapshotError)?
+            .ok_or(SnapshotError)
+            .attach_printable("group size option not found")?;
+
+        Ok(Self {
+            starting_block,
+            finalized,
+            segmented,
+            grouped,
+            pruned,
+            expired,
+            chain_segment_size,
+            segment_size,
+            group_size,
+        })
+    }
+
+    /// Write the ingestion state to etcd in a single transaction.
+    ///
+    /// The `recent` etag is the etag of the recent canonical chain segment in the
+    /// destination bucket.
+    pub async fn write(
+        &self,
+        etcd_client: &EtcdClient,
+        recent: ObjectETag,
+    ) -> Result<(), SnapshotError> {
+        let mut put = vec![
+            (
+                STARTING_BLOCK_KEY.to_string(),
+                self.starting_block.to_string().into_bytes(),
+            ),
+            (
+                CHAIN_SEGMENT_SIZE_KEY.to_string(),
+                self.chain_segment_size.to_string().into_bytes(),
+            ),
+            (
+                SEGMENT_SIZE_KEY.to_string(),
+                self.segment_size.to_string().into_bytes(),
+            ),
+            (
+                GROUP_SIZE_KEY.to_string(),
+                self.group_size.to_string().into_bytes(),
+            ),
+            (INGESTED_KEY.to_string(), recent.0.into_bytes()),
+        ];
+        let mut delete = Vec::new();
+
+        for (key, value) in [
+            (FINALIZED_KEY, self.finalized),
+            (SEGMENTED_KEY, self.segmented),
+            (GROUPED_KEY, self.grouped),
+            (PRUNED_KEY, self.pruned),
+            (EXPIRED_KEY, self.expired),
+        ] {
+            match value {
+                Some(block) => put.push((key.to_string(), block.to_string().into_bytes())),
+                None => delete.push(key.to_string()),
+            }
+        }
+
+        etcd_client
+            .kv_client()
+            .put_and_delete_many(&put, &delete)
+            .await
+            .change_context(SnapshotError)
+            .attach_printable("failed to write ingestion state")?;
+
+        Ok(())
+    }
+
+    /// The first block that is not stored in a segment.
+    pub fn first_unsegmented_block(&self) -> u64 {
+        match self.segmented {
+            Some(segmented) => segmented + 1,
+            None => self
+                .expired
+                .map(|expired| expired + 1)
+                .unwrap_or(self.starting_block),
+        }
+    }
+}
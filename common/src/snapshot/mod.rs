@@ -0,0 +1,18 @@
+//! Export and import bucket snapshots.
+//!
+//! A snapshot contains the canonical chain segments, segments, groups and blocks
+//! together with the ingestion state needed to resume ingestion from where the
+//! snapshot was taken.
+mod error;
+mod export;
+mod import;
+mod manifest;
+mod storage;
+mod tarball;
+mod transfer;
+
+pub use self::error::SnapshotError;
+pub use self::export::ExportCommand;
+pub use self::import::ImportCommand;
+pub use self::manifest::{SnapshotManifest, SnapshotObject, SnapshotState};
+pub use self::transfer::TransferArgs;
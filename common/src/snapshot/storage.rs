@@ -0,0 +1,186 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use bytes::Bytes;
+use error_stack::{Result, ResultExt};
+
+use crate::object_store::{GetOptions, ListOptions, ObjectETag, ObjectStore, PutOptions};
+
+use super::SnapshotError;
+
+/// Where snapshot objects are read from or written to.
+#[derive(Clone)]
+pub enum SnapshotStorage {
+    /// Objects are stored in an object store bucket, under its prefix.
+    ObjectStore(ObjectStore),
+    /// Objects are stored as files in a local directory.
+    Directory(PathBuf),
+}
+
+impl SnapshotStorage {
+    pub async fn get(&self, path: &str) -> Result<Bytes, SnapshotError> {
+        match self {
+            SnapshotStorage::ObjectStore(store) => {
+                let response = store
+                    .get(path, GetOptions::default())
+                    .await
+                    .change_context(SnapshotError)
+                    .attach_printable("failed to get object")
+                    .attach_printable_lazy(|| format!("path: {path}"))?;
+                Ok(response.body)
+            }
+            SnapshotStorage::Directory(root) => {
+                let data = tokio::fs::read(root.join(path))
+                    .await
+                    .change_context(SnapshotError)
+                    .attach_printable("failed to read snapshot file")
+                    .attach_printable_lazy(|| format!("path: {path}"))?;
+                Ok(Bytes::from(data))
+            }
+        }
+    }
+
+    /// Store the object, returning its etag if the storage supports them.
+    pub async fn put(&self, path: &str, data: Bytes) -> Result<Option<ObjectETag>, SnapshotError> {
+        match self {
+            SnapshotStorage::ObjectStore(store) => {
+                let response = store
+                    .put(path, data, PutOptions::default())
+                    .await
+                    .change_context(SnapshotError)
+                    .attach_printable("failed to put object")
+                    .attach_printable_lazy(|| format!("path: {path}"))?;
+                Ok(Some(response.etag))
+            }
+            SnapshotStorage::Directory(root) => {
+                let file_path = root.join(path);
+                if let Some(parent) = file_path.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .change_context(SnapshotError)
+                        .attach_printable("failed to create snapshot directory")
+                        .attach_printable_lazy(|| format!("path: {}", parent.display()))?;
+                }
+
+                // Write to a temporary file first so that an interrupted export never
+                // leaves a partial file behind.
+                let tmp_path = file_path.with_extension("tmp");
+                tokio::fs::write(&tmp_path, &data)
+                    .await
+                    .change_context(SnapshotError)
+                    .attach_printable("failed to write snapshot file")
+                    .attach_printable_lazy(|| format!("path: {path}"))?;
+                tokio::fs::rename(&tmp_path, &file_path)
+                    .await
+                    .change_context(SnapshotError)
+                    .attach_printable("failed to rename snapshot file")
+                    .attach_printable_lazy(|| format!("path: {path}"))?;
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// List all objects under the given prefixes.
+    pub async fn list(&self, prefixes: &[&str]) -> Result<HashSet<String>, SnapshotError> {
+        let mut objects = HashSet::new();
+
+        match self {
+            SnapshotStorage::ObjectStore(store) => {
+                for prefix in prefixes {
+                    let response = store
+                        .list(&format!("{prefix}/"), ListOptions::default())
+                        .await
+                        .change_context(SnapshotError)
+                        .attach_printable("failed to list objects")
+                        .attach_printable_lazy(|| format!("prefix: {prefix}"))?;
+
+                    objects.extend(
+                        response
+                            .object_ids
+                            .iter()
+                            .map(|object_id| store.relative_path(object_id).to_string()),
+                    );
+                }
+            }
+            SnapshotStorage::Directory(root) => {
+                for prefix in prefixes {
+                    let mut pending = vec![root.join(prefix)];
+                    while let Some(dir) = pending.pop() {
+                        let mut entries = match tokio::fs::read_dir(&dir).await {
+                            Ok(entries) => entries,
+                            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                            Err(err) => {
+                                return Err(err)
+                                    .change_context(SnapshotError)
+                                    .attach_printable("failed to read snapshot directory")
+                                    .attach_printable_lazy(|| format!("path: {}", dir.display()))
+                            }
+                        };
+
+                        while let Some(entry) =
+                            entries.next_entry().await.change_context(SnapshotError)?
+                        {
+                            let path = entry.path();
+                            if path.is_dir() {
+                                pending.push(path);
+                                continue;
+                            }
+
+                            if path.extension().is_some_and(|ext| ext == "tmp") {
+                                continue;
+                            }
+
+                            if let Ok(relative) = path.strip_prefix(root) {
+                                objects.insert(relative.to_string_lossy().to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::object_store::{MemoryClient, ObjectStore, ObjectStoreOptions};
+
+    use super::SnapshotStorage;
+
+    #[tokio::test]
+    async fn test_list_object_store_follows_pages() {
+        let storage = SnapshotStorage::ObjectStore(ObjectStore::new_memory(
+            MemoryClient::new(),
+            ObjectStoreOptions {
+                bucket: "test".to_string(),
+                prefix: Some("snapshot".to_string()),
+            },
+        ));
+
+        // More objects than returned by a single list request.
+        for number in 0..2_500 {
+            storage
+                .put(
+                    &format!("block/{number:0>10}/0x00"),
+                    Bytes::from_static(b"0"),
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .put("segment/0000000000/index", Bytes::from_static(b"0"))
+            .await
+            .unwrap();
+
+        let objects = storage.list(&["block"]).await.unwrap();
+        assert_eq!(objects.len(), 2_500);
+        assert!(objects.contains("block/0000002499/0x00"));
+
+        let objects = storage.list(&["block", "segment"]).await.unwrap();
+        assert_eq!(objects.len(), 2_501);
+    }
+}
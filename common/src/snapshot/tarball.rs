@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+use error_stack::{Result, ResultExt};
+use tracing::info;
+
+use super::{
+    manifest::{snapshot_prefixes, MANIFEST_FILENAME},
+    SnapshotError,
+};
+
+/// The directory where the tarball content is staged before packing or after unpacking.
+///
+/// Keeping the staged files around until the operation completes is what makes
+/// export and import resumable.
+pub fn staging_dir(tarball: &Path) -> PathBuf {
+    let mut name = tarball.as_os_str().to_owned();
+    name.push(".parts");
+    PathBuf::from(name)
+}
+
+/// Pack the staging directory into the tarball.
+pub async fn pack(staging: &Path, tarball: &Path) -> Result<(), SnapshotError> {
+    info!(path = %tarball.display(), "writing snapshot tarball");
+
+    let staging = staging.to_path_buf();
+    let tarball = tarball.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut tmp_name = tarball.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let file = std::fs::File::create(&tmp_path)
+            .change_context(SnapshotError)
+            .attach_printable("failed to create tarball")
+            .attach_printable_lazy(|| format!("path: {}", tmp_path.display()))?;
+
+        let mut builder = tar::Builder::new(file);
+        for prefix in snapshot_prefixes() {
+            let dir = staging.join(prefix);
+            if !dir.exists() {
+                continue;
+            }
+
+            builder
+                .append_dir_all(prefix, &dir)
+                .change_context(SnapshotError)
+                .attach_printable("failed to add files to tarball")
+                .attach_printable_lazy(|| format!("prefix: {prefix}"))?;
+        }
+
+        // The manifest goes last so that its presence means the tarball was fully unpacked.
+        builder
+            .append_path_with_name(staging.join(MANIFEST_FILENAME), MANIFEST_FILENAME)
+            .change_context(SnapshotError)
+            .attach_printable("failed to add manifest to tarball")?;
+
+        let file = builder
+            .into_inner()
+            .change_context(SnapshotError)
+            .attach_printable("failed to finish tarball")?;
+        file.sync_all()
+            .change_context(SnapshotError)
+            .attach_printable("failed to sync tarball")?;
+
+        std::fs::rename(&tmp_path, &tarball)
+            .change_context(SnapshotError)
+            .attach_printable("failed to rename tarball")
+            .attach_printable_lazy(|| format!("path: {}", tarball.display()))
+    })
+    .await
+    .change_context(SnapshotError)
+    .attach_printable("tarball task failed")?
+}
+
+/// Unpack the tarball into the staging directory.
+///
+/// If the staging directory already contains a manifest, the tarball was unpacked by a
+/// previous run and is not unpacked again.
+pub async fn unpack(tarball: &Path, staging: &Path) -> Result<(), SnapshotError> {
+    if tokio::fs::try_exists(staging.join(MANIFEST_FILENAME))
+        .await
+        .change_context(SnapshotError)?
+    {
+        info!(path = %staging.display(), "using previously unpacked snapshot");
+        return Ok(());
+    }
+
+    info!(path = %tarball.display(), "unpacking snapshot tarball");
+
+    let staging = staging.to_path_buf();
+    let tarball = tarball.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&tarball)
+            .change_context(SnapshotError)
+            .attach_printable("failed to open tarball")
+            .attach_printable_lazy(|| format!("path: {}", tarball.display()))?;
+
+        tar::Archive::new(file)
+            .unpack(&staging)
+            .change_context(SnapshotError)
+            .attach_printable("failed to unpack tarball")
+            .attach_printable_lazy(|| format!("path: {}", tarball.display()))
+    })
+    .await
+    .change_context(SnapshotError)
+    .attach_printable("tarball task failed")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack, staging_dir, unpack, MANIFEST_FILENAME};
+
+    #[tokio::test]
+    async fn test_pack_and_unpack() {
+        let dir = tempfile::tempdir().unwrap();
+        let tarball = dir.path().join("snapshot.tar");
+
+        let files = [
+            ("canon/z-0000000000", "canon"),
+            ("segment/0000000000/index", "index"),
+            ("block/0000000100/0x01", "block"),
+            (MANIFEST_FILENAME, "{}"),
+        ];
+
+        let staging = dir.path().join("export");
+        for (path, content) in files {
+            let path = staging.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        // Files outside of the snapshot prefixes are not packed.
+        std::fs::write(staging.join("other"), "other").unwrap();
+
+        pack(&staging, &tarball).await.unwrap();
+
+        let unpacked = staging_dir(&tarball);
+        unpack(&tarball, &unpacked).await.unwrap();
+
+        for (path, content) in files {
+            assert_eq!(
+                std::fs::read_to_string(unpacked.join(path)).unwrap(),
+                content
+            );
+        }
+        assert!(!unpacked.join("other").exists());
+    }
+}
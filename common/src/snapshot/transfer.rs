@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+
+use apibara_etcd::Lock;
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use futures::StreamExt;
+use futures_buffered::FuturesUnorderedBounded;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use super::{manifest::snapshot_prefixes, storage::SnapshotStorage, SnapshotError, SnapshotObject};
+
+#[derive(Args, Clone, Debug)]
+pub struct TransferArgs {
+    /// How many objects to copy concurrently.
+    #[arg(
+        long = "snapshot.parallelism",
+        env = "DNA_SNAPSHOT_PARALLELISM",
+        default_value = "16"
+    )]
+    pub parallelism: usize,
+    /// Read back every object after writing it and compare its checksum.
+    #[arg(long = "snapshot.verify", env = "DNA_SNAPSHOT_VERIFY")]
+    pub verify: bool,
+    /// Skip objects that already exist in the destination.
+    ///
+    /// Existing objects are read back and checked before being skipped.
+    #[arg(long = "snapshot.resume", env = "DNA_SNAPSHOT_RESUME")]
+    pub resume: bool,
+}
+
+/// Copy objects between two snapshot storages.
+pub struct Transfer {
+    source: SnapshotStorage,
+    destination: SnapshotStorage,
+    args: TransferArgs,
+}
+
+impl Transfer {
+    pub fn new(source: SnapshotStorage, destination: SnapshotStorage, args: TransferArgs) -> Self {
+        Self {
+            source,
+            destination,
+            args,
+        }
+    }
+
+    pub fn source(&self) -> &SnapshotStorage {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &SnapshotStorage {
+        &self.destination
+    }
+
+    /// Copy all objects from the source to the destination.
+    ///
+    /// If `expected` is provided, the checksum of the source objects is checked against it.
+    /// The lock, if any, is kept alive while copying.
+    pub async fn copy_objects(
+        &self,
+        paths: Vec<String>,
+        expected: Option<&HashMap<String, u32>>,
+        mut lock: Option<&mut Lock>,
+        ct: &CancellationToken,
+    ) -> Result<Option<Vec<SnapshotObject>>, SnapshotError> {
+        let existing = if self.args.resume {
+            self.destination.list(&snapshot_prefixes()).await?
+        } else {
+            HashSet::default()
+        };
+
+        let total = paths.len();
+        let parallelism = usize::max(self.args.parallelism, 1);
+        let mut queue = FuturesUnorderedBounded::new(parallelism);
+        let mut paths = paths.into_iter();
+        let mut objects = Vec::with_capacity(total);
+
+        info!(total, existing = existing.len(), "copying snapshot objects");
+
+        loop {
+            while queue.len() < parallelism {
+                let Some(path) = paths.next() else {
+                    break;
+                };
+
+                let skip = existing.contains(&path);
+                queue.push(self.copy_object(path, skip, expected));
+            }
+
+            let Some(object) = ct.run_until_cancelled(queue.next()).await else {
+                return Ok(None);
+            };
+
+            let Some(object) = object else {
+                break;
+            };
+
+            objects.push(object?);
+
+            if let Some(lock) = lock.as_mut() {
+                lock.keep_alive().await.change_context(SnapshotError)?;
+            }
+
+            if objects.len().is_multiple_of(1_000) {
+                info!(copied = objects.len(), total, "copying snapshot objects");
+            }
+        }
+
+        info!(copied = objects.len(), "snapshot objects copied");
+
+        Ok(Some(objects))
+    }
+
+    async fn copy_object(
+        &self,
+        path: String,
+        skip: bool,
+        expected: Option<&HashMap<String, u32>>,
+    ) -> Result<SnapshotObject, SnapshotError> {
+        if skip {
+            debug!(path, "object already copied");
+            let data = self.destination.get(&path).await?;
+            let crc32 = crc32fast::hash(&data);
+            check_expected(&path, crc32, expected)?;
+
+            return Ok(SnapshotObject {
+                path,
+                size: data.len() as u64,
+                crc32,
+            });
+        }
+
+        let data = self.source.get(&path).await?;
+        let crc32 = crc32fast::hash(&data);
+        let size = data.len() as u64;
+
+        check_expected(&path, crc32, expected)?;
+
+        self.destination.put(&path, data).await?;
+
+        if self.args.verify {
+            let written = self.destination.get(&path).await?;
+            if crc32fast::hash(&written) != crc32 {
+                return Err(SnapshotError)
+                    .attach_printable("object checksum mismatch after copy")
+                    .attach_printable_lazy(|| format!("path: {path}"));
+            }
+        }
+
+        debug!(path, size, "object copied");
+
+        Ok(SnapshotObject { path, size, crc32 })
+    }
+}
+
+fn check_expected(
+    path: &str,
+    crc32: u32,
+    expected: Option<&HashMap<String, u32>>,
+) -> Result<(), SnapshotError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    match expected.get(path) {
+        Some(expected_crc32) if *expected_crc32 == crc32 => Ok(()),
+        Some(_) => Err(SnapshotError)
+            .attach_printable("object checksum does not match the snapshot manifest")
+            .attach_printable_lazy(|| format!("path: {path}")),
+        None => Err(SnapshotError)
+            .attach_printable("object not found in the snapshot manifest")
+            .attach_printable_lazy(|| format!("path: {path}")),
+    }
+}
@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use alloy_rpc_types::BlockNumberOrTag;
-use apibara_etcd::EtcdClient;
+use apibara_etcd::{EtcdClient, LockOptions};
 use error_stack::{Result, ResultExt};
 use foyer::HybridCacheBuilder;
 use testcontainers::{runners::AsyncRunner, ContainerAsync};
@@ -168,6 +168,134 @@ async fn test_ingestion_initialize_with_starting_block() {
     assert!(ingested.is_none());
 }
 
+#[tokio::test]
+async fn test_ingestion_initialize_with_ending_block() {
+    let (_minio, object_store) = init_minio().await;
+    let (_etcd_server, etcd_client) = init_etcd_server().await;
+    let (_anvil_server, anvil_provider) = init_anvil().await;
+
+    let file_cache = init_file_cache().await;
+
+    let block_ingestion = TestBlockIngestion {
+        provider: anvil_provider.clone(),
+    };
+
+    let options = IngestionServiceOptions {
+        ending_block: Some(50),
+        ..Default::default()
+    };
+
+    let mut service = IngestionService::new(
+        block_ingestion,
+        etcd_client,
+        object_store,
+        file_cache,
+        options,
+        IngestionMetrics::default(),
+    );
+
+    anvil_provider.anvil_mine(100, 3).await;
+
+    let starting_state = service.initialize().await.unwrap();
+    let ingest_state = starting_state.take_ingest().unwrap();
+
+    // The head never moves past the ending block.
+    assert_eq!(ingest_state.head.number, 50);
+    assert!(ingest_state.finalized.number <= 50);
+
+    anvil_provider.anvil_mine(10, 3).await;
+
+    let state = service.tick_refresh_head(ingest_state).await.unwrap();
+    let state = state.take_ingest().unwrap();
+    assert_eq!(state.head.number, 50);
+    assert_eq!(state.queued_block_number, 50);
+}
+
+#[tokio::test]
+async fn test_ingestion_restart_after_ending_block() {
+    let (_minio, object_store) = init_minio().await;
+    let (_etcd_server, etcd_client) = init_etcd_server().await;
+    let (_anvil_server, anvil_provider) = init_anvil().await;
+
+    let mut state_client = IngestionStateClient::new(&etcd_client);
+    let file_cache = init_file_cache().await;
+
+    let ct = CancellationToken::new();
+    let mut lock = etcd_client
+        .lock_client(LockOptions::default())
+        .lock("ingestion/lock", ct.clone())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let new_service = |ending_block: Option<u64>| {
+        let options = IngestionServiceOptions {
+            ending_block,
+            ..Default::default()
+        };
+
+        IngestionService::new(
+            TestBlockIngestion {
+                provider: anvil_provider.clone(),
+            },
+            etcd_client.clone(),
+            object_store.clone(),
+            file_cache.clone(),
+            options,
+            IngestionMetrics::default(),
+        )
+    };
+
+    anvil_provider.anvil_mine(200, 3).await;
+
+    new_service(Some(50))
+        .start(&mut lock, ct.clone())
+        .await
+        .unwrap();
+    assert_eq!(state_client.get_ended().await.unwrap(), Some(50));
+
+    // Restarting with the same ending block doesn't ingest anything.
+    new_service(Some(50))
+        .start(&mut lock, ct.clone())
+        .await
+        .unwrap();
+    assert_eq!(state_client.get_ended().await.unwrap(), Some(50));
+
+    // The ending block can't be lowered.
+    let err = new_service(Some(40))
+        .start(&mut lock, ct.clone())
+        .await
+        .unwrap_err();
+    assert!(matches!(err.current_context(), IngestionError::Options));
+    assert_eq!(state_client.get_ended().await.unwrap(), Some(50));
+
+    // Raising the ending block resumes ingestion up to the new ending block.
+    new_service(Some(80))
+        .start(&mut lock, ct.clone())
+        .await
+        .unwrap();
+    assert_eq!(state_client.get_ended().await.unwrap(), Some(80));
+
+    // Clearing the ending block resumes ingestion without end.
+    let service = new_service(None);
+    let service_ct = ct.child_token();
+    let service_task = tokio::spawn({
+        let ct = service_ct.clone();
+        async move { service.start(&mut lock, ct).await }
+    });
+
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while state_client.get_ended().await.unwrap().is_some() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("ended block not cleared");
+
+    service_ct.cancel();
+    service_task.await.unwrap().unwrap();
+}
+
 #[tokio::test]
 async fn test_ingestion_advances_as_head_changes() {
     let (_minio, object_store) = init_minio().await;
@@ -2,8 +2,8 @@ use testcontainers::{runners::AsyncRunner, ContainerAsync};
 
 use apibara_dna_common::object_store::{
     testing::{self, azurite_container, minio_container, AzuriteExt, MinIOExt},
-    AwsS3Client, AzureBlobClient, DeleteOptions, GetOptions, ObjectETag, ObjectStore,
-    ObjectStoreClient, ObjectStoreOptions, ObjectStoreResultExt, PutMode, PutOptions,
+    AwsS3Client, AzureBlobClient, DeleteOptions, GetOptions, ListOptions, MemoryClient, ObjectETag,
+    ObjectStore, ObjectStoreClient, ObjectStoreOptions, ObjectStoreResultExt, PutMode, PutOptions,
 };
 
 async fn start_minio() -> (ContainerAsync<testing::MinIO>, ObjectStoreClient) {
@@ -344,3 +344,106 @@ async fn test_azure_delete() {
     let (_azurite, inner) = start_azurite().await;
     do_delete(inner).await;
 }
+
+async fn do_list_pages(inner: ObjectStoreClient) {
+    let client = ObjectStore::new(
+        inner,
+        ObjectStoreOptions {
+            bucket: "test".to_string(),
+            prefix: Some("list".to_string()),
+        },
+    );
+
+    client.ensure_bucket().await.unwrap();
+
+    // More objects than returned by a single list request.
+    for i in 0..1_100 {
+        client
+            .put(
+                &format!("segment/{i:0>10}/index"),
+                "Hello, World".into(),
+                PutOptions::default(),
+            )
+            .await
+            .unwrap();
+    }
+    client
+        .put(
+            "other/0000000000/index",
+            "Hello, World".into(),
+            PutOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    // Azure returns up to 5000 objects by default, so request S3's page size explicitly.
+    let first_page = client
+        .list_page(
+            "segment/",
+            ListOptions {
+                max_keys: Some(1_000),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(first_page.objects.len(), 1_000);
+    assert!(first_page.continuation_token.is_some());
+
+    let second_page = client
+        .list_page(
+            "segment/",
+            ListOptions {
+                continuation_token: first_page.continuation_token,
+                max_keys: Some(1_000),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(second_page.objects.len(), 100);
+    assert!(second_page.continuation_token.is_none());
+    assert_eq!(
+        second_page
+            .object_ids
+            .last()
+            .map(|id| client.relative_path(id)),
+        Some("segment/0000001099/index")
+    );
+
+    let small_page = client
+        .list_page(
+            "segment/",
+            ListOptions {
+                max_keys: Some(10),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(small_page.objects.len(), 10);
+    assert!(small_page.continuation_token.is_some());
+
+    let all = client
+        .list("segment/", ListOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(all.objects.len(), 1_100);
+    assert!(all.continuation_token.is_none());
+}
+
+#[tokio::test]
+async fn test_s3_list_pages() {
+    let (_minio, inner) = start_minio().await;
+    do_list_pages(inner).await;
+}
+
+#[tokio::test]
+async fn test_azure_list_pages() {
+    let (_azurite, inner) = start_azurite().await;
+    do_list_pages(inner).await;
+}
+
+#[tokio::test]
+async fn test_memory_list_pages() {
+    do_list_pages(MemoryClient::new().into()).await;
+}
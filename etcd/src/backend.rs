@@ -0,0 +1,341 @@
+//! Stores for the coordination state.
+//!
+//! The clients in this crate format keys and attach context to errors, then forward the
+//! request to a [CoordinationBackend]. The backend is either a real etcd cluster or the
+//! in-process [EmbeddedStore](crate::embedded::EmbeddedStore).
+use std::time::{Duration, Instant};
+
+use error_stack::{Result, ResultExt};
+use etcd_client::{Compare, CompareOp, GetOptions, LeaseKeeper, PutOptions, Txn, TxnOp};
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, StreamExt},
+    FutureExt,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::{
+    client::{AuthOptions, EtcdClientError, StatusResponse},
+    kv::KeyValue,
+    lock::Lock,
+    watch::{WatchResponse, Watcher},
+};
+
+pub(crate) type BackendFuture<'a, T> = BoxFuture<'a, Result<T, EtcdClientError>>;
+
+pub(crate) type WatchStream = BoxStream<'static, Result<WatchResponse, EtcdClientError>>;
+
+/// A store for the state shared by the nodes of a deployment.
+///
+/// Keys passed to the backend already include the client prefix.
+pub(crate) trait CoordinationBackend: Send + Sync {
+    fn status(&self) -> BackendFuture<'_, StatusResponse>;
+
+    /// Refresh the authentication token, if the backend uses one.
+    fn renew_auth_token(&self, auth: &AuthOptions) -> BackendFuture<'_, ()>;
+
+    /// Returns the value of `key`, or of all keys starting with `key` if `with_prefix` is set.
+    fn get(&self, key: Vec<u8>, with_prefix: bool) -> BackendFuture<'_, Vec<KeyValue>>;
+
+    /// Atomically put and delete the given keys.
+    fn txn(&self, put: Vec<(Vec<u8>, Vec<u8>)>, delete: Vec<Vec<u8>>) -> BackendFuture<'_, ()>;
+
+    fn watch_prefix(&self, prefix: Vec<u8>) -> BackendFuture<'_, (Watcher, WatchStream)>;
+
+    /// Wait until the lock is acquired, or return `None` if cancelled.
+    fn lock(
+        &self,
+        key: Vec<u8>,
+        ttl: Duration,
+        ct: CancellationToken,
+    ) -> BackendFuture<'_, Option<Lock>>;
+
+    /// Acquire the lock, or return `None` if it's held by someone else.
+    fn try_lock(&self, key: Vec<u8>, ttl: Duration) -> BackendFuture<'_, Option<Lock>>;
+}
+
+/// The lease that keeps a lock held.
+pub(crate) trait LockLease: Send + Sync {
+    /// Extend the lease so that the lock doesn't expire.
+    fn keep_alive(&mut self) -> BackendFuture<'_, ()>;
+
+    /// Returns whether the lock is still held.
+    fn is_alive(&self) -> BackendFuture<'_, bool>;
+
+    /// Release the lock.
+    fn release(self: Box<Self>) -> BackendFuture<'static, ()>;
+}
+
+/// A backend that stores the state in etcd.
+pub(crate) struct EtcdBackend {
+    client: etcd_client::Client,
+}
+
+struct EtcdLease {
+    client: etcd_client::Client,
+    key: Vec<u8>,
+    keeper: LeaseKeeper,
+    lease_id: i64,
+    min_refresh_interval: Duration,
+    last_refresh: Instant,
+}
+
+impl EtcdBackend {
+    pub fn new(client: etcd_client::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl CoordinationBackend for EtcdBackend {
+    fn status(&self) -> BackendFuture<'_, StatusResponse> {
+        let mut client = self.client.clone();
+        async move {
+            let response = client
+                .status()
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to get etcd status")?;
+
+            Ok(StatusResponse::new(response.version()))
+        }
+        .boxed()
+    }
+
+    fn renew_auth_token(&self, auth: &AuthOptions) -> BackendFuture<'_, ()> {
+        let mut client = self.client.clone();
+        let auth = auth.clone();
+        async move {
+            client
+                .set_client_auth(auth.user, auth.password)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to renew auth token")
+        }
+        .boxed()
+    }
+
+    fn get(&self, key: Vec<u8>, with_prefix: bool) -> BackendFuture<'_, Vec<KeyValue>> {
+        let mut client = self.client.kv_client();
+        async move {
+            let options = with_prefix.then(|| GetOptions::new().with_prefix());
+            let response = client
+                .get(key, options)
+                .await
+                .change_context(EtcdClientError)?;
+
+            Ok(response.kvs().iter().map(KeyValue::from).collect())
+        }
+        .boxed()
+    }
+
+    fn txn(&self, put: Vec<(Vec<u8>, Vec<u8>)>, delete: Vec<Vec<u8>>) -> BackendFuture<'_, ()> {
+        let mut client = self.client.kv_client();
+        async move {
+            let mut ops = Vec::with_capacity(put.len() + delete.len());
+
+            for (key, value) in put {
+                ops.push(TxnOp::put(key, value, None));
+            }
+
+            for key in delete {
+                ops.push(TxnOp::delete(key, None));
+            }
+
+            client
+                .txn(Txn::new().and_then(ops))
+                .await
+                .change_context(EtcdClientError)?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn watch_prefix(&self, prefix: Vec<u8>) -> BackendFuture<'_, (Watcher, WatchStream)> {
+        let mut client = self.client.watch_client();
+        async move {
+            let options = etcd_client::WatchOptions::new().with_prefix();
+
+            let (watcher, stream) = client
+                .watch(prefix, options.into())
+                .await
+                .change_context(EtcdClientError)?;
+
+            let stream = stream
+                .map(|res| res.map(WatchResponse::from).change_context(EtcdClientError))
+                .boxed();
+
+            Ok((Watcher::new(watcher), stream))
+        }
+        .boxed()
+    }
+
+    fn lock(
+        &self,
+        key: Vec<u8>,
+        ttl: Duration,
+        ct: CancellationToken,
+    ) -> BackendFuture<'_, Option<Lock>> {
+        let mut client = self.client.clone();
+        async move {
+            let lease = client
+                .lease_grant(ttl.as_secs() as i64, None)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to acquire lock")?;
+
+            let lease_id = lease.id();
+
+            let (mut keep_alive, _) = client
+                .lease_keep_alive(lease_id)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to keep lease alive")?;
+
+            let options = etcd_client::LockOptions::new().with_lease(lease_id);
+
+            let min_refresh_interval = ttl / 2;
+
+            let mut refresh_interval = tokio::time::interval(min_refresh_interval);
+
+            let mut lock_client = client.clone();
+            let lock_fut = lock_client.lock(key, options.into());
+            tokio::pin!(lock_fut);
+
+            // Keep refreshing the lease while waiting for the lock.
+            loop {
+                tokio::select! {
+                    _ = ct.cancelled() => {
+                        return Ok(None);
+                    }
+                    _ = refresh_interval.tick() => {
+                        keep_alive.keep_alive().await.change_context(EtcdClientError)?;
+                    }
+                    response = &mut lock_fut => {
+                        let inner = response.change_context(EtcdClientError)
+                            .attach_printable("failed to lock key")?;
+
+                        let lease = EtcdLease {
+                            client,
+                            key: inner.key().to_vec(),
+                            keeper: keep_alive,
+                            lease_id,
+                            min_refresh_interval,
+                            last_refresh: Instant::now(),
+                        };
+
+                        return Ok(Lock::new(Box::new(lease)).into());
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn try_lock(&self, key: Vec<u8>, ttl: Duration) -> BackendFuture<'_, Option<Lock>> {
+        let mut client = self.client.clone();
+        async move {
+            let lease = client
+                .lease_grant(ttl.as_secs() as i64, None)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to acquire lock")?;
+
+            let lease_id = lease.id();
+
+            let txn = Txn::new()
+                .when([Compare::create_revision(key.clone(), CompareOp::Equal, 0)])
+                .and_then([TxnOp::put(
+                    key.clone(),
+                    Vec::<u8>::new(),
+                    PutOptions::new().with_lease(lease_id).into(),
+                )]);
+
+            let response = client
+                .txn(txn)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to lock key")?;
+
+            if !response.succeeded() {
+                client
+                    .lease_revoke(lease_id)
+                    .await
+                    .change_context(EtcdClientError)
+                    .attach_printable("failed to revoke lease")?;
+                return Ok(None);
+            }
+
+            let (keeper, _) = client
+                .lease_keep_alive(lease_id)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to keep lease alive")?;
+
+            let lease = EtcdLease {
+                client,
+                key,
+                keeper,
+                lease_id,
+                min_refresh_interval: ttl / 2,
+                last_refresh: Instant::now(),
+            };
+
+            Ok(Lock::new(Box::new(lease)).into())
+        }
+        .boxed()
+    }
+}
+
+impl LockLease for EtcdLease {
+    fn keep_alive(&mut self) -> BackendFuture<'_, ()> {
+        async move {
+            if self.last_refresh.elapsed() <= self.min_refresh_interval {
+                return Ok(());
+            }
+
+            debug!(lease_id = %self.lease_id, "send keep alive message");
+            self.keeper
+                .keep_alive()
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to keep lease alive")?;
+            self.last_refresh = Instant::now();
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn is_alive(&self) -> BackendFuture<'_, bool> {
+        let mut client = self.client.clone();
+        async move {
+            let response = client
+                .lease_time_to_live(self.lease_id, None)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to get lease time to live")
+                .attach_printable_lazy(|| format!("lease id: {}", self.lease_id))?;
+
+            Ok(response.ttl() > 0)
+        }
+        .boxed()
+    }
+
+    fn release(self: Box<Self>) -> BackendFuture<'static, ()> {
+        let Self {
+            mut client, key, ..
+        } = *self;
+        async move {
+            client
+                .unlock(key)
+                .await
+                .change_context(EtcdClientError)
+                .attach_printable("failed to unlock lock")?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
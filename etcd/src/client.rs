@@ -1,14 +1,17 @@
-use std::time::Duration;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use error_stack::{Result, ResultExt};
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
-use crate::{lock::LockOptions, utils::normalize_prefix, watch::WatchClient};
-
-pub use etcd_client::StatusResponse;
-
-use crate::{kv::KvClient, lock::LockClient};
+use crate::{
+    backend::{CoordinationBackend, EtcdBackend},
+    embedded::EmbeddedStore,
+    kv::KvClient,
+    lock::{LockClient, LockOptions},
+    utils::normalize_prefix,
+    watch::WatchClient,
+};
 
 #[derive(Debug)]
 pub struct EtcdClientError;
@@ -28,11 +31,16 @@ pub struct EtcdClientOptions {
 
 #[derive(Clone)]
 pub struct EtcdClient {
-    pub(crate) client: etcd_client::Client,
+    pub(crate) backend: Arc<dyn CoordinationBackend>,
     auth: Option<AuthOptions>,
     prefix: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct StatusResponse {
+    version: String,
+}
+
 impl EtcdClient {
     pub async fn connect<E: AsRef<str>, S: AsRef<[E]>>(
         endpoints: S,
@@ -54,18 +62,34 @@ impl EtcdClient {
         let prefix = normalize_prefix(options.prefix);
 
         Ok(Self {
-            client,
+            backend: Arc::new(EtcdBackend::new(client)),
             prefix,
             auth: options.auth,
         })
     }
 
+    /// Store the state in a local file instead of etcd.
+    ///
+    /// Only one client can be created for each file, creating another one fails until the
+    /// first one is dropped.
+    pub async fn embedded(
+        path: impl Into<PathBuf>,
+        options: EtcdClientOptions,
+    ) -> Result<Self, EtcdClientError> {
+        let store = EmbeddedStore::open(path).await?;
+        let prefix = normalize_prefix(options.prefix);
+
+        Ok(Self {
+            backend: Arc::new(store),
+            prefix,
+            auth: None,
+        })
+    }
+
     pub async fn start_renew_auth_token(
         self,
         ct: CancellationToken,
     ) -> Result<(), EtcdClientError> {
-        let mut inner_client = self.client.clone();
-
         if let Some(auth) = self.auth.clone() {
             let renew_interval = auth.token_ttl / 2;
 
@@ -76,9 +100,7 @@ impl EtcdClient {
                     }
                     _ = tokio::time::sleep(renew_interval) => {
                         info!("renewing etcd auth token");
-                        inner_client.set_client_auth(auth.user.clone(), auth.password.clone()).await
-                            .change_context(EtcdClientError)
-                            .attach_printable("failed to renew auth token")?;
+                        self.backend.renew_auth_token(&auth).await?;
                     }
                 }
             }
@@ -90,36 +112,44 @@ impl EtcdClient {
     }
 
     pub async fn status(&mut self) -> Result<StatusResponse, EtcdClientError> {
-        self.client
-            .status()
-            .await
-            .change_context(EtcdClientError)
-            .attach_printable("failed to get etcd status")
+        self.backend.status().await
     }
 
     pub fn kv_client(&self) -> KvClient {
         KvClient {
-            client: self.client.kv_client(),
+            backend: self.backend.clone(),
             prefix: self.prefix.clone(),
         }
     }
 
     pub fn watch_client(&self) -> WatchClient {
         WatchClient {
-            client: self.client.watch_client(),
+            backend: self.backend.clone(),
             prefix: self.prefix.clone(),
         }
     }
 
     pub fn lock_client(&self, options: LockOptions) -> LockClient {
         LockClient {
-            client: self.client.clone(),
+            backend: self.backend.clone(),
             prefix: self.prefix.clone(),
             options,
         }
     }
 }
 
+impl StatusResponse {
+    pub(crate) fn new(version: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+        }
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+}
+
 impl error_stack::Context for EtcdClientError {}
 
 impl std::fmt::Display for EtcdClientError {
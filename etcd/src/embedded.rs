@@ -0,0 +1,692 @@
+//! An in-process replacement for etcd, backed by a local state file.
+//!
+//! This backend is meant for single-node deployments: the state is only shared between the
+//! tasks of one process and watches are delivered through an in-process channel.
+//!
+//! Locks are held by leases that expire after their ttl unless kept alive, like in etcd.
+//! They're not persisted, so all locks are released when the process exits.
+//!
+//! The store takes an exclusive lock on a `.lock` file next to the state file, so that a
+//! second process using the same state file fails to start instead of overwriting the state.
+//! The state file itself is replaced atomically on every write.
+use std::{
+    collections::BTreeMap,
+    fs::{File, TryLockError},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use error_stack::{Result, ResultExt};
+use futures::{FutureExt, StreamExt};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{broadcast, broadcast::error::RecvError, Mutex, Notify},
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    backend::{BackendFuture, CoordinationBackend, LockLease, WatchStream},
+    client::{AuthOptions, EtcdClientError, StatusResponse},
+    kv::KeyValue,
+    lock::Lock,
+    watch::{EventType, WatchEvent, WatchResponse, Watcher},
+};
+
+const WATCH_CHANNEL_SIZE: usize = 1024;
+
+#[derive(Clone)]
+pub struct EmbeddedStore {
+    path: PathBuf,
+    state: Arc<Mutex<BTreeMap<String, String>>>,
+    changes: broadcast::Sender<WatchEvent>,
+    locks: Arc<EmbeddedLocks>,
+    /// Held for as long as the store is alive, the lock is released when it's closed.
+    _lock_file: Arc<File>,
+}
+
+/// The locks currently held, keyed by lock key.
+#[derive(Default)]
+struct EmbeddedLocks {
+    held: Mutex<BTreeMap<Vec<u8>, HeldLock>>,
+    released: Notify,
+    next_lease_id: AtomicU64,
+}
+
+struct HeldLock {
+    lease_id: u64,
+    expires_at: Instant,
+}
+
+struct EmbeddedLease {
+    locks: Arc<EmbeddedLocks>,
+    key: Vec<u8>,
+    lease_id: u64,
+    ttl: Duration,
+}
+
+impl EmbeddedStore {
+    /// Load the state from the given file, creating an empty state if it doesn't exist.
+    ///
+    /// Fails if another store has the state file open, in this or in another process.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self, EtcdClientError> {
+        let path = path.into();
+        let lock_file = lock_state_file(&path)?;
+
+        let state = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .change_context(EtcdClientError)
+                .attach_printable("failed to decode embedded state file")
+                .attach_printable_lazy(|| format!("path: {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::default(),
+            Err(err) => {
+                return Err(err)
+                    .change_context(EtcdClientError)
+                    .attach_printable("failed to read embedded state file")
+                    .attach_printable_lazy(|| format!("path: {}", path.display()));
+            }
+        };
+
+        let (changes, _) = broadcast::channel(WATCH_CHANNEL_SIZE);
+
+        Ok(Self {
+            path,
+            state: Arc::new(Mutex::new(state)),
+            changes,
+            locks: Default::default(),
+            _lock_file: Arc::new(lock_file),
+        })
+    }
+
+    async fn get_key(&self, key: &[u8]) -> Result<Vec<KeyValue>, EtcdClientError> {
+        let key = decode_key(key)?;
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&key)
+            .map(|value| KeyValue::new(key.clone(), value.clone()))
+            .into_iter()
+            .collect())
+    }
+
+    async fn get_prefix(&self, prefix: &[u8]) -> Result<Vec<KeyValue>, EtcdClientError> {
+        let prefix = decode_key(prefix)?;
+        let state = self.state.lock().await;
+
+        Ok(state
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+            .collect())
+    }
+
+    /// Atomically put and delete the given keys, then notify watchers.
+    async fn apply_txn(
+        &self,
+        put: Vec<(Vec<u8>, Vec<u8>)>,
+        delete: Vec<Vec<u8>>,
+    ) -> Result<(), EtcdClientError> {
+        let mut events = Vec::with_capacity(put.len() + delete.len());
+        let mut state = self.state.lock().await;
+        let mut new_state = state.clone();
+
+        for (key, value) in put {
+            let key = decode_key(&key)?;
+            let value = String::from_utf8(value)
+                .change_context(EtcdClientError)
+                .attach_printable("embedded state values must be valid utf-8")
+                .attach_printable_lazy(|| format!("key: {key}"))?;
+
+            new_state.insert(key.clone(), value.clone());
+            events.push(WatchEvent::new(EventType::Put, KeyValue::new(key, value)));
+        }
+
+        for key in delete {
+            let key = decode_key(&key)?;
+            if new_state.remove(&key).is_some() {
+                events.push(WatchEvent::new(
+                    EventType::Delete,
+                    KeyValue::new(key, String::new()),
+                ));
+            }
+        }
+
+        self.persist(&new_state).await?;
+        *state = new_state;
+
+        for event in events {
+            // Sending only fails if there are no watchers.
+            let _ = self.changes.send(event);
+        }
+
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<WatchEvent> {
+        self.changes.subscribe()
+    }
+
+    /// Acquire the lock, or return when the current lease expires if it's held.
+    async fn try_acquire(&self, key: &[u8], ttl: Duration) -> std::result::Result<Lock, Instant> {
+        let mut held = self.locks.held.lock().await;
+        let now = Instant::now();
+
+        if let Some(current) = held.get(key) {
+            if current.expires_at > now {
+                return Err(current.expires_at);
+            }
+        }
+
+        let lease_id = self.locks.next_lease_id.fetch_add(1, Ordering::Relaxed);
+        held.insert(
+            key.to_vec(),
+            HeldLock {
+                lease_id,
+                expires_at: now + ttl,
+            },
+        );
+
+        let lease = EmbeddedLease {
+            locks: self.locks.clone(),
+            key: key.to_vec(),
+            lease_id,
+            ttl,
+        };
+
+        Ok(Lock::new(Box::new(lease)))
+    }
+
+    async fn persist(&self, state: &BTreeMap<String, String>) -> Result<(), EtcdClientError> {
+        let data = serde_json::to_vec_pretty(state)
+            .change_context(EtcdClientError)
+            .attach_printable("failed to encode embedded state")?;
+
+        // Write to a temporary file first so that a crash never leaves a partial state behind.
+        // The data is synced before the rename, so the new file is complete once it's visible.
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp_file = tokio::fs::File::create(&tmp_path)
+            .await
+            .change_context(EtcdClientError)
+            .attach_printable("failed to create embedded state file")
+            .attach_printable_lazy(|| format!("path: {}", tmp_path.display()))?;
+        tmp_file
+            .write_all(&data)
+            .await
+            .change_context(EtcdClientError)
+            .attach_printable("failed to write embedded state file")
+            .attach_printable_lazy(|| format!("path: {}", tmp_path.display()))?;
+        tmp_file
+            .sync_all()
+            .await
+            .change_context(EtcdClientError)
+            .attach_printable("failed to sync embedded state file")
+            .attach_printable_lazy(|| format!("path: {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .change_context(EtcdClientError)
+            .attach_printable("failed to rename embedded state file")
+            .attach_printable_lazy(|| format!("path: {}", self.path.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Take an exclusive lock on the `.lock` file next to the state file.
+///
+/// The state file is replaced on every write, so it can't hold the lock itself.
+fn lock_state_file(path: &std::path::Path) -> Result<File, EtcdClientError> {
+    let lock_path = path.with_extension("lock");
+
+    let lock_file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .change_context(EtcdClientError)
+        .attach_printable("failed to open embedded state lock file")
+        .attach_printable_lazy(|| format!("path: {}", lock_path.display()))?;
+
+    match lock_file.try_lock() {
+        Ok(()) => Ok(lock_file),
+        Err(TryLockError::WouldBlock) => Err(EtcdClientError)
+            .attach_printable("embedded state file is already in use")
+            .attach_printable("only one process can use the embedded state file at a time")
+            .attach_printable_lazy(|| format!("path: {}", path.display())),
+        Err(TryLockError::Error(err)) => Err(err)
+            .change_context(EtcdClientError)
+            .attach_printable("failed to lock embedded state file")
+            .attach_printable_lazy(|| format!("path: {}", lock_path.display())),
+    }
+}
+
+impl CoordinationBackend for EmbeddedStore {
+    fn status(&self) -> BackendFuture<'_, StatusResponse> {
+        async move { Ok(StatusResponse::new("embedded")) }.boxed()
+    }
+
+    fn renew_auth_token(&self, _auth: &AuthOptions) -> BackendFuture<'_, ()> {
+        async move { Ok(()) }.boxed()
+    }
+
+    fn get(&self, key: Vec<u8>, with_prefix: bool) -> BackendFuture<'_, Vec<KeyValue>> {
+        async move {
+            if with_prefix {
+                self.get_prefix(&key).await
+            } else {
+                self.get_key(&key).await
+            }
+        }
+        .boxed()
+    }
+
+    fn txn(&self, put: Vec<(Vec<u8>, Vec<u8>)>, delete: Vec<Vec<u8>>) -> BackendFuture<'_, ()> {
+        self.apply_txn(put, delete).boxed()
+    }
+
+    fn watch_prefix(&self, prefix: Vec<u8>) -> BackendFuture<'_, (Watcher, WatchStream)> {
+        let changes = self.subscribe();
+
+        let stream = futures::stream::unfold(changes, move |mut changes| {
+            let prefix = prefix.clone();
+            async move {
+                loop {
+                    match changes.recv().await {
+                        Ok(event) => {
+                            let matches =
+                                event.kv().is_some_and(|kv| kv.key().starts_with(&prefix));
+                            if matches {
+                                let response = WatchResponse::new(vec![event]);
+                                return Some((Ok(response), changes));
+                            }
+                        }
+                        Err(RecvError::Closed) => return None,
+                        Err(RecvError::Lagged(skipped)) => {
+                            let err = Err(EtcdClientError)
+                                .attach_printable("embedded watch lagged behind")
+                                .attach_printable(format!("skipped: {skipped}"));
+                            return Some((err, changes));
+                        }
+                    }
+                }
+            }
+        });
+
+        async move { Ok((Watcher::new(()), stream.boxed())) }.boxed()
+    }
+
+    fn lock(
+        &self,
+        key: Vec<u8>,
+        ttl: Duration,
+        ct: CancellationToken,
+    ) -> BackendFuture<'_, Option<Lock>> {
+        async move {
+            loop {
+                // Register for release notifications before checking the lock to avoid
+                // missing a release that happens in between.
+                let released = self.locks.released.notified();
+                tokio::pin!(released);
+                released.as_mut().enable();
+
+                let expires_at = match self.try_acquire(&key, ttl).await {
+                    Ok(lock) => return Ok(Some(lock)),
+                    Err(expires_at) => expires_at,
+                };
+
+                tokio::select! {
+                    _ = ct.cancelled() => return Ok(None),
+                    _ = &mut released => {}
+                    _ = tokio::time::sleep_until(expires_at) => {}
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn try_lock(&self, key: Vec<u8>, ttl: Duration) -> BackendFuture<'_, Option<Lock>> {
+        async move { Ok(self.try_acquire(&key, ttl).await.ok()) }.boxed()
+    }
+}
+
+impl LockLease for EmbeddedLease {
+    fn keep_alive(&mut self) -> BackendFuture<'_, ()> {
+        async move {
+            let mut held = self.locks.held.lock().await;
+            let now = Instant::now();
+
+            match held.get_mut(&self.key) {
+                Some(current) if current.lease_id == self.lease_id && current.expires_at > now => {
+                    current.expires_at = now + self.ttl;
+                    Ok(())
+                }
+                _ => Err(EtcdClientError)
+                    .attach_printable("failed to keep lease alive: lease expired")
+                    .attach_printable_lazy(|| format!("lease id: {}", self.lease_id)),
+            }
+        }
+        .boxed()
+    }
+
+    fn is_alive(&self) -> BackendFuture<'_, bool> {
+        async move {
+            let held = self.locks.held.lock().await;
+            Ok(held.get(&self.key).is_some_and(|current| {
+                current.lease_id == self.lease_id && current.expires_at > Instant::now()
+            }))
+        }
+        .boxed()
+    }
+
+    fn release(self: Box<Self>) -> BackendFuture<'static, ()> {
+        async move {
+            let mut held = self.locks.held.lock().await;
+
+            // Don't release the lock if it expired and was acquired by someone else.
+            if held
+                .get(&self.key)
+                .is_some_and(|current| current.lease_id == self.lease_id)
+            {
+                held.remove(&self.key);
+                self.locks.released.notify_waiters();
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+fn decode_key(key: &[u8]) -> Result<String, EtcdClientError> {
+    String::from_utf8(key.to_vec())
+        .change_context(EtcdClientError)
+        .attach_printable("embedded state keys must be valid utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{EtcdClient, EtcdClientOptions, EventType, LockOptions};
+
+    async fn new_client(dir: &tempfile::TempDir) -> EtcdClient {
+        EtcdClient::embedded(dir.path().join("state.json"), EtcdClientOptions::default())
+            .await
+            .expect("embedded client")
+    }
+
+    fn value_of(response: &crate::GetResponse) -> Option<String> {
+        response
+            .kvs()
+            .first()
+            .map(|kv| String::from_utf8(kv.value().to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_get_and_put() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut kv = client.kv_client();
+
+        assert!(kv.get("ingestion/ingested").await.unwrap().kvs().is_empty());
+
+        kv.put("ingestion/ingested", "100").await.unwrap();
+        kv.put("ingestion/segmented", "99").await.unwrap();
+        kv.put("options/segment_size", "10").await.unwrap();
+
+        let ingested = kv.get("ingestion/ingested").await.unwrap();
+        assert_eq!(value_of(&ingested).as_deref(), Some("100"));
+
+        let ingestion = kv.get_prefix("ingestion/").await.unwrap();
+        let keys = ingestion
+            .kvs()
+            .iter()
+            .map(|kv| String::from_utf8(kv.key().to_vec()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["ingestion/ingested", "ingestion/segmented"]);
+
+        kv.put_and_delete("ingestion/ingested", "101", "ingestion/segmented")
+            .await
+            .unwrap();
+
+        let ingestion = kv.get_prefix("ingestion/").await.unwrap();
+        assert_eq!(ingestion.kvs().len(), 1);
+        assert_eq!(value_of(&ingestion).as_deref(), Some("101"));
+    }
+
+    #[tokio::test]
+    async fn test_state_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let client = new_client(&dir).await;
+            client
+                .kv_client()
+                .put_and_delete_many(
+                    &[
+                        ("a".to_string(), b"1".to_vec()),
+                        ("b".to_string(), b"2".to_vec()),
+                    ],
+                    &["c".to_string()],
+                )
+                .await
+                .unwrap();
+        }
+
+        let client = new_client(&dir).await;
+        let mut kv = client.kv_client();
+        assert_eq!(value_of(&kv.get("a").await.unwrap()).as_deref(), Some("1"));
+        assert_eq!(value_of(&kv.get("b").await.unwrap()).as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_state_file_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let client = new_client(&dir).await;
+        client.kv_client().put("a", "1").await.unwrap();
+
+        let second = EtcdClient::embedded(dir.path().join("state.json"), Default::default()).await;
+        assert!(second.is_err());
+
+        drop(client);
+
+        let client = new_client(&dir).await;
+        let mut kv = client.kv_client();
+        assert_eq!(value_of(&kv.get("a").await.unwrap()).as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn test_client_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = dir.path().join("state.json");
+
+        let client = EtcdClient::embedded(
+            &store_path,
+            EtcdClientOptions {
+                prefix: Some("dna".to_string()),
+                auth: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        client.kv_client().put("key", "value").await.unwrap();
+
+        let state = std::fs::read_to_string(&store_path).unwrap();
+        assert!(state.contains("dna/key"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut kv = client.kv_client();
+
+        let ct = CancellationToken::new();
+        let (_watcher, stream) = client
+            .watch_client()
+            .watch_prefix("ingestion/", ct.clone())
+            .await
+            .unwrap();
+        tokio::pin!(stream);
+
+        kv.put("options/segment_size", "10").await.unwrap();
+        kv.put("ingestion/ingested", "100").await.unwrap();
+        kv.put_and_delete("ingestion/segmented", "99", "ingestion/ingested")
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while events.len() < 3 {
+            let response = stream.next().await.unwrap().unwrap();
+            for event in response.events() {
+                let key = String::from_utf8(event.kv().unwrap().key().to_vec()).unwrap();
+                events.push((event.event_type(), key));
+            }
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                (EventType::Put, "ingestion/ingested".to_string()),
+                (EventType::Put, "ingestion/segmented".to_string()),
+                (EventType::Delete, "ingestion/ingested".to_string()),
+            ]
+        );
+
+        ct.cancel();
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut lock_client = client.lock_client(LockOptions::default());
+
+        let lock = lock_client.try_lock("compaction/lock").await.unwrap();
+        let lock = lock.expect("lock is free");
+
+        assert!(lock_client
+            .try_lock("compaction/lock")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(lock_client.try_lock("other/lock").await.unwrap().is_some());
+        assert!(lock_client.is_locked(&lock).await.unwrap());
+
+        lock_client.unlock(lock).await.unwrap();
+
+        assert!(lock_client
+            .try_lock("compaction/lock")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_lock_waits_for_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut lock_client = client.lock_client(LockOptions::default());
+
+        let lock = lock_client
+            .lock("compaction/lock", CancellationToken::new())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let waiter = tokio::spawn({
+            let mut lock_client = client.lock_client(LockOptions::default());
+            async move {
+                lock_client
+                    .lock("compaction/lock", CancellationToken::new())
+                    .await
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiter.is_finished());
+
+        lock_client.unlock(lock).await.unwrap();
+
+        let lock = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter acquires the lock")
+            .unwrap()
+            .unwrap();
+        assert!(lock.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_lock_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut lock_client = client.lock_client(LockOptions::default());
+
+        let _lock = lock_client.try_lock("compaction/lock").await.unwrap();
+
+        let ct = CancellationToken::new();
+        ct.cancel();
+
+        let lock = lock_client.lock("compaction/lock", ct).await.unwrap();
+        assert!(lock.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lock_expires_without_keep_alive() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut lock_client = client.lock_client(LockOptions { ttl: 1 });
+
+        let mut lock = lock_client
+            .try_lock("compaction/lock")
+            .await
+            .unwrap()
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(1_100)).await;
+
+        assert!(!lock_client.is_locked(&lock).await.unwrap());
+        assert!(lock.keep_alive().await.is_err());
+
+        let other = lock_client.try_lock("compaction/lock").await.unwrap();
+        let other = other.expect("expired lock can be acquired");
+
+        // Releasing the expired lock must not release the new holder's lock.
+        lock_client.unlock(lock).await.unwrap();
+        assert!(lock_client.is_locked(&other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_lock_keep_alive() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir).await;
+        let mut lock_client = client.lock_client(LockOptions { ttl: 1 });
+
+        let mut lock = lock_client
+            .try_lock("compaction/lock")
+            .await
+            .unwrap()
+            .unwrap();
+
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(600)).await;
+            lock.keep_alive().await.unwrap();
+        }
+
+        assert!(lock_client.is_locked(&lock).await.unwrap());
+        assert!(lock_client
+            .try_lock("compaction/lock")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
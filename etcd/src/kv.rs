@@ -1,16 +1,26 @@
-use error_stack::{Result, ResultExt};
+use std::sync::Arc;
 
-use etcd_client::{GetOptions, TxnResponse};
-pub use etcd_client::{GetResponse, PutResponse};
+use error_stack::{Result, ResultExt};
 
-use crate::client::EtcdClientError;
+use crate::{backend::CoordinationBackend, client::EtcdClientError, utils::format_key};
 
 #[derive(Clone)]
 pub struct KvClient {
-    pub(crate) client: etcd_client::KvClient,
+    pub(crate) backend: Arc<dyn CoordinationBackend>,
     pub(crate) prefix: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct KeyValue {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GetResponse {
+    kvs: Vec<KeyValue>,
+}
+
 impl KvClient {
     #[tracing::instrument(level = "debug", skip_all, fields(key = prefix.as_ref()))]
     pub async fn get_prefix(
@@ -18,24 +28,27 @@ impl KvClient {
         prefix: impl AsRef<str>,
     ) -> Result<GetResponse, EtcdClientError> {
         let prefix = prefix.as_ref();
-        let options = GetOptions::new().with_prefix();
-        self.client
-            .get(self.format_key(prefix), options.into())
+        let kvs = self
+            .backend
+            .get(format_key(&self.prefix, prefix), true)
             .await
-            .change_context(EtcdClientError)
             .attach_printable("failed to get key with prefix from etcd")
-            .attach_printable_lazy(|| format!("prefix: {}", prefix))
+            .attach_printable_lazy(|| format!("prefix: {}", prefix))?;
+
+        Ok(GetResponse { kvs })
     }
 
     #[tracing::instrument(level = "debug", skip_all, fields(key = key.as_ref()))]
     pub async fn get(&mut self, key: impl AsRef<str>) -> Result<GetResponse, EtcdClientError> {
         let key = key.as_ref();
-        self.client
-            .get(self.format_key(key), None)
+        let kvs = self
+            .backend
+            .get(format_key(&self.prefix, key), false)
             .await
-            .change_context(EtcdClientError)
             .attach_printable("failed to get key from etcd")
-            .attach_printable_lazy(|| format!("key: {}", key))
+            .attach_printable_lazy(|| format!("key: {}", key))?;
+
+        Ok(GetResponse { kvs })
     }
 
     #[tracing::instrument(level = "debug", skip_all, fields(key = key.as_ref()))]
@@ -43,43 +56,104 @@ impl KvClient {
         &mut self,
         key: impl AsRef<str>,
         value: impl AsRef<[u8]>,
-    ) -> Result<PutResponse, EtcdClientError> {
+    ) -> Result<(), EtcdClientError> {
         let key = key.as_ref();
-        let value = value.as_ref();
-        self.client
-            .put(self.format_key(key), value, None)
+        let put = vec![(format_key(&self.prefix, key), value.as_ref().to_vec())];
+        self.backend
+            .txn(put, Vec::new())
             .await
-            .change_context(EtcdClientError)
             .attach_printable("failed to put key to etcd")
             .attach_printable_lazy(|| format!("key: {}", key))
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(key = key.as_ref()))]
+    pub async fn delete(&mut self, key: impl AsRef<str>) -> Result<(), EtcdClientError> {
+        let key = key.as_ref();
+        let delete = vec![format_key(&self.prefix, key)];
+        self.backend
+            .txn(Vec::new(), delete)
+            .await
+            .attach_printable("failed to delete key from etcd")
+            .attach_printable_lazy(|| format!("key: {}", key))
+    }
+
     #[tracing::instrument(level = "debug", skip_all, fields(put_key = put_key.as_ref(), del_key = del_key.as_ref()))]
     pub async fn put_and_delete(
         &mut self,
         put_key: impl AsRef<str>,
         put_value: impl AsRef<[u8]>,
         del_key: impl AsRef<str>,
-    ) -> Result<TxnResponse, EtcdClientError> {
+    ) -> Result<(), EtcdClientError> {
         let put_key = put_key.as_ref();
-        let put_value = put_value.as_ref();
         let del_key = del_key.as_ref();
 
-        let txn = etcd_client::Txn::new().and_then(&[
-            etcd_client::TxnOp::put(self.format_key(put_key), put_value, None),
-            etcd_client::TxnOp::delete(self.format_key(del_key), None),
-        ]);
+        let put = vec![(
+            format_key(&self.prefix, put_key),
+            put_value.as_ref().to_vec(),
+        )];
+        let delete = vec![format_key(&self.prefix, del_key)];
 
-        self.client
-            .txn(txn)
+        self.backend
+            .txn(put, delete)
             .await
-            .change_context(EtcdClientError)
             .attach_printable("failed to put and delete key to etcd")
             .attach_printable_lazy(|| format!("put_key: {}", put_key))
             .attach_printable_lazy(|| format!("del_key: {}", del_key))
     }
 
-    fn format_key(&self, key: &str) -> Vec<u8> {
-        format!("{}{}", self.prefix, key).into_bytes()
+    /// Atomically put and delete multiple keys.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn put_and_delete_many(
+        &mut self,
+        put: &[(String, Vec<u8>)],
+        delete: &[String],
+    ) -> Result<(), EtcdClientError> {
+        let put_keys = put
+            .iter()
+            .map(|(key, value)| (format_key(&self.prefix, key), value.clone()))
+            .collect();
+        let delete_keys = delete
+            .iter()
+            .map(|key| format_key(&self.prefix, key))
+            .collect();
+
+        self.backend
+            .txn(put_keys, delete_keys)
+            .await
+            .attach_printable("failed to put and delete keys to etcd")
+            .attach_printable_lazy(|| {
+                let keys = put.iter().map(|(key, _)| key).collect::<Vec<_>>();
+                format!("put keys: {:?}", keys)
+            })
+            .attach_printable_lazy(|| format!("delete keys: {:?}", delete))
+    }
+}
+
+impl KeyValue {
+    pub(crate) fn new(key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+impl From<&etcd_client::KeyValue> for KeyValue {
+    fn from(kv: &etcd_client::KeyValue) -> Self {
+        Self::new(kv.key(), kv.value())
+    }
+}
+
+impl GetResponse {
+    pub fn kvs(&self) -> &[KeyValue] {
+        &self.kvs
     }
 }
@@ -1,5 +1,7 @@
 //! A collection of utilities for working with etcd.
+mod backend;
 mod client;
+mod embedded;
 mod kv;
 mod lock;
 mod utils;
@@ -8,9 +10,9 @@ mod watch;
 pub use self::client::{
     AuthOptions, EtcdClient, EtcdClientError, EtcdClientOptions, StatusResponse,
 };
-pub use self::kv::{GetResponse, KvClient, PutResponse};
+pub use self::kv::{GetResponse, KeyValue, KvClient};
 pub use self::lock::{Lock, LockClient, LockOptions};
 pub use self::utils::normalize_prefix;
-pub use self::watch::WatchClient;
+pub use self::watch::{EventType, WatchClient, WatchEvent, WatchResponse, Watcher};
 
 pub use etcd_client::LeaseKeepAliveStream;
@@ -1,11 +1,13 @@
-use std::time::{Duration, Instant};
+use std::{sync::Arc, time::Duration};
 
 use error_stack::{Result, ResultExt};
-use etcd_client::{LeaseKeeper, LockResponse};
 use tokio_util::sync::CancellationToken;
-use tracing::debug;
 
-use crate::EtcdClientError;
+use crate::{
+    backend::{CoordinationBackend, LockLease},
+    utils::format_key,
+    EtcdClientError,
+};
 
 #[derive(Debug)]
 pub struct LockOptions {
@@ -13,17 +15,14 @@ pub struct LockOptions {
 }
 
 pub struct LockClient {
-    pub(crate) client: etcd_client::Client,
+    pub(crate) backend: Arc<dyn CoordinationBackend>,
     pub(crate) prefix: String,
     pub(crate) options: LockOptions,
 }
 
+/// A lock held until released or until its lease expires.
 pub struct Lock {
-    inner: LockResponse,
-    keeper: LeaseKeeper,
-    lease_id: i64,
-    min_refresh_interval: Duration,
-    last_refresh: Instant,
+    lease: Box<dyn LockLease>,
 }
 
 impl LockClient {
@@ -34,107 +33,54 @@ impl LockClient {
         ct: CancellationToken,
     ) -> Result<Option<Lock>, EtcdClientError> {
         let key = key.as_ref();
-
-        let lease = self
-            .client
-            .lease_grant(self.options.ttl, None)
+        self.backend
+            .lock(format_key(&self.prefix, key), self.ttl(), ct)
             .await
-            .change_context(EtcdClientError)
-            .attach_printable("failed to acquire lock")?;
-
-        let lease_id = lease.id();
+            .attach_printable("failed to lock key")
+            .attach_printable_lazy(|| format!("key: {}", key))
+    }
 
-        let (mut keep_alive, _) = self
-            .client
-            .lease_keep_alive(lease_id)
+    /// Acquire the lock only if nobody else is holding it.
+    ///
+    /// Unlike `lock`, this method returns `None` immediately if the lock is taken.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn try_lock(
+        &mut self,
+        key: impl AsRef<str>,
+    ) -> Result<Option<Lock>, EtcdClientError> {
+        let key = key.as_ref();
+        self.backend
+            .try_lock(format_key(&self.prefix, key), self.ttl())
             .await
-            .change_context(EtcdClientError)
-            .attach_printable("failed to keep lease alive")?;
-
-        let options = etcd_client::LockOptions::new().with_lease(lease_id);
-
-        let min_refresh_interval = Duration::from_secs(self.options.ttl as u64 / 2);
-
-        let mut refresh_interval = tokio::time::interval(min_refresh_interval);
-
-        let lock_fut = self.client.lock(self.format_key(key), options.into());
-        tokio::pin!(lock_fut);
-
-        // Keep refreshing the lease while waiting for the lock.
-        loop {
-            tokio::select! {
-                _ = ct.cancelled() => {
-                    return Ok(None);
-                }
-                _ = refresh_interval.tick() => {
-                    keep_alive.keep_alive().await.change_context(EtcdClientError)?;
-                }
-                response = &mut lock_fut => {
-                    let inner = response.change_context(EtcdClientError)
-                        .attach_printable("failed to lock key")
-                        .attach_printable_lazy(|| format!("key: {}", key))?;
-
-                    let lock = Lock {
-                        inner,
-                        keeper: keep_alive,
-                        lease_id,
-                        min_refresh_interval,
-                        last_refresh: Instant::now(),
-                    };
-
-                    return Ok(lock.into())
-                }
-            }
-        }
+            .attach_printable("failed to lock key")
+            .attach_printable_lazy(|| format!("key: {}", key))
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn unlock(&mut self, lock: Lock) -> Result<(), EtcdClientError> {
-        let lock = lock.inner;
-        let key = lock.key().to_vec();
-        self.client
-            .unlock(key)
-            .await
-            .change_context(EtcdClientError)
-            .attach_printable("failed to unlock lock")?;
-
-        Ok(())
+        lock.lease.release().await
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn is_locked(&mut self, lock: &Lock) -> Result<bool, EtcdClientError> {
-        let response = self
-            .client
-            .lease_time_to_live(lock.lease_id, None)
-            .await
-            .change_context(EtcdClientError)
-            .attach_printable("failed to get lease time to live")
-            .attach_printable_lazy(|| format!("lease id: {}", lock.lease_id))?;
-        Ok(response.ttl() > 0)
+        lock.lease.is_alive().await
     }
 
-    fn format_key(&self, key: &str) -> Vec<u8> {
-        format!("{}{}", self.prefix, key).into_bytes()
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.options.ttl.max(1) as u64)
     }
 }
 
 impl Lock {
-    pub async fn keep_alive(&mut self) -> Result<(), EtcdClientError> {
-        if self.last_refresh.elapsed() <= self.min_refresh_interval {
-            return Ok(());
-        }
-
-        debug!(lease_id = %self.lease_id, "send keep alive message");
-        self.keeper
-            .keep_alive()
-            .await
-            .change_context(EtcdClientError)
-            .attach_printable("failed to keep lease alive")?;
-        self.last_refresh = Instant::now();
+    pub(crate) fn new(lease: Box<dyn LockLease>) -> Self {
+        Self { lease }
+    }
 
-        Ok(())
+    pub async fn keep_alive(&mut self) -> Result<(), EtcdClientError> {
+        self.lease.keep_alive().await
     }
 }
+
 impl Default for LockOptions {
     fn default() -> Self {
         Self { ttl: 60 }
@@ -6,3 +6,7 @@ pub fn normalize_prefix(prefix: Option<String>) -> String {
         format!("{}/", prefix)
     }
 }
+
+pub(crate) fn format_key(prefix: &str, key: &str) -> Vec<u8> {
+    format!("{}{}", prefix, key).into_bytes()
+}
@@ -1,18 +1,41 @@
+use std::{any::Any, sync::Arc};
+
 use error_stack::{Result, ResultExt};
-use etcd_client::WatchOptions;
 use futures::{Stream, StreamExt};
 use tokio_util::sync::CancellationToken;
 
-use crate::client::EtcdClientError;
-
-pub use etcd_client::{WatchResponse, Watcher};
+use crate::{
+    backend::CoordinationBackend, client::EtcdClientError, kv::KeyValue, utils::format_key,
+};
 
 #[derive(Clone)]
 pub struct WatchClient {
-    pub(crate) client: etcd_client::WatchClient,
+    pub(crate) backend: Arc<dyn CoordinationBackend>,
     pub(crate) prefix: String,
 }
 
+/// Keeps the watch alive until dropped.
+pub struct Watcher {
+    _inner: Box<dyn Any + Send>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    Put,
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+    event_type: EventType,
+    kv: Option<KeyValue>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WatchResponse {
+    events: Vec<WatchEvent>,
+}
+
 impl WatchClient {
     pub async fn watch_prefix(
         &mut self,
@@ -26,24 +49,69 @@ impl WatchClient {
         EtcdClientError,
     > {
         let key = key.as_ref();
-        let options = WatchOptions::new().with_prefix();
 
         let (watcher, stream) = self
-            .client
-            .watch(self.format_key(key), options.into())
+            .backend
+            .watch_prefix(format_key(&self.prefix, key))
             .await
-            .change_context(EtcdClientError)
             .attach_printable("failed to watch key with prefix from etcd")
             .attach_printable_lazy(|| format!("prefix: {}", key))?;
 
-        let stream = stream
-            .map(|res| res.change_context(EtcdClientError))
-            .take_until(async move { ct.cancelled().await });
+        let stream = stream.take_until(async move { ct.cancelled().await });
 
         Ok((watcher, stream))
     }
+}
+
+impl Watcher {
+    pub(crate) fn new(inner: impl Any + Send) -> Self {
+        Self {
+            _inner: Box::new(inner),
+        }
+    }
+}
+
+impl WatchEvent {
+    pub(crate) fn new(event_type: EventType, kv: KeyValue) -> Self {
+        Self {
+            event_type,
+            kv: Some(kv),
+        }
+    }
+
+    pub fn event_type(&self) -> EventType {
+        self.event_type
+    }
+
+    pub fn kv(&self) -> Option<&KeyValue> {
+        self.kv.as_ref()
+    }
+}
+
+impl WatchResponse {
+    pub(crate) fn new(events: Vec<WatchEvent>) -> Self {
+        Self { events }
+    }
+
+    pub fn events(&self) -> &[WatchEvent] {
+        &self.events
+    }
+}
+
+impl From<etcd_client::WatchResponse> for WatchResponse {
+    fn from(response: etcd_client::WatchResponse) -> Self {
+        let events = response
+            .events()
+            .iter()
+            .map(|event| WatchEvent {
+                event_type: match event.event_type() {
+                    etcd_client::EventType::Put => EventType::Put,
+                    etcd_client::EventType::Delete => EventType::Delete,
+                },
+                kv: event.kv().map(KeyValue::from),
+            })
+            .collect();
 
-    fn format_key(&self, key: &str) -> Vec<u8> {
-        format!("{}{}", self.prefix, key).into_bytes()
+        Self { events }
     }
 }
@@ -0,0 +1,328 @@
+//! Decode logs and transaction input with the ABI provided in the filters.
+//!
+//! Decoding happens in the data stream, after filtering. Items that don't match the ABI are
+//! still sent to the client, with the decoding error in place of the decoded parameters.
+use alloy_dyn_abi::{DynSolValue, EventExt, JsonAbiExt};
+use alloy_json_abi::{Event, Function};
+use apibara_dna_common::query::{FilterId, FragmentDecoder};
+use apibara_dna_protocol::evm;
+use prost::Message;
+use serde::de::DeserializeOwned;
+
+use crate::{proto::ModelExt, provider::models};
+
+/// The tag of the `decoded` field in `Log`.
+const LOG_DECODED_TAG: u32 = 10;
+/// The tag of the `decoded` field in `Transaction`.
+const TRANSACTION_DECODED_TAG: u32 = 24;
+
+/// Decode logs with an event ABI.
+#[derive(Debug)]
+pub struct EventDecoder {
+    event: Event,
+}
+
+/// Decode the transaction input with a function ABI.
+#[derive(Debug)]
+pub struct FunctionDecoder {
+    function: Function,
+}
+
+impl EventDecoder {
+    /// Parse a JSON ABI fragment or an event signature.
+    pub fn parse(abi: &str) -> Result<Self, String> {
+        let event = parse_abi_item(abi, Event::parse)?;
+        Ok(Self { event })
+    }
+
+    /// Returns the first topic of the event's logs, if the event is not anonymous.
+    pub fn topic0(&self) -> Option<models::B256> {
+        (!self.event.anonymous).then(|| self.event.selector())
+    }
+
+    fn decode_log(&self, log: &evm::Log) -> Result<Vec<evm::DecodedParam>, String> {
+        let topics = log
+            .topics
+            .iter()
+            .map(|topic| models::B256::from(topic.to_bytes()));
+
+        let decoded = self
+            .event
+            .decode_log_parts(topics, &log.data)
+            .map_err(|err| err.to_string())?;
+
+        let mut indexed = decoded.indexed.iter();
+        let mut body = decoded.body.iter();
+
+        self.event
+            .inputs
+            .iter()
+            .map(|input| {
+                let value = if input.indexed {
+                    indexed.next()
+                } else {
+                    body.next()
+                };
+
+                let value = value.ok_or_else(|| format!("missing value for {}", input.name))?;
+
+                Ok(decoded_param(
+                    &input.name,
+                    &input.selector_type(),
+                    input.indexed,
+                    value,
+                ))
+            })
+            .collect()
+    }
+}
+
+impl FunctionDecoder {
+    /// Parse a JSON ABI fragment or a function signature.
+    pub fn parse(abi: &str) -> Result<Self, String> {
+        let function = parse_abi_item(abi, Function::parse)?;
+        Ok(Self { function })
+    }
+
+    /// Returns the function selector.
+    pub fn selector(&self) -> [u8; 4] {
+        self.function.selector().0
+    }
+
+    fn decode_input(
+        &self,
+        transaction: &evm::Transaction,
+    ) -> Result<Vec<evm::DecodedParam>, String> {
+        let Some((selector, input)) = transaction.input.split_first_chunk::<4>() else {
+            return Err("input is shorter than the function selector".to_string());
+        };
+
+        if *selector != self.selector() {
+            return Err("function selector mismatch".to_string());
+        }
+
+        let values = self
+            .function
+            .abi_decode_input(input)
+            .map_err(|err| err.to_string())?;
+
+        Ok(self
+            .function
+            .inputs
+            .iter()
+            .zip(values.iter())
+            .map(|(input, value)| decoded_param(&input.name, &input.selector_type(), false, value))
+            .collect())
+    }
+}
+
+impl FragmentDecoder for EventDecoder {
+    fn decode(&self, filter_id: FilterId, message: &[u8], output: &mut Vec<u8>) {
+        let params = evm::Log::decode(message)
+            .map_err(|err| format!("failed to decode log: {}", err))
+            .and_then(|log| self.decode_log(&log));
+
+        let decoded = new_decoded(filter_id, &self.event.name, params);
+        prost::encoding::message::encode(LOG_DECODED_TAG, &decoded, output);
+    }
+}
+
+impl FragmentDecoder for FunctionDecoder {
+    fn decode(&self, filter_id: FilterId, message: &[u8], output: &mut Vec<u8>) {
+        let params = evm::Transaction::decode(message)
+            .map_err(|err| format!("failed to decode transaction: {}", err))
+            .and_then(|transaction| self.decode_input(&transaction));
+
+        let decoded = new_decoded(filter_id, &self.function.name, params);
+        prost::encoding::message::encode(TRANSACTION_DECODED_TAG, &decoded, output);
+    }
+}
+
+/// Parse a JSON ABI fragment, or fall back to the human readable signature.
+fn parse_abi_item<T, E>(
+    abi: &str,
+    parse_signature: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<T, String>
+where
+    T: DeserializeOwned,
+    E: std::fmt::Display,
+{
+    let abi = abi.trim();
+
+    if abi.starts_with('{') {
+        serde_json::from_str(abi).map_err(|err| err.to_string())
+    } else {
+        parse_signature(abi).map_err(|err| err.to_string())
+    }
+}
+
+fn new_decoded(
+    filter_id: FilterId,
+    name: &str,
+    params: Result<Vec<evm::DecodedParam>, String>,
+) -> evm::Decoded {
+    let (params, error) = match params {
+        Ok(params) => (params, None),
+        Err(error) => (Vec::new(), Some(error)),
+    };
+
+    evm::Decoded {
+        filter_id,
+        name: name.to_string(),
+        params,
+        error,
+    }
+}
+
+fn decoded_param(name: &str, ty: &str, indexed: bool, value: &DynSolValue) -> evm::DecodedParam {
+    evm::DecodedParam {
+        name: name.to_string(),
+        r#type: ty.to_string(),
+        indexed,
+        value: Some(decoded_value(value)),
+    }
+}
+
+fn decoded_value(value: &DynSolValue) -> evm::DecodedValue {
+    use evm::decoded_value::Value;
+
+    let value = match value {
+        DynSolValue::Bool(value) => Value::Bool(*value),
+        DynSolValue::Int(value, _) => Value::Int(value.into_raw().to_proto()),
+        DynSolValue::Uint(value, _) => Value::Uint(value.to_proto()),
+        DynSolValue::Address(address) => Value::Address(address.to_proto()),
+        DynSolValue::FixedBytes(word, size) => Value::FixedBytes(word[..*size].to_vec()),
+        DynSolValue::Bytes(bytes) => Value::Bytes(bytes.clone()),
+        DynSolValue::String(value) => Value::String(value.clone()),
+        DynSolValue::Function(function) => Value::Function(function.to_vec()),
+        DynSolValue::Array(values) | DynSolValue::FixedArray(values) => {
+            Value::Array(decoded_value_list(values))
+        }
+        DynSolValue::Tuple(values) => Value::Tuple(decoded_value_list(values)),
+        // Structs are decoded as custom structs when the `eip712` feature is enabled.
+        #[allow(unreachable_patterns)]
+        value => Value::Tuple(decoded_value_list(value.as_tuple().unwrap_or_default())),
+    };
+
+    evm::DecodedValue { value: Some(value) }
+}
+
+fn decoded_value_list(values: &[DynSolValue]) -> evm::DecodedValueList {
+    evm::DecodedValueList {
+        values: values.iter().map(decoded_value).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{b256, hex};
+    use apibara_dna_common::query::FragmentDecoder;
+    use apibara_dna_protocol::evm;
+    use prost::Message;
+
+    use super::{EventDecoder, FunctionDecoder};
+
+    fn topic(value: &str) -> evm::B256 {
+        evm::B256::from_bytes(&hex::decode(value).unwrap().try_into().unwrap())
+    }
+
+    #[test]
+    fn test_decode_log() {
+        let decoder = EventDecoder::parse(
+            "Transfer(address indexed from, address indexed to, uint256 value)",
+        )
+        .unwrap();
+
+        assert_eq!(
+            decoder.topic0(),
+            Some(b256!(
+                "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+            ))
+        );
+
+        let log = evm::Log {
+            topics: vec![
+                topic("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"),
+                topic("0000000000000000000000000000000000000000000000000000000000000001"),
+                topic("0000000000000000000000000000000000000000000000000000000000000002"),
+            ],
+            data: hex::decode("0000000000000000000000000000000000000000000000000000000000000064")
+                .unwrap(),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        decoder.decode(7, &log.encode_to_vec(), &mut output);
+
+        // The decoded field is merged into the log by the client.
+        let mut message = log.encode_to_vec();
+        message.extend(output);
+        let log = evm::Log::decode(message.as_slice()).unwrap();
+
+        let decoded = &log.decoded[0];
+        assert_eq!(decoded.filter_id, 7);
+        assert_eq!(decoded.name, "Transfer");
+        assert!(decoded.error.is_none());
+        assert_eq!(decoded.params.len(), 3);
+        assert_eq!(decoded.params[2].name, "value");
+        assert_eq!(decoded.params[2].r#type, "uint256");
+        assert!(decoded.params[0].indexed);
+        assert!(!decoded.params[2].indexed);
+    }
+
+    #[test]
+    fn test_decode_log_error() {
+        let decoder = EventDecoder::parse(
+            r#"{"type":"event","name":"Approval","anonymous":false,"inputs":[{"name":"owner","type":"address","indexed":true},{"name":"spender","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}]}"#,
+        )
+        .unwrap();
+
+        // Missing the data.
+        let log = evm::Log {
+            topics: vec![
+                evm::B256::from_bytes(&decoder.topic0().unwrap().0),
+                topic("0000000000000000000000000000000000000000000000000000000000000001"),
+                topic("0000000000000000000000000000000000000000000000000000000000000002"),
+            ],
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        decoder.decode(1, &log.encode_to_vec(), &mut output);
+
+        let log = evm::Log::decode(output.as_slice()).unwrap();
+        assert!(log.decoded[0].error.is_some());
+        assert!(log.decoded[0].params.is_empty());
+    }
+
+    #[test]
+    fn test_decode_transaction_input() {
+        let decoder = FunctionDecoder::parse("transfer(address to, uint256 amount)").unwrap();
+        assert_eq!(decoder.selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+
+        let transaction = evm::Transaction {
+            input: hex::decode(concat!(
+                "a9059cbb",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "0000000000000000000000000000000000000000000000000000000000000064",
+            ))
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        decoder.decode(2, &transaction.encode_to_vec(), &mut output);
+
+        let transaction = evm::Transaction::decode(output.as_slice()).unwrap();
+        let decoded = &transaction.decoded[0];
+        assert_eq!(decoded.name, "transfer");
+        assert_eq!(decoded.params[0].name, "to");
+        assert!(matches!(
+            decoded.params[0]
+                .value
+                .as_ref()
+                .and_then(|v| v.value.as_ref()),
+            Some(evm::decoded_value::Value::Address(_))
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+//! Convert the output of Geth's `callTracer` to Parity-style traces.
+//!
+//! The call tracer returns a tree of calls for each transaction, while Parity returns the
+//! calls as a flat list in depth-first order. Each call's position in the tree is stored in
+//! `trace_address` and the number of direct children in `subtraces`.
+//!
+//! Gas values are reported as-is. Geth includes the intrinsic gas in the root call, so gas
+//! for the root call differs from the value returned by Parity.
+use error_stack::{Result, ResultExt};
+
+use crate::{error::EvmError, provider::models};
+
+/// Flatten the call tracer output for a block into Parity transaction traces.
+pub fn flatten_block_call_frames(
+    results: Vec<models::CallFrameResult>,
+) -> Result<Vec<models::TraceResultsWithTransactionHash>, EvmError> {
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(transaction_index, result)| match result {
+            models::TraceResult::Success {
+                result,
+                tx_hash: Some(transaction_hash),
+            } => Ok(models::TraceResultsWithTransactionHash {
+                full_trace: flatten_call_frame(result),
+                transaction_hash,
+            }),
+            models::TraceResult::Success { tx_hash: None, .. } => Err(EvmError)
+                .attach_printable("call tracer result is missing the transaction hash")
+                .attach_printable_lazy(|| format!("transaction index: {}", transaction_index)),
+            models::TraceResult::Error { error, tx_hash } => Err(EvmError)
+                .attach_printable("failed to trace transaction")
+                .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))
+                .attach_printable_lazy(|| format!("transaction hash: {:?}", tx_hash))
+                .attach_printable_lazy(|| format!("error: {}", error)),
+        })
+        .collect()
+}
+
+/// Flatten the call tree of a single transaction.
+pub fn flatten_call_frame(frame: models::CallFrame) -> models::TraceResults {
+    let output = frame.output.clone().unwrap_or_default();
+
+    let mut trace = Vec::new();
+    flatten_call_frame_into(frame, Vec::new(), &mut trace);
+
+    models::TraceResults {
+        output,
+        state_diff: None,
+        trace,
+        vm_trace: None,
+    }
+}
+
+fn flatten_call_frame_into(
+    frame: models::CallFrame,
+    trace_address: Vec<usize>,
+    traces: &mut Vec<models::TransactionTrace>,
+) {
+    let value = frame.value.unwrap_or_default();
+    let gas = frame.gas.saturating_to::<u64>();
+    let gas_used = frame.gas_used.saturating_to::<u64>();
+    let error = frame.error.as_deref().map(normalize_error);
+
+    let (action, result) = match frame.typ.as_str() {
+        "CREATE" | "CREATE2" => {
+            let creation_method = if frame.typ == "CREATE2" {
+                models::CreationMethod::Create2
+            } else {
+                models::CreationMethod::Create
+            };
+
+            let action = models::Action::Create(models::CreateAction {
+                from: frame.from,
+                gas,
+                init: frame.input,
+                value,
+                creation_method,
+            });
+
+            let result = models::TraceOutput::Create(models::CreateOutput {
+                address: frame.to.unwrap_or_default(),
+                code: frame.output.unwrap_or_default(),
+                gas_used,
+            });
+
+            (action, result)
+        }
+        "SELFDESTRUCT" => {
+            let action = models::Action::Selfdestruct(models::SelfdestructAction {
+                address: frame.from,
+                balance: value,
+                refund_address: frame.to.unwrap_or_default(),
+            });
+
+            // Self destructs have no output, same as Parity.
+            traces.push(models::TransactionTrace {
+                action,
+                error,
+                result: None,
+                subtraces: 0,
+                trace_address,
+            });
+
+            return;
+        }
+        call_type => {
+            let call_type = match call_type {
+                "CALL" => models::CallType::Call,
+                "CALLCODE" => models::CallType::CallCode,
+                "DELEGATECALL" => models::CallType::DelegateCall,
+                "STATICCALL" => models::CallType::StaticCall,
+                _ => models::CallType::None,
+            };
+
+            let action = models::Action::Call(models::CallAction {
+                from: frame.from,
+                call_type,
+                gas,
+                input: frame.input,
+                to: frame.to.unwrap_or_default(),
+                value,
+            });
+
+            let result = models::TraceOutput::Call(models::CallOutput {
+                gas_used,
+                output: frame.output.unwrap_or_default(),
+            });
+
+            (action, result)
+        }
+    };
+
+    // Parity doesn't return the output of failed calls.
+    let result = if error.is_some() { None } else { Some(result) };
+
+    traces.push(models::TransactionTrace {
+        action,
+        error,
+        result,
+        subtraces: frame.calls.len(),
+        trace_address: trace_address.clone(),
+    });
+
+    for (index, call) in frame.calls.into_iter().enumerate() {
+        let mut child_address = trace_address.clone();
+        child_address.push(index);
+        flatten_call_frame_into(call, child_address, traces);
+    }
+}
+
+/// Use the same error messages as Parity for the most common errors.
+fn normalize_error(error: &str) -> String {
+    match error {
+        "execution reverted" => "Reverted".to_string(),
+        "out of gas" => "Out of gas".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flatten_call_frame;
+    use crate::provider::models;
+
+    #[test]
+    fn test_flatten_call_frame() {
+        let frame: models::CallFrame = serde_json::from_value(serde_json::json!({
+            "type": "CALL",
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x0000000000000000000000000000000000000002",
+            "value": "0x0",
+            "gas": "0x10000",
+            "gasUsed": "0x5000",
+            "input": "0xa9059cbb",
+            "output": "0x",
+            "calls": [
+                {
+                    "type": "STATICCALL",
+                    "from": "0x0000000000000000000000000000000000000002",
+                    "to": "0x0000000000000000000000000000000000000003",
+                    "gas": "0x1000",
+                    "gasUsed": "0x100",
+                    "input": "0x70a08231",
+                    "output": "0x",
+                },
+                {
+                    "type": "CREATE2",
+                    "from": "0x0000000000000000000000000000000000000002",
+                    "to": "0x0000000000000000000000000000000000000004",
+                    "value": "0x0",
+                    "gas": "0x1000",
+                    "gasUsed": "0x100",
+                    "input": "0x6000",
+                    "output": "0x6001",
+                    "calls": [
+                        {
+                            "type": "CALL",
+                            "from": "0x0000000000000000000000000000000000000004",
+                            "to": "0x0000000000000000000000000000000000000005",
+                            "value": "0x0",
+                            "gas": "0x100",
+                            "gasUsed": "0x100",
+                            "input": "0x",
+                            "error": "execution reverted",
+                        }
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let traces = flatten_call_frame(frame).trace;
+
+        let trace_addresses = traces
+            .iter()
+            .map(|trace| trace.trace_address.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(trace_addresses, vec![vec![], vec![0], vec![1], vec![1, 0]]);
+
+        let subtraces = traces
+            .iter()
+            .map(|trace| trace.subtraces)
+            .collect::<Vec<_>>();
+        assert_eq!(subtraces, vec![2, 0, 1, 0]);
+
+        assert!(matches!(
+            traces[1].action,
+            models::Action::Call(models::CallAction {
+                call_type: models::CallType::StaticCall,
+                ..
+            })
+        ));
+        assert!(matches!(traces[2].action, models::Action::Create(_)));
+        assert!(matches!(
+            traces[2].result,
+            Some(models::TraceOutput::Create(_))
+        ));
+        assert_eq!(traces[3].error.as_deref(), Some("Reverted"));
+        assert!(traces[3].result.is_none());
+    }
+}
@@ -1,4 +1,7 @@
+use std::path::PathBuf;
+
 use alloy_primitives::hex::FromHex;
+use apibara_dna_common::{fixture::FixtureStore, ingestion::BlockIngestion};
 use clap::Subcommand;
 use error_stack::{Result, ResultExt};
 use tracing::info;
@@ -6,6 +9,7 @@ use tracing::info;
 use crate::{
     cli::rpc::RpcArgs,
     error::EvmError,
+    ingestion::{ChainFlavour, EvmBlockIngestion, EvmBlockIngestionOptions, TraceBackend},
     provider::{models, BlockId, JsonRpcProvider},
 };
 
@@ -26,15 +30,70 @@ pub enum DebugRpcCommand {
         #[arg(long, env, default_value = "head")]
         block_id: String,
     },
+    /// Record the responses needed to ingest a range of blocks.
+    ///
+    /// The recorded responses can be replayed offline, for example in tests.
+    Record {
+        #[clap(flatten)]
+        rpc: RpcArgs,
+        /// Directory where to store the responses.
+        #[arg(long)]
+        fixtures_dir: PathBuf,
+        /// First block to record.
+        #[arg(long)]
+        from_block: u64,
+        /// Last block to record (inclusive).
+        #[arg(long)]
+        to_block: u64,
+        /// Also record the block transaction traces.
+        #[arg(long)]
+        ingest_traces: bool,
+        /// The RPC method used to fetch transaction traces.
+        #[arg(long, value_enum, default_value_t = TraceBackend::Parity)]
+        trace_backend: TraceBackend,
+        /// Also record the block state diffs.
+        #[arg(long)]
+        ingest_state_diffs: bool,
+        /// Also record the block uncles.
+        #[arg(long)]
+        ingest_uncles: bool,
+        /// Record the raw blocks and receipts of this L2 chain flavour.
+        #[arg(long, value_enum, default_value_t = ChainFlavour::Ethereum)]
+        chain_flavour: ChainFlavour,
+    },
 }
 
 impl DebugRpcCommand {
     pub async fn run(self) -> Result<(), EvmError> {
         let rpc_provider = self.rpc_provider()?;
-        let block_id = self.block_id()?;
 
         match self {
-            DebugRpcCommand::GetBlockWithTransactions { .. } => {
+            DebugRpcCommand::Record {
+                fixtures_dir,
+                from_block,
+                to_block,
+                ingest_traces,
+                trace_backend,
+                ingest_state_diffs,
+                ingest_uncles,
+                chain_flavour,
+                ..
+            } => {
+                let options = EvmBlockIngestionOptions {
+                    ingest_pending: false,
+                    ingest_traces,
+                    trace_backend,
+                    ingest_state_diffs,
+                    ingest_uncles,
+                    chain_flavour,
+                    verify_block_data: false,
+                    head_subscription: None,
+                };
+
+                record_blocks(rpc_provider, fixtures_dir, from_block, to_block, options).await
+            }
+            DebugRpcCommand::GetBlockWithTransactions { ref block_id, .. } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting block with transactions");
                 let block_with_transactions = rpc_provider
                     .get_block_with_transactions(block_id)
@@ -45,7 +104,8 @@ impl DebugRpcCommand {
 
                 Ok(())
             }
-            DebugRpcCommand::GetBlockReceipts { .. } => {
+            DebugRpcCommand::GetBlockReceipts { ref block_id, .. } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting block receipts");
                 let block_receipts = rpc_provider
                     .get_block_receipts(block_id)
@@ -63,29 +123,49 @@ impl DebugRpcCommand {
         match self {
             DebugRpcCommand::GetBlockWithTransactions { rpc, .. } => rpc.to_json_rpc_provider(),
             DebugRpcCommand::GetBlockReceipts { rpc, .. } => rpc.to_json_rpc_provider(),
+            DebugRpcCommand::Record { rpc, .. } => rpc.to_json_rpc_provider(),
         }
     }
+}
 
-    fn block_id(&self) -> Result<BlockId, EvmError> {
-        let block_id = match self {
-            DebugRpcCommand::GetBlockWithTransactions { block_id, .. } => block_id,
-            DebugRpcCommand::GetBlockReceipts { block_id, .. } => block_id,
-        };
-
-        match block_id.as_str() {
-            "head" => Ok(BlockId::latest()),
-            "finalized" => Ok(BlockId::finalized()),
-            str_value => {
-                if let Ok(number) = str_value.parse::<u64>() {
-                    return Ok(BlockId::Number(number.into()));
-                }
-                if let Ok(hash) = models::B256::from_hex(str_value) {
-                    return Ok(BlockId::Hash(hash.into()));
-                }
-                Err(EvmError)
-                    .attach_printable("invalid block id")
-                    .attach_printable_lazy(|| format!("block id: {}", block_id))
+fn parse_block_id(block_id: &str) -> Result<BlockId, EvmError> {
+    match block_id {
+        "head" => Ok(BlockId::latest()),
+        "finalized" => Ok(BlockId::finalized()),
+        str_value => {
+            if let Ok(number) = str_value.parse::<u64>() {
+                return Ok(BlockId::Number(number.into()));
             }
+            if let Ok(hash) = models::B256::from_hex(str_value) {
+                return Ok(BlockId::Hash(hash.into()));
+            }
+            Err(EvmError)
+                .attach_printable("invalid block id")
+                .attach_printable_lazy(|| format!("block id: {}", block_id))
         }
     }
 }
+
+async fn record_blocks(
+    provider: JsonRpcProvider,
+    fixtures_dir: PathBuf,
+    from_block: u64,
+    to_block: u64,
+    options: EvmBlockIngestionOptions,
+) -> Result<(), EvmError> {
+    let provider = provider.with_recorder(FixtureStore::new(&fixtures_dir));
+    let ingestion = EvmBlockIngestion::new(provider, options);
+
+    for block_number in from_block..=to_block {
+        info!(block_number, "recording block");
+        ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .change_context(EvmError)
+            .attach_printable_lazy(|| format!("failed to record block {block_number}"))?;
+    }
+
+    info!(dir = %fixtures_dir.display(), "recorded blocks");
+
+    Ok(())
+}
@@ -1,15 +1,20 @@
 mod dbg;
+mod repair;
 mod rpc;
 mod start;
 
-use apibara_dna_common::dbg::DebugIndexCommand;
+use apibara_dna_common::{
+    compaction::{GarbageCollectCommand, RecompactCommand},
+    dbg::{DebugIndexCommand, DebugReorgsCommand},
+    snapshot::{ExportCommand, ImportCommand},
+};
 use clap::{Parser, Subcommand};
 use error_stack::{Result, ResultExt};
 use tokio_util::sync::CancellationToken;
 
 use crate::error::EvmError;
 
-use self::{dbg::DebugRpcCommand, start::StartCommand};
+use self::{dbg::DebugRpcCommand, repair::RepairCommand, start::StartCommand};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -34,6 +39,19 @@ pub enum Command {
         #[clap(subcommand)]
         command: DebugIndexCommand,
     },
+    /// List the chain reorganizations seen by the ingestion service.
+    #[command(name = "dbg-reorgs")]
+    DebugReorgs(Box<DebugReorgsCommand>),
+    /// Rebuild segments and groups with a different segment or group size.
+    Recompact(Box<RecompactCommand>),
+    /// Re-ingest a range of finalized blocks and overwrite the stored data.
+    Repair(Box<RepairCommand>),
+    /// Export a snapshot of the bucket and ingestion state.
+    Export(Box<ExportCommand>),
+    /// Import a snapshot exported with the `export` command.
+    Import(Box<ImportCommand>),
+    /// Delete objects that are not reachable anymore.
+    Gc(Box<GarbageCollectCommand>),
 }
 
 impl Cli {
@@ -42,6 +60,12 @@ impl Cli {
             Command::Start(command) => command.run(ct).await,
             Command::DebugRpc { command } => command.run().await,
             Command::DebugIndex { command } => command.run().await.change_context(EvmError),
+            Command::DebugReorgs(command) => command.run(ct).await.change_context(EvmError),
+            Command::Recompact(command) => command.run(ct).await.change_context(EvmError),
+            Command::Repair(command) => command.run(ct).await,
+            Command::Export(command) => command.run(ct).await.change_context(EvmError),
+            Command::Import(command) => command.run(ct).await.change_context(EvmError),
+            Command::Gc(command) => command.run(ct).await.change_context(EvmError),
         }
     }
 }
@@ -0,0 +1,35 @@
+use apibara_dna_common::{compaction::RepairArgs, ChainSupport};
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{error::EvmError, EvmChainSupport};
+
+use super::{rpc::RpcArgs, start::EvmArgs};
+
+#[derive(Args, Debug)]
+pub struct RepairCommand {
+    #[clap(flatten)]
+    rpc: RpcArgs,
+    #[clap(flatten)]
+    repair: RepairArgs,
+    #[clap(flatten)]
+    evm: EvmArgs,
+}
+
+impl RepairCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), EvmError> {
+        let provider = self.rpc.to_json_rpc_provider()?;
+        let evm_ingestion_options = self.evm.to_block_ingestion_options()?;
+        let mut evm_chain = EvmChainSupport::new(provider, evm_ingestion_options);
+
+        if let Some(era1) = self.evm.open_era1_archive().await? {
+            evm_chain = evm_chain.with_era1(era1);
+        }
+
+        self.repair
+            .run(evm_chain.block_ingestion(), ct)
+            .await
+            .change_context(EvmError)
+    }
+}
@@ -1,10 +1,13 @@
 use std::time::Duration;
 
+use apibara_dna_common::{
+    ingestion::{HeadEvent, JsonRpcWsSubscription},
+    provider_pool::ProviderPoolArgs,
+};
 use backon::ExponentialBuilder;
 use clap::Args;
 use error_stack::{Result, ResultExt};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use url::Url;
 
 use crate::{
     error::EvmError,
@@ -13,13 +16,16 @@ use crate::{
 
 #[derive(Args, Debug)]
 pub struct RpcArgs {
-    /// Evm RPC URL.
+    /// Evm RPC URLs.
+    ///
+    /// Pass multiple URLs to spread requests and fail over between them.
     #[arg(
         long = "rpc.url",
         env = "EVM_RPC_URL",
+        value_delimiter = ',',
         default_value = "http://localhost:9545"
     )]
-    pub rpc_url: String,
+    pub rpc_url: Vec<String>,
 
     /// Request timeout.
     #[arg(
@@ -32,16 +38,37 @@ pub struct RpcArgs {
     /// Headers to send with the requests.
     #[arg(long = "rpc.headers", env = "EVM_RPC_HEADERS")]
     pub rpc_headers: Vec<String>,
+
+    /// WebSocket URL used to subscribe to new heads with `eth_subscribe("newHeads")`.
+    ///
+    /// The RPC is polled for new heads if not set, or while the subscription is disconnected.
+    #[arg(long = "rpc.ws-url", env = "EVM_RPC_WS_URL")]
+    pub rpc_ws_url: Option<String>,
+
+    #[clap(flatten)]
+    pub pool: ProviderPoolArgs,
 }
 
 impl RpcArgs {
+    pub fn to_head_subscription(&self) -> Option<JsonRpcWsSubscription> {
+        let url = self.rpc_ws_url.as_ref()?;
+        Some(JsonRpcWsSubscription::new(
+            url,
+            "eth_subscribe",
+            serde_json::json!(["newHeads"]),
+            HeadEvent::Head,
+        ))
+    }
+
     pub fn to_json_rpc_provider(&self) -> Result<JsonRpcProvider, EvmError> {
-        let url = self
-            .rpc_url
-            .parse::<Url>()
-            .change_context(EvmError)
-            .attach_printable("failed to parse RPC URL")
-            .attach_printable_lazy(|| format!("url: {}", self.rpc_url))?;
+        let upstreams = self
+            .pool
+            .to_upstreams(&self.rpc_url)
+            .change_context(EvmError)?;
+        let pool = self
+            .pool
+            .to_provider_pool_options()
+            .change_context(EvmError)?;
 
         let headers = {
             let mut headers = HeaderMap::default();
@@ -75,8 +102,9 @@ impl RpcArgs {
             timeout,
             headers,
             exponential_backoff: ExponentialBuilder::default().with_max_delay(max_delay),
+            pool,
         };
 
-        JsonRpcProvider::new(url, options).change_context(EvmError)
+        JsonRpcProvider::new(upstreams, options).change_context(EvmError)
     }
 }
@@ -1,10 +1,15 @@
+use std::path::PathBuf;
+
 use apibara_dna_common::{run_server, StartArgs};
 use clap::Args;
 use error_stack::{Result, ResultExt};
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::{error::EvmError, EvmBlockIngestionOptions, EvmChainSupport};
+use crate::{
+    era1::Era1Archive, error::EvmError, ChainFlavour, EvmBlockIngestionOptions, EvmChainSupport,
+    TraceBackend,
+};
 
 use super::rpc::RpcArgs;
 
@@ -23,27 +28,130 @@ pub struct StartCommand {
     )]
     no_ingest_pending: bool,
 
+    #[clap(flatten)]
+    evm: EvmArgs,
+}
+
+/// Options that change the ingested data.
+///
+/// Commands that re-ingest blocks must use the same options as the ingestion service.
+#[derive(Args, Clone, Debug)]
+pub struct EvmArgs {
     /// Ingest transaction traces.
     #[arg(
         long = "evm.ingest-traces",
         env = "EVM_INGEST_TRACES",
         default_value = "false"
     )]
-    ingest_traces: bool,
+    pub ingest_traces: bool,
+
+    /// The RPC method used to fetch transaction traces.
+    #[arg(
+        long = "evm.trace-backend",
+        env = "EVM_TRACE_BACKEND",
+        value_enum,
+        default_value_t = TraceBackend::Parity
+    )]
+    pub trace_backend: TraceBackend,
+
+    /// Ingest the balance, nonce, code and storage changes of each transaction.
+    ///
+    /// Requires an RPC that supports `trace_replayBlockTransactions`.
+    #[arg(
+        long = "evm.ingest-state-diffs",
+        env = "EVM_INGEST_STATE_DIFFS",
+        default_value = "false"
+    )]
+    pub ingest_state_diffs: bool,
+
+    /// Ingest the block uncles with `eth_getUncleByBlockNumberAndIndex`.
+    #[arg(
+        long = "evm.ingest-uncles",
+        env = "EVM_INGEST_UNCLES",
+        default_value = "false"
+    )]
+    pub ingest_uncles: bool,
+
+    /// Parse the L2 specific transaction and receipt fields of this chain flavour.
+    #[arg(
+        long = "evm.chain-flavour",
+        env = "EVM_CHAIN_FLAVOUR",
+        value_enum,
+        default_value_t = ChainFlavour::Ethereum
+    )]
+    pub chain_flavour: ChainFlavour,
+
+    /// Verify the transactions, receipts and logs returned by the RPC against the roots and
+    /// logs bloom in the block header.
+    ///
+    /// Only enable this on chains that use the same encoding as Ethereum mainnet.
+    #[arg(
+        long = "evm.verify-block-data",
+        env = "EVM_VERIFY_BLOCK_DATA",
+        default_value = "false"
+    )]
+    pub verify_block_data: bool,
+
+    /// Directory with Era1 files used to ingest pre-merge history.
+    ///
+    /// Blocks not covered by the files are fetched from the RPC.
+    #[arg(long = "evm.era1-dir", env = "EVM_ERA1_DIR")]
+    pub era1_dir: Option<PathBuf>,
 }
 
 impl StartCommand {
     pub async fn run(self, ct: CancellationToken) -> Result<(), EvmError> {
         info!("Starting EVM DNA server");
         let provider = self.rpc.to_json_rpc_provider()?;
-        let evm_ingestion_options = EvmBlockIngestionOptions {
-            ingest_pending: !self.no_ingest_pending,
+        let mut evm_ingestion_options = self.evm.to_block_ingestion_options()?;
+        evm_ingestion_options.ingest_pending = !self.no_ingest_pending;
+        evm_ingestion_options.head_subscription = self.rpc.to_head_subscription();
+        let mut evm_chain = EvmChainSupport::new(provider, evm_ingestion_options);
+
+        if let Some(era1) = self.evm.open_era1_archive().await? {
+            evm_chain = evm_chain.with_era1(era1);
+        }
+
+        run_server(evm_chain, self.start, env!("CARGO_PKG_VERSION"), ct)
+            .await
+            .change_context(EvmError)
+    }
+}
+
+impl EvmArgs {
+    pub fn to_block_ingestion_options(&self) -> Result<EvmBlockIngestionOptions, EvmError> {
+        if self.verify_block_data && self.chain_flavour != ChainFlavour::Ethereum {
+            return Err(EvmError)
+                .attach_printable("block data verification requires the ethereum chain flavour");
+        }
+
+        Ok(EvmBlockIngestionOptions {
+            ingest_pending: false,
             ingest_traces: self.ingest_traces,
+            trace_backend: self.trace_backend,
+            ingest_state_diffs: self.ingest_state_diffs,
+            ingest_uncles: self.ingest_uncles,
+            chain_flavour: self.chain_flavour,
+            verify_block_data: self.verify_block_data,
+            head_subscription: None,
+        })
+    }
+
+    pub async fn open_era1_archive(&self) -> Result<Option<Era1Archive>, EvmError> {
+        let Some(era1_dir) = &self.era1_dir else {
+            return Ok(None);
         };
-        let evm_chain = EvmChainSupport::new(provider, evm_ingestion_options);
 
-        run_server(evm_chain, self.start, env!("CARGO_PKG_VERSION"), ct)
+        let era1 = Era1Archive::open(era1_dir)
             .await
             .change_context(EvmError)
+            .attach_printable("failed to open era1 archive")?;
+
+        match era1.block_range() {
+            Some((first, last)) => info!(first, last, "using era1 archive"),
+            None => warn!(dir = %era1_dir.display(), "era1 directory is empty"),
+        }
+
+        Ok(Some(era1))
     }
 }
@@ -0,0 +1,141 @@
+//! Minimal reader for the e2store container format used by Era1 files.
+//!
+//! Each entry is an 8 bytes header (type, length, reserved) followed by the entry data.
+use std::{io::SeekFrom, path::Path};
+
+use error_stack::{Result, ResultExt};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use super::Era1Error;
+
+pub const VERSION: u16 = 0x3265;
+pub const COMPRESSED_HEADER: u16 = 0x03;
+pub const COMPRESSED_BODY: u16 = 0x04;
+pub const COMPRESSED_RECEIPTS: u16 = 0x05;
+pub const TOTAL_DIFFICULTY: u16 = 0x06;
+pub const ACCUMULATOR: u16 = 0x07;
+pub const BLOCK_INDEX: u16 = 0x6632;
+
+/// Size of the entry header.
+pub const HEADER_SIZE: u64 = 8;
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub entry_type: u16,
+    pub data: Vec<u8>,
+}
+
+/// The block index stored at the end of each Era1 file.
+#[derive(Debug, Clone)]
+pub struct BlockIndex {
+    /// Offset of the block index entry in the file.
+    pub offset: u64,
+    /// First block in the file.
+    pub start: u64,
+    /// Number of blocks in the file.
+    pub count: u64,
+}
+
+pub async fn read_entry(file: &mut File) -> Result<Entry, Era1Error> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.read_exact(&mut header)
+        .await
+        .change_context(Era1Error)
+        .attach_printable("failed to read entry header")?;
+
+    let entry_type = u16::from_le_bytes([header[0], header[1]]);
+    let length = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+
+    let mut data = vec![0u8; length as usize];
+    file.read_exact(&mut data)
+        .await
+        .change_context(Era1Error)
+        .attach_printable("failed to read entry data")
+        .attach_printable_lazy(|| format!("entry type: {entry_type:#x}"))?;
+
+    Ok(Entry { entry_type, data })
+}
+
+/// Read the block index at the end of the file.
+pub async fn read_block_index(path: &Path) -> Result<BlockIndex, Era1Error> {
+    let mut file = File::open(path)
+        .await
+        .change_context(Era1Error)
+        .attach_printable("failed to open era1 file")?;
+
+    let length = file
+        .metadata()
+        .await
+        .change_context(Era1Error)
+        .attach_printable("failed to read era1 file metadata")?
+        .len();
+
+    // The block index is: header | starting-number | offsets | count.
+    if length < HEADER_SIZE + 16 {
+        return Err(Era1Error)
+            .attach_printable("era1 file too small")
+            .attach_printable_lazy(|| format!("length: {length}"));
+    }
+
+    file.seek(SeekFrom::End(-8))
+        .await
+        .change_context(Era1Error)?;
+    let count = file.read_u64_le().await.change_context(Era1Error)?;
+
+    let offset = count
+        .checked_mul(8)
+        .and_then(|size| size.checked_add(HEADER_SIZE + 16))
+        .and_then(|size| length.checked_sub(size))
+        .ok_or(Era1Error)
+        .attach_printable("invalid block index count")
+        .attach_printable_lazy(|| format!("count: {count}"))?;
+
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .change_context(Era1Error)?;
+
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.read_exact(&mut header)
+        .await
+        .change_context(Era1Error)?;
+    let entry_type = u16::from_le_bytes([header[0], header[1]]);
+    if entry_type != BLOCK_INDEX {
+        return Err(Era1Error)
+            .attach_printable("expected block index entry")
+            .attach_printable_lazy(|| format!("entry type: {entry_type:#x}"));
+    }
+
+    let start = file.read_u64_le().await.change_context(Era1Error)?;
+
+    Ok(BlockIndex {
+        offset,
+        start,
+        count,
+    })
+}
+
+impl BlockIndex {
+    pub fn contains(&self, block_number: u64) -> bool {
+        block_number >= self.start && block_number - self.start < self.count
+    }
+
+    /// Returns the offset of the first entry of the given block.
+    pub async fn block_offset(&self, file: &mut File, block_number: u64) -> Result<u64, Era1Error> {
+        let index = block_number - self.start;
+        file.seek(SeekFrom::Start(self.offset + HEADER_SIZE + 8 + index * 8))
+            .await
+            .change_context(Era1Error)?;
+
+        // Offsets are relative to the start of the block index entry.
+        let relative = file.read_i64_le().await.change_context(Era1Error)?;
+
+        self.offset
+            .checked_add_signed(relative)
+            .ok_or(Era1Error)
+            .attach_printable("invalid block offset")
+            .attach_printable_lazy(|| format!("block number: {block_number}"))
+    }
+}
@@ -0,0 +1,480 @@
+//! Read pre-merge history from Era1 archive files.
+//!
+//! Era1 files contain the headers, bodies and receipts of 8192 blocks each. Reading them from
+//! disk is much faster (and cheaper) than fetching the same data from a JSON-RPC node.
+pub mod e2store;
+
+use std::{
+    io::{Read, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use alloy_consensus::{
+    transaction::{Recovered, SignerRecoverable},
+    BlockBody, ReceiptEnvelope, Transaction as _, TxEnvelope,
+};
+use alloy_rlp::Decodable;
+use alloy_rpc_types::BlockTransactions;
+use error_stack::{Result, ResultExt};
+use tokio::{fs::File, io::AsyncSeekExt};
+use tracing::debug;
+
+use crate::provider::models;
+
+use self::e2store::BlockIndex;
+
+#[derive(Debug)]
+pub struct Era1Error;
+
+/// A directory of Era1 files.
+#[derive(Clone)]
+pub struct Era1Archive {
+    files: Arc<Vec<Era1File>>,
+}
+
+#[derive(Debug, Clone)]
+struct Era1File {
+    path: PathBuf,
+    index: BlockIndex,
+}
+
+/// A block as stored in an Era1 file.
+#[derive(Debug, Clone)]
+pub struct Era1Block {
+    pub header: alloy_consensus::Header,
+    pub body: BlockBody<TxEnvelope>,
+    pub receipts: Vec<ReceiptEnvelope>,
+    pub total_difficulty: models::U256,
+}
+
+impl Era1Archive {
+    /// Open all the `.era1` files in the given directory.
+    pub async fn open(dir: impl AsRef<Path>) -> Result<Self, Era1Error> {
+        let dir = dir.as_ref();
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .change_context(Era1Error)
+            .attach_printable("failed to read era1 directory")
+            .attach_printable_lazy(|| format!("dir: {}", dir.display()))?;
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await.change_context(Era1Error)? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "era1") {
+                continue;
+            }
+
+            let index = e2store::read_block_index(&path)
+                .await
+                .attach_printable_lazy(|| format!("path: {}", path.display()))?;
+
+            if index.count == 0 {
+                continue;
+            }
+
+            debug!(path = %path.display(), start = index.start, count = index.count, "found era1 file");
+
+            files.push(Era1File { path, index });
+        }
+
+        files.sort_by_key(|file| file.index.start);
+
+        Ok(Self {
+            files: Arc::new(files),
+        })
+    }
+
+    /// Returns the range of blocks covered by the archive, if any.
+    pub fn block_range(&self) -> Option<(u64, u64)> {
+        let first = self.files.first()?;
+        let last = self.files.last()?;
+        Some((first.index.start, last.index.start + last.index.count - 1))
+    }
+
+    pub fn contains(&self, block_number: u64) -> bool {
+        self.file_for_block(block_number).is_some()
+    }
+
+    pub async fn read_block(&self, block_number: u64) -> Result<Era1Block, Era1Error> {
+        let era1_file = self
+            .file_for_block(block_number)
+            .ok_or(Era1Error)
+            .attach_printable("block not in era1 archive")
+            .attach_printable_lazy(|| format!("block number: {block_number}"))?;
+
+        era1_file
+            .read_block(block_number)
+            .await
+            .attach_printable_lazy(|| format!("path: {}", era1_file.path.display()))
+            .attach_printable_lazy(|| format!("block number: {block_number}"))
+    }
+
+    fn file_for_block(&self, block_number: u64) -> Option<&Era1File> {
+        self.files
+            .iter()
+            .find(|file| file.index.contains(block_number))
+    }
+}
+
+impl Era1File {
+    async fn read_block(&self, block_number: u64) -> Result<Era1Block, Era1Error> {
+        let mut file = File::open(&self.path)
+            .await
+            .change_context(Era1Error)
+            .attach_printable("failed to open era1 file")?;
+
+        let offset = self.index.block_offset(&mut file, block_number).await?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .change_context(Era1Error)?;
+
+        let header = read_compressed(&mut file, e2store::COMPRESSED_HEADER).await?;
+        let header = alloy_consensus::Header::decode(&mut header.as_slice())
+            .change_context(Era1Error)
+            .attach_printable("failed to decode header")?;
+
+        let body = read_compressed(&mut file, e2store::COMPRESSED_BODY).await?;
+        let body = BlockBody::<TxEnvelope>::decode(&mut body.as_slice())
+            .change_context(Era1Error)
+            .attach_printable("failed to decode body")?;
+
+        let receipts = read_compressed(&mut file, e2store::COMPRESSED_RECEIPTS).await?;
+        let receipts = Vec::<ReceiptEnvelope>::decode(&mut receipts.as_slice())
+            .change_context(Era1Error)
+            .attach_printable("failed to decode receipts")?;
+
+        let total_difficulty = e2store::read_entry(&mut file).await?;
+        if total_difficulty.entry_type != e2store::TOTAL_DIFFICULTY {
+            return Err(Era1Error)
+                .attach_printable("expected total difficulty entry")
+                .attach_printable_lazy(|| {
+                    format!("entry type: {:#x}", total_difficulty.entry_type)
+                });
+        }
+        let total_difficulty = models::U256::try_from_le_slice(&total_difficulty.data)
+            .ok_or(Era1Error)
+            .attach_printable("invalid total difficulty")?;
+
+        if header.number != block_number {
+            return Err(Era1Error)
+                .attach_printable("block number mismatch")
+                .attach_printable_lazy(|| format!("header number: {}", header.number));
+        }
+
+        Ok(Era1Block {
+            header,
+            body,
+            receipts,
+            total_difficulty,
+        })
+    }
+}
+
+impl Era1Block {
+    /// Convert the block to the same types returned by the JSON-RPC provider.
+    pub fn into_rpc(self) -> Result<(models::Block, Vec<models::TransactionReceipt>), Era1Error> {
+        if self.body.transactions.len() != self.receipts.len() {
+            return Err(Era1Error)
+                .attach_printable("transactions and receipts count mismatch")
+                .attach_printable_lazy(|| format!("transactions: {}", self.body.transactions.len()))
+                .attach_printable_lazy(|| format!("receipts: {}", self.receipts.len()));
+        }
+
+        let block_hash = self.header.hash_slow();
+        let block_number = self.header.number;
+        let block_timestamp = self.header.timestamp;
+        let base_fee = self.header.base_fee_per_gas;
+
+        let mut transactions = Vec::with_capacity(self.receipts.len());
+        let mut receipts = Vec::with_capacity(self.receipts.len());
+
+        let mut log_index = 0;
+        let mut cumulative_gas_used = 0;
+
+        for (transaction_index, (transaction, receipt)) in self
+            .body
+            .transactions
+            .into_iter()
+            .zip(self.receipts)
+            .enumerate()
+        {
+            let transaction_index = transaction_index as u64;
+            let transaction_hash = *transaction.tx_hash();
+
+            let transaction: Recovered<TxEnvelope> = transaction
+                .try_into_recovered()
+                .change_context(Era1Error)
+                .attach_printable("failed to recover transaction signer")
+                .attach_printable_lazy(|| format!("transaction hash: {transaction_hash}"))?;
+
+            let from = transaction.signer();
+            let effective_gas_price = transaction.effective_gas_price(base_fee);
+            let contract_address = if transaction.kind().is_create() {
+                Some(from.create(transaction.nonce()))
+            } else {
+                None
+            };
+
+            let gas_used = receipt.cumulative_gas_used() - cumulative_gas_used;
+            cumulative_gas_used = receipt.cumulative_gas_used();
+
+            let inner = receipt.map_logs(|log| {
+                let log = models::Log {
+                    inner: log,
+                    block_hash: Some(block_hash),
+                    block_number: Some(block_number),
+                    block_timestamp: Some(block_timestamp),
+                    transaction_hash: Some(transaction_hash),
+                    transaction_index: Some(transaction_index),
+                    log_index: Some(log_index),
+                    removed: false,
+                };
+                log_index += 1;
+                log
+            });
+
+            receipts.push(models::TransactionReceipt {
+                inner,
+                transaction_hash,
+                transaction_index: Some(transaction_index),
+                block_hash: Some(block_hash),
+                block_number: Some(block_number),
+                gas_used,
+                effective_gas_price,
+                blob_gas_used: None,
+                blob_gas_price: None,
+                from,
+                to: transaction.to(),
+                contract_address,
+            });
+
+            transactions.push(models::Transaction {
+                inner: transaction,
+                block_hash: Some(block_hash),
+                block_number: Some(block_number),
+                transaction_index: Some(transaction_index),
+                effective_gas_price: Some(effective_gas_price),
+                block_timestamp: Some(block_timestamp),
+            });
+        }
+
+        let uncles = self
+            .body
+            .ommers
+            .iter()
+            .map(|header| header.hash_slow())
+            .collect();
+
+        let block = models::Block {
+            header: models::Header {
+                hash: block_hash,
+                inner: self.header,
+                total_difficulty: Some(self.total_difficulty),
+                size: None,
+            },
+            uncles,
+            transactions: BlockTransactions::Full(transactions),
+            withdrawals: None,
+        };
+
+        Ok((block, receipts))
+    }
+}
+
+async fn read_compressed(file: &mut File, entry_type: u16) -> Result<Vec<u8>, Era1Error> {
+    let entry = e2store::read_entry(file).await?;
+    if entry.entry_type != entry_type {
+        return Err(Era1Error)
+            .attach_printable("unexpected entry type")
+            .attach_printable_lazy(|| format!("expected: {entry_type:#x}"))
+            .attach_printable_lazy(|| format!("actual: {:#x}", entry.entry_type));
+    }
+
+    let mut data = Vec::new();
+    snap::read::FrameDecoder::new(entry.data.as_slice())
+        .read_to_end(&mut data)
+        .change_context(Era1Error)
+        .attach_printable("failed to decompress entry")?;
+
+    Ok(data)
+}
+
+impl error_stack::Context for Era1Error {}
+
+impl std::fmt::Display for Era1Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "era1 error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use alloy_consensus::{BlockBody, Header, Transaction as _, TxEnvelope};
+    use alloy_rlp::Encodable;
+
+    use super::{e2store, Era1Archive};
+    use crate::provider::models;
+
+    fn entry(entry_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&entry_type.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn compressed_entry(entry_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder.write_all(data).unwrap();
+        entry(entry_type, &encoder.into_inner().unwrap())
+    }
+
+    fn rlp(value: &impl Encodable) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        out
+    }
+
+    #[tokio::test]
+    async fn test_read_era1_file() {
+        let start = 8192;
+        let headers = (start..start + 2)
+            .map(|number| Header {
+                number,
+                timestamp: 1_000 + number,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let mut file = entry(e2store::VERSION, &[]);
+        let mut offsets = Vec::new();
+        for header in headers.iter() {
+            offsets.push(file.len() as i64);
+            let body = BlockBody::<TxEnvelope>::default();
+            let total_difficulty = models::U256::from(header.number);
+            file.extend(compressed_entry(e2store::COMPRESSED_HEADER, &rlp(header)));
+            file.extend(compressed_entry(e2store::COMPRESSED_BODY, &rlp(&body)));
+            file.extend(compressed_entry(
+                e2store::COMPRESSED_RECEIPTS,
+                &rlp(&Vec::<alloy_consensus::ReceiptEnvelope>::new()),
+            ));
+            file.extend(entry(
+                e2store::TOTAL_DIFFICULTY,
+                &total_difficulty.to_le_bytes::<32>(),
+            ));
+        }
+        file.extend(entry(e2store::ACCUMULATOR, &[0; 32]));
+
+        let index_offset = file.len() as i64;
+        let mut index = Vec::new();
+        index.extend_from_slice(&start.to_le_bytes());
+        for offset in offsets {
+            index.extend_from_slice(&(offset - index_offset).to_le_bytes());
+        }
+        index.extend_from_slice(&(headers.len() as u64).to_le_bytes());
+        file.extend(entry(e2store::BLOCK_INDEX, &index));
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("test-00001-00000000.era1"), file).unwrap();
+
+        let archive = Era1Archive::open(dir.path()).await.unwrap();
+        assert_eq!(archive.block_range(), Some((start, start + 1)));
+        assert!(!archive.contains(start - 1));
+        assert!(!archive.contains(start + 2));
+
+        for header in headers {
+            let block = archive.read_block(header.number).await.unwrap();
+            assert_eq!(block.header, header);
+            assert_eq!(block.total_difficulty, models::U256::from(header.number));
+
+            let (block, receipts) = block.into_rpc().unwrap();
+            assert_eq!(block.header.hash, header.hash_slow());
+            assert_eq!(block.header.number, header.number);
+            assert!(receipts.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_into_rpc() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/era1");
+        let archive = Era1Archive::open(dir).await.unwrap();
+
+        let block = archive.read_block(1_000_000_000).await.unwrap();
+        let total_difficulty = block.total_difficulty;
+        let (block, receipts) = block.into_rpc().unwrap();
+
+        let block_hash = "0xe7b4b4d592d5fc0e3c9d7f421ff7eb4da56e727a581f1c43127f3abae19d7eb8"
+            .parse::<models::B256>()
+            .unwrap();
+        assert_eq!(block.header.hash, block_hash);
+        assert_eq!(block.header.total_difficulty, Some(total_difficulty));
+
+        let transactions = block.transactions.as_transactions().unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(receipts.len(), 3);
+
+        let senders = [
+            "0xe05fcc23807536bee418f142d19fa0d21bb0cff7",
+            "0x0376aac07ad725e01357b1725b5cec61ae10473c",
+            "0x0f89f1868af3b14a5eca86ed9a5404a742a11454",
+        ]
+        .map(|address| address.parse::<models::Address>().unwrap());
+
+        // The second transaction is EIP-1559 and pays the base fee plus the priority fee.
+        let effective_gas_prices = [30_000_000_000, 16_500_000_000, 30_000_000_000];
+
+        // Era1 receipts only store the cumulative gas used.
+        let gas_used = [21_000, 46_109, 112_345];
+
+        for (index, (transaction, receipt)) in transactions.iter().zip(&receipts).enumerate() {
+            assert_eq!(transaction.transaction_index, Some(index as u64));
+            assert_eq!(transaction.block_hash, Some(block_hash));
+            assert_eq!(transaction.inner.signer(), senders[index]);
+            assert_eq!(
+                transaction.effective_gas_price,
+                Some(effective_gas_prices[index])
+            );
+
+            assert_eq!(&receipt.transaction_hash, transaction.inner.tx_hash());
+            assert_eq!(receipt.transaction_index, Some(index as u64));
+            assert_eq!(receipt.from, senders[index]);
+            assert_eq!(receipt.to, transaction.to());
+            assert_eq!(receipt.effective_gas_price, effective_gas_prices[index]);
+            assert_eq!(receipt.gas_used, gas_used[index]);
+            assert!(receipt.inner.status());
+        }
+
+        assert_eq!(
+            receipts.last().unwrap().inner.cumulative_gas_used(),
+            block.header.gas_used
+        );
+
+        // Only the last transaction creates a contract, at the address derived from its
+        // sender and nonce.
+        let contract_address = "0x3e5109cba195b5f6ce1b36cbdabc1717afa5e490"
+            .parse::<models::Address>()
+            .unwrap();
+        assert_eq!(receipts[0].contract_address, None);
+        assert_eq!(receipts[1].contract_address, None);
+        assert_eq!(receipts[2].contract_address, Some(contract_address));
+        assert_eq!(receipts[2].inner.logs()[0].address(), contract_address);
+
+        // Log indices are continuous across the block's receipts.
+        let logs = receipts
+            .iter()
+            .flat_map(|receipt| receipt.inner.logs())
+            .collect::<Vec<_>>();
+        assert_eq!(logs.len(), 2);
+        for (index, log) in logs.iter().enumerate() {
+            assert_eq!(log.log_index, Some(index as u64));
+            assert_eq!(log.block_hash, Some(block_hash));
+            assert_eq!(log.block_number, Some(1_000_000_000));
+        }
+        assert_eq!(logs[0].transaction_index, Some(1));
+        assert_eq!(logs[1].transaction_index, Some(2));
+    }
+}
@@ -0,0 +1,189 @@
+//! Summarize the fees paid by the transactions in a block.
+//!
+//! The summary is stored in its own fragment and merged into the block header when the
+//! client requests it. Pending blocks have no summary, since their fees are not final.
+use std::collections::BTreeMap;
+
+use apibara_dna_protocol::evm;
+
+use crate::{l2::ReceiptExtension, proto::ModelExt, provider::models};
+
+/// The tag of the `fee_summary` field in `BlockHeader`.
+const FEE_SUMMARY_TAG: u32 = 24;
+
+/// Returns the fee summary of the block's transactions.
+///
+/// The priority fee is the part of the effective gas price above the base fee, or the whole
+/// gas price before EIP-1559. Transactions with an L2 specific type, such as OP Stack deposits,
+/// don't pay a priority fee and are not included in the priority fee statistics.
+pub fn block_fee_summary(
+    base_fee_per_gas: Option<u64>,
+    gas_used: u64,
+    receipts: &[models::TransactionReceipt],
+    extensions: &[ReceiptExtension],
+) -> evm::BlockFeeSummary {
+    let base_fee_per_gas = base_fee_per_gas.unwrap_or_default() as u128;
+
+    let mut priority_fees = Vec::with_capacity(receipts.len());
+    let mut blob_fee = models::U256::ZERO;
+    let mut gas_used_by_transaction_type = BTreeMap::<u64, (u32, u128)>::new();
+
+    for (transaction_index, receipt) in receipts.iter().enumerate() {
+        let l2_transaction_type = extensions
+            .get(transaction_index)
+            .and_then(ReceiptExtension::l2_transaction_type);
+
+        if l2_transaction_type.is_none() {
+            priority_fees.push(receipt.effective_gas_price.saturating_sub(base_fee_per_gas));
+        }
+
+        if let (Some(blob_gas_used), Some(blob_gas_price)) =
+            (receipt.blob_gas_used, receipt.blob_gas_price)
+        {
+            blob_fee += models::U256::from(blob_gas_used) * models::U256::from(blob_gas_price);
+        }
+
+        let transaction_type =
+            l2_transaction_type.unwrap_or(receipt.transaction_type() as u8 as u64);
+
+        let (transaction_count, transaction_gas_used) = gas_used_by_transaction_type
+            .entry(transaction_type)
+            .or_default();
+        *transaction_count += 1;
+        *transaction_gas_used += receipt.gas_used as u128;
+    }
+
+    priority_fees.sort_unstable();
+
+    let burned_fee = models::U256::from(base_fee_per_gas) * models::U256::from(gas_used);
+
+    evm::BlockFeeSummary {
+        min_priority_fee_per_gas: priority_fees.first().copied().map(evm::U128::from_u128),
+        median_priority_fee_per_gas: median(&priority_fees).map(evm::U128::from_u128),
+        max_priority_fee_per_gas: priority_fees.last().copied().map(evm::U128::from_u128),
+        burned_fee: burned_fee.to_proto().into(),
+        blob_fee: blob_fee.to_proto().into(),
+        gas_used_by_transaction_type: gas_used_by_transaction_type
+            .into_iter()
+            .map(
+                |(transaction_type, (transaction_count, gas_used))| evm::TransactionTypeGasUsed {
+                    transaction_type,
+                    transaction_count,
+                    gas_used: evm::U128::from_u128(gas_used).into(),
+                },
+            )
+            .collect(),
+    }
+}
+
+/// Encode the fee summary as the `fee_summary` field of the block header.
+///
+/// The client merges the field into the header sent by the stream.
+pub fn encode_fee_summary(summary: &evm::BlockFeeSummary) -> Vec<u8> {
+    let mut output = Vec::new();
+    prost::encoding::message::encode(FEE_SUMMARY_TAG, summary, &mut output);
+    output
+}
+
+/// Returns the median of the sorted values, averaging the two middle values if needed.
+fn median(sorted: &[u128]) -> Option<u128> {
+    let upper = *sorted.get(sorted.len() / 2)?;
+
+    if !sorted.len().is_multiple_of(2) {
+        return Some(upper);
+    }
+
+    let lower = sorted[sorted.len() / 2 - 1];
+    Some(lower / 2 + upper / 2 + (lower % 2 + upper % 2) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_dna_protocol::evm;
+    use prost::Message;
+
+    use super::{block_fee_summary, encode_fee_summary, median};
+    use crate::{l2::ReceiptExtension, provider::models};
+
+    fn receipt(
+        transaction_type: u8,
+        effective_gas_price: u64,
+        gas_used: u64,
+    ) -> models::TransactionReceipt {
+        serde_json::from_value(serde_json::json!({
+            "type": format!("{:#x}", transaction_type),
+            "status": "0x1",
+            "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "transactionIndex": "0x0",
+            "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+            "blockNumber": "0x1",
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x0000000000000000000000000000000000000002",
+            "cumulativeGasUsed": format!("{:#x}", gas_used),
+            "gasUsed": format!("{:#x}", gas_used),
+            "effectiveGasPrice": format!("{:#x}", effective_gas_price),
+            "contractAddress": null,
+            "logs": [],
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+        }))
+        .unwrap()
+    }
+
+    fn to_u128(value: &Option<evm::U128>) -> u128 {
+        u128::from_be_bytes(value.unwrap().to_bytes())
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[1, 5, 7]), Some(5));
+        assert_eq!(median(&[1, 4, 7, 9]), Some(5));
+        assert_eq!(median(&[u128::MAX, u128::MAX]), Some(u128::MAX));
+    }
+
+    #[test]
+    fn test_block_fee_summary() {
+        let receipts = vec![
+            receipt(2, 12, 21_000),
+            receipt(0, 20, 50_000),
+            receipt(2, 11, 30_000),
+            receipt(0, 0, 40_000),
+        ];
+
+        // The last transaction is an OP Stack deposit.
+        let mut extensions = vec![ReceiptExtension::default(); 4];
+        extensions[3].transaction_type = Some(models::U64::from(0x7e));
+
+        let summary = block_fee_summary(Some(10), 141_000, &receipts, &extensions);
+
+        assert_eq!(to_u128(&summary.min_priority_fee_per_gas), 1);
+        assert_eq!(to_u128(&summary.median_priority_fee_per_gas), 2);
+        assert_eq!(to_u128(&summary.max_priority_fee_per_gas), 10);
+        assert_eq!(
+            summary.burned_fee,
+            Some(evm::U256::from_bytes(
+                &models::U256::from(1_410_000).to_be_bytes()
+            ))
+        );
+
+        let gas_used = summary
+            .gas_used_by_transaction_type
+            .iter()
+            .map(|entry| {
+                (
+                    entry.transaction_type,
+                    entry.transaction_count,
+                    to_u128(&entry.gas_used),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            gas_used,
+            vec![(0, 1, 50_000), (2, 2, 51_000), (0x7e, 1, 40_000)]
+        );
+
+        // The encoded summary is merged into the header.
+        let header = evm::BlockHeader::decode(encode_fee_summary(&summary).as_slice()).unwrap();
+        assert_eq!(header.fee_summary, Some(summary));
+    }
+}
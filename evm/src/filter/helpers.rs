@@ -1,9 +1,54 @@
-use apibara_dna_common::query::{BlockFilter, Filter};
+use std::sync::Arc;
+
+use apibara_dna_common::{
+    index::ScalarValue,
+    query::{BlockFilter, Filter, FragmentDecoder},
+};
+use apibara_dna_protocol::evm;
 
 pub trait BlockFilterExt {
     fn compile_to_block_filter(&self) -> tonic::Result<BlockFilter, tonic::Status>;
 }
 
+/// A filter and the decoder for the data it matches, if any.
+pub type FilterWithDecoder = (Filter, Option<Arc<dyn FragmentDecoder>>);
+
 pub trait FragmentFilterExt {
     fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status>;
+
+    /// Returns the filter together with the decoder for the data it matches, if any.
+    fn compile_to_filter_with_decoder(&self) -> tonic::Result<FilterWithDecoder, tonic::Status> {
+        Ok((self.compile_to_filter()?, None))
+    }
+}
+
+/// Returns the index key for the transaction status filter, defaulting to `Succeeded`.
+pub fn transaction_status_key(
+    transaction_status: Option<i32>,
+    filter_name: &str,
+    filter_id: u32,
+) -> tonic::Result<Option<ScalarValue>, tonic::Status> {
+    let transaction_status = if let Some(transaction_status) = transaction_status {
+        evm::TransactionStatusFilter::try_from(transaction_status).map_err(|_| {
+            tonic::Status::invalid_argument(format!(
+                "invalid transaction status in {} filter with id {}",
+                filter_name, filter_id
+            ))
+        })?
+    } else {
+        evm::TransactionStatusFilter::Succeeded
+    };
+
+    let key = match transaction_status {
+        evm::TransactionStatusFilter::Unspecified => None,
+        evm::TransactionStatusFilter::All => None,
+        evm::TransactionStatusFilter::Succeeded => {
+            Some(ScalarValue::Int32(evm::TransactionStatus::Succeeded as i32))
+        }
+        evm::TransactionStatusFilter::Reverted => {
+            Some(ScalarValue::Int32(evm::TransactionStatus::Reverted as i32))
+        }
+    };
+
+    Ok(key)
 }
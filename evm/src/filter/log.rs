@@ -1,19 +1,64 @@
+use std::sync::Arc;
+
 use apibara_dna_common::{
     index::ScalarValue,
-    query::{Condition, Filter},
+    query::{Condition, Filter, FragmentDecoder},
 };
 use apibara_dna_protocol::evm;
 
-use crate::fragment::{
-    INDEX_LOG_BY_ADDRESS, INDEX_LOG_BY_TOPIC0, INDEX_LOG_BY_TOPIC1, INDEX_LOG_BY_TOPIC2,
-    INDEX_LOG_BY_TOPIC3, INDEX_LOG_BY_TOPIC_LENGTH, INDEX_LOG_BY_TRANSACTION_STATUS,
-    LOG_FRAGMENT_ID, RECEIPT_FRAGMENT_ID, TRACE_FRAGMENT_ID, TRANSACTION_FRAGMENT_ID,
+use crate::{
+    abi::EventDecoder,
+    fragment::{
+        INDEX_LOG_BY_ADDRESS, INDEX_LOG_BY_TOPIC0, INDEX_LOG_BY_TOPIC1, INDEX_LOG_BY_TOPIC2,
+        INDEX_LOG_BY_TOPIC3, INDEX_LOG_BY_TOPIC_LENGTH, INDEX_LOG_BY_TRANSACTION_FROM,
+        INDEX_LOG_BY_TRANSACTION_STATUS, INDEX_LOG_BY_TRANSACTION_TO, LOG_FRAGMENT_ID,
+        RECEIPT_FRAGMENT_ID, TRACE_FRAGMENT_ID, TRANSACTION_FRAGMENT_ID,
+    },
 };
 
-use super::helpers::FragmentFilterExt;
+use super::helpers::{FilterWithDecoder, FragmentFilterExt};
 
 impl FragmentFilterExt for evm::LogFilter {
     fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let decoder = self.event_decoder()?;
+        self.compile_to_filter_with_event_decoder(decoder.as_ref())
+    }
+
+    fn compile_to_filter_with_decoder(&self) -> tonic::Result<FilterWithDecoder, tonic::Status> {
+        let decoder = self.event_decoder()?;
+        let filter = self.compile_to_filter_with_event_decoder(decoder.as_ref())?;
+        let decoder = decoder.map(|decoder| Arc::new(decoder) as Arc<dyn FragmentDecoder>);
+        Ok((filter, decoder))
+    }
+}
+
+trait LogFilterExt {
+    fn event_decoder(&self) -> tonic::Result<Option<EventDecoder>, tonic::Status>;
+
+    fn compile_to_filter_with_event_decoder(
+        &self,
+        decoder: Option<&EventDecoder>,
+    ) -> tonic::Result<Filter, tonic::Status>;
+}
+
+impl LogFilterExt for evm::LogFilter {
+    fn event_decoder(&self) -> tonic::Result<Option<EventDecoder>, tonic::Status> {
+        let Some(abi) = self.abi.as_ref() else {
+            return Ok(None);
+        };
+
+        EventDecoder::parse(abi).map(Some).map_err(|err| {
+            tonic::Status::invalid_argument(format!(
+                "invalid event abi in log filter with id {}: {}",
+                self.id, err
+            ))
+        })
+    }
+
+    fn compile_to_filter_with_event_decoder(
+        &self,
+        decoder: Option<&EventDecoder>,
+    ) -> tonic::Result<Filter, tonic::Status> {
         let mut conditions = Vec::new();
 
         if let Some(address) = self.address {
@@ -23,6 +68,20 @@ impl FragmentFilterExt for evm::LogFilter {
             });
         }
 
+        if let Some(transaction_from) = self.transaction_from {
+            conditions.push(Condition {
+                index_id: INDEX_LOG_BY_TRANSACTION_FROM,
+                key: ScalarValue::B160(transaction_from.to_bytes()),
+            });
+        }
+
+        if let Some(transaction_to) = self.transaction_to {
+            conditions.push(Condition {
+                index_id: INDEX_LOG_BY_TRANSACTION_TO,
+                key: ScalarValue::B160(transaction_to.to_bytes()),
+            });
+        }
+
         if let Some(true) = self.strict {
             conditions.push(Condition {
                 index_id: INDEX_LOG_BY_TOPIC_LENGTH,
@@ -30,6 +89,16 @@ impl FragmentFilterExt for evm::LogFilter {
             });
         }
 
+        // Use the event selector if no topic is set. A null first topic matches any event.
+        if self.topics.is_empty() {
+            if let Some(topic) = decoder.and_then(EventDecoder::topic0) {
+                conditions.push(Condition {
+                    index_id: INDEX_LOG_BY_TOPIC0,
+                    key: ScalarValue::B256(topic.0),
+                });
+            }
+        }
+
         let mut topics = self.topics.iter();
 
         if let Some(topic) = topics.next().and_then(|t| t.value.as_ref()) {
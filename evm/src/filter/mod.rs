@@ -1,6 +1,10 @@
 mod helpers;
 mod log;
+mod state_diff;
+mod token_transfer;
+mod trace;
 mod transaction;
+mod uncle;
 mod withdrawal;
 
 use apibara_dna_common::{
@@ -10,6 +14,8 @@ use apibara_dna_common::{
 use apibara_dna_protocol::evm;
 use prost::Message;
 
+use crate::fragment::{FEE_SUMMARY_FRAGMENT_ID, OPTIONAL_FRAGMENTS, OPTIONAL_INDEXES};
+
 use self::helpers::{BlockFilterExt, FragmentFilterExt};
 
 pub struct EvmFilterFactory;
@@ -65,17 +71,60 @@ impl BlockFilterExt for evm::Filter {
 
         block_filter.set_header_filter(header_filter);
 
+        for fragment_id in OPTIONAL_FRAGMENTS {
+            block_filter.add_optional_fragment(*fragment_id);
+        }
+
+        for (fragment_id, index_id) in OPTIONAL_INDEXES {
+            block_filter.add_optional_index(*fragment_id, *index_id);
+        }
+
+        if let Some(true) = self.include_fee_summary {
+            block_filter.add_header_fragment(FEE_SUMMARY_FRAGMENT_ID);
+        }
+
         for filter in self.withdrawals.iter() {
             let filter = filter.compile_to_filter()?;
             block_filter.add_filter(filter);
         }
 
         for filter in self.transactions.iter() {
-            let filter = filter.compile_to_filter()?;
+            let (filter, decoder) = filter.compile_to_filter_with_decoder()?;
+            if let Some(decoder) = decoder {
+                block_filter.add_decoder(filter.fragment_id, filter.filter_id, decoder);
+            }
             block_filter.add_filter(filter);
         }
 
         for filter in self.logs.iter() {
+            let (filter, decoder) = filter.compile_to_filter_with_decoder()?;
+            if let Some(decoder) = decoder {
+                block_filter.add_decoder(filter.fragment_id, filter.filter_id, decoder);
+            }
+            block_filter.add_filter(filter);
+        }
+
+        for filter in self.traces.iter() {
+            let filter = filter.compile_to_filter()?;
+            block_filter.add_filter(filter);
+        }
+
+        for filter in self.account_diffs.iter() {
+            let filter = filter.compile_to_filter()?;
+            block_filter.add_filter(filter);
+        }
+
+        for filter in self.storage_diffs.iter() {
+            let filter = filter.compile_to_filter()?;
+            block_filter.add_filter(filter);
+        }
+
+        for filter in self.token_transfers.iter() {
+            let filter = filter.compile_to_filter()?;
+            block_filter.add_filter(filter);
+        }
+
+        for filter in self.uncles.iter() {
             let filter = filter.compile_to_filter()?;
             block_filter.add_filter(filter);
         }
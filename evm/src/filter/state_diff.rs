@@ -0,0 +1,119 @@
+use apibara_dna_common::{
+    index::ScalarValue,
+    query::{Condition, Filter},
+};
+use apibara_dna_protocol::evm;
+
+use crate::fragment::{
+    ACCOUNT_DIFF_FRAGMENT_ID, INDEX_ACCOUNT_DIFF_BY_ADDRESS, INDEX_ACCOUNT_DIFF_BY_BALANCE_CHANGED,
+    INDEX_ACCOUNT_DIFF_BY_CODE_CHANGED, INDEX_ACCOUNT_DIFF_BY_NONCE_CHANGED,
+    INDEX_ACCOUNT_DIFF_BY_TRANSACTION_STATUS, INDEX_STORAGE_DIFF_BY_ADDRESS,
+    INDEX_STORAGE_DIFF_BY_SLOT, INDEX_STORAGE_DIFF_BY_TRANSACTION_STATUS, RECEIPT_FRAGMENT_ID,
+    STORAGE_DIFF_FRAGMENT_ID, TRANSACTION_FRAGMENT_ID,
+};
+
+use super::helpers::{transaction_status_key, FragmentFilterExt};
+
+impl FragmentFilterExt for evm::AccountDiffFilter {
+    fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let mut conditions = Vec::new();
+
+        if let Some(address) = self.address {
+            conditions.push(Condition {
+                index_id: INDEX_ACCOUNT_DIFF_BY_ADDRESS,
+                key: ScalarValue::B160(address.to_bytes()),
+            });
+        }
+
+        if let Some(balance_changed) = self.balance_changed {
+            conditions.push(Condition {
+                index_id: INDEX_ACCOUNT_DIFF_BY_BALANCE_CHANGED,
+                key: ScalarValue::Bool(balance_changed),
+            });
+        }
+
+        if let Some(nonce_changed) = self.nonce_changed {
+            conditions.push(Condition {
+                index_id: INDEX_ACCOUNT_DIFF_BY_NONCE_CHANGED,
+                key: ScalarValue::Bool(nonce_changed),
+            });
+        }
+
+        if let Some(code_changed) = self.code_changed {
+            conditions.push(Condition {
+                index_id: INDEX_ACCOUNT_DIFF_BY_CODE_CHANGED,
+                key: ScalarValue::Bool(code_changed),
+            });
+        }
+
+        if let Some(key) = transaction_status_key(self.transaction_status, "account diff", self.id)?
+        {
+            conditions.push(Condition {
+                index_id: INDEX_ACCOUNT_DIFF_BY_TRANSACTION_STATUS,
+                key,
+            });
+        }
+
+        let mut joins = Vec::new();
+
+        if let Some(true) = self.include_transaction {
+            joins.push(TRANSACTION_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_receipt {
+            joins.push(RECEIPT_FRAGMENT_ID);
+        }
+
+        Ok(Filter {
+            filter_id: self.id,
+            fragment_id: ACCOUNT_DIFF_FRAGMENT_ID,
+            conditions,
+            joins,
+        })
+    }
+}
+
+impl FragmentFilterExt for evm::StorageDiffFilter {
+    fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let mut conditions = Vec::new();
+
+        if let Some(address) = self.address {
+            conditions.push(Condition {
+                index_id: INDEX_STORAGE_DIFF_BY_ADDRESS,
+                key: ScalarValue::B160(address.to_bytes()),
+            });
+        }
+
+        if let Some(slot) = self.slot {
+            conditions.push(Condition {
+                index_id: INDEX_STORAGE_DIFF_BY_SLOT,
+                key: ScalarValue::B256(slot.to_bytes()),
+            });
+        }
+
+        if let Some(key) = transaction_status_key(self.transaction_status, "storage diff", self.id)?
+        {
+            conditions.push(Condition {
+                index_id: INDEX_STORAGE_DIFF_BY_TRANSACTION_STATUS,
+                key,
+            });
+        }
+
+        let mut joins = Vec::new();
+
+        if let Some(true) = self.include_transaction {
+            joins.push(TRANSACTION_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_receipt {
+            joins.push(RECEIPT_FRAGMENT_ID);
+        }
+
+        Ok(Filter {
+            filter_id: self.id,
+            fragment_id: STORAGE_DIFF_FRAGMENT_ID,
+            conditions,
+            joins,
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use apibara_dna_common::{
+    index::ScalarValue,
+    query::{Condition, Filter},
+};
+use apibara_dna_protocol::evm;
+
+use crate::fragment::{
+    INDEX_TOKEN_TRANSFER_BY_ADDRESS, INDEX_TOKEN_TRANSFER_BY_FROM_ADDRESS,
+    INDEX_TOKEN_TRANSFER_BY_STANDARD, INDEX_TOKEN_TRANSFER_BY_TOKEN,
+    INDEX_TOKEN_TRANSFER_BY_TO_ADDRESS, LOG_FRAGMENT_ID, RECEIPT_FRAGMENT_ID,
+    TOKEN_TRANSFER_FRAGMENT_ID, TRANSACTION_FRAGMENT_ID,
+};
+
+use super::helpers::FragmentFilterExt;
+
+impl FragmentFilterExt for evm::TokenTransferFilter {
+    fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let mut conditions = Vec::new();
+
+        if let Some(standard) = self.standard {
+            let standard = evm::TokenStandard::try_from(standard).map_err(|_| {
+                tonic::Status::invalid_argument(format!(
+                    "invalid token standard in token transfer filter with id {}",
+                    self.id
+                ))
+            })?;
+
+            if standard != evm::TokenStandard::Unspecified {
+                conditions.push(Condition {
+                    index_id: INDEX_TOKEN_TRANSFER_BY_STANDARD,
+                    key: ScalarValue::Int32(standard as i32),
+                });
+            }
+        }
+
+        if let Some(token) = self.token {
+            conditions.push(Condition {
+                index_id: INDEX_TOKEN_TRANSFER_BY_TOKEN,
+                key: ScalarValue::B160(token.to_bytes()),
+            });
+        }
+
+        if let Some(from) = self.from {
+            conditions.push(Condition {
+                index_id: INDEX_TOKEN_TRANSFER_BY_FROM_ADDRESS,
+                key: ScalarValue::B160(from.to_bytes()),
+            });
+        }
+
+        if let Some(to) = self.to {
+            conditions.push(Condition {
+                index_id: INDEX_TOKEN_TRANSFER_BY_TO_ADDRESS,
+                key: ScalarValue::B160(to.to_bytes()),
+            });
+        }
+
+        if let Some(address) = self.address {
+            conditions.push(Condition {
+                index_id: INDEX_TOKEN_TRANSFER_BY_ADDRESS,
+                key: ScalarValue::B160(address.to_bytes()),
+            });
+        }
+
+        let mut joins = Vec::new();
+
+        if let Some(true) = self.include_transaction {
+            joins.push(TRANSACTION_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_receipt {
+            joins.push(RECEIPT_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_log {
+            joins.push(LOG_FRAGMENT_ID);
+        }
+
+        Ok(Filter {
+            filter_id: self.id,
+            fragment_id: TOKEN_TRANSFER_FRAGMENT_ID,
+            conditions,
+            joins,
+        })
+    }
+}
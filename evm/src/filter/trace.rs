@@ -0,0 +1,139 @@
+use apibara_dna_common::{
+    index::ScalarValue,
+    query::{Condition, Filter},
+};
+use apibara_dna_protocol::evm;
+
+use crate::fragment::{
+    CALL_TRACE_FRAGMENT_ID, INDEX_CALL_TRACE_BY_ACTION_TYPE, INDEX_CALL_TRACE_BY_CALL_TYPE,
+    INDEX_CALL_TRACE_BY_CREATED_ADDRESS, INDEX_CALL_TRACE_BY_FROM_ADDRESS,
+    INDEX_CALL_TRACE_BY_SELECTOR, INDEX_CALL_TRACE_BY_TO_ADDRESS,
+    INDEX_CALL_TRACE_BY_TRANSACTION_STATUS, LOG_FRAGMENT_ID, RECEIPT_FRAGMENT_ID,
+    TRACE_FRAGMENT_ID, TRANSACTION_FRAGMENT_ID,
+};
+
+use super::helpers::FragmentFilterExt;
+
+impl FragmentFilterExt for evm::TraceFilter {
+    fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let mut conditions = Vec::new();
+
+        if let Some(from) = self.from {
+            conditions.push(Condition {
+                index_id: INDEX_CALL_TRACE_BY_FROM_ADDRESS,
+                key: ScalarValue::B160(from.to_bytes()),
+            });
+        }
+
+        if let Some(to) = self.to {
+            conditions.push(Condition {
+                index_id: INDEX_CALL_TRACE_BY_TO_ADDRESS,
+                key: ScalarValue::B160(to.to_bytes()),
+            });
+        }
+
+        if let Some(action_type) = self.action_type {
+            let action_type = evm::TraceActionType::try_from(action_type).map_err(|_| {
+                tonic::Status::invalid_argument(format!(
+                    "invalid action type in trace filter with id {}",
+                    self.id
+                ))
+            })?;
+
+            if action_type != evm::TraceActionType::Unspecified {
+                conditions.push(Condition {
+                    index_id: INDEX_CALL_TRACE_BY_ACTION_TYPE,
+                    key: ScalarValue::Int32(action_type as i32),
+                });
+            }
+        }
+
+        if let Some(call_type) = self.call_type {
+            let call_type = evm::CallType::try_from(call_type).map_err(|_| {
+                tonic::Status::invalid_argument(format!(
+                    "invalid call type in trace filter with id {}",
+                    self.id
+                ))
+            })?;
+
+            conditions.push(Condition {
+                index_id: INDEX_CALL_TRACE_BY_CALL_TYPE,
+                key: ScalarValue::Int32(call_type as i32),
+            });
+        }
+
+        if !self.selector.is_empty() {
+            let selector = <[u8; 4]>::try_from(self.selector.as_slice()).map_err(|_| {
+                tonic::Status::invalid_argument(format!(
+                    "selector must be 4 bytes in trace filter with id {}",
+                    self.id
+                ))
+            })?;
+
+            conditions.push(Condition {
+                index_id: INDEX_CALL_TRACE_BY_SELECTOR,
+                key: ScalarValue::Uint32(u32::from_be_bytes(selector)),
+            });
+        }
+
+        if let Some(created_address) = self.created_address {
+            conditions.push(Condition {
+                index_id: INDEX_CALL_TRACE_BY_CREATED_ADDRESS,
+                key: ScalarValue::B160(created_address.to_bytes()),
+            });
+        }
+
+        let transaction_status = if let Some(transaction_status) = self.transaction_status {
+            evm::TransactionStatusFilter::try_from(transaction_status).map_err(|_| {
+                tonic::Status::invalid_argument(format!(
+                    "invalid transaction status in trace filter with id {}",
+                    self.id
+                ))
+            })?
+        } else {
+            evm::TransactionStatusFilter::Succeeded
+        };
+
+        match transaction_status {
+            evm::TransactionStatusFilter::Unspecified => {}
+            evm::TransactionStatusFilter::All => {}
+            evm::TransactionStatusFilter::Succeeded => {
+                conditions.push(Condition {
+                    index_id: INDEX_CALL_TRACE_BY_TRANSACTION_STATUS,
+                    key: ScalarValue::Int32(evm::TransactionStatus::Succeeded as i32),
+                });
+            }
+            evm::TransactionStatusFilter::Reverted => {
+                conditions.push(Condition {
+                    index_id: INDEX_CALL_TRACE_BY_TRANSACTION_STATUS,
+                    key: ScalarValue::Int32(evm::TransactionStatus::Reverted as i32),
+                });
+            }
+        };
+
+        let mut joins = Vec::new();
+
+        if let Some(true) = self.include_transaction {
+            joins.push(TRANSACTION_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_receipt {
+            joins.push(RECEIPT_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_logs {
+            joins.push(LOG_FRAGMENT_ID);
+        }
+
+        if let Some(true) = self.include_transaction_trace {
+            joins.push(TRACE_FRAGMENT_ID);
+        }
+
+        Ok(Filter {
+            filter_id: self.id,
+            fragment_id: CALL_TRACE_FRAGMENT_ID,
+            conditions,
+            joins,
+        })
+    }
+}
@@ -1,19 +1,66 @@
+use std::sync::Arc;
+
 use apibara_dna_common::{
     index::ScalarValue,
-    query::{Condition, Filter},
+    query::{Condition, Filter, FragmentDecoder},
 };
 use apibara_dna_protocol::evm;
 
-use crate::fragment::{
-    INDEX_TRANSACTION_BY_CREATE, INDEX_TRANSACTION_BY_FROM_ADDRESS, INDEX_TRANSACTION_BY_STATUS,
-    INDEX_TRANSACTION_BY_TO_ADDRESS, LOG_FRAGMENT_ID, RECEIPT_FRAGMENT_ID, TRACE_FRAGMENT_ID,
-    TRANSACTION_FRAGMENT_ID,
+use crate::{
+    abi::FunctionDecoder,
+    fragment::{
+        INDEX_TRANSACTION_BY_AUTHORIZATION_AUTHORITY, INDEX_TRANSACTION_BY_AUTHORIZATION_DELEGATE,
+        INDEX_TRANSACTION_BY_CREATE, INDEX_TRANSACTION_BY_CREATED_ADDRESS,
+        INDEX_TRANSACTION_BY_DEPOSIT, INDEX_TRANSACTION_BY_FROM_ADDRESS, INDEX_TRANSACTION_BY_MINT,
+        INDEX_TRANSACTION_BY_SELECTOR, INDEX_TRANSACTION_BY_STATUS,
+        INDEX_TRANSACTION_BY_TO_ADDRESS, LOG_FRAGMENT_ID, RECEIPT_FRAGMENT_ID, TRACE_FRAGMENT_ID,
+        TRANSACTION_FRAGMENT_ID,
+    },
 };
 
-use super::helpers::FragmentFilterExt;
+use super::helpers::{FilterWithDecoder, FragmentFilterExt};
 
 impl FragmentFilterExt for evm::TransactionFilter {
     fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let decoder = self.function_decoder()?;
+        self.compile_to_filter_with_function_decoder(decoder.as_ref())
+    }
+
+    fn compile_to_filter_with_decoder(&self) -> tonic::Result<FilterWithDecoder, tonic::Status> {
+        let decoder = self.function_decoder()?;
+        let filter = self.compile_to_filter_with_function_decoder(decoder.as_ref())?;
+        let decoder = decoder.map(|decoder| Arc::new(decoder) as Arc<dyn FragmentDecoder>);
+        Ok((filter, decoder))
+    }
+}
+
+trait TransactionFilterExt {
+    fn function_decoder(&self) -> tonic::Result<Option<FunctionDecoder>, tonic::Status>;
+
+    fn compile_to_filter_with_function_decoder(
+        &self,
+        decoder: Option<&FunctionDecoder>,
+    ) -> tonic::Result<Filter, tonic::Status>;
+}
+
+impl TransactionFilterExt for evm::TransactionFilter {
+    fn function_decoder(&self) -> tonic::Result<Option<FunctionDecoder>, tonic::Status> {
+        let Some(abi) = self.abi.as_ref() else {
+            return Ok(None);
+        };
+
+        FunctionDecoder::parse(abi).map(Some).map_err(|err| {
+            tonic::Status::invalid_argument(format!(
+                "invalid function abi in transaction filter with id {}: {}",
+                self.id, err
+            ))
+        })
+    }
+
+    fn compile_to_filter_with_function_decoder(
+        &self,
+        decoder: Option<&FunctionDecoder>,
+    ) -> tonic::Result<Filter, tonic::Status> {
         let mut conditions = Vec::new();
 
         if let Some(from) = self.from {
@@ -37,6 +84,61 @@ impl FragmentFilterExt for evm::TransactionFilter {
             });
         }
 
+        // Use the function selector if the selector is not set.
+        let selector = if !self.selector.is_empty() {
+            let selector = <[u8; 4]>::try_from(self.selector.as_slice()).map_err(|_| {
+                tonic::Status::invalid_argument(format!(
+                    "selector must be 4 bytes in transaction filter with id {}",
+                    self.id
+                ))
+            })?;
+            Some(selector)
+        } else {
+            decoder.map(FunctionDecoder::selector)
+        };
+
+        if let Some(selector) = selector {
+            conditions.push(Condition {
+                index_id: INDEX_TRANSACTION_BY_SELECTOR,
+                key: ScalarValue::Uint32(u32::from_be_bytes(selector)),
+            });
+        }
+
+        if let Some(created_address) = self.created_address {
+            conditions.push(Condition {
+                index_id: INDEX_TRANSACTION_BY_CREATED_ADDRESS,
+                key: ScalarValue::B160(created_address.to_bytes()),
+            });
+        }
+
+        if let Some(authority) = self.authority {
+            conditions.push(Condition {
+                index_id: INDEX_TRANSACTION_BY_AUTHORIZATION_AUTHORITY,
+                key: ScalarValue::B160(authority.to_bytes()),
+            });
+        }
+
+        if let Some(delegate) = self.delegate {
+            conditions.push(Condition {
+                index_id: INDEX_TRANSACTION_BY_AUTHORIZATION_DELEGATE,
+                key: ScalarValue::B160(delegate.to_bytes()),
+            });
+        }
+
+        if let Some(deposit) = self.deposit {
+            conditions.push(Condition {
+                index_id: INDEX_TRANSACTION_BY_DEPOSIT,
+                key: ScalarValue::Bool(deposit),
+            });
+        }
+
+        if let Some(mint) = self.mint {
+            conditions.push(Condition {
+                index_id: INDEX_TRANSACTION_BY_MINT,
+                key: ScalarValue::Bool(mint),
+            });
+        }
+
         let transaction_status = if let Some(transaction_status) = self.transaction_status {
             evm::TransactionStatusFilter::try_from(transaction_status).map_err(|_| {
                 tonic::Status::invalid_argument(format!(
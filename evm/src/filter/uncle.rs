@@ -0,0 +1,29 @@
+use apibara_dna_common::{
+    index::ScalarValue,
+    query::{Condition, Filter},
+};
+use apibara_dna_protocol::evm;
+
+use crate::fragment::{INDEX_UNCLE_BY_MINER, UNCLE_FRAGMENT_ID};
+
+use super::helpers::FragmentFilterExt;
+
+impl FragmentFilterExt for evm::UncleFilter {
+    fn compile_to_filter(&self) -> tonic::Result<Filter, tonic::Status> {
+        let mut conditions = Vec::new();
+
+        if let Some(miner) = self.miner {
+            conditions.push(Condition {
+                index_id: INDEX_UNCLE_BY_MINER,
+                key: ScalarValue::B160(miner.to_bytes()),
+            });
+        }
+
+        Ok(Filter {
+            filter_id: self.id,
+            fragment_id: UNCLE_FRAGMENT_ID,
+            conditions,
+            joins: Vec::default(),
+        })
+    }
+}
@@ -1,6 +1,11 @@
 //! Fragment constants.
 
 // Make sure the fragment IDs match the field tags in the protobuf Block message.
+//
+// Only append new fragment and index IDs, never reuse them. Data ingested before a fragment or
+// index was added doesn't have it, so list it in `OPTIONAL_FRAGMENTS` or `OPTIONAL_INDEXES`.
+// Filters on it then match nothing in that range. Use the `repair` command to re-ingest the
+// range and backfill them.
 
 pub const WITHDRAWAL_FRAGMENT_ID: u8 = 2;
 pub const WITHDRAWAL_FRAGMENT_NAME: &str = "withdrawal";
@@ -17,6 +22,25 @@ pub const LOG_FRAGMENT_NAME: &str = "log";
 pub const TRACE_FRAGMENT_ID: u8 = 6;
 pub const TRACE_FRAGMENT_NAME: &str = "trace";
 
+pub const CALL_TRACE_FRAGMENT_ID: u8 = 7;
+pub const CALL_TRACE_FRAGMENT_NAME: &str = "call_trace";
+
+pub const ACCOUNT_DIFF_FRAGMENT_ID: u8 = 8;
+pub const ACCOUNT_DIFF_FRAGMENT_NAME: &str = "account_diff";
+
+pub const STORAGE_DIFF_FRAGMENT_ID: u8 = 9;
+pub const STORAGE_DIFF_FRAGMENT_NAME: &str = "storage_diff";
+
+pub const TOKEN_TRANSFER_FRAGMENT_ID: u8 = 10;
+pub const TOKEN_TRANSFER_FRAGMENT_NAME: &str = "token_transfer";
+
+pub const UNCLE_FRAGMENT_ID: u8 = 11;
+pub const UNCLE_FRAGMENT_NAME: &str = "uncle";
+
+// The fee summary is merged into the header, so its ID is reserved in the Block message.
+pub const FEE_SUMMARY_FRAGMENT_ID: u8 = 12;
+pub const FEE_SUMMARY_FRAGMENT_NAME: &str = "fee_summary";
+
 pub const INDEX_WITHDRAWAL_BY_VALIDATOR_INDEX: u8 = 0;
 pub const INDEX_WITHDRAWAL_BY_ADDRESS: u8 = 1;
 
@@ -24,6 +48,12 @@ pub const INDEX_TRANSACTION_BY_FROM_ADDRESS: u8 = 0;
 pub const INDEX_TRANSACTION_BY_TO_ADDRESS: u8 = 1;
 pub const INDEX_TRANSACTION_BY_CREATE: u8 = 2;
 pub const INDEX_TRANSACTION_BY_STATUS: u8 = 3;
+pub const INDEX_TRANSACTION_BY_SELECTOR: u8 = 4;
+pub const INDEX_TRANSACTION_BY_CREATED_ADDRESS: u8 = 5;
+pub const INDEX_TRANSACTION_BY_AUTHORIZATION_AUTHORITY: u8 = 6;
+pub const INDEX_TRANSACTION_BY_AUTHORIZATION_DELEGATE: u8 = 7;
+pub const INDEX_TRANSACTION_BY_DEPOSIT: u8 = 8;
+pub const INDEX_TRANSACTION_BY_MINT: u8 = 9;
 
 // No receipts index.
 
@@ -34,3 +64,62 @@ pub const INDEX_LOG_BY_TOPIC2: u8 = 3;
 pub const INDEX_LOG_BY_TOPIC3: u8 = 4;
 pub const INDEX_LOG_BY_TOPIC_LENGTH: u8 = 5;
 pub const INDEX_LOG_BY_TRANSACTION_STATUS: u8 = 6;
+pub const INDEX_LOG_BY_TRANSACTION_FROM: u8 = 7;
+pub const INDEX_LOG_BY_TRANSACTION_TO: u8 = 8;
+
+pub const INDEX_CALL_TRACE_BY_FROM_ADDRESS: u8 = 0;
+pub const INDEX_CALL_TRACE_BY_TO_ADDRESS: u8 = 1;
+pub const INDEX_CALL_TRACE_BY_ACTION_TYPE: u8 = 2;
+pub const INDEX_CALL_TRACE_BY_CALL_TYPE: u8 = 3;
+pub const INDEX_CALL_TRACE_BY_SELECTOR: u8 = 4;
+pub const INDEX_CALL_TRACE_BY_CREATED_ADDRESS: u8 = 5;
+pub const INDEX_CALL_TRACE_BY_TRANSACTION_STATUS: u8 = 6;
+
+pub const INDEX_ACCOUNT_DIFF_BY_ADDRESS: u8 = 0;
+pub const INDEX_ACCOUNT_DIFF_BY_BALANCE_CHANGED: u8 = 1;
+pub const INDEX_ACCOUNT_DIFF_BY_NONCE_CHANGED: u8 = 2;
+pub const INDEX_ACCOUNT_DIFF_BY_CODE_CHANGED: u8 = 3;
+pub const INDEX_ACCOUNT_DIFF_BY_TRANSACTION_STATUS: u8 = 4;
+
+pub const INDEX_STORAGE_DIFF_BY_ADDRESS: u8 = 0;
+pub const INDEX_STORAGE_DIFF_BY_SLOT: u8 = 1;
+pub const INDEX_STORAGE_DIFF_BY_TRANSACTION_STATUS: u8 = 2;
+
+pub const INDEX_TOKEN_TRANSFER_BY_STANDARD: u8 = 0;
+pub const INDEX_TOKEN_TRANSFER_BY_TOKEN: u8 = 1;
+pub const INDEX_TOKEN_TRANSFER_BY_FROM_ADDRESS: u8 = 2;
+pub const INDEX_TOKEN_TRANSFER_BY_TO_ADDRESS: u8 = 3;
+pub const INDEX_TOKEN_TRANSFER_BY_ADDRESS: u8 = 4;
+
+pub const INDEX_UNCLE_BY_MINER: u8 = 0;
+
+/// Fragments added after the first release.
+pub const OPTIONAL_FRAGMENTS: &[u8] = &[
+    CALL_TRACE_FRAGMENT_ID,
+    ACCOUNT_DIFF_FRAGMENT_ID,
+    STORAGE_DIFF_FRAGMENT_ID,
+    TOKEN_TRANSFER_FRAGMENT_ID,
+    UNCLE_FRAGMENT_ID,
+    FEE_SUMMARY_FRAGMENT_ID,
+];
+
+/// Indexes added to existing fragments after the first release.
+pub const OPTIONAL_INDEXES: &[(u8, u8)] = &[
+    (TRANSACTION_FRAGMENT_ID, INDEX_TRANSACTION_BY_SELECTOR),
+    (
+        TRANSACTION_FRAGMENT_ID,
+        INDEX_TRANSACTION_BY_CREATED_ADDRESS,
+    ),
+    (
+        TRANSACTION_FRAGMENT_ID,
+        INDEX_TRANSACTION_BY_AUTHORIZATION_AUTHORITY,
+    ),
+    (
+        TRANSACTION_FRAGMENT_ID,
+        INDEX_TRANSACTION_BY_AUTHORIZATION_DELEGATE,
+    ),
+    (TRANSACTION_FRAGMENT_ID, INDEX_TRANSACTION_BY_DEPOSIT),
+    (TRANSACTION_FRAGMENT_ID, INDEX_TRANSACTION_BY_MINT),
+    (LOG_FRAGMENT_ID, INDEX_LOG_BY_TRANSACTION_FROM),
+    (LOG_FRAGMENT_ID, INDEX_LOG_BY_TRANSACTION_TO),
+];
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use alloy_rpc_types::BlockId;
 use apibara_dna_common::{
     chain::{BlockInfo, PendingBlockInfo},
@@ -6,44 +8,343 @@ use apibara_dna_common::{
         JoinFragment, JoinGroupFragment,
     },
     index::{BitmapIndexBuilder, ScalarValue},
-    ingestion::{BlockIngestion, IngestionError},
+    ingestion::{BlockIngestion, HeadNotifications, IngestionError, JsonRpcWsSubscription},
     join::{JoinToManyIndexBuilder, JoinToOneIndexBuilder},
     Cursor, Hash,
 };
 use apibara_dna_protocol::evm;
 use error_stack::{Result, ResultExt};
 use prost::Message;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 use crate::{
+    call_tracer::flatten_block_call_frames,
+    era1::{Era1Archive, Era1Block},
+    fee::{block_fee_summary, encode_fee_summary},
     fragment::{
+        ACCOUNT_DIFF_FRAGMENT_ID, ACCOUNT_DIFF_FRAGMENT_NAME, CALL_TRACE_FRAGMENT_ID,
+        CALL_TRACE_FRAGMENT_NAME, FEE_SUMMARY_FRAGMENT_ID, FEE_SUMMARY_FRAGMENT_NAME,
+        INDEX_ACCOUNT_DIFF_BY_ADDRESS, INDEX_ACCOUNT_DIFF_BY_BALANCE_CHANGED,
+        INDEX_ACCOUNT_DIFF_BY_CODE_CHANGED, INDEX_ACCOUNT_DIFF_BY_NONCE_CHANGED,
+        INDEX_ACCOUNT_DIFF_BY_TRANSACTION_STATUS, INDEX_CALL_TRACE_BY_ACTION_TYPE,
+        INDEX_CALL_TRACE_BY_CALL_TYPE, INDEX_CALL_TRACE_BY_CREATED_ADDRESS,
+        INDEX_CALL_TRACE_BY_FROM_ADDRESS, INDEX_CALL_TRACE_BY_SELECTOR,
+        INDEX_CALL_TRACE_BY_TO_ADDRESS, INDEX_CALL_TRACE_BY_TRANSACTION_STATUS,
         INDEX_LOG_BY_ADDRESS, INDEX_LOG_BY_TOPIC0, INDEX_LOG_BY_TOPIC1, INDEX_LOG_BY_TOPIC2,
-        INDEX_LOG_BY_TOPIC3, INDEX_LOG_BY_TOPIC_LENGTH, INDEX_LOG_BY_TRANSACTION_STATUS,
-        INDEX_TRANSACTION_BY_CREATE, INDEX_TRANSACTION_BY_FROM_ADDRESS,
-        INDEX_TRANSACTION_BY_STATUS, INDEX_TRANSACTION_BY_TO_ADDRESS, INDEX_WITHDRAWAL_BY_ADDRESS,
+        INDEX_LOG_BY_TOPIC3, INDEX_LOG_BY_TOPIC_LENGTH, INDEX_LOG_BY_TRANSACTION_FROM,
+        INDEX_LOG_BY_TRANSACTION_STATUS, INDEX_LOG_BY_TRANSACTION_TO,
+        INDEX_STORAGE_DIFF_BY_ADDRESS, INDEX_STORAGE_DIFF_BY_SLOT,
+        INDEX_STORAGE_DIFF_BY_TRANSACTION_STATUS, INDEX_TOKEN_TRANSFER_BY_ADDRESS,
+        INDEX_TOKEN_TRANSFER_BY_FROM_ADDRESS, INDEX_TOKEN_TRANSFER_BY_STANDARD,
+        INDEX_TOKEN_TRANSFER_BY_TOKEN, INDEX_TOKEN_TRANSFER_BY_TO_ADDRESS,
+        INDEX_TRANSACTION_BY_AUTHORIZATION_AUTHORITY, INDEX_TRANSACTION_BY_AUTHORIZATION_DELEGATE,
+        INDEX_TRANSACTION_BY_CREATE, INDEX_TRANSACTION_BY_CREATED_ADDRESS,
+        INDEX_TRANSACTION_BY_DEPOSIT, INDEX_TRANSACTION_BY_FROM_ADDRESS, INDEX_TRANSACTION_BY_MINT,
+        INDEX_TRANSACTION_BY_SELECTOR, INDEX_TRANSACTION_BY_STATUS,
+        INDEX_TRANSACTION_BY_TO_ADDRESS, INDEX_UNCLE_BY_MINER, INDEX_WITHDRAWAL_BY_ADDRESS,
         INDEX_WITHDRAWAL_BY_VALIDATOR_INDEX, LOG_FRAGMENT_ID, LOG_FRAGMENT_NAME,
-        RECEIPT_FRAGMENT_ID, RECEIPT_FRAGMENT_NAME, TRACE_FRAGMENT_ID, TRACE_FRAGMENT_NAME,
-        TRANSACTION_FRAGMENT_ID, TRANSACTION_FRAGMENT_NAME, WITHDRAWAL_FRAGMENT_ID,
-        WITHDRAWAL_FRAGMENT_NAME,
+        RECEIPT_FRAGMENT_ID, RECEIPT_FRAGMENT_NAME, STORAGE_DIFF_FRAGMENT_ID,
+        STORAGE_DIFF_FRAGMENT_NAME, TOKEN_TRANSFER_FRAGMENT_ID, TOKEN_TRANSFER_FRAGMENT_NAME,
+        TRACE_FRAGMENT_ID, TRACE_FRAGMENT_NAME, TRANSACTION_FRAGMENT_ID, TRANSACTION_FRAGMENT_NAME,
+        UNCLE_FRAGMENT_ID, UNCLE_FRAGMENT_NAME, WITHDRAWAL_FRAGMENT_ID, WITHDRAWAL_FRAGMENT_NAME,
     },
-    proto::{convert_block_header, ModelExt},
-    provider::{models, BlockExt, JsonRpcProvider, JsonRpcProviderErrorExt},
+    l2::{self, BlockExtension, ReceiptExtension, TransactionExtension},
+    proto::{convert_block_header, delta_values, ModelExt},
+    provider::{models, BlockExt, JsonRpcProvider, JsonRpcProviderError, JsonRpcProviderErrorExt},
+    token_transfer::{decode_log_transfers, decode_trace_transfers, decode_transaction_transfer},
+    verify::{verify_block_data, verify_transaction_traces},
 };
 
+/// How many times to fetch a block that fails verification before giving up.
+const VERIFY_MAX_ATTEMPTS: usize = 3;
+/// Delay between fetching a block that failed verification.
+const VERIFY_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The RPC method used to fetch transaction traces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TraceBackend {
+    /// Use `trace_replayBlockTransactions` (Erigon, Nethermind, Reth).
+    #[default]
+    Parity,
+    /// Use `debug_traceBlockByNumber` with the call tracer (Geth, Reth).
+    Geth,
+}
+
+/// The chain flavour, used to parse chain specific fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChainFlavour {
+    /// Ethereum and chains with the same transaction types.
+    #[default]
+    Ethereum,
+    /// OP Stack chains, with deposit transactions and L1 fees in receipts.
+    Optimism,
+    /// Arbitrum chains, with L1 block number and gas in receipts.
+    Arbitrum,
+}
+
+/// The data needed to ingest a block.
+struct BlockData {
+    block: models::Block,
+    receipts: Vec<models::TransactionReceipt>,
+    transaction_traces: Vec<models::TraceResultsWithTransactionHash>,
+    state_diffs: Vec<models::TraceResultsWithTransactionHash>,
+    uncles: Vec<models::Header>,
+    extension: BlockExtension,
+}
+
 #[derive(Clone, Debug)]
 pub struct EvmBlockIngestionOptions {
     pub ingest_pending: bool,
     pub ingest_traces: bool,
+    /// The RPC method used to fetch transaction traces.
+    pub trace_backend: TraceBackend,
+    /// Ingest the balance, nonce, code and storage changes of each transaction.
+    pub ingest_state_diffs: bool,
+    /// Ingest the block's uncles.
+    pub ingest_uncles: bool,
+    /// Parse the L2 specific fields of this chain flavour.
+    pub chain_flavour: ChainFlavour,
+    /// Verify the transactions, receipts and logs against the block header.
+    pub verify_block_data: bool,
+    /// Subscribe to new heads instead of polling.
+    pub head_subscription: Option<JsonRpcWsSubscription>,
 }
 
 #[derive(Clone)]
 pub struct EvmBlockIngestion {
     provider: JsonRpcProvider,
+    era1: Option<Era1Archive>,
     options: EvmBlockIngestionOptions,
 }
 
 impl EvmBlockIngestion {
     pub fn new(provider: JsonRpcProvider, options: EvmBlockIngestionOptions) -> Self {
-        Self { provider, options }
+        Self {
+            provider,
+            era1: None,
+            options,
+        }
+    }
+
+    /// Read the blocks covered by the Era1 archive from disk instead of the RPC.
+    pub fn with_era1(mut self, era1: Era1Archive) -> Self {
+        self.era1 = Some(era1);
+        self
+    }
+
+    async fn get_block_data(&self, block_number: u64) -> Result<BlockData, IngestionError> {
+        if let Some(era1) = self
+            .era1
+            .as_ref()
+            .filter(|era1| era1.contains(block_number))
+        {
+            let (block_with_transactions, block_receipts) = era1
+                .read_block(block_number)
+                .await
+                .and_then(Era1Block::into_rpc)
+                .change_context(IngestionError::Model)
+                .attach_printable("failed to read block from era1 archive")?;
+
+            let block_hash = block_with_transactions.header.hash;
+            let (block_transaction_traces, block_state_diffs, block_uncles) = tokio::try_join!(
+                self.get_block_transaction_traces(&block_with_transactions),
+                self.get_block_state_diffs(block_number, block_hash),
+                self.get_block_uncles(block_number, &block_with_transactions.uncles),
+            )?;
+
+            return Ok(BlockData {
+                block: block_with_transactions,
+                receipts: block_receipts,
+                transaction_traces: block_transaction_traces,
+                state_diffs: block_state_diffs,
+                uncles: block_uncles,
+                extension: BlockExtension::default(),
+            });
+        }
+
+        let (block_with_transactions, transaction_extensions) = match self
+            .get_block_with_transactions(BlockId::number(block_number))
+            .await
+        {
+            Ok(block_with_transactions) => block_with_transactions,
+            Err(err) if err.is_not_found() => {
+                return Err(err).change_context(IngestionError::BlockNotFound)
+            }
+            Err(err) => {
+                return Err(err)
+                    .change_context(IngestionError::RpcRequest)
+                    .attach_printable("failed to get block with transactions")
+                    .attach_printable_lazy(|| format!("block number: {}", block_number))
+            }
+        };
+
+        let block_hash = block_with_transactions.header.hash;
+        let block_id = BlockId::hash(block_hash);
+
+        let block_receipts = async {
+            self.get_block_receipts(block_id)
+                .await
+                .change_context(IngestionError::RpcRequest)
+                .attach_printable("failed to get block receipts")
+                .attach_printable_lazy(|| format!("block number: {}", block_number))
+                .attach_printable_lazy(|| format!("block hash: {}", block_hash))
+        };
+
+        let block_transaction_traces = self.get_block_transaction_traces(&block_with_transactions);
+        let block_state_diffs = self.get_block_state_diffs(block_number, block_hash);
+        let block_uncles = self.get_block_uncles(block_number, &block_with_transactions.uncles);
+
+        let (
+            (block_receipts, receipt_extensions),
+            block_transaction_traces,
+            block_state_diffs,
+            block_uncles,
+        ) = tokio::try_join!(
+            block_receipts,
+            block_transaction_traces,
+            block_state_diffs,
+            block_uncles
+        )?;
+
+        Ok(BlockData {
+            block: block_with_transactions,
+            receipts: block_receipts,
+            transaction_traces: block_transaction_traces,
+            state_diffs: block_state_diffs,
+            uncles: block_uncles,
+            extension: BlockExtension {
+                transactions: transaction_extensions,
+                receipts: receipt_extensions,
+            },
+        })
+    }
+
+    /// Fetch the block with its transactions, parsing the L2 fields if needed.
+    async fn get_block_with_transactions(
+        &self,
+        block_id: BlockId,
+    ) -> Result<(models::Block, Vec<TransactionExtension>), JsonRpcProviderError> {
+        if self.options.chain_flavour == ChainFlavour::Ethereum {
+            let block = self.provider.get_block_with_transactions(block_id).await?;
+            return Ok((block, Vec::default()));
+        }
+
+        let block = self
+            .provider
+            .get_raw_block_with_transactions(block_id)
+            .await?;
+
+        l2::parse_block_with_transactions(block).change_context(JsonRpcProviderError::Request)
+    }
+
+    /// Fetch the block receipts, parsing the L2 fields if needed.
+    async fn get_block_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> Result<(Vec<models::TransactionReceipt>, Vec<ReceiptExtension>), JsonRpcProviderError>
+    {
+        if self.options.chain_flavour == ChainFlavour::Ethereum {
+            let receipts = self.provider.get_block_receipts(block_id).await?;
+            return Ok((receipts, Vec::default()));
+        }
+
+        let receipts = self.provider.get_raw_block_receipts(block_id).await?;
+
+        l2::parse_block_receipts(receipts).change_context(JsonRpcProviderError::Request)
+    }
+
+    async fn get_block_transaction_traces(
+        &self,
+        block: &models::Block,
+    ) -> Result<Vec<models::TraceResultsWithTransactionHash>, IngestionError> {
+        if !self.options.ingest_traces {
+            return Ok(Vec::default());
+        }
+
+        let block_number = block.header.number;
+        let block_hash = block.header.hash;
+
+        let traces = match self.options.trace_backend {
+            TraceBackend::Parity => self
+                .provider
+                .trace_block_transactions(BlockId::hash(block_hash))
+                .await
+                .change_context(IngestionError::RpcRequest),
+            // Traced by number, so check the traces belong to the block in case of a reorg.
+            TraceBackend::Geth => self
+                .provider
+                .debug_trace_block(BlockId::number(block_number))
+                .await
+                .change_context(IngestionError::RpcRequest)
+                .and_then(|results| {
+                    flatten_block_call_frames(results).change_context(IngestionError::RpcRequest)
+                })
+                .and_then(|traces| {
+                    let transactions = block.transactions.as_transactions().unwrap_or_default();
+                    verify_transaction_traces(transactions, &traces)?;
+                    Ok(traces)
+                }),
+        };
+
+        traces
+            .attach_printable("failed to get block transaction traces")
+            .attach_printable_lazy(|| format!("block number: {}", block_number))
+            .attach_printable_lazy(|| format!("block hash: {}", block_hash))
+    }
+
+    async fn get_block_state_diffs(
+        &self,
+        block_number: u64,
+        block_hash: models::B256,
+    ) -> Result<Vec<models::TraceResultsWithTransactionHash>, IngestionError> {
+        if !self.options.ingest_state_diffs {
+            return Ok(Vec::default());
+        }
+
+        self.provider
+            .trace_block_state_diffs(BlockId::hash(block_hash))
+            .await
+            .change_context(IngestionError::RpcRequest)
+            .attach_printable("failed to get block state diffs")
+            .attach_printable_lazy(|| format!("block number: {}", block_number))
+            .attach_printable_lazy(|| format!("block hash: {}", block_hash))
+    }
+
+    /// Fetch the block's uncles by index, checking them against the hashes in the block.
+    async fn get_block_uncles(
+        &self,
+        block_number: u64,
+        uncle_hashes: &[models::B256],
+    ) -> Result<Vec<models::Header>, IngestionError> {
+        if !self.options.ingest_uncles {
+            return Ok(Vec::default());
+        }
+
+        let mut uncles = Vec::with_capacity(uncle_hashes.len());
+
+        for (uncle_index, uncle_hash) in uncle_hashes.iter().enumerate() {
+            let uncle = self
+                .provider
+                .get_uncle_by_block_number_and_index(block_number, uncle_index as u64)
+                .await
+                .change_context(IngestionError::RpcRequest)
+                .attach_printable("failed to get block uncle")
+                .attach_printable_lazy(|| format!("block number: {}", block_number))
+                .attach_printable_lazy(|| format!("uncle index: {}", uncle_index))?;
+
+            if uncle.header.hash != *uncle_hash {
+                return Err(IngestionError::Model)
+                    .attach_printable("uncle hash mismatch")
+                    .attach_printable_lazy(|| format!("block number: {}", block_number))
+                    .attach_printable_lazy(|| format!("expected: {}", uncle_hash))
+                    .attach_printable_lazy(|| format!("actual: {}", uncle.header.hash));
+            }
+
+            uncles.push(uncle.header);
+        }
+
+        Ok(uncles)
     }
 }
 
@@ -52,6 +353,11 @@ impl BlockIngestion for EvmBlockIngestion {
         self.options.ingest_pending
     }
 
+    fn head_notifications(&self, ct: CancellationToken) -> Option<HeadNotifications> {
+        let subscription = self.options.head_subscription.clone()?;
+        Some(HeadNotifications::spawn(subscription, ct))
+    }
+
     #[tracing::instrument("evm_get_head_cursor", skip_all, err(Debug), level = "debug")]
     async fn get_head_cursor(&self) -> Result<Cursor, IngestionError> {
         let block = self
@@ -102,66 +408,42 @@ impl BlockIngestion for EvmBlockIngestion {
         &self,
         block_number: u64,
     ) -> Result<(BlockInfo, Block), IngestionError> {
-        let mut block_with_transactions = match self
-            .provider
-            .get_block_with_transactions(BlockId::number(block_number))
-            .await
-        {
-            Ok(block_with_transactions) => block_with_transactions,
-            Err(err) if err.is_not_found() => {
-                return Err(err).change_context(IngestionError::BlockNotFound)
-            }
-            Err(err) => {
-                return Err(err)
-                    .change_context(IngestionError::RpcRequest)
-                    .attach_printable("failed to get block with transactions")
-                    .attach_printable_lazy(|| format!("block number: {}", block_number))
-            }
-        };
-
-        let block_id = BlockId::hash(block_with_transactions.header.hash);
-
-        let block_receipts = tokio::spawn({
-            let provider = self.provider.clone();
-            async move {
-                provider
-                    .get_block_receipts(block_id)
-                    .await
-                    .change_context(IngestionError::RpcRequest)
-                    .attach_printable("failed to get block receipts")
-                    .attach_printable_lazy(|| format!("block number: {}", block_number))
-                    .attach_printable_lazy(|| {
-                        format!("block hash: {}", block_with_transactions.header.hash)
-                    })
+        let mut attempt = 1;
+        let BlockData {
+            block: mut block_with_transactions,
+            receipts: block_receipts,
+            transaction_traces: block_transaction_traces,
+            state_diffs: block_state_diffs,
+            uncles: block_uncles,
+            extension: block_extension,
+        } = loop {
+            let data = self.get_block_data(block_number).await?;
+
+            if !self.options.verify_block_data {
+                break data;
             }
-        });
 
-        let block_transaction_traces = tokio::spawn({
-            let provider = self.provider.clone();
-            let should_ingest_traces = self.options.ingest_traces;
-            async move {
-                if should_ingest_traces {
-                    provider
-                        .trace_block_transactions(block_id)
-                        .await
-                        .change_context(IngestionError::RpcRequest)
-                        .attach_printable("failed to get block transaction traces")
+            let transactions = data
+                .block
+                .transactions
+                .as_transactions()
+                .unwrap_or_default();
+
+            match verify_block_data(&data.block.header, transactions, &data.receipts) {
+                Ok(_) => break data,
+                Err(err) if attempt < VERIFY_MAX_ATTEMPTS => {
+                    warn!(block_number, attempt, error = ?err, "block data verification failed, retrying");
+                    attempt += 1;
+                    tokio::time::sleep(VERIFY_RETRY_DELAY).await;
+                }
+                Err(err) => {
+                    return Err(err)
                         .attach_printable_lazy(|| format!("block number: {}", block_number))
-                        .attach_printable_lazy(|| {
-                            format!("block hash: {}", block_with_transactions.header.hash)
-                        })
-                } else {
-                    Ok(Vec::default())
+                        .attach_printable_lazy(|| format!("attempts: {}", attempt));
                 }
             }
-        });
+        };
 
-        let block_receipts = block_receipts
-            .await
-            .change_context(IngestionError::RpcRequest)??;
-        let block_transaction_traces = block_transaction_traces
-            .await
-            .change_context(IngestionError::RpcRequest)??;
         let block_transactions = std::mem::take(&mut block_with_transactions.transactions);
         let Some(block_transactions) = block_transactions.as_transactions() else {
             return Err(IngestionError::RpcRequest)
@@ -173,6 +455,13 @@ impl BlockIngestion for EvmBlockIngestion {
 
         let block_info = block_with_transactions.block_info();
 
+        let fee_summary = block_fee_summary(
+            block_with_transactions.header.base_fee_per_gas,
+            block_with_transactions.header.gas_used,
+            &block_receipts,
+            &block_extension.receipts,
+        );
+
         let header_fragment = {
             let header = convert_block_header(block_with_transactions.header);
             HeaderFragment {
@@ -180,12 +469,16 @@ impl BlockIngestion for EvmBlockIngestion {
             }
         };
 
-        let (body, index, join) = collect_block_body_and_index(
-            block_transactions,
-            &block_withdrawals,
-            &block_receipts,
-            &block_transaction_traces,
-        )?;
+        let (body, index, join) = collect_block_body_and_index(BlockBody {
+            transactions: block_transactions,
+            withdrawals: &block_withdrawals,
+            receipts: &block_receipts,
+            transaction_traces: &block_transaction_traces,
+            state_diffs: &block_state_diffs,
+            uncles: &block_uncles,
+            fee_summary: Some(&fee_summary),
+            extension: &block_extension,
+        })?;
 
         let block = Block {
             header: header_fragment,
@@ -205,8 +498,8 @@ impl BlockIngestion for EvmBlockIngestion {
     ) -> Result<Option<(PendingBlockInfo, Block)>, IngestionError> {
         let block_id = BlockId::pending();
 
-        let mut block_with_transactions =
-            match self.provider.get_block_with_transactions(block_id).await {
+        let (mut block_with_transactions, transaction_extensions) =
+            match self.get_block_with_transactions(block_id).await {
                 Ok(block_with_transactions) => block_with_transactions,
                 Err(err) if err.is_not_found() => {
                     return Ok(None);
@@ -227,25 +520,23 @@ impl BlockIngestion for EvmBlockIngestion {
             return Ok(None);
         }
 
-        let block_receipts = tokio::spawn({
-            let provider = self.provider.clone();
-            async move {
-                provider
-                    .get_block_receipts(block_id)
-                    .await
-                    .change_context(IngestionError::RpcRequest)
-                    .attach_printable("failed to get pending block receipts")
-                    .attach_printable_lazy(|| {
-                        format!("block hash: {}", block_with_transactions.header.hash)
-                    })
-            }
-        });
-
-        let block_receipts = block_receipts
+        let (block_receipts, receipt_extensions) = self
+            .get_block_receipts(block_id)
             .await
-            .change_context(IngestionError::RpcRequest)??;
+            .change_context(IngestionError::RpcRequest)
+            .attach_printable("failed to get pending block receipts")
+            .attach_printable_lazy(|| {
+                format!("block hash: {}", block_with_transactions.header.hash)
+            })?;
         // Nodes struggle to server traces for pending blocks, so we don't collect them.
         let block_transaction_traces = Vec::default();
+        let block_state_diffs = Vec::default();
+        // Uncles are fetched by block number, which doesn't identify the pending block.
+        let block_uncles = Vec::default();
+        let block_extension = BlockExtension {
+            transactions: transaction_extensions,
+            receipts: receipt_extensions,
+        };
 
         let block_transactions = std::mem::take(&mut block_with_transactions.transactions);
         let Some(block_transactions) = block_transactions.as_transactions() else {
@@ -263,12 +554,17 @@ impl BlockIngestion for EvmBlockIngestion {
             }
         };
 
-        let (body, index, join) = collect_block_body_and_index(
-            block_transactions,
-            &block_withdrawals,
-            &block_receipts,
-            &block_transaction_traces,
-        )?;
+        // The pending block is still being filled, so its fees are not final.
+        let (body, index, join) = collect_block_body_and_index(BlockBody {
+            transactions: block_transactions,
+            withdrawals: &block_withdrawals,
+            receipts: &block_receipts,
+            transaction_traces: &block_transaction_traces,
+            state_diffs: &block_state_diffs,
+            uncles: &block_uncles,
+            fee_summary: None,
+            extension: &block_extension,
+        })?;
 
         let pending_block_info = PendingBlockInfo {
             number: parent.number + 1,
@@ -287,41 +583,94 @@ impl BlockIngestion for EvmBlockIngestion {
     }
 }
 
+/// The tag of the `trace` field in `CallTrace`.
+const CALL_TRACE_TRACE_TAG: u32 = 6;
+
+/// The block data, besides the header, stored in the block fragments.
+struct BlockBody<'a> {
+    transactions: &'a [models::Transaction],
+    withdrawals: &'a [models::Withdrawal],
+    receipts: &'a [models::TransactionReceipt],
+    transaction_traces: &'a [models::TraceResultsWithTransactionHash],
+    state_diffs: &'a [models::TraceResultsWithTransactionHash],
+    uncles: &'a [models::Header],
+    /// Not computed for pending blocks.
+    fee_summary: Option<&'a evm::BlockFeeSummary>,
+    extension: &'a BlockExtension,
+}
+
+struct BlockIngestionResult {
+    body: Vec<BodyFragment>,
+    index: Vec<IndexFragment>,
+    join: Vec<JoinFragment>,
+}
+
+impl BlockIngestionResult {
+    fn extend(&mut self, other: BlockIngestionResult) {
+        self.body.extend(other.body);
+        self.index.extend(other.index);
+        self.join.extend(other.join);
+    }
+}
+
+/// The transactions, receipts and logs referenced by the other fragments.
+struct BlockTransactions {
+    transactions: Vec<evm::Transaction>,
+    receipts: Vec<evm::TransactionReceipt>,
+    logs: Vec<evm::Log>,
+    /// The index of the logs emitted by each transaction.
+    transaction_logs: Vec<Vec<u32>>,
+}
+
 fn collect_block_body_and_index(
-    transactions: &[models::Transaction],
-    withdrawals: &[models::Withdrawal],
-    receipts: &[models::TransactionReceipt],
-    transaction_traces: &[models::TraceResultsWithTransactionHash],
+    block: BlockBody<'_>,
 ) -> Result<(Vec<BodyFragment>, IndexGroupFragment, JoinGroupFragment), IngestionError> {
+    let (block_traces, trace_result) = collect_trace_body_and_index(block.transaction_traces);
+
+    let (block_transactions, transaction_result) = collect_transaction_body_and_index(
+        block.transactions,
+        block.receipts,
+        block.extension,
+        block_traces.len(),
+    )?;
+
+    let mut result = collect_withdrawal_body_and_index(block.withdrawals)?;
+    result.extend(transaction_result);
+    result.extend(trace_result);
+    result.extend(collect_call_trace_body_and_index(
+        &block_traces,
+        &block_transactions,
+    )?);
+    result.extend(collect_state_diff_body_and_index(
+        block.state_diffs,
+        &block_transactions.transactions,
+    )?);
+    result.extend(collect_token_transfer_body_and_index(
+        &block_traces,
+        &block_transactions,
+    )?);
+    result.extend(collect_uncle_body_and_index(block.uncles)?);
+    if let Some(fee_summary) = block.fee_summary {
+        result.extend(collect_fee_summary_body_and_index(fee_summary));
+    }
+
+    Ok((
+        result.body,
+        IndexGroupFragment {
+            indexes: result.index,
+        },
+        JoinGroupFragment { joins: result.join },
+    ))
+}
+
+fn collect_withdrawal_body_and_index(
+    withdrawals: &[models::Withdrawal],
+) -> Result<BlockIngestionResult, IngestionError> {
     let mut block_withdrawals = Vec::new();
-    let mut block_transactions = Vec::new();
-    let mut block_receipts = Vec::new();
-    let mut block_logs = Vec::new();
-    let mut block_traces = Vec::new();
 
     let mut index_withdrawal_by_validator_index = BitmapIndexBuilder::default();
     let mut index_withdrawal_by_address = BitmapIndexBuilder::default();
 
-    let mut index_transaction_by_from_address = BitmapIndexBuilder::default();
-    let mut index_transaction_by_to_address = BitmapIndexBuilder::default();
-    let mut index_transaction_by_create = BitmapIndexBuilder::default();
-    let mut index_transaction_by_status = BitmapIndexBuilder::default();
-    let mut join_transaction_to_receipt = JoinToOneIndexBuilder::default();
-    let mut join_transaction_to_logs = JoinToManyIndexBuilder::default();
-    let mut join_transaction_to_trace = JoinToOneIndexBuilder::default();
-
-    let mut index_log_by_address = BitmapIndexBuilder::default();
-    let mut index_log_by_topic0 = BitmapIndexBuilder::default();
-    let mut index_log_by_topic1 = BitmapIndexBuilder::default();
-    let mut index_log_by_topic2 = BitmapIndexBuilder::default();
-    let mut index_log_by_topic3 = BitmapIndexBuilder::default();
-    let mut index_log_by_topic_length = BitmapIndexBuilder::default();
-    let mut index_log_by_transaction_status = BitmapIndexBuilder::default();
-    let mut join_log_to_transaction = JoinToOneIndexBuilder::default();
-    let mut join_log_to_receipt = JoinToOneIndexBuilder::default();
-    let mut join_log_to_siblings = JoinToManyIndexBuilder::default();
-    let mut join_log_to_trace = JoinToOneIndexBuilder::default();
-
     for (withdrawal_index, withdrawal) in withdrawals.iter().enumerate() {
         let withdrawal_index = withdrawal_index as u32;
 
@@ -342,6 +691,60 @@ fn collect_block_body_and_index(
         block_withdrawals.push(withdrawal);
     }
 
+    let withdrawal_index = {
+        let index_withdrawal_by_validator_index = Index {
+            index_id: INDEX_WITHDRAWAL_BY_VALIDATOR_INDEX,
+            index: index_withdrawal_by_validator_index
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_withdrawal_by_address = Index {
+            index_id: INDEX_WITHDRAWAL_BY_ADDRESS,
+            index: index_withdrawal_by_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        IndexFragment {
+            fragment_id: WITHDRAWAL_FRAGMENT_ID,
+            range_start: 0,
+            range_len: block_withdrawals.len() as u32,
+            indexes: vec![
+                index_withdrawal_by_validator_index,
+                index_withdrawal_by_address,
+            ],
+        }
+    };
+
+    let withdrawal_join = JoinFragment {
+        fragment_id: WITHDRAWAL_FRAGMENT_ID,
+        joins: Vec::default(),
+    };
+
+    let withdrawal_fragment = BodyFragment {
+        fragment_id: WITHDRAWAL_FRAGMENT_ID,
+        name: WITHDRAWAL_FRAGMENT_NAME.to_string(),
+        data: block_withdrawals
+            .iter()
+            .map(Message::encode_to_vec)
+            .collect(),
+    };
+
+    Ok(BlockIngestionResult {
+        body: vec![withdrawal_fragment],
+        index: vec![withdrawal_index],
+        join: vec![withdrawal_join],
+    })
+}
+
+fn collect_trace_body_and_index(
+    transaction_traces: &[models::TraceResultsWithTransactionHash],
+) -> (Vec<evm::TransactionTrace>, BlockIngestionResult) {
+    let mut block_traces = Vec::new();
+
     for (transaction_index, transaction_trace) in transaction_traces.iter().enumerate() {
         let transaction_index = transaction_index as u32;
         let transaction_hash = transaction_trace.transaction_hash.to_proto();
@@ -351,39 +754,109 @@ fn collect_block_body_and_index(
         trace.transaction_index = transaction_index;
         trace.transaction_hash = Some(transaction_hash);
 
-        join_transaction_to_trace.insert(transaction_index, transaction_index);
-
         block_traces.push(trace);
     }
 
-    for (transaction_index, (transaction, receipt)) in
-        transactions.iter().zip(receipts.iter()).enumerate()
-    {
-        let transaction_index = transaction_index as u32;
-        let transaction_hash = receipt.transaction_hash.to_proto();
+    let trace_index = IndexFragment {
+        fragment_id: TRACE_FRAGMENT_ID,
+        range_start: 0,
+        range_len: block_traces.len() as u32,
+        indexes: Vec::default(),
+    };
 
-        let transaction_status = if receipt.status() {
-            evm::TransactionStatus::Succeeded as i32
-        } else {
-            evm::TransactionStatus::Reverted as i32
-        };
+    let trace_join = JoinFragment {
+        fragment_id: TRACE_FRAGMENT_ID,
+        joins: Vec::default(),
+    };
 
-        if let Some(rpc_transaction_index) = transaction.transaction_index {
-            if rpc_transaction_index != transaction_index as u64 {
-                return Err(IngestionError::Model)
-                    .attach_printable("transaction index mismatch")
-                    .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))
-                    .attach_printable_lazy(|| {
-                        format!("rpc transaction index: {}", rpc_transaction_index)
-                    });
-            }
-        }
+    let trace_fragment = BodyFragment {
+        fragment_id: TRACE_FRAGMENT_ID,
+        name: TRACE_FRAGMENT_NAME.to_string(),
+        data: block_traces.iter().map(Message::encode_to_vec).collect(),
+    };
 
-        if let Some(rpc_transaction_index) = receipt.transaction_index {
-            if rpc_transaction_index != transaction_index as u64 {
-                return Err(IngestionError::Model)
-                    .attach_printable("transaction index mismatch in receipt")
-                    .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))
+    let result = BlockIngestionResult {
+        body: vec![trace_fragment],
+        index: vec![trace_index],
+        join: vec![trace_join],
+    };
+
+    (block_traces, result)
+}
+
+/// Collects the transaction, receipt and log fragments.
+///
+/// `trace_count` is the number of transaction traces in the block, used to join transactions
+/// and logs with their trace.
+fn collect_transaction_body_and_index(
+    transactions: &[models::Transaction],
+    receipts: &[models::TransactionReceipt],
+    extension: &BlockExtension,
+    trace_count: usize,
+) -> Result<(BlockTransactions, BlockIngestionResult), IngestionError> {
+    let mut block_transactions = Vec::new();
+    let mut block_receipts = Vec::new();
+    let mut block_logs = Vec::new();
+
+    let mut index_transaction_by_from_address = BitmapIndexBuilder::default();
+    let mut index_transaction_by_to_address = BitmapIndexBuilder::default();
+    let mut index_transaction_by_create = BitmapIndexBuilder::default();
+    let mut index_transaction_by_status = BitmapIndexBuilder::default();
+    let mut index_transaction_by_selector = BitmapIndexBuilder::default();
+    let mut index_transaction_by_created_address = BitmapIndexBuilder::default();
+    let mut index_transaction_by_authorization_authority = BitmapIndexBuilder::default();
+    let mut index_transaction_by_authorization_delegate = BitmapIndexBuilder::default();
+    let mut index_transaction_by_deposit = BitmapIndexBuilder::default();
+    let mut index_transaction_by_mint = BitmapIndexBuilder::default();
+    let mut join_transaction_to_receipt = JoinToOneIndexBuilder::default();
+    let mut join_transaction_to_logs = JoinToManyIndexBuilder::default();
+    let mut join_transaction_to_trace = JoinToOneIndexBuilder::default();
+
+    let mut index_log_by_address = BitmapIndexBuilder::default();
+    let mut index_log_by_topic0 = BitmapIndexBuilder::default();
+    let mut index_log_by_topic1 = BitmapIndexBuilder::default();
+    let mut index_log_by_topic2 = BitmapIndexBuilder::default();
+    let mut index_log_by_topic3 = BitmapIndexBuilder::default();
+    let mut index_log_by_topic_length = BitmapIndexBuilder::default();
+    let mut index_log_by_transaction_status = BitmapIndexBuilder::default();
+    let mut index_log_by_transaction_from = BitmapIndexBuilder::default();
+    let mut index_log_by_transaction_to = BitmapIndexBuilder::default();
+    let mut join_log_to_transaction = JoinToOneIndexBuilder::default();
+    let mut join_log_to_receipt = JoinToOneIndexBuilder::default();
+    let mut join_log_to_siblings = JoinToManyIndexBuilder::default();
+    let mut join_log_to_trace = JoinToOneIndexBuilder::default();
+
+    let mut transaction_logs = Vec::new();
+
+    for (transaction_index, (transaction, receipt)) in
+        transactions.iter().zip(receipts.iter()).enumerate()
+    {
+        let has_trace = transaction_index < trace_count;
+        let transaction_index = transaction_index as u32;
+        let transaction_hash = receipt.transaction_hash.to_proto();
+
+        let transaction_status = if receipt.status() {
+            evm::TransactionStatus::Succeeded as i32
+        } else {
+            evm::TransactionStatus::Reverted as i32
+        };
+
+        if let Some(rpc_transaction_index) = transaction.transaction_index {
+            if rpc_transaction_index != transaction_index as u64 {
+                return Err(IngestionError::Model)
+                    .attach_printable("transaction index mismatch")
+                    .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))
+                    .attach_printable_lazy(|| {
+                        format!("rpc transaction index: {}", rpc_transaction_index)
+                    });
+            }
+        }
+
+        if let Some(rpc_transaction_index) = receipt.transaction_index {
+            if rpc_transaction_index != transaction_index as u64 {
+                return Err(IngestionError::Model)
+                    .attach_printable("transaction index mismatch in receipt")
+                    .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))
                     .attach_printable_lazy(|| {
                         format!("rpc transaction index: {}", rpc_transaction_index)
                     });
@@ -396,7 +869,25 @@ fn collect_block_body_and_index(
         transaction.transaction_hash = transaction_hash.into();
         transaction.transaction_status = transaction_status;
 
+        let transaction_extension = extension.transactions.get(transaction_index as usize);
+
+        if let Some(transaction_extension) = transaction_extension {
+            transaction_extension.apply(&mut transaction);
+        }
+
+        index_transaction_by_deposit.insert(
+            ScalarValue::Bool(transaction_extension.is_some_and(TransactionExtension::is_deposit)),
+            transaction_index,
+        );
+        index_transaction_by_mint.insert(
+            ScalarValue::Bool(transaction_extension.is_some_and(TransactionExtension::is_mint)),
+            transaction_index,
+        );
+
         join_transaction_to_receipt.insert(transaction_index, transaction_index);
+        if has_trace {
+            join_transaction_to_trace.insert(transaction_index, transaction_index);
+        }
 
         if let Some(from) = transaction.from {
             index_transaction_by_from_address
@@ -417,6 +908,35 @@ fn collect_block_body_and_index(
         index_transaction_by_status
             .insert(ScalarValue::Int32(transaction_status), transaction_index);
 
+        if let Some(selector) = transaction.input.first_chunk::<4>() {
+            index_transaction_by_selector.insert(
+                ScalarValue::Uint32(u32::from_be_bytes(*selector)),
+                transaction_index,
+            );
+        }
+
+        for authorization in transaction.authorization_list.iter() {
+            if let Some(authority) = authorization.authority {
+                index_transaction_by_authorization_authority
+                    .insert(ScalarValue::B160(authority.to_bytes()), transaction_index);
+            }
+
+            if let Some(delegate) = authorization.address {
+                index_transaction_by_authorization_delegate
+                    .insert(ScalarValue::B160(delegate.to_bytes()), transaction_index);
+            }
+        }
+
+        if let Some(contract_address) = receipt.contract_address {
+            index_transaction_by_created_address.insert(
+                ScalarValue::B160(contract_address.to_proto().to_bytes()),
+                transaction_index,
+            );
+        }
+
+        let transaction_from = transaction.from;
+        let transaction_to = transaction.to;
+
         block_transactions.push(transaction);
 
         let mut transaction_logs_id = Vec::new();
@@ -457,7 +977,7 @@ fn collect_block_body_and_index(
             join_log_to_transaction.insert(log_index, transaction_index);
             join_log_to_receipt.insert(log_index, transaction_index);
             join_transaction_to_logs.insert(transaction_index, log_index);
-            if !block_traces.is_empty() {
+            if has_trace {
                 join_log_to_trace.insert(log_index, transaction_index);
             }
 
@@ -488,6 +1008,14 @@ fn collect_block_body_and_index(
             index_log_by_transaction_status
                 .insert(ScalarValue::Int32(transaction_status), log_index);
 
+            if let Some(from) = transaction_from {
+                index_log_by_transaction_from.insert(ScalarValue::B160(from.to_bytes()), log_index);
+            }
+
+            if let Some(to) = transaction_to {
+                index_log_by_transaction_to.insert(ScalarValue::B160(to.to_bytes()), log_index);
+            }
+
             block_logs.push(log);
         }
 
@@ -499,103 +1027,97 @@ fn collect_block_body_and_index(
             }
         }
 
+        transaction_logs.push(transaction_logs_id);
+
         let mut receipt = receipt.to_proto();
 
         receipt.transaction_index = transaction_index;
         receipt.transaction_hash = transaction_hash.into();
         receipt.transaction_status = transaction_status;
 
+        if let Some(receipt_extension) = extension.receipts.get(transaction_index as usize) {
+            receipt_extension.apply(&mut receipt);
+        }
+
         block_receipts.push(receipt);
     }
 
-    let withdrawal_index = {
-        let index_withdrawal_by_validator_index = Index {
-            index_id: INDEX_WITHDRAWAL_BY_VALIDATOR_INDEX,
-            index: index_withdrawal_by_validator_index
+    let transaction_index = {
+        let index_transaction_by_from_address = Index {
+            index_id: INDEX_TRANSACTION_BY_FROM_ADDRESS,
+            index: index_transaction_by_from_address
                 .build()
                 .change_context(IngestionError::Indexing)?
                 .into(),
         };
 
-        let index_withdrawal_by_address = Index {
-            index_id: INDEX_WITHDRAWAL_BY_ADDRESS,
-            index: index_withdrawal_by_address
+        let index_transaction_by_to_address = Index {
+            index_id: INDEX_TRANSACTION_BY_TO_ADDRESS,
+            index: index_transaction_by_to_address
                 .build()
                 .change_context(IngestionError::Indexing)?
                 .into(),
         };
 
-        IndexFragment {
-            fragment_id: WITHDRAWAL_FRAGMENT_ID,
-            range_start: 0,
-            range_len: block_withdrawals.len() as u32,
-            indexes: vec![
-                index_withdrawal_by_validator_index,
-                index_withdrawal_by_address,
-            ],
-        }
-    };
-
-    let withdrawal_join = JoinFragment {
-        fragment_id: WITHDRAWAL_FRAGMENT_ID,
-        joins: Vec::default(),
-    };
-
-    let withdrawal_fragment = BodyFragment {
-        fragment_id: WITHDRAWAL_FRAGMENT_ID,
-        name: WITHDRAWAL_FRAGMENT_NAME.to_string(),
-        data: block_withdrawals
-            .iter()
-            .map(Message::encode_to_vec)
-            .collect(),
-    };
+        let index_transaction_by_create = Index {
+            index_id: INDEX_TRANSACTION_BY_CREATE,
+            index: index_transaction_by_create
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
 
-    let trace_index = IndexFragment {
-        fragment_id: TRACE_FRAGMENT_ID,
-        range_start: 0,
-        range_len: block_traces.len() as u32,
-        indexes: Vec::default(),
-    };
+        let index_transaction_by_status = Index {
+            index_id: INDEX_TRANSACTION_BY_STATUS,
+            index: index_transaction_by_status
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
 
-    let trace_join = JoinFragment {
-        fragment_id: TRACE_FRAGMENT_ID,
-        joins: Vec::default(),
-    };
+        let index_transaction_by_selector = Index {
+            index_id: INDEX_TRANSACTION_BY_SELECTOR,
+            index: index_transaction_by_selector
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
 
-    let trace_fragment = BodyFragment {
-        fragment_id: TRACE_FRAGMENT_ID,
-        name: TRACE_FRAGMENT_NAME.to_string(),
-        data: block_traces.iter().map(Message::encode_to_vec).collect(),
-    };
+        let index_transaction_by_created_address = Index {
+            index_id: INDEX_TRANSACTION_BY_CREATED_ADDRESS,
+            index: index_transaction_by_created_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
 
-    let transaction_index = {
-        let index_transaction_by_from_address = Index {
-            index_id: INDEX_TRANSACTION_BY_FROM_ADDRESS,
-            index: index_transaction_by_from_address
+        let index_transaction_by_authorization_authority = Index {
+            index_id: INDEX_TRANSACTION_BY_AUTHORIZATION_AUTHORITY,
+            index: index_transaction_by_authorization_authority
                 .build()
                 .change_context(IngestionError::Indexing)?
                 .into(),
         };
 
-        let index_transaction_by_to_address = Index {
-            index_id: INDEX_TRANSACTION_BY_TO_ADDRESS,
-            index: index_transaction_by_to_address
+        let index_transaction_by_authorization_delegate = Index {
+            index_id: INDEX_TRANSACTION_BY_AUTHORIZATION_DELEGATE,
+            index: index_transaction_by_authorization_delegate
                 .build()
                 .change_context(IngestionError::Indexing)?
                 .into(),
         };
 
-        let index_transaction_by_create = Index {
-            index_id: INDEX_TRANSACTION_BY_CREATE,
-            index: index_transaction_by_create
+        let index_transaction_by_deposit = Index {
+            index_id: INDEX_TRANSACTION_BY_DEPOSIT,
+            index: index_transaction_by_deposit
                 .build()
                 .change_context(IngestionError::Indexing)?
                 .into(),
         };
 
-        let index_transaction_by_status = Index {
-            index_id: INDEX_TRANSACTION_BY_STATUS,
-            index: index_transaction_by_status
+        let index_transaction_by_mint = Index {
+            index_id: INDEX_TRANSACTION_BY_MINT,
+            index: index_transaction_by_mint
                 .build()
                 .change_context(IngestionError::Indexing)?
                 .into(),
@@ -610,6 +1132,12 @@ fn collect_block_body_and_index(
                 index_transaction_by_to_address,
                 index_transaction_by_create,
                 index_transaction_by_status,
+                index_transaction_by_selector,
+                index_transaction_by_created_address,
+                index_transaction_by_authorization_authority,
+                index_transaction_by_authorization_delegate,
+                index_transaction_by_deposit,
+                index_transaction_by_mint,
             ],
         }
     };
@@ -728,6 +1256,22 @@ fn collect_block_body_and_index(
                 .into(),
         };
 
+        let index_log_by_transaction_from = Index {
+            index_id: INDEX_LOG_BY_TRANSACTION_FROM,
+            index: index_log_by_transaction_from
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_log_by_transaction_to = Index {
+            index_id: INDEX_LOG_BY_TRANSACTION_TO,
+            index: index_log_by_transaction_to
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
         IndexFragment {
             fragment_id: LOG_FRAGMENT_ID,
             range_start: 0,
@@ -740,6 +1284,8 @@ fn collect_block_body_and_index(
                 index_log_by_topic3,
                 index_log_by_topic_length,
                 index_log_by_transaction_status,
+                index_log_by_transaction_from,
+                index_log_by_transaction_to,
             ],
         }
     };
@@ -785,35 +1331,1446 @@ fn collect_block_body_and_index(
         data: block_logs.iter().map(Message::encode_to_vec).collect(),
     };
 
-    let index_group = IndexGroupFragment {
-        indexes: vec![
-            withdrawal_index,
-            transaction_index,
-            receipt_index,
-            log_index,
-            trace_index,
-        ],
+    let block_transactions = BlockTransactions {
+        transactions: block_transactions,
+        receipts: block_receipts,
+        logs: block_logs,
+        transaction_logs,
     };
 
-    let join_group = JoinGroupFragment {
-        joins: vec![
-            withdrawal_join,
-            transaction_join,
-            receipt_join,
-            log_join,
-            trace_join,
-        ],
+    let result = BlockIngestionResult {
+        body: vec![transaction_fragment, receipt_fragment, log_fragment],
+        index: vec![transaction_index, receipt_index, log_index],
+        join: vec![transaction_join, receipt_join, log_join],
     };
 
-    Ok((
-        vec![
-            withdrawal_fragment,
-            transaction_fragment,
-            receipt_fragment,
-            log_fragment,
-            trace_fragment,
-        ],
-        index_group,
-        join_group,
-    ))
+    Ok((block_transactions, result))
+}
+
+fn collect_call_trace_body_and_index(
+    block_traces: &[evm::TransactionTrace],
+    block_transactions: &BlockTransactions,
+) -> Result<BlockIngestionResult, IngestionError> {
+    let mut block_call_traces = Vec::new();
+
+    let mut index_call_trace_by_from_address = BitmapIndexBuilder::default();
+    let mut index_call_trace_by_to_address = BitmapIndexBuilder::default();
+    let mut index_call_trace_by_action_type = BitmapIndexBuilder::default();
+    let mut index_call_trace_by_call_type = BitmapIndexBuilder::default();
+    let mut index_call_trace_by_selector = BitmapIndexBuilder::default();
+    let mut index_call_trace_by_created_address = BitmapIndexBuilder::default();
+    let mut index_call_trace_by_transaction_status = BitmapIndexBuilder::default();
+    let mut join_call_trace_to_transaction = JoinToOneIndexBuilder::default();
+    let mut join_call_trace_to_receipt = JoinToOneIndexBuilder::default();
+    let mut join_call_trace_to_logs = JoinToManyIndexBuilder::default();
+    let mut join_call_trace_to_trace = JoinToOneIndexBuilder::default();
+
+    for (transaction_index, transaction_trace) in block_traces.iter().enumerate() {
+        let Some(transaction) = block_transactions.transactions.get(transaction_index) else {
+            return Err(IngestionError::Model)
+                .attach_printable("transaction trace without transaction")
+                .attach_printable_lazy(|| format!("transaction index: {}", transaction_index));
+        };
+
+        let transaction_logs_id = &block_transactions.transaction_logs[transaction_index];
+        let transaction_index = transaction_index as u32;
+        let transaction_status = transaction.transaction_status;
+
+        for trace in transaction_trace.traces.iter() {
+            let call_index = block_call_traces.len() as u32;
+
+            match trace.action.as_ref() {
+                Some(evm::trace::Action::Call(call)) => {
+                    index_call_trace_by_action_type.insert(
+                        ScalarValue::Int32(evm::TraceActionType::Call as i32),
+                        call_index,
+                    );
+                    index_call_trace_by_call_type
+                        .insert(ScalarValue::Int32(call.r#type), call_index);
+
+                    if let Some(from) = call.from_address {
+                        index_call_trace_by_from_address
+                            .insert(ScalarValue::B160(from.to_bytes()), call_index);
+                    }
+
+                    if let Some(to) = call.to_address {
+                        index_call_trace_by_to_address
+                            .insert(ScalarValue::B160(to.to_bytes()), call_index);
+                    }
+
+                    if let Some(selector) = call.input.first_chunk::<4>() {
+                        index_call_trace_by_selector.insert(
+                            ScalarValue::Uint32(u32::from_be_bytes(*selector)),
+                            call_index,
+                        );
+                    }
+                }
+                Some(evm::trace::Action::Create(create)) => {
+                    index_call_trace_by_action_type.insert(
+                        ScalarValue::Int32(evm::TraceActionType::Create as i32),
+                        call_index,
+                    );
+
+                    if let Some(from) = create.from_address {
+                        index_call_trace_by_from_address
+                            .insert(ScalarValue::B160(from.to_bytes()), call_index);
+                    }
+                }
+                Some(evm::trace::Action::SelfDestruct(self_destruct)) => {
+                    index_call_trace_by_action_type.insert(
+                        ScalarValue::Int32(evm::TraceActionType::SelfDestruct as i32),
+                        call_index,
+                    );
+
+                    if let Some(address) = self_destruct.address {
+                        index_call_trace_by_from_address
+                            .insert(ScalarValue::B160(address.to_bytes()), call_index);
+                    }
+
+                    if let Some(refund_address) = self_destruct.refund_address {
+                        index_call_trace_by_to_address
+                            .insert(ScalarValue::B160(refund_address.to_bytes()), call_index);
+                    }
+                }
+                Some(evm::trace::Action::Reward(reward)) => {
+                    index_call_trace_by_action_type.insert(
+                        ScalarValue::Int32(evm::TraceActionType::Reward as i32),
+                        call_index,
+                    );
+
+                    if let Some(author) = reward.author {
+                        index_call_trace_by_to_address
+                            .insert(ScalarValue::B160(author.to_bytes()), call_index);
+                    }
+                }
+                None => {}
+            }
+
+            if let Some(evm::trace::Output::CreateOutput(output)) = trace.output.as_ref() {
+                if let Some(address) = output.address {
+                    index_call_trace_by_created_address
+                        .insert(ScalarValue::B160(address.to_bytes()), call_index);
+                }
+            }
+
+            index_call_trace_by_transaction_status
+                .insert(ScalarValue::Int32(transaction_status), call_index);
+
+            join_call_trace_to_transaction.insert(call_index, transaction_index);
+            join_call_trace_to_receipt.insert(call_index, transaction_index);
+            join_call_trace_to_trace.insert(call_index, transaction_index);
+            for log_id in transaction_logs_id.iter() {
+                join_call_trace_to_logs.insert(call_index, *log_id);
+            }
+
+            let call_trace = evm::CallTrace {
+                filter_ids: Vec::default(),
+                call_index,
+                transaction_index,
+                transaction_hash: transaction_trace.transaction_hash,
+                transaction_status,
+                trace: None,
+            };
+
+            block_call_traces.push(encode_call_trace(&call_trace, trace));
+        }
+    }
+
+    let call_trace_index = {
+        let index_call_trace_by_from_address = Index {
+            index_id: INDEX_CALL_TRACE_BY_FROM_ADDRESS,
+            index: index_call_trace_by_from_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_call_trace_by_to_address = Index {
+            index_id: INDEX_CALL_TRACE_BY_TO_ADDRESS,
+            index: index_call_trace_by_to_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_call_trace_by_action_type = Index {
+            index_id: INDEX_CALL_TRACE_BY_ACTION_TYPE,
+            index: index_call_trace_by_action_type
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_call_trace_by_call_type = Index {
+            index_id: INDEX_CALL_TRACE_BY_CALL_TYPE,
+            index: index_call_trace_by_call_type
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_call_trace_by_selector = Index {
+            index_id: INDEX_CALL_TRACE_BY_SELECTOR,
+            index: index_call_trace_by_selector
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_call_trace_by_created_address = Index {
+            index_id: INDEX_CALL_TRACE_BY_CREATED_ADDRESS,
+            index: index_call_trace_by_created_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_call_trace_by_transaction_status = Index {
+            index_id: INDEX_CALL_TRACE_BY_TRANSACTION_STATUS,
+            index: index_call_trace_by_transaction_status
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        IndexFragment {
+            fragment_id: CALL_TRACE_FRAGMENT_ID,
+            range_start: 0,
+            range_len: block_call_traces.len() as u32,
+            indexes: vec![
+                index_call_trace_by_from_address,
+                index_call_trace_by_to_address,
+                index_call_trace_by_action_type,
+                index_call_trace_by_call_type,
+                index_call_trace_by_selector,
+                index_call_trace_by_created_address,
+                index_call_trace_by_transaction_status,
+            ],
+        }
+    };
+
+    let call_trace_join = {
+        let join_call_trace_to_transaction = Join {
+            to_fragment_id: TRANSACTION_FRAGMENT_ID,
+            index: join_call_trace_to_transaction.build().into(),
+        };
+
+        let join_call_trace_to_receipt = Join {
+            to_fragment_id: RECEIPT_FRAGMENT_ID,
+            index: join_call_trace_to_receipt.build().into(),
+        };
+
+        let join_call_trace_to_logs = Join {
+            to_fragment_id: LOG_FRAGMENT_ID,
+            index: join_call_trace_to_logs
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let join_call_trace_to_trace = Join {
+            to_fragment_id: TRACE_FRAGMENT_ID,
+            index: join_call_trace_to_trace.build().into(),
+        };
+
+        JoinFragment {
+            fragment_id: CALL_TRACE_FRAGMENT_ID,
+            joins: vec![
+                join_call_trace_to_transaction,
+                join_call_trace_to_receipt,
+                join_call_trace_to_logs,
+                join_call_trace_to_trace,
+            ],
+        }
+    };
+
+    let call_trace_fragment = BodyFragment {
+        fragment_id: CALL_TRACE_FRAGMENT_ID,
+        name: CALL_TRACE_FRAGMENT_NAME.to_string(),
+        data: block_call_traces,
+    };
+
+    Ok(BlockIngestionResult {
+        body: vec![call_trace_fragment],
+        index: vec![call_trace_index],
+        join: vec![call_trace_join],
+    })
+}
+
+/// Encodes the call trace with its `trace` field set to `trace`.
+///
+/// The trace is written after the other fields, in the same position prost would put it, so
+/// that it doesn't need to be copied into the call trace.
+fn encode_call_trace(call_trace: &evm::CallTrace, trace: &evm::Trace) -> Vec<u8> {
+    let mut output = call_trace.encode_to_vec();
+    prost::encoding::message::encode(CALL_TRACE_TRACE_TAG, trace, &mut output);
+    output
+}
+
+/// Collects the account diff and storage diff fragments.
+fn collect_state_diff_body_and_index(
+    transaction_state_diffs: &[models::TraceResultsWithTransactionHash],
+    block_transactions: &[evm::Transaction],
+) -> Result<BlockIngestionResult, IngestionError> {
+    let mut block_account_diffs = Vec::new();
+    let mut block_storage_diffs = Vec::new();
+
+    let mut index_account_diff_by_address = BitmapIndexBuilder::default();
+    let mut index_account_diff_by_balance_changed = BitmapIndexBuilder::default();
+    let mut index_account_diff_by_nonce_changed = BitmapIndexBuilder::default();
+    let mut index_account_diff_by_code_changed = BitmapIndexBuilder::default();
+    let mut index_account_diff_by_transaction_status = BitmapIndexBuilder::default();
+    let mut join_account_diff_to_transaction = JoinToOneIndexBuilder::default();
+    let mut join_account_diff_to_receipt = JoinToOneIndexBuilder::default();
+
+    let mut index_storage_diff_by_address = BitmapIndexBuilder::default();
+    let mut index_storage_diff_by_slot = BitmapIndexBuilder::default();
+    let mut index_storage_diff_by_transaction_status = BitmapIndexBuilder::default();
+    let mut join_storage_diff_to_transaction = JoinToOneIndexBuilder::default();
+    let mut join_storage_diff_to_receipt = JoinToOneIndexBuilder::default();
+
+    for (transaction_index, transaction_state_diff) in transaction_state_diffs.iter().enumerate() {
+        let Some(transaction) = block_transactions.get(transaction_index) else {
+            return Err(IngestionError::Model)
+                .attach_printable("state diff without transaction")
+                .attach_printable_lazy(|| format!("transaction index: {}", transaction_index));
+        };
+
+        let transaction_index = transaction_index as u32;
+        let transaction_hash = transaction.transaction_hash;
+        let transaction_status = transaction.transaction_status;
+
+        let Some(state_diff) = transaction_state_diff.full_trace.state_diff.as_ref() else {
+            continue;
+        };
+
+        for (address, account_diff) in state_diff.0.iter() {
+            let address = address.to_proto();
+
+            let balance = account_diff.balance.to_proto();
+            let nonce = account_diff.nonce.to_proto();
+            let code = account_diff.code.to_proto();
+
+            // Accounts with only storage changes don't have an account diff.
+            if balance.is_some() || nonce.is_some() || code.is_some() {
+                let account_diff_index = block_account_diffs.len() as u32;
+
+                index_account_diff_by_address
+                    .insert(ScalarValue::B160(address.to_bytes()), account_diff_index);
+                index_account_diff_by_balance_changed
+                    .insert(ScalarValue::Bool(balance.is_some()), account_diff_index);
+                index_account_diff_by_nonce_changed
+                    .insert(ScalarValue::Bool(nonce.is_some()), account_diff_index);
+                index_account_diff_by_code_changed
+                    .insert(ScalarValue::Bool(code.is_some()), account_diff_index);
+                index_account_diff_by_transaction_status
+                    .insert(ScalarValue::Int32(transaction_status), account_diff_index);
+
+                join_account_diff_to_transaction.insert(account_diff_index, transaction_index);
+                join_account_diff_to_receipt.insert(account_diff_index, transaction_index);
+
+                block_account_diffs.push(evm::AccountDiff {
+                    filter_ids: Vec::default(),
+                    account_diff_index,
+                    transaction_index,
+                    transaction_hash,
+                    transaction_status,
+                    address: address.into(),
+                    balance,
+                    nonce,
+                    code,
+                });
+            }
+
+            for (slot, value) in account_diff.storage.iter() {
+                let Some((previous_value, new_value)) = delta_values(value) else {
+                    continue;
+                };
+
+                let storage_diff_index = block_storage_diffs.len() as u32;
+
+                index_storage_diff_by_address
+                    .insert(ScalarValue::B160(address.to_bytes()), storage_diff_index);
+                index_storage_diff_by_slot.insert(ScalarValue::B256(slot.0), storage_diff_index);
+                index_storage_diff_by_transaction_status
+                    .insert(ScalarValue::Int32(transaction_status), storage_diff_index);
+
+                join_storage_diff_to_transaction.insert(storage_diff_index, transaction_index);
+                join_storage_diff_to_receipt.insert(storage_diff_index, transaction_index);
+
+                block_storage_diffs.push(evm::StorageDiff {
+                    filter_ids: Vec::default(),
+                    storage_diff_index,
+                    transaction_index,
+                    transaction_hash,
+                    transaction_status,
+                    address: address.into(),
+                    slot: slot.to_proto().into(),
+                    previous_value: previous_value.map(ModelExt::to_proto),
+                    new_value: new_value.map(ModelExt::to_proto),
+                });
+            }
+        }
+    }
+
+    let account_diff_index = {
+        let index_account_diff_by_address = Index {
+            index_id: INDEX_ACCOUNT_DIFF_BY_ADDRESS,
+            index: index_account_diff_by_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_account_diff_by_balance_changed = Index {
+            index_id: INDEX_ACCOUNT_DIFF_BY_BALANCE_CHANGED,
+            index: index_account_diff_by_balance_changed
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_account_diff_by_nonce_changed = Index {
+            index_id: INDEX_ACCOUNT_DIFF_BY_NONCE_CHANGED,
+            index: index_account_diff_by_nonce_changed
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_account_diff_by_code_changed = Index {
+            index_id: INDEX_ACCOUNT_DIFF_BY_CODE_CHANGED,
+            index: index_account_diff_by_code_changed
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_account_diff_by_transaction_status = Index {
+            index_id: INDEX_ACCOUNT_DIFF_BY_TRANSACTION_STATUS,
+            index: index_account_diff_by_transaction_status
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        IndexFragment {
+            fragment_id: ACCOUNT_DIFF_FRAGMENT_ID,
+            range_start: 0,
+            range_len: block_account_diffs.len() as u32,
+            indexes: vec![
+                index_account_diff_by_address,
+                index_account_diff_by_balance_changed,
+                index_account_diff_by_nonce_changed,
+                index_account_diff_by_code_changed,
+                index_account_diff_by_transaction_status,
+            ],
+        }
+    };
+
+    let account_diff_join = {
+        let join_account_diff_to_transaction = Join {
+            to_fragment_id: TRANSACTION_FRAGMENT_ID,
+            index: join_account_diff_to_transaction.build().into(),
+        };
+
+        let join_account_diff_to_receipt = Join {
+            to_fragment_id: RECEIPT_FRAGMENT_ID,
+            index: join_account_diff_to_receipt.build().into(),
+        };
+
+        JoinFragment {
+            fragment_id: ACCOUNT_DIFF_FRAGMENT_ID,
+            joins: vec![
+                join_account_diff_to_transaction,
+                join_account_diff_to_receipt,
+            ],
+        }
+    };
+
+    let account_diff_fragment = BodyFragment {
+        fragment_id: ACCOUNT_DIFF_FRAGMENT_ID,
+        name: ACCOUNT_DIFF_FRAGMENT_NAME.to_string(),
+        data: block_account_diffs
+            .iter()
+            .map(Message::encode_to_vec)
+            .collect(),
+    };
+
+    let storage_diff_index = {
+        let index_storage_diff_by_address = Index {
+            index_id: INDEX_STORAGE_DIFF_BY_ADDRESS,
+            index: index_storage_diff_by_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_storage_diff_by_slot = Index {
+            index_id: INDEX_STORAGE_DIFF_BY_SLOT,
+            index: index_storage_diff_by_slot
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_storage_diff_by_transaction_status = Index {
+            index_id: INDEX_STORAGE_DIFF_BY_TRANSACTION_STATUS,
+            index: index_storage_diff_by_transaction_status
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        IndexFragment {
+            fragment_id: STORAGE_DIFF_FRAGMENT_ID,
+            range_start: 0,
+            range_len: block_storage_diffs.len() as u32,
+            indexes: vec![
+                index_storage_diff_by_address,
+                index_storage_diff_by_slot,
+                index_storage_diff_by_transaction_status,
+            ],
+        }
+    };
+
+    let storage_diff_join = {
+        let join_storage_diff_to_transaction = Join {
+            to_fragment_id: TRANSACTION_FRAGMENT_ID,
+            index: join_storage_diff_to_transaction.build().into(),
+        };
+
+        let join_storage_diff_to_receipt = Join {
+            to_fragment_id: RECEIPT_FRAGMENT_ID,
+            index: join_storage_diff_to_receipt.build().into(),
+        };
+
+        JoinFragment {
+            fragment_id: STORAGE_DIFF_FRAGMENT_ID,
+            joins: vec![
+                join_storage_diff_to_transaction,
+                join_storage_diff_to_receipt,
+            ],
+        }
+    };
+
+    let storage_diff_fragment = BodyFragment {
+        fragment_id: STORAGE_DIFF_FRAGMENT_ID,
+        name: STORAGE_DIFF_FRAGMENT_NAME.to_string(),
+        data: block_storage_diffs
+            .iter()
+            .map(Message::encode_to_vec)
+            .collect(),
+    };
+
+    Ok(BlockIngestionResult {
+        body: vec![account_diff_fragment, storage_diff_fragment],
+        index: vec![account_diff_index, storage_diff_index],
+        join: vec![account_diff_join, storage_diff_join],
+    })
+}
+
+fn collect_token_transfer_body_and_index(
+    block_traces: &[evm::TransactionTrace],
+    block_transactions: &BlockTransactions,
+) -> Result<BlockIngestionResult, IngestionError> {
+    let mut block_token_transfers = Vec::new();
+
+    let mut index_token_transfer_by_standard = BitmapIndexBuilder::default();
+    let mut index_token_transfer_by_token = BitmapIndexBuilder::default();
+    let mut index_token_transfer_by_from_address = BitmapIndexBuilder::default();
+    let mut index_token_transfer_by_to_address = BitmapIndexBuilder::default();
+    let mut index_token_transfer_by_address = BitmapIndexBuilder::default();
+    let mut join_token_transfer_to_transaction = JoinToOneIndexBuilder::default();
+    let mut join_token_transfer_to_receipt = JoinToOneIndexBuilder::default();
+    let mut join_token_transfer_to_log = JoinToOneIndexBuilder::default();
+
+    for (transaction_index, (transaction, receipt)) in block_transactions
+        .transactions
+        .iter()
+        .zip(block_transactions.receipts.iter())
+        .enumerate()
+    {
+        // Reverted transactions don't transfer any value.
+        if transaction.transaction_status != evm::TransactionStatus::Succeeded as i32 {
+            continue;
+        }
+
+        let mut transaction_transfers = Vec::new();
+
+        match block_traces.get(transaction_index) {
+            Some(transaction_trace) => {
+                transaction_transfers.extend(decode_trace_transfers(&transaction_trace.traces));
+            }
+            None => {
+                transaction_transfers.extend(decode_transaction_transfer(transaction, receipt));
+            }
+        }
+
+        for log_index in block_transactions.transaction_logs[transaction_index].iter() {
+            let log = &block_transactions.logs[*log_index as usize];
+            for mut transfer in decode_log_transfers(log) {
+                transfer.log_index = Some(*log_index);
+                transaction_transfers.push(transfer);
+            }
+        }
+
+        let transaction_index = transaction_index as u32;
+
+        for mut transfer in transaction_transfers {
+            let token_transfer_index = block_token_transfers.len() as u32;
+
+            transfer.token_transfer_index = token_transfer_index;
+            transfer.transaction_index = transaction_index;
+            transfer.transaction_hash = transaction.transaction_hash;
+
+            index_token_transfer_by_standard
+                .insert(ScalarValue::Int32(transfer.standard), token_transfer_index);
+
+            if let Some(token) = transfer.token {
+                index_token_transfer_by_token
+                    .insert(ScalarValue::B160(token.to_bytes()), token_transfer_index);
+            }
+
+            if let Some(from) = transfer.from {
+                index_token_transfer_by_from_address
+                    .insert(ScalarValue::B160(from.to_bytes()), token_transfer_index);
+                index_token_transfer_by_address
+                    .insert(ScalarValue::B160(from.to_bytes()), token_transfer_index);
+            }
+
+            if let Some(to) = transfer.to {
+                index_token_transfer_by_to_address
+                    .insert(ScalarValue::B160(to.to_bytes()), token_transfer_index);
+                index_token_transfer_by_address
+                    .insert(ScalarValue::B160(to.to_bytes()), token_transfer_index);
+            }
+
+            join_token_transfer_to_transaction.insert(token_transfer_index, transaction_index);
+            join_token_transfer_to_receipt.insert(token_transfer_index, transaction_index);
+            if let Some(log_index) = transfer.log_index {
+                join_token_transfer_to_log.insert(token_transfer_index, log_index);
+            }
+
+            block_token_transfers.push(transfer);
+        }
+    }
+
+    let token_transfer_index = {
+        let index_token_transfer_by_standard = Index {
+            index_id: INDEX_TOKEN_TRANSFER_BY_STANDARD,
+            index: index_token_transfer_by_standard
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_token_transfer_by_token = Index {
+            index_id: INDEX_TOKEN_TRANSFER_BY_TOKEN,
+            index: index_token_transfer_by_token
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_token_transfer_by_from_address = Index {
+            index_id: INDEX_TOKEN_TRANSFER_BY_FROM_ADDRESS,
+            index: index_token_transfer_by_from_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_token_transfer_by_to_address = Index {
+            index_id: INDEX_TOKEN_TRANSFER_BY_TO_ADDRESS,
+            index: index_token_transfer_by_to_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        let index_token_transfer_by_address = Index {
+            index_id: INDEX_TOKEN_TRANSFER_BY_ADDRESS,
+            index: index_token_transfer_by_address
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        IndexFragment {
+            fragment_id: TOKEN_TRANSFER_FRAGMENT_ID,
+            range_start: 0,
+            range_len: block_token_transfers.len() as u32,
+            indexes: vec![
+                index_token_transfer_by_standard,
+                index_token_transfer_by_token,
+                index_token_transfer_by_from_address,
+                index_token_transfer_by_to_address,
+                index_token_transfer_by_address,
+            ],
+        }
+    };
+
+    let token_transfer_join = {
+        let join_token_transfer_to_transaction = Join {
+            to_fragment_id: TRANSACTION_FRAGMENT_ID,
+            index: join_token_transfer_to_transaction.build().into(),
+        };
+
+        let join_token_transfer_to_receipt = Join {
+            to_fragment_id: RECEIPT_FRAGMENT_ID,
+            index: join_token_transfer_to_receipt.build().into(),
+        };
+
+        let join_token_transfer_to_log = Join {
+            to_fragment_id: LOG_FRAGMENT_ID,
+            index: join_token_transfer_to_log.build().into(),
+        };
+
+        JoinFragment {
+            fragment_id: TOKEN_TRANSFER_FRAGMENT_ID,
+            joins: vec![
+                join_token_transfer_to_transaction,
+                join_token_transfer_to_receipt,
+                join_token_transfer_to_log,
+            ],
+        }
+    };
+
+    let token_transfer_fragment = BodyFragment {
+        fragment_id: TOKEN_TRANSFER_FRAGMENT_ID,
+        name: TOKEN_TRANSFER_FRAGMENT_NAME.to_string(),
+        data: block_token_transfers
+            .iter()
+            .map(Message::encode_to_vec)
+            .collect(),
+    };
+
+    Ok(BlockIngestionResult {
+        body: vec![token_transfer_fragment],
+        index: vec![token_transfer_index],
+        join: vec![token_transfer_join],
+    })
+}
+
+fn collect_uncle_body_and_index(
+    uncles: &[models::Header],
+) -> Result<BlockIngestionResult, IngestionError> {
+    let mut block_uncles = Vec::new();
+
+    let mut index_uncle_by_miner = BitmapIndexBuilder::default();
+
+    for (uncle_index, uncle) in uncles.iter().enumerate() {
+        let uncle_index = uncle_index as u32;
+
+        let header = convert_block_header(uncle.clone());
+
+        if let Some(miner) = header.miner {
+            index_uncle_by_miner.insert(ScalarValue::B160(miner.to_bytes()), uncle_index);
+        }
+
+        block_uncles.push(evm::Uncle {
+            filter_ids: Vec::new(),
+            uncle_index,
+            header: Some(header),
+        });
+    }
+
+    let uncle_index = {
+        let index_uncle_by_miner = Index {
+            index_id: INDEX_UNCLE_BY_MINER,
+            index: index_uncle_by_miner
+                .build()
+                .change_context(IngestionError::Indexing)?
+                .into(),
+        };
+
+        IndexFragment {
+            fragment_id: UNCLE_FRAGMENT_ID,
+            range_start: 0,
+            range_len: block_uncles.len() as u32,
+            indexes: vec![index_uncle_by_miner],
+        }
+    };
+
+    let uncle_join = JoinFragment {
+        fragment_id: UNCLE_FRAGMENT_ID,
+        joins: Vec::default(),
+    };
+
+    let uncle_fragment = BodyFragment {
+        fragment_id: UNCLE_FRAGMENT_ID,
+        name: UNCLE_FRAGMENT_NAME.to_string(),
+        data: block_uncles.iter().map(Message::encode_to_vec).collect(),
+    };
+
+    Ok(BlockIngestionResult {
+        body: vec![uncle_fragment],
+        index: vec![uncle_index],
+        join: vec![uncle_join],
+    })
+}
+
+fn collect_fee_summary_body_and_index(fee_summary: &evm::BlockFeeSummary) -> BlockIngestionResult {
+    // The fee summary is never filtered, it's merged into the header on request.
+    let fee_summary_index = IndexFragment {
+        fragment_id: FEE_SUMMARY_FRAGMENT_ID,
+        range_start: 0,
+        range_len: 1,
+        indexes: Vec::default(),
+    };
+
+    let fee_summary_join = JoinFragment {
+        fragment_id: FEE_SUMMARY_FRAGMENT_ID,
+        joins: Vec::default(),
+    };
+
+    let fee_summary_fragment = BodyFragment {
+        fragment_id: FEE_SUMMARY_FRAGMENT_ID,
+        name: FEE_SUMMARY_FRAGMENT_NAME.to_string(),
+        data: vec![encode_fee_summary(fee_summary)],
+    };
+
+    BlockIngestionResult {
+        body: vec![fee_summary_fragment],
+        index: vec![fee_summary_index],
+        join: vec![fee_summary_join],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::hex;
+    use apibara_dna_common::{
+        data_stream::BlockFilterFactory,
+        fragment::{ArchivedIndexFragment, IndexFragment},
+    };
+    use apibara_dna_protocol::evm;
+    use prost::Message;
+    use serde_json::Value;
+
+    use crate::{
+        fee::block_fee_summary,
+        filter::EvmFilterFactory,
+        fragment::{
+            ACCOUNT_DIFF_FRAGMENT_ID, CALL_TRACE_FRAGMENT_ID, FEE_SUMMARY_FRAGMENT_ID,
+            LOG_FRAGMENT_ID, RECEIPT_FRAGMENT_ID, STORAGE_DIFF_FRAGMENT_ID,
+            TOKEN_TRANSFER_FRAGMENT_ID, TRACE_FRAGMENT_ID, TRANSACTION_FRAGMENT_ID,
+            UNCLE_FRAGMENT_ID, WITHDRAWAL_FRAGMENT_ID,
+        },
+        l2::BlockExtension,
+        provider::models,
+    };
+
+    use super::{
+        collect_block_body_and_index, collect_call_trace_body_and_index,
+        collect_state_diff_body_and_index, collect_transaction_body_and_index, BlockBody,
+        BlockIngestionResult, BlockTransactions,
+    };
+
+    const BLOCK: &str =
+        include_str!("../tests/fixtures/get_block_with_transactions/1000000001.json");
+    const RECEIPTS: &str = include_str!(
+        "../tests/fixtures/get_block_receipts/0x0bce7b859199014bd4724a8c45faecada3285c4c73bed48984b7e88ce9e1453a.json"
+    );
+
+    /// Collect the recorded block's transactions after applying `tamper` to its transactions.
+    fn collect_transactions(
+        tamper: impl FnOnce(&mut Value),
+    ) -> (BlockTransactions, BlockIngestionResult) {
+        let mut block: Value = serde_json::from_str(BLOCK).unwrap();
+        tamper(&mut block["transactions"]);
+
+        let block: models::Block = serde_json::from_value(block).unwrap();
+        let receipts: Vec<models::TransactionReceipt> = serde_json::from_str(RECEIPTS).unwrap();
+        let transactions = block.transactions.as_transactions().unwrap();
+
+        collect_transaction_body_and_index(transactions, &receipts, &BlockExtension::default(), 0)
+            .unwrap()
+    }
+
+    fn address(byte: u8) -> evm::Address {
+        evm::Address::from_bytes(&[byte; 20])
+    }
+
+    /// Compiles the filter and returns the rows of the index fragment it matches.
+    fn filter_rows(filter: evm::Filter, index: &IndexFragment) -> Vec<u32> {
+        let block_filter = EvmFilterFactory
+            .create_block_filter(&[filter.encode_to_vec()])
+            .unwrap()
+            .remove(0);
+
+        let (_, filters) = block_filter
+            .iter()
+            .find(|(fragment_id, _)| **fragment_id == index.fragment_id)
+            .unwrap();
+
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(index).unwrap();
+        let index = unsafe { rkyv::access_unchecked::<ArchivedIndexFragment>(&bytes) };
+
+        filters[0]
+            .filter(index, block_filter.optional_indexes(index.fragment_id))
+            .unwrap()
+            .iter()
+            .collect()
+    }
+
+    fn block_transactions(statuses: &[evm::TransactionStatus]) -> BlockTransactions {
+        BlockTransactions {
+            transactions: statuses
+                .iter()
+                .enumerate()
+                .map(|(transaction_index, status)| evm::Transaction {
+                    transaction_index: transaction_index as u32,
+                    transaction_status: *status as i32,
+                    ..Default::default()
+                })
+                .collect(),
+            receipts: statuses
+                .iter()
+                .enumerate()
+                .map(|(transaction_index, status)| evm::TransactionReceipt {
+                    transaction_index: transaction_index as u32,
+                    transaction_status: *status as i32,
+                    ..Default::default()
+                })
+                .collect(),
+            logs: Vec::new(),
+            transaction_logs: vec![Vec::new(); statuses.len()],
+        }
+    }
+
+    fn call(from: u8, to: u8, input: &[u8]) -> evm::Trace {
+        evm::Trace {
+            action: Some(evm::trace::Action::Call(evm::CallAction {
+                from_address: Some(address(from)),
+                r#type: evm::CallType::Call as i32,
+                input: input.to_vec(),
+                to_address: Some(address(to)),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_call_trace_index_and_filter() {
+        let create = evm::Trace {
+            action: Some(evm::trace::Action::Create(evm::CreateAction {
+                from_address: Some(address(2)),
+                ..Default::default()
+            })),
+            output: Some(evm::trace::Output::CreateOutput(evm::CreateOutput {
+                address: Some(address(3)),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let traces = vec![
+            evm::TransactionTrace {
+                transaction_index: 0,
+                traces: vec![call(1, 2, &[0xa9, 0x05, 0x9c, 0xbb, 0x00]), create.clone()],
+                ..Default::default()
+            },
+            evm::TransactionTrace {
+                transaction_index: 1,
+                traces: vec![call(1, 4, &[])],
+                ..Default::default()
+            },
+        ];
+
+        let transactions = block_transactions(&[
+            evm::TransactionStatus::Succeeded,
+            evm::TransactionStatus::Reverted,
+        ]);
+
+        let result = collect_call_trace_body_and_index(&traces, &transactions).unwrap();
+
+        let call_traces = result.body[0]
+            .data
+            .iter()
+            .map(|data| evm::CallTrace::decode(data.as_slice()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(call_traces.len(), 3);
+        assert_eq!(call_traces[1].call_index, 1);
+        assert_eq!(call_traces[1].trace, Some(create));
+        assert_eq!(call_traces[2].transaction_index, 1);
+        assert_eq!(
+            call_traces[2].transaction_status,
+            evm::TransactionStatus::Reverted as i32
+        );
+
+        let index = &result.index[0];
+        assert_eq!(index.range_len, 3);
+
+        let filter = |filter: evm::TraceFilter| {
+            filter_rows(
+                evm::Filter {
+                    traces: vec![filter],
+                    ..Default::default()
+                },
+                index,
+            )
+        };
+
+        // Traces of reverted transactions are excluded by default.
+        assert_eq!(
+            filter(evm::TraceFilter {
+                from: Some(address(1)),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert_eq!(
+            filter(evm::TraceFilter {
+                from: Some(address(1)),
+                transaction_status: Some(evm::TransactionStatusFilter::All as i32),
+                ..Default::default()
+            }),
+            vec![0, 2]
+        );
+        assert_eq!(
+            filter(evm::TraceFilter {
+                to: Some(address(4)),
+                transaction_status: Some(evm::TransactionStatusFilter::Reverted as i32),
+                ..Default::default()
+            }),
+            vec![2]
+        );
+        assert_eq!(
+            filter(evm::TraceFilter {
+                action_type: Some(evm::TraceActionType::Create as i32),
+                ..Default::default()
+            }),
+            vec![1]
+        );
+        assert_eq!(
+            filter(evm::TraceFilter {
+                selector: vec![0xa9, 0x05, 0x9c, 0xbb],
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert_eq!(
+            filter(evm::TraceFilter {
+                created_address: Some(address(3)),
+                ..Default::default()
+            }),
+            vec![1]
+        );
+        assert!(filter(evm::TraceFilter {
+            call_type: Some(evm::CallType::StaticCall as i32),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_state_diff_index_and_filter() {
+        let state_diffs: Vec<models::TraceResultsWithTransactionHash> =
+            serde_json::from_value(serde_json::json!([
+                {
+                    "output": "0x",
+                    "trace": [],
+                    "vmTrace": null,
+                    "transactionHash": format!("0x{}", "aa".repeat(32)),
+                    "stateDiff": {
+                        "0x0101010101010101010101010101010101010101": {
+                            "balance": { "*": { "from": "0x10", "to": "0x20" } },
+                            "code": "=",
+                            "nonce": { "*": { "from": "0x1", "to": "0x2" } },
+                            "storage": {
+                                "0x0000000000000000000000000000000000000000000000000000000000000001": {
+                                    "*": {
+                                        "from": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                                        "to": "0x0000000000000000000000000000000000000000000000000000000000000002"
+                                    }
+                                },
+                                "0x0000000000000000000000000000000000000000000000000000000000000002": "="
+                            }
+                        },
+                        "0x0202020202020202020202020202020202020202": {
+                            "balance": "=",
+                            "code": "=",
+                            "nonce": "=",
+                            "storage": {
+                                "0x0000000000000000000000000000000000000000000000000000000000000001": {
+                                    "+": "0x0000000000000000000000000000000000000000000000000000000000000005"
+                                }
+                            }
+                        }
+                    }
+                },
+                {
+                    "output": "0x",
+                    "trace": [],
+                    "vmTrace": null,
+                    "transactionHash": format!("0x{}", "bb".repeat(32)),
+                    "stateDiff": {
+                        "0x0303030303030303030303030303030303030303": {
+                            "balance": "=",
+                            "code": { "+": "0x6000" },
+                            "nonce": "=",
+                            "storage": {}
+                        }
+                    }
+                }
+            ]))
+            .unwrap();
+
+        let transactions = block_transactions(&[
+            evm::TransactionStatus::Succeeded,
+            evm::TransactionStatus::Reverted,
+        ]);
+
+        let result =
+            collect_state_diff_body_and_index(&state_diffs, &transactions.transactions).unwrap();
+
+        // Unchanged storage slots and accounts with only storage changes are skipped.
+        let account_diffs = result.body[0]
+            .data
+            .iter()
+            .map(|data| evm::AccountDiff::decode(data.as_slice()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(account_diffs.len(), 2);
+        assert_eq!(account_diffs[0].address, Some(address(1)));
+        assert_eq!(account_diffs[1].address, Some(address(3)));
+        assert_eq!(account_diffs[1].transaction_index, 1);
+
+        let storage_diffs = result.body[1]
+            .data
+            .iter()
+            .map(|data| evm::StorageDiff::decode(data.as_slice()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(storage_diffs.len(), 2);
+        assert_eq!(storage_diffs[1].address, Some(address(2)));
+        assert!(storage_diffs[1].previous_value.is_none());
+
+        let account_diff_index = &result.index[0];
+        let account_diff_filter = |filter: evm::AccountDiffFilter| {
+            filter_rows(
+                evm::Filter {
+                    account_diffs: vec![filter],
+                    ..Default::default()
+                },
+                account_diff_index,
+            )
+        };
+
+        assert_eq!(
+            account_diff_filter(evm::AccountDiffFilter {
+                address: Some(address(1)),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert_eq!(
+            account_diff_filter(evm::AccountDiffFilter {
+                nonce_changed: Some(true),
+                balance_changed: Some(true),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        // Account diffs of reverted transactions are excluded by default.
+        assert!(account_diff_filter(evm::AccountDiffFilter {
+            code_changed: Some(true),
+            ..Default::default()
+        })
+        .is_empty());
+        assert_eq!(
+            account_diff_filter(evm::AccountDiffFilter {
+                code_changed: Some(true),
+                transaction_status: Some(evm::TransactionStatusFilter::Reverted as i32),
+                ..Default::default()
+            }),
+            vec![1]
+        );
+
+        let storage_diff_index = &result.index[1];
+        let storage_diff_filter = |filter: evm::StorageDiffFilter| {
+            filter_rows(
+                evm::Filter {
+                    storage_diffs: vec![filter],
+                    ..Default::default()
+                },
+                storage_diff_index,
+            )
+        };
+
+        let mut slot = [0; 32];
+        slot[31] = 1;
+
+        assert_eq!(
+            storage_diff_filter(evm::StorageDiffFilter {
+                slot: Some(evm::B256::from_bytes(&slot)),
+                ..Default::default()
+            }),
+            vec![0, 1]
+        );
+        assert_eq!(
+            storage_diff_filter(evm::StorageDiffFilter {
+                address: Some(address(2)),
+                slot: Some(evm::B256::from_bytes(&slot)),
+                ..Default::default()
+            }),
+            vec![1]
+        );
+        assert!(storage_diff_filter(evm::StorageDiffFilter {
+            address: Some(address(3)),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_transaction_authorization_index_and_filter() {
+        let (transactions, result) = collect_transactions(|transactions| {
+            let transaction = &mut transactions[1];
+            transaction["type"] = "0x4".into();
+            transaction["authorizationList"] = serde_json::json!([{
+                "chainId": "0x1",
+                "address": "0x0505050505050505050505050505050505050505",
+                "nonce": "0x0",
+                "yParity": "0x0",
+                // The x coordinate of the curve generator, so that the authority can be recovered.
+                "r": "0x79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                "s": "0x1",
+            }]);
+        });
+
+        let authorization = &transactions.transactions[1].authorization_list[0];
+        assert_eq!(authorization.address, Some(address(5)));
+        let authority = authorization
+            .authority
+            .expect("authority recovered from the signature");
+
+        let index = &result.index[0];
+        assert_eq!(index.range_len, 3);
+
+        let filter = |filter: evm::TransactionFilter| {
+            filter_rows(
+                evm::Filter {
+                    transactions: vec![filter],
+                    ..Default::default()
+                },
+                index,
+            )
+        };
+
+        assert_eq!(
+            filter(evm::TransactionFilter {
+                delegate: Some(address(5)),
+                ..Default::default()
+            }),
+            vec![1]
+        );
+        assert_eq!(
+            filter(evm::TransactionFilter {
+                authority: Some(authority),
+                ..Default::default()
+            }),
+            vec![1]
+        );
+        assert!(filter(evm::TransactionFilter {
+            delegate: Some(address(6)),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_log_transaction_address_index_and_filter() {
+        let (_, result) = collect_transactions(|_| {});
+
+        let index = &result.index[2];
+        assert_eq!(index.range_len, 1);
+
+        let filter = |filter: evm::LogFilter| {
+            filter_rows(
+                evm::Filter {
+                    logs: vec![filter],
+                    ..Default::default()
+                },
+                index,
+            )
+        };
+
+        let sender = evm::Address::from_bytes(&hex!("0376aac07ad725e01357b1725b5cec61ae10473c"));
+        let usdc = evm::Address::from_bytes(&hex!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+        // Sends a transaction without logs.
+        let other_sender =
+            evm::Address::from_bytes(&hex!("e05fcc23807536bee418f142d19fa0d21bb0cff7"));
+
+        assert_eq!(
+            filter(evm::LogFilter {
+                transaction_from: Some(sender),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert_eq!(
+            filter(evm::LogFilter {
+                transaction_from: Some(sender),
+                transaction_to: Some(usdc),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert!(filter(evm::LogFilter {
+            transaction_from: Some(other_sender),
+            ..Default::default()
+        })
+        .is_empty());
+        assert!(filter(evm::LogFilter {
+            transaction_to: Some(sender),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_log_abi_selector_and_wildcard_topic() {
+        let (_, result) = collect_transactions(|_| {});
+
+        let index = &result.index[2];
+        assert_eq!(index.range_len, 1);
+
+        let filter = |abi: &str, topics: Vec<evm::Topic>| {
+            filter_rows(
+                evm::Filter {
+                    logs: vec![evm::LogFilter {
+                        abi: Some(abi.to_string()),
+                        topics,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                index,
+            )
+        };
+
+        const TRANSFER: &str = "Transfer(address indexed from, address indexed to, uint256 value)";
+        const APPROVAL: &str =
+            "Approval(address indexed owner, address indexed spender, uint256 value)";
+        let transfer = evm::B256::from_bytes(&hex!(
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        ));
+
+        // Without topics, the event selector is used as the first topic.
+        assert_eq!(filter(TRANSFER, Vec::new()), vec![0]);
+        assert!(filter(APPROVAL, Vec::new()).is_empty());
+
+        // An explicit null first topic matches any event.
+        assert_eq!(filter(APPROVAL, vec![evm::Topic { value: None }]), vec![0]);
+
+        // An explicit first topic is used instead of the event selector.
+        assert_eq!(
+            filter(
+                APPROVAL,
+                vec![evm::Topic {
+                    value: Some(transfer)
+                }]
+            ),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_fee_summary_only_for_finalized_blocks() {
+        let block: models::Block = serde_json::from_str(BLOCK).unwrap();
+        let receipts: Vec<models::TransactionReceipt> = serde_json::from_str(RECEIPTS).unwrap();
+        let transactions = block.transactions.as_transactions().unwrap();
+        let extension = BlockExtension::default();
+
+        let fee_summary = block_fee_summary(
+            block.header.base_fee_per_gas,
+            block.header.gas_used,
+            &receipts,
+            &extension.receipts,
+        );
+
+        let has_fee_summary = |fee_summary| {
+            let (body, index, join) = collect_block_body_and_index(BlockBody {
+                transactions,
+                withdrawals: &[],
+                receipts: &receipts,
+                transaction_traces: &[],
+                state_diffs: &[],
+                uncles: &[],
+                fee_summary,
+                extension: &extension,
+            })
+            .unwrap();
+
+            let in_body = body
+                .iter()
+                .any(|f| f.fragment_id == FEE_SUMMARY_FRAGMENT_ID);
+            let in_index = index
+                .indexes
+                .iter()
+                .any(|f| f.fragment_id == FEE_SUMMARY_FRAGMENT_ID);
+            let in_join = join
+                .joins
+                .iter()
+                .any(|f| f.fragment_id == FEE_SUMMARY_FRAGMENT_ID);
+            assert_eq!(in_body, in_index);
+            assert_eq!(in_body, in_join);
+            in_body
+        };
+
+        assert!(has_fee_summary(Some(&fee_summary)));
+        // Pending blocks.
+        assert!(!has_fee_summary(None));
+    }
+
+    /// The body, index and join fragments are in the same order as before the per-fragment
+    /// builders were introduced.
+    #[test]
+    fn test_block_fragment_layout() {
+        let block: models::Block = serde_json::from_str(BLOCK).unwrap();
+        let receipts: Vec<models::TransactionReceipt> = serde_json::from_str(RECEIPTS).unwrap();
+        let transactions = block.transactions.as_transactions().unwrap();
+        let extension = BlockExtension::default();
+        let fee_summary = evm::BlockFeeSummary::default();
+
+        let (body, index, join) = collect_block_body_and_index(BlockBody {
+            transactions,
+            withdrawals: &[],
+            receipts: &receipts,
+            transaction_traces: &[],
+            state_diffs: &[],
+            uncles: &[],
+            fee_summary: Some(&fee_summary),
+            extension: &extension,
+        })
+        .unwrap();
+
+        let expected = vec![
+            WITHDRAWAL_FRAGMENT_ID,
+            TRANSACTION_FRAGMENT_ID,
+            RECEIPT_FRAGMENT_ID,
+            LOG_FRAGMENT_ID,
+            TRACE_FRAGMENT_ID,
+            CALL_TRACE_FRAGMENT_ID,
+            ACCOUNT_DIFF_FRAGMENT_ID,
+            STORAGE_DIFF_FRAGMENT_ID,
+            TOKEN_TRANSFER_FRAGMENT_ID,
+            UNCLE_FRAGMENT_ID,
+            FEE_SUMMARY_FRAGMENT_ID,
+        ];
+
+        let body_ids = body.iter().map(|f| f.fragment_id).collect::<Vec<_>>();
+        let index_ids = index
+            .indexes
+            .iter()
+            .map(|f| f.fragment_id)
+            .collect::<Vec<_>>();
+        let join_ids = join.joins.iter().map(|f| f.fragment_id).collect::<Vec<_>>();
+
+        assert_eq!(body_ids, expected);
+        assert_eq!(index_ids, expected);
+        assert_eq!(join_ids, expected);
+    }
 }
@@ -0,0 +1,302 @@
+//! Parse the L2 specific fields returned by OP Stack and Arbitrum nodes.
+//!
+//! The RPC models only support Ethereum transaction types. Transactions with an L2 specific
+//! type (OP Stack deposits, Arbitrum internal and retryable transactions) are rewritten as
+//! unsigned legacy transactions before parsing. Their original type and L2 fields are then
+//! restored after the transaction is converted to protobuf.
+use apibara_dna_protocol::evm;
+use error_stack::{Result, ResultExt};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{error::EvmError, proto::ModelExt, provider::models};
+
+/// The OP Stack deposit transaction type.
+pub const DEPOSIT_TRANSACTION_TYPE: u64 = 0x7e;
+
+/// The highest transaction type supported by the RPC models (EIP-7702).
+const MAX_ETHEREUM_TRANSACTION_TYPE: u64 = 4;
+
+/// The `v` value of a legacy signature without chain id.
+const PLACEHOLDER_SIGNATURE_V: &str = "0x1b";
+
+/// The L2 fields of the transactions and receipts in a block.
+#[derive(Debug, Clone, Default)]
+pub struct BlockExtension {
+    pub transactions: Vec<TransactionExtension>,
+    pub receipts: Vec<ReceiptExtension>,
+}
+
+/// The L2 fields of a transaction.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionExtension {
+    #[serde(rename = "type")]
+    pub transaction_type: Option<models::U64>,
+    pub source_hash: Option<models::B256>,
+    pub mint: Option<models::U256>,
+    pub is_system_tx: Option<bool>,
+}
+
+/// The L2 fields of a transaction receipt.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptExtension {
+    #[serde(rename = "type")]
+    pub transaction_type: Option<models::U64>,
+    pub l1_fee: Option<models::U256>,
+    pub l1_gas_used: Option<models::U128>,
+    pub l1_gas_price: Option<models::U128>,
+    pub l1_block_number: Option<models::U64>,
+    pub gas_used_for_l1: Option<models::U128>,
+}
+
+/// Parse a block with transactions returned by an L2 node.
+pub fn parse_block_with_transactions(
+    mut block: Value,
+) -> Result<(models::Block, Vec<TransactionExtension>), EvmError> {
+    let mut extensions = Vec::new();
+
+    if let Some(transactions) = block.get_mut("transactions").and_then(Value::as_array_mut) {
+        for (transaction_index, transaction) in transactions.iter_mut().enumerate() {
+            // Transactions are returned as hashes if the block was requested without them.
+            if !transaction.is_object() {
+                continue;
+            }
+
+            let extension = TransactionExtension::deserialize(&*transaction)
+                .change_context(EvmError)
+                .attach_printable("failed to parse transaction L2 fields")
+                .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))?;
+
+            if l2_transaction_type(extension.transaction_type).is_some() {
+                normalize_transaction(transaction);
+            }
+
+            extensions.push(extension);
+        }
+    }
+
+    let block = serde_json::from_value(block)
+        .change_context(EvmError)
+        .attach_printable("failed to parse block with transactions")?;
+
+    Ok((block, extensions))
+}
+
+/// Parse the block receipts returned by an L2 node.
+pub fn parse_block_receipts(
+    receipts: Vec<Value>,
+) -> Result<(Vec<models::TransactionReceipt>, Vec<ReceiptExtension>), EvmError> {
+    let mut block_receipts = Vec::with_capacity(receipts.len());
+    let mut extensions = Vec::with_capacity(receipts.len());
+
+    for (transaction_index, mut receipt) in receipts.into_iter().enumerate() {
+        let extension = ReceiptExtension::deserialize(&receipt)
+            .change_context(EvmError)
+            .attach_printable("failed to parse receipt L2 fields")
+            .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))?;
+
+        if l2_transaction_type(extension.transaction_type).is_some() {
+            normalize_receipt(&mut receipt);
+        }
+
+        let receipt = serde_json::from_value(receipt)
+            .change_context(EvmError)
+            .attach_printable("failed to parse receipt")
+            .attach_printable_lazy(|| format!("transaction index: {}", transaction_index))?;
+
+        block_receipts.push(receipt);
+        extensions.push(extension);
+    }
+
+    Ok((block_receipts, extensions))
+}
+
+impl TransactionExtension {
+    /// Restore the transaction type and add the L2 fields to the transaction.
+    pub fn apply(&self, transaction: &mut evm::Transaction) {
+        if let Some(transaction_type) = l2_transaction_type(self.transaction_type) {
+            transaction.transaction_type = transaction_type;
+            transaction.signature = None;
+            transaction.chain_id = None;
+        }
+
+        transaction.source_hash = self.source_hash.as_ref().map(ModelExt::to_proto);
+        transaction.mint = self.mint.as_ref().map(ModelExt::to_proto);
+        transaction.is_system_tx = self.is_system_tx;
+    }
+
+    /// Returns true if the transaction is an OP Stack deposit transaction.
+    pub fn is_deposit(&self) -> bool {
+        self.source_hash.is_some()
+            || self.transaction_type.map(|ty| ty.to::<u64>()) == Some(DEPOSIT_TRANSACTION_TYPE)
+    }
+
+    /// Returns true if the transaction mints ETH on L2.
+    pub fn is_mint(&self) -> bool {
+        self.mint.is_some_and(|mint| !mint.is_zero())
+    }
+}
+
+impl ReceiptExtension {
+    /// Returns the transaction type if it's specific to the L2.
+    pub fn l2_transaction_type(&self) -> Option<u64> {
+        l2_transaction_type(self.transaction_type)
+    }
+
+    /// Restore the transaction type and add the L2 fields to the receipt.
+    pub fn apply(&self, receipt: &mut evm::TransactionReceipt) {
+        if let Some(transaction_type) = l2_transaction_type(self.transaction_type) {
+            receipt.transaction_type = transaction_type;
+        }
+
+        receipt.l1_fee = self.l1_fee.as_ref().map(ModelExt::to_proto);
+        receipt.l1_gas_used = self
+            .l1_gas_used
+            .map(|value| evm::U128::from_u128(value.to::<u128>()));
+        receipt.l1_gas_price = self
+            .l1_gas_price
+            .map(|value| evm::U128::from_u128(value.to::<u128>()));
+        receipt.l1_block_number = self.l1_block_number.map(|value| value.to::<u64>());
+        receipt.gas_used_for_l1 = self
+            .gas_used_for_l1
+            .map(|value| evm::U128::from_u128(value.to::<u128>()));
+    }
+}
+
+/// Returns the transaction type if it's not supported by the RPC models.
+fn l2_transaction_type(transaction_type: Option<models::U64>) -> Option<u64> {
+    let transaction_type = transaction_type?.to::<u64>();
+    (transaction_type > MAX_ETHEREUM_TRANSACTION_TYPE).then_some(transaction_type)
+}
+
+/// Rewrite the transaction as an unsigned legacy transaction.
+///
+/// The sender and hash are taken from the response, so the signature is never checked.
+fn normalize_transaction(transaction: &mut Value) {
+    let Some(fields) = transaction.as_object_mut() else {
+        return;
+    };
+
+    fields.insert("type".to_string(), "0x0".into());
+    fields.remove("chainId");
+    fields.insert("v".to_string(), PLACEHOLDER_SIGNATURE_V.into());
+
+    for field in ["nonce", "gasPrice", "value", "r", "s"] {
+        if fields.get(field).is_none_or(Value::is_null) {
+            fields.insert(field.to_string(), "0x0".into());
+        }
+    }
+
+    if fields.get("input").is_none_or(Value::is_null) {
+        fields.insert("input".to_string(), "0x".into());
+    }
+}
+
+/// Rewrite the receipt as a legacy transaction receipt.
+fn normalize_receipt(receipt: &mut Value) {
+    let Some(fields) = receipt.as_object_mut() else {
+        return;
+    };
+
+    fields.insert("type".to_string(), "0x0".into());
+
+    if fields.get("effectiveGasPrice").is_none_or(Value::is_null) {
+        fields.insert("effectiveGasPrice".to_string(), "0x0".into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::address;
+
+    use super::{parse_block_receipts, parse_block_with_transactions, DEPOSIT_TRANSACTION_TYPE};
+    use crate::proto::ModelExt;
+
+    #[test]
+    fn test_parse_op_stack_deposit() {
+        let block = serde_json::json!({
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "parentHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+            "miner": "0x4200000000000000000000000000000000000011",
+            "stateRoot": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "transactionsRoot": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "receiptsRoot": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "difficulty": "0x0",
+            "number": "0x1",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0xab7d",
+            "timestamp": "0x6560a7b1",
+            "extraData": "0x",
+            "mixHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "nonce": "0x0000000000000000",
+            "baseFeePerGas": "0x3b9aca00",
+            "uncles": [],
+            "transactions": [{
+                "type": "0x7e",
+                "hash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "blockNumber": "0x1",
+                "transactionIndex": "0x0",
+                "sourceHash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+                "from": "0xdeaddeaddeaddeaddeaddeaddeaddeaddead0001",
+                "to": "0x4200000000000000000000000000000000000015",
+                "mint": "0x10",
+                "value": "0x0",
+                "gas": "0xf4240",
+                "isSystemTx": false,
+                "input": "0x440a5e20"
+            }]
+        });
+
+        let (block, extensions) = parse_block_with_transactions(block).unwrap();
+        let transaction = &block.transactions.as_transactions().unwrap()[0];
+
+        let mut transaction = transaction.to_proto();
+        extensions[0].apply(&mut transaction);
+
+        assert!(extensions[0].is_deposit());
+        assert!(extensions[0].is_mint());
+        assert_eq!(transaction.transaction_type, DEPOSIT_TRANSACTION_TYPE);
+        assert!(transaction.signature.is_none());
+        assert!(transaction.source_hash.is_some());
+        assert_eq!(transaction.is_system_tx, Some(false));
+        assert_eq!(
+            transaction.from,
+            Some(address!("deaddeaddeaddeaddeaddeaddeaddeaddead0001").to_proto())
+        );
+    }
+
+    #[test]
+    fn test_parse_arbitrum_receipt() {
+        let receipt = serde_json::json!({
+            "type": "0x6a",
+            "status": "0x1",
+            "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+            "transactionIndex": "0x0",
+            "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "blockNumber": "0x1",
+            "from": "0x00000000000000000000000000000000000a4b05",
+            "to": "0x00000000000000000000000000000000000a4b05",
+            "cumulativeGasUsed": "0x0",
+            "gasUsed": "0x0",
+            "contractAddress": null,
+            "logs": [],
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "gasUsedForL1": "0x0",
+            "l1BlockNumber": "0x1286d61"
+        });
+
+        let (receipts, extensions) = parse_block_receipts(vec![receipt]).unwrap();
+
+        let mut receipt = receipts[0].to_proto();
+        extensions[0].apply(&mut receipt);
+
+        assert_eq!(receipt.transaction_type, 0x6a);
+        assert_eq!(receipt.l1_block_number, Some(0x1286d61));
+        assert!(receipt.l1_fee.is_none());
+    }
+}
@@ -1,15 +1,29 @@
+pub mod abi;
+pub mod call_tracer;
 pub mod cli;
+pub mod era1;
 pub mod error;
+pub mod fee;
 pub mod filter;
 pub mod fragment;
 pub mod ingestion;
+pub mod l2;
 pub mod proto;
 pub mod provider;
+pub mod token_transfer;
+pub mod verify;
 
 use apibara_dna_common::{fragment::FragmentInfo, ChainSupport};
-use fragment::{TRACE_FRAGMENT_ID, TRACE_FRAGMENT_NAME};
+use fragment::{
+    ACCOUNT_DIFF_FRAGMENT_ID, ACCOUNT_DIFF_FRAGMENT_NAME, CALL_TRACE_FRAGMENT_ID,
+    CALL_TRACE_FRAGMENT_NAME, FEE_SUMMARY_FRAGMENT_ID, FEE_SUMMARY_FRAGMENT_NAME,
+    STORAGE_DIFF_FRAGMENT_ID, STORAGE_DIFF_FRAGMENT_NAME, TOKEN_TRANSFER_FRAGMENT_ID,
+    TOKEN_TRANSFER_FRAGMENT_NAME, TRACE_FRAGMENT_ID, TRACE_FRAGMENT_NAME, UNCLE_FRAGMENT_ID,
+    UNCLE_FRAGMENT_NAME,
+};
 
 use crate::{
+    era1::Era1Archive,
     filter::EvmFilterFactory,
     fragment::{
         LOG_FRAGMENT_ID, LOG_FRAGMENT_NAME, RECEIPT_FRAGMENT_ID, RECEIPT_FRAGMENT_NAME,
@@ -20,16 +34,27 @@ use crate::{
     provider::JsonRpcProvider,
 };
 
-pub use ingestion::EvmBlockIngestionOptions;
+pub use ingestion::{ChainFlavour, EvmBlockIngestionOptions, TraceBackend};
 
 pub struct EvmChainSupport {
     provider: JsonRpcProvider,
+    era1: Option<Era1Archive>,
     options: EvmBlockIngestionOptions,
 }
 
 impl EvmChainSupport {
     pub fn new(provider: JsonRpcProvider, options: EvmBlockIngestionOptions) -> Self {
-        Self { provider, options }
+        Self {
+            provider,
+            era1: None,
+            options,
+        }
+    }
+
+    /// Ingest the blocks covered by the Era1 archive from disk.
+    pub fn with_era1(mut self, era1: Era1Archive) -> Self {
+        self.era1 = Some(era1);
+        self
     }
 }
 
@@ -59,6 +84,30 @@ impl ChainSupport for EvmChainSupport {
                 fragment_id: TRACE_FRAGMENT_ID,
                 name: TRACE_FRAGMENT_NAME.to_string(),
             },
+            FragmentInfo {
+                fragment_id: CALL_TRACE_FRAGMENT_ID,
+                name: CALL_TRACE_FRAGMENT_NAME.to_string(),
+            },
+            FragmentInfo {
+                fragment_id: ACCOUNT_DIFF_FRAGMENT_ID,
+                name: ACCOUNT_DIFF_FRAGMENT_NAME.to_string(),
+            },
+            FragmentInfo {
+                fragment_id: STORAGE_DIFF_FRAGMENT_ID,
+                name: STORAGE_DIFF_FRAGMENT_NAME.to_string(),
+            },
+            FragmentInfo {
+                fragment_id: TOKEN_TRANSFER_FRAGMENT_ID,
+                name: TOKEN_TRANSFER_FRAGMENT_NAME.to_string(),
+            },
+            FragmentInfo {
+                fragment_id: UNCLE_FRAGMENT_ID,
+                name: UNCLE_FRAGMENT_NAME.to_string(),
+            },
+            FragmentInfo {
+                fragment_id: FEE_SUMMARY_FRAGMENT_ID,
+                name: FEE_SUMMARY_FRAGMENT_NAME.to_string(),
+            },
         ]
     }
 
@@ -67,6 +116,10 @@ impl ChainSupport for EvmChainSupport {
     }
 
     fn block_ingestion(&self) -> Self::BlockIngestion {
-        EvmBlockIngestion::new(self.provider.clone(), self.options.clone())
+        let ingestion = EvmBlockIngestion::new(self.provider.clone(), self.options.clone());
+        match self.era1.clone() {
+            Some(era1) => ingestion.with_era1(era1),
+            None => ingestion,
+        }
     }
 }
@@ -41,6 +41,8 @@ pub fn convert_block_header(block: models::Header) -> evm::BlockHeader {
             .as_ref()
             .map(ModelExt::to_proto),
         requests_hash: block.requests_hash.as_ref().map(ModelExt::to_proto),
+        // Appended by ingestion, see `fee::encode_fee_summary`.
+        fee_summary: None,
     }
 }
 
@@ -103,7 +105,12 @@ impl ModelExt for models::Signed<models::TxLegacy> {
                 .blob_versioned_hashes()
                 .map(|l| l.iter().map(ModelExt::to_proto).collect())
                 .unwrap_or_default(),
+            authorization_list: Vec::new(),
             transaction_status: 0,
+            source_hash: None,
+            mint: None,
+            is_system_tx: None,
+            decoded: Vec::new(),
         }
     }
 }
@@ -137,7 +144,12 @@ impl ModelExt for models::Signed<models::TxEip2930> {
                 .blob_versioned_hashes()
                 .map(|l| l.iter().map(ModelExt::to_proto).collect())
                 .unwrap_or_default(),
+            authorization_list: Vec::new(),
             transaction_status: 0,
+            source_hash: None,
+            mint: None,
+            is_system_tx: None,
+            decoded: Vec::new(),
         }
     }
 }
@@ -171,7 +183,12 @@ impl ModelExt for models::Signed<models::TxEip1559> {
                 .blob_versioned_hashes()
                 .map(|l| l.iter().map(ModelExt::to_proto).collect())
                 .unwrap_or_default(),
+            authorization_list: Vec::new(),
             transaction_status: 0,
+            source_hash: None,
+            mint: None,
+            is_system_tx: None,
+            decoded: Vec::new(),
         }
     }
 }
@@ -220,7 +237,12 @@ impl ModelExt for models::TxEip4844 {
                 .blob_versioned_hashes()
                 .map(|l| l.iter().map(ModelExt::to_proto).collect())
                 .unwrap_or_default(),
+            authorization_list: Vec::new(),
             transaction_status: 0,
+            source_hash: None,
+            mint: None,
+            is_system_tx: None,
+            decoded: Vec::new(),
         }
     }
 }
@@ -254,8 +276,16 @@ impl ModelExt for models::Signed<models::TxEip7702> {
                 .blob_versioned_hashes()
                 .map(|l| l.iter().map(ModelExt::to_proto).collect())
                 .unwrap_or_default(),
-            // SKIP: authorization list
+            authorization_list: tx
+                .authorization_list
+                .iter()
+                .map(ModelExt::to_proto)
+                .collect(),
             transaction_status: 0,
+            source_hash: None,
+            mint: None,
+            is_system_tx: None,
+            decoded: Vec::new(),
         }
     }
 }
@@ -298,6 +328,11 @@ impl ModelExt for models::TransactionReceipt {
             } else {
                 evm::TransactionStatus::Reverted as i32
             },
+            l1_fee: None,
+            l1_gas_used: None,
+            l1_gas_price: None,
+            l1_block_number: None,
+            gas_used_for_l1: None,
         }
     }
 }
@@ -316,6 +351,28 @@ impl ModelExt for models::Log {
             transaction_hash: None,
             transaction_status: 0,
             log_index_in_transaction: u32::MAX,
+            decoded: Vec::new(),
+        }
+    }
+}
+
+impl ModelExt for models::SignedAuthorization {
+    type Proto = evm::Authorization;
+
+    fn to_proto(&self) -> Self::Proto {
+        let signature = evm::Signature {
+            r: self.r().to_proto().into(),
+            s: self.s().to_proto().into(),
+            v: None,
+            y_parity: Some(self.y_parity() != 0),
+        };
+
+        evm::Authorization {
+            chain_id: self.chain_id().to_proto().into(),
+            address: self.address().to_proto().into(),
+            nonce: self.nonce(),
+            signature: signature.into(),
+            authority: self.recover_authority().ok().map(|a| a.to_proto()),
         }
     }
 }
@@ -513,6 +570,52 @@ impl ModelExt for models::RewardType {
     }
 }
 
+impl ModelExt for models::Delta<models::U256> {
+    type Proto = Option<evm::BalanceChange>;
+
+    fn to_proto(&self) -> Self::Proto {
+        let (previous, new) = delta_values(self)?;
+        Some(evm::BalanceChange {
+            previous_balance: previous.map(ModelExt::to_proto),
+            new_balance: new.map(ModelExt::to_proto),
+        })
+    }
+}
+
+impl ModelExt for models::Delta<models::U64> {
+    type Proto = Option<evm::NonceChange>;
+
+    fn to_proto(&self) -> Self::Proto {
+        let (previous, new) = delta_values(self)?;
+        Some(evm::NonceChange {
+            previous_nonce: previous.map(|nonce| nonce.to::<u64>()),
+            new_nonce: new.map(|nonce| nonce.to::<u64>()),
+        })
+    }
+}
+
+impl ModelExt for models::Delta<models::Bytes> {
+    type Proto = Option<evm::CodeChange>;
+
+    fn to_proto(&self) -> Self::Proto {
+        let (previous, new) = delta_values(self)?;
+        Some(evm::CodeChange {
+            previous_code: previous.map(|code| code.to_vec()),
+            new_code: new.map(|code| code.to_vec()),
+        })
+    }
+}
+
+/// Returns the values before and after the change, or `None` if the value didn't change.
+pub fn delta_values<T>(delta: &models::Delta<T>) -> Option<(Option<&T>, Option<&T>)> {
+    match delta {
+        models::Delta::Unchanged => None,
+        models::Delta::Added(value) => Some((None, Some(value))),
+        models::Delta::Removed(value) => Some((Some(value), None)),
+        models::Delta::Changed(models::ChangedType { from, to }) => Some((Some(from), Some(to))),
+    }
+}
+
 impl ModelExt for models::B256 {
     type Proto = evm::B256;
 
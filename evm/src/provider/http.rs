@@ -3,9 +3,17 @@ use std::{sync::Arc, time::Duration};
 use alloy_primitives::BlockHash;
 use alloy_provider::{ext::TraceApi, network::Ethereum, Provider, ProviderBuilder};
 use alloy_rpc_client::ClientBuilder;
+use alloy_rpc_types::BlockNumberOrTag;
+use apibara_dna_common::{
+    fixture::FixtureStore,
+    provider_pool::{
+        ConsistentRead, ProviderPool, ProviderPoolOptions, UpstreamConfig, UpstreamError,
+    },
+};
 use backon::{ExponentialBuilder, Retryable};
 use error_stack::{Report, Result, ResultExt};
 use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{de::DeserializeOwned, Serialize};
 use url::Url;
 
 pub use alloy_rpc_types::BlockId;
@@ -28,6 +36,8 @@ pub struct JsonRpcProviderOptions {
     pub headers: HeaderMap<HeaderValue>,
     /// Exponential backoff options.
     pub exponential_backoff: ExponentialBuilder,
+    /// Upstream pool options.
+    pub pool: ProviderPoolOptions,
 }
 
 pub trait ProviderWithTraceApi: Provider<Ethereum> + TraceApi<Ethereum> {}
@@ -36,6 +46,21 @@ impl<T> ProviderWithTraceApi for T where T: Provider<Ethereum> + TraceApi<Ethere
 
 #[derive(Clone)]
 pub struct JsonRpcProvider {
+    backend: JsonRpcBackend,
+    recorder: Option<FixtureStore>,
+}
+
+#[derive(Clone)]
+enum JsonRpcBackend {
+    /// Send requests to the upstream nodes.
+    Pool(ProviderPool<JsonRpcUpstream>),
+    /// Serve responses recorded previously.
+    Replay(FixtureStore),
+}
+
+/// A client for a single JSON-RPC endpoint.
+#[derive(Clone)]
+struct JsonRpcUpstream {
     provider: Arc<dyn ProviderWithTraceApi>,
     options: JsonRpcProviderOptions,
 }
@@ -45,7 +70,337 @@ pub trait JsonRpcProviderErrorExt {
 }
 
 impl JsonRpcProvider {
-    pub fn new(url: Url, options: JsonRpcProviderOptions) -> Result<Self, JsonRpcProviderError> {
+    pub fn new(
+        upstreams: Vec<UpstreamConfig>,
+        options: JsonRpcProviderOptions,
+    ) -> Result<Self, JsonRpcProviderError> {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|upstream| {
+                let provider = JsonRpcUpstream::new(upstream.url.clone(), options.clone())?;
+                Ok((upstream, provider))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pool = ProviderPool::new(upstreams, options.pool)
+            .change_context(JsonRpcProviderError::Configuration)?;
+
+        Ok(Self {
+            backend: JsonRpcBackend::Pool(pool),
+            recorder: None,
+        })
+    }
+
+    /// Create a provider that serves the responses recorded in the given fixture store.
+    pub fn replay(fixtures: FixtureStore) -> Self {
+        Self {
+            backend: JsonRpcBackend::Replay(fixtures),
+            recorder: None,
+        }
+    }
+
+    /// Record all responses to the given fixture store.
+    pub fn with_recorder(mut self, fixtures: FixtureStore) -> Self {
+        self.recorder = Some(fixtures);
+        self
+    }
+
+    pub async fn get_block_header(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::BlockWithTxHashes, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_header", &key).await;
+            }
+        };
+
+        let request =
+            |upstream: JsonRpcUpstream| async move { upstream.get_block_header(block_id).await };
+
+        let block_number = |block: &models::BlockWithTxHashes| block.header.number;
+
+        let response = match block_id {
+            BlockId::Number(BlockNumberOrTag::Latest) => {
+                pool.request_consistent(
+                    ConsistentRead::Head,
+                    "get_block_header",
+                    request,
+                    block_number,
+                )
+                .await?
+            }
+            BlockId::Number(BlockNumberOrTag::Finalized) => {
+                pool.request_consistent(
+                    ConsistentRead::Finalized,
+                    "get_block_header",
+                    request,
+                    block_number,
+                )
+                .await?
+            }
+            _ => pool.request("get_block_header", request).await?,
+        };
+
+        self.record("get_block_header", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_block_with_transactions(
+        &self,
+        block_id: BlockId,
+    ) -> Result<models::Block, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_with_transactions", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_block_with_transactions", |upstream| async move {
+                upstream.get_block_with_transactions(block_id).await
+            })
+            .await?;
+
+        self.record("get_block_with_transactions", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_block_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<models::TransactionReceipt>, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_receipts", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_block_receipts", |upstream| async move {
+                upstream.get_block_receipts(block_id).await
+            })
+            .await?;
+
+        self.record("get_block_receipts", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    /// Returns the block with transactions as returned by the node, without parsing it.
+    ///
+    /// Used on L2 chains whose transactions are not supported by the Ethereum models.
+    pub async fn get_raw_block_with_transactions(
+        &self,
+        block_id: BlockId,
+    ) -> Result<serde_json::Value, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_raw_block_with_transactions", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_raw_block_with_transactions", |upstream| async move {
+                upstream.get_raw_block_with_transactions(block_id).await
+            })
+            .await?;
+
+        self.record("get_raw_block_with_transactions", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Returns the block receipts as returned by the node, without parsing them.
+    pub async fn get_raw_block_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<serde_json::Value>, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_raw_block_receipts", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_raw_block_receipts", |upstream| async move {
+                upstream.get_raw_block_receipts(block_id).await
+            })
+            .await?;
+
+        self.record("get_raw_block_receipts", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Returns the block's uncle at the given index.
+    pub async fn get_uncle_by_block_number_and_index(
+        &self,
+        block_number: u64,
+        uncle_index: u64,
+    ) -> Result<models::BlockWithTxHashes, JsonRpcProviderError> {
+        let key = format!("{block_number}-{uncle_index}");
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_uncle_by_block_number_and_index", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request(
+                "get_uncle_by_block_number_and_index",
+                |upstream| async move {
+                    upstream
+                        .get_uncle_by_block_number_and_index(block_number, uncle_index)
+                        .await
+                },
+            )
+            .await?;
+
+        self.record("get_uncle_by_block_number_and_index", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    pub async fn trace_block_transactions(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<models::TraceResultsWithTransactionHash>, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "trace_block_transactions", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("trace_block_transactions", |upstream| async move {
+                upstream.trace_block_transactions(block_id).await
+            })
+            .await?;
+
+        self.record("trace_block_transactions", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Trace the block's transactions with Geth's `callTracer`.
+    pub async fn debug_trace_block(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<models::CallFrameResult>, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "debug_trace_block", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("debug_trace_block", |upstream| async move {
+                upstream.debug_trace_block(block_id).await
+            })
+            .await?;
+
+        self.record("debug_trace_block", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    /// Replay the block's transactions and return the state changes of each transaction.
+    pub async fn trace_block_state_diffs(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<models::TraceResultsWithTransactionHash>, JsonRpcProviderError> {
+        let key = fixture_key(&block_id);
+        let pool = match &self.backend {
+            JsonRpcBackend::Pool(pool) => pool,
+            JsonRpcBackend::Replay(fixtures) => {
+                return replay(fixtures, "trace_block_state_diffs", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("trace_block_state_diffs", |upstream| async move {
+                upstream.trace_block_state_diffs(block_id).await
+            })
+            .await?;
+
+        self.record("trace_block_state_diffs", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    async fn record<T>(
+        &self,
+        method: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), JsonRpcProviderError>
+    where
+        T: Serialize,
+    {
+        let Some(recorder) = &self.recorder else {
+            return Ok(());
+        };
+
+        recorder
+            .put(method, key, value)
+            .await
+            .change_context(JsonRpcProviderError::Request)
+            .attach_printable("failed to record response")
+    }
+}
+
+async fn replay<T>(
+    fixtures: &FixtureStore,
+    method: &str,
+    key: &str,
+) -> Result<T, JsonRpcProviderError>
+where
+    T: DeserializeOwned,
+{
+    fixtures
+        .get(method, key)
+        .await
+        .change_context(JsonRpcProviderError::Request)?
+        .ok_or(JsonRpcProviderError::NotFound)
+        .attach_printable("response not recorded")
+        .attach_printable_lazy(|| format!("method: {method}, key: {key}"))
+}
+
+/// Returns the key used to record responses for the given block.
+fn fixture_key(block_id: &BlockId) -> String {
+    match block_id {
+        BlockId::Number(BlockNumberOrTag::Number(number)) => number.to_string(),
+        BlockId::Number(tag) => format!("{tag:?}").to_lowercase(),
+        BlockId::Hash(hash) => hash.block_hash.to_string(),
+    }
+}
+
+impl JsonRpcUpstream {
+    fn new(url: Url, options: JsonRpcProviderOptions) -> Result<Self, JsonRpcProviderError> {
         if !options.headers.is_empty() {
             return Err(JsonRpcProviderError::Configuration)
                 .attach_printable("custom headers are not supported");
@@ -60,7 +415,7 @@ impl JsonRpcProvider {
         })
     }
 
-    pub async fn get_block_header(
+    async fn get_block_header(
         &self,
         block_id: BlockId,
     ) -> Result<models::BlockWithTxHashes, JsonRpcProviderError> {
@@ -100,7 +455,7 @@ impl JsonRpcProvider {
             .ok_or(JsonRpcProviderError::NotFound.into())
     }
 
-    pub async fn get_block_with_transactions(
+    async fn get_block_with_transactions(
         &self,
         block_id: BlockId,
     ) -> Result<models::Block, JsonRpcProviderError> {
@@ -118,7 +473,7 @@ impl JsonRpcProvider {
             .ok_or(JsonRpcProviderError::NotFound.into())
     }
 
-    pub async fn get_block_receipts(
+    async fn get_block_receipts(
         &self,
         block_id: BlockId,
     ) -> Result<Vec<models::TransactionReceipt>, JsonRpcProviderError> {
@@ -136,7 +491,98 @@ impl JsonRpcProvider {
             .ok_or(JsonRpcProviderError::NotFound.into())
     }
 
-    pub async fn trace_block_transactions(
+    async fn get_raw_block_with_transactions(
+        &self,
+        block_id: BlockId,
+    ) -> Result<serde_json::Value, JsonRpcProviderError> {
+        let request = (|| async {
+            match block_id {
+                BlockId::Number(number) => {
+                    self.provider
+                        .client()
+                        .request::<_, Option<serde_json::Value>>(
+                            "eth_getBlockByNumber",
+                            (number, true),
+                        )
+                        .await
+                }
+                BlockId::Hash(hash) => {
+                    let hash = BlockHash::from(hash);
+                    self.provider
+                        .client()
+                        .request::<_, Option<serde_json::Value>>("eth_getBlockByHash", (hash, true))
+                        .await
+                }
+            }
+        })
+        .retry(self.options.exponential_backoff);
+
+        let Ok(response) = tokio::time::timeout(self.options.timeout, request).await else {
+            return Err(JsonRpcProviderError::Timeout)
+                .attach_printable("failed to get raw block with transactions")
+                .attach_printable_lazy(|| format!("block id: {block_id:?}"));
+        };
+
+        response
+            .change_context(JsonRpcProviderError::Request)?
+            .ok_or(JsonRpcProviderError::NotFound.into())
+    }
+
+    async fn get_raw_block_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<serde_json::Value>, JsonRpcProviderError> {
+        let request = (|| async {
+            self.provider
+                .client()
+                .request::<_, Option<Vec<serde_json::Value>>>("eth_getBlockReceipts", (block_id,))
+                .await
+        })
+        .retry(self.options.exponential_backoff);
+
+        let Ok(response) = tokio::time::timeout(self.options.timeout, request).await else {
+            return Err(JsonRpcProviderError::Timeout)
+                .attach_printable("failed to get raw block receipts")
+                .attach_printable_lazy(|| format!("block id: {block_id:?}"));
+        };
+
+        response
+            .change_context(JsonRpcProviderError::Request)?
+            .ok_or(JsonRpcProviderError::NotFound.into())
+    }
+
+    async fn get_uncle_by_block_number_and_index(
+        &self,
+        block_number: u64,
+        uncle_index: u64,
+    ) -> Result<models::BlockWithTxHashes, JsonRpcProviderError> {
+        let request = (|| async {
+            self.provider
+                .client()
+                .request::<_, Option<models::BlockWithTxHashes>>(
+                    "eth_getUncleByBlockNumberAndIndex",
+                    (
+                        BlockNumberOrTag::Number(block_number),
+                        models::U64::from(uncle_index),
+                    ),
+                )
+                .await
+        })
+        .retry(self.options.exponential_backoff);
+
+        let Ok(response) = tokio::time::timeout(self.options.timeout, request).await else {
+            return Err(JsonRpcProviderError::Timeout)
+                .attach_printable("failed to get block uncle")
+                .attach_printable_lazy(|| format!("block number: {block_number}"))
+                .attach_printable_lazy(|| format!("uncle index: {uncle_index}"));
+        };
+
+        response
+            .change_context(JsonRpcProviderError::Request)?
+            .ok_or(JsonRpcProviderError::NotFound.into())
+    }
+
+    async fn trace_block_transactions(
         &self,
         block_id: BlockId,
     ) -> Result<Vec<models::TraceResultsWithTransactionHash>, JsonRpcProviderError> {
@@ -156,6 +602,67 @@ impl JsonRpcProvider {
 
         response.change_context(JsonRpcProviderError::Request)
     }
+
+    async fn debug_trace_block(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<models::CallFrameResult>, JsonRpcProviderError> {
+        let options = serde_json::json!({ "tracer": "callTracer" });
+
+        let request = (|| async {
+            match block_id {
+                BlockId::Number(number) => {
+                    self.provider
+                        .client()
+                        .request::<_, Vec<models::CallFrameResult>>(
+                            "debug_traceBlockByNumber",
+                            (number, options.clone()),
+                        )
+                        .await
+                }
+                BlockId::Hash(hash) => {
+                    let hash = BlockHash::from(hash);
+                    self.provider
+                        .client()
+                        .request::<_, Vec<models::CallFrameResult>>(
+                            "debug_traceBlockByHash",
+                            (hash, options.clone()),
+                        )
+                        .await
+                }
+            }
+        })
+        .retry(self.options.exponential_backoff);
+
+        let Ok(response) = tokio::time::timeout(self.options.timeout, request).await else {
+            return Err(JsonRpcProviderError::Timeout)
+                .attach_printable("failed to get block call traces")
+                .attach_printable_lazy(|| format!("block id: {block_id:?}"));
+        };
+
+        response.change_context(JsonRpcProviderError::Request)
+    }
+
+    async fn trace_block_state_diffs(
+        &self,
+        block_id: BlockId,
+    ) -> Result<Vec<models::TraceResultsWithTransactionHash>, JsonRpcProviderError> {
+        let request = (|| async {
+            self.provider
+                .trace_replay_block_transactions(block_id)
+                .state_diff()
+                .await
+        })
+        .retry(self.options.exponential_backoff);
+
+        let Ok(response) = tokio::time::timeout(self.options.timeout, request).await else {
+            return Err(JsonRpcProviderError::Timeout)
+                .attach_printable("failed to get block state diffs")
+                .attach_printable_lazy(|| format!("block id: {block_id:?}"));
+        };
+
+        response.change_context(JsonRpcProviderError::Request)
+    }
 }
 
 impl error_stack::Context for JsonRpcProviderError {}
@@ -171,6 +678,15 @@ impl std::fmt::Display for JsonRpcProviderError {
     }
 }
 
+impl UpstreamError for JsonRpcProviderError {
+    fn is_upstream_failure(&self) -> bool {
+        matches!(
+            self,
+            JsonRpcProviderError::Request | JsonRpcProviderError::Timeout
+        )
+    }
+}
+
 impl JsonRpcProviderErrorExt for Report<JsonRpcProviderError> {
     fn is_not_found(&self) -> bool {
         matches!(self.current_context(), JsonRpcProviderError::NotFound)
@@ -2,19 +2,27 @@ pub use alloy_consensus::{
     EthereumTxEnvelope, Signed, TxEip1559, TxEip2930, TxEip4844, TxEip4844Variant,
     TxEip4844WithSidecar, TxEip7702, TxEnvelope, TxLegacy,
 };
-pub use alloy_primitives::{Address, Bloom, ChainId, Signature, TxKind, B256, U128, U256};
+pub use alloy_eips::eip7702::SignedAuthorization;
+pub use alloy_primitives::{
+    Address, Bloom, Bytes, ChainId, Signature, TxKind, B256, U128, U256, U64,
+};
 pub use alloy_rpc_types::{
     AccessList, AccessListItem, Block, Header, Log, Transaction, TransactionReceipt, Withdrawal,
 };
+pub use alloy_rpc_types_trace::common::TraceResult;
+pub use alloy_rpc_types_trace::geth::CallFrame;
 pub use alloy_rpc_types_trace::parity::{
-    Action, CallAction, CallOutput, CallType, CreateAction, CreateOutput, RewardAction, RewardType,
-    SelfdestructAction, TraceOutput, TraceResults, TraceResultsWithTransactionHash,
-    TransactionTrace,
+    AccountDiff, Action, CallAction, CallOutput, CallType, ChangedType, CreateAction, CreateOutput,
+    CreationMethod, Delta, RewardAction, RewardType, SelfdestructAction, StateDiff, TraceOutput,
+    TraceResults, TraceResultsWithTransactionHash, TransactionTrace,
 };
 use apibara_dna_common::{chain::BlockInfo, Cursor, Hash};
 
 pub type BlockWithTxHashes = Block<B256>;
 
+/// The result of tracing a transaction with Geth's `callTracer`.
+pub type CallFrameResult = TraceResult<CallFrame, String>;
+
 pub trait BlockExt {
     fn cursor(&self) -> Cursor;
     fn block_info(&self) -> BlockInfo;
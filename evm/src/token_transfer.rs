@@ -0,0 +1,338 @@
+//! Decode token and native currency transfers.
+//!
+//! Token transfers are decoded from the standard ERC-20, ERC-721 and ERC-1155 events.
+//! Logs that look like transfer events but are malformed are ignored.
+use alloy_primitives::{b256, B256, U256};
+use apibara_dna_protocol::evm;
+
+/// `Transfer(address,address,uint256)`, shared by ERC-20 and ERC-721.
+const TRANSFER_TOPIC: B256 =
+    b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+/// `TransferSingle(address,address,address,uint256,uint256)`.
+const TRANSFER_SINGLE_TOPIC: B256 =
+    b256!("c3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62");
+/// `TransferBatch(address,address,address,uint256[],uint256[])`.
+const TRANSFER_BATCH_TOPIC: B256 =
+    b256!("4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb");
+
+/// Returns the token transfers emitted by the log.
+///
+/// ERC-20 and ERC-721 transfers share the same signature and are told apart by the
+/// number of indexed topics.
+pub fn decode_log_transfers(log: &evm::Log) -> Vec<evm::TokenTransfer> {
+    let Some(token) = log.address else {
+        return Vec::default();
+    };
+
+    let topics = log
+        .topics
+        .iter()
+        .map(|topic| B256::from(topic.to_bytes()))
+        .collect::<Vec<_>>();
+
+    let Some(topic0) = topics.first() else {
+        return Vec::default();
+    };
+
+    if *topic0 == TRANSFER_TOPIC && topics.len() == 3 {
+        let Some(amount) = word(&log.data, 0) else {
+            return Vec::default();
+        };
+
+        return vec![new_transfer(
+            evm::TokenStandard::Erc20,
+            Some(token),
+            topic_address(&topics[1]),
+            topic_address(&topics[2]),
+            evm::U256::from_bytes(&amount),
+            None,
+        )];
+    }
+
+    if *topic0 == TRANSFER_TOPIC && topics.len() == 4 {
+        return vec![new_transfer(
+            evm::TokenStandard::Erc721,
+            Some(token),
+            topic_address(&topics[1]),
+            topic_address(&topics[2]),
+            evm::U256::from_bytes(&U256::from(1).to_be_bytes()),
+            Some(evm::U256::from_bytes(&topics[3].0)),
+        )];
+    }
+
+    if *topic0 == TRANSFER_SINGLE_TOPIC && topics.len() == 4 {
+        let (Some(token_id), Some(amount)) = (word(&log.data, 0), word(&log.data, 1)) else {
+            return Vec::default();
+        };
+
+        return vec![new_transfer(
+            evm::TokenStandard::Erc1155,
+            Some(token),
+            topic_address(&topics[2]),
+            topic_address(&topics[3]),
+            evm::U256::from_bytes(&amount),
+            Some(evm::U256::from_bytes(&token_id)),
+        )];
+    }
+
+    if *topic0 == TRANSFER_BATCH_TOPIC && topics.len() == 4 {
+        let (Some(token_ids), Some(amounts)) = (array(&log.data, 0), array(&log.data, 1)) else {
+            return Vec::default();
+        };
+
+        if token_ids.len() != amounts.len() {
+            return Vec::default();
+        }
+
+        let from = topic_address(&topics[2]);
+        let to = topic_address(&topics[3]);
+
+        return token_ids
+            .iter()
+            .zip(amounts.iter())
+            .map(|(token_id, amount)| {
+                new_transfer(
+                    evm::TokenStandard::Erc1155,
+                    Some(token),
+                    from,
+                    to,
+                    evm::U256::from_bytes(amount),
+                    Some(evm::U256::from_bytes(token_id)),
+                )
+            })
+            .collect();
+    }
+
+    Vec::default()
+}
+
+/// Returns the native currency transfers in the transaction's traces.
+///
+/// Calls that failed, or that are nested inside a failed call, are skipped since their
+/// value transfer was reverted.
+pub fn decode_trace_transfers(traces: &[evm::Trace]) -> Vec<evm::TokenTransfer> {
+    let mut transfers = Vec::new();
+    let mut reverted: Vec<&[u32]> = Vec::new();
+
+    for trace in traces.iter() {
+        if reverted
+            .iter()
+            .any(|address| trace.trace_address.starts_with(address))
+        {
+            continue;
+        }
+
+        if trace.error.is_some() {
+            reverted.push(&trace.trace_address);
+            continue;
+        }
+
+        let transfer = match trace.action.as_ref() {
+            // Other call types don't move value between accounts.
+            Some(evm::trace::Action::Call(call)) if call.r#type == evm::CallType::Call as i32 => {
+                native_transfer(call.from_address, call.to_address, call.value)
+            }
+            Some(evm::trace::Action::Create(create)) => {
+                let created_address = match trace.output.as_ref() {
+                    Some(evm::trace::Output::CreateOutput(output)) => output.address,
+                    _ => None,
+                };
+                native_transfer(create.from_address, created_address, create.value)
+            }
+            Some(evm::trace::Action::SelfDestruct(self_destruct)) => native_transfer(
+                self_destruct.address,
+                self_destruct.refund_address,
+                self_destruct.balance,
+            ),
+            _ => None,
+        };
+
+        transfers.extend(transfer);
+    }
+
+    transfers
+}
+
+/// Returns the native currency transfer of a transaction, for blocks without traces.
+///
+/// This only includes the value sent by the transaction itself, not by internal calls.
+pub fn decode_transaction_transfer(
+    transaction: &evm::Transaction,
+    receipt: &evm::TransactionReceipt,
+) -> Option<evm::TokenTransfer> {
+    let to = transaction.to.or(receipt.contract_address);
+    native_transfer(transaction.from, to, transaction.value)
+}
+
+fn native_transfer(
+    from: Option<evm::Address>,
+    to: Option<evm::Address>,
+    value: Option<evm::U256>,
+) -> Option<evm::TokenTransfer> {
+    let (from, to, value) = (from?, to?, value?);
+
+    if value.to_bytes() == [0; 32] {
+        return None;
+    }
+
+    Some(new_transfer(
+        evm::TokenStandard::Native,
+        None,
+        from,
+        to,
+        value,
+        None,
+    ))
+}
+
+fn new_transfer(
+    standard: evm::TokenStandard,
+    token: Option<evm::Address>,
+    from: evm::Address,
+    to: evm::Address,
+    amount: evm::U256,
+    token_id: Option<evm::U256>,
+) -> evm::TokenTransfer {
+    evm::TokenTransfer {
+        filter_ids: Vec::default(),
+        token_transfer_index: u32::MAX,
+        transaction_index: u32::MAX,
+        transaction_hash: None,
+        standard: standard as i32,
+        token,
+        from: Some(from),
+        to: Some(to),
+        amount: Some(amount),
+        token_id,
+        log_index: None,
+    }
+}
+
+fn topic_address(topic: &B256) -> evm::Address {
+    let mut address = [0; 20];
+    address.copy_from_slice(&topic[12..]);
+    evm::Address::from_bytes(&address)
+}
+
+/// Returns the n-th 32 bytes word of the ABI-encoded data.
+fn word(data: &[u8], index: usize) -> Option<[u8; 32]> {
+    let start = index.checked_mul(32)?;
+    let end = start.checked_add(32)?;
+    data.get(start..end)?.try_into().ok()
+}
+
+/// Returns the dynamic `uint256[]` array whose offset is stored in the n-th word.
+fn array(data: &[u8], index: usize) -> Option<Vec<[u8; 32]>> {
+    let offset = usize::try_from(U256::from_be_bytes(word(data, index)?)).ok()?;
+    let data = data.get(offset..)?;
+
+    let len = usize::try_from(U256::from_be_bytes(word(data, 0)?)).ok()?;
+    // Check the length before allocating, the log data is untrusted.
+    if len.checked_add(1)?.checked_mul(32)? > data.len() {
+        return None;
+    }
+
+    (1..=len).map(|index| word(data, index)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{hex, B256, U256};
+    use apibara_dna_protocol::evm;
+
+    use super::{decode_log_transfers, TRANSFER_BATCH_TOPIC, TRANSFER_TOPIC};
+
+    fn address_topic(byte: u8) -> evm::B256 {
+        let mut topic = [0; 32];
+        topic[31] = byte;
+        evm::B256::from_bytes(&topic)
+    }
+
+    fn log(topics: Vec<evm::B256>, data: Vec<u8>) -> evm::Log {
+        evm::Log {
+            address: Some(evm::Address::from_bytes(&[0xaa; 20])),
+            topics,
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn uint(value: u64) -> [u8; 32] {
+        U256::from(value).to_be_bytes()
+    }
+
+    fn topic(topic: B256) -> evm::B256 {
+        evm::B256::from_bytes(&topic.0)
+    }
+
+    #[test]
+    fn test_decode_erc20_and_erc721_transfer() {
+        let erc20 = log(
+            vec![topic(TRANSFER_TOPIC), address_topic(1), address_topic(2)],
+            uint(100).to_vec(),
+        );
+
+        let transfers = decode_log_transfers(&erc20);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].standard, evm::TokenStandard::Erc20 as i32);
+        assert_eq!(transfers[0].amount, Some(evm::U256::from_bytes(&uint(100))));
+        assert!(transfers[0].token_id.is_none());
+
+        let erc721 = log(
+            vec![
+                topic(TRANSFER_TOPIC),
+                address_topic(1),
+                address_topic(2),
+                evm::B256::from_bytes(&uint(7)),
+            ],
+            Vec::default(),
+        );
+
+        let transfers = decode_log_transfers(&erc721);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].standard, evm::TokenStandard::Erc721 as i32);
+        assert_eq!(transfers[0].token_id, Some(evm::U256::from_bytes(&uint(7))));
+        assert_eq!(
+            transfers[0].to,
+            Some(evm::Address::from_bytes(&{
+                let mut address = [0; 20];
+                address[19] = 2;
+                address
+            }))
+        );
+    }
+
+    #[test]
+    fn test_decode_erc1155_transfer_batch() {
+        let data = hex::decode(concat!(
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "000000000000000000000000000000000000000000000000000000000000000a",
+            "0000000000000000000000000000000000000000000000000000000000000014",
+        ))
+        .unwrap();
+
+        let batch = log(
+            vec![
+                topic(TRANSFER_BATCH_TOPIC),
+                address_topic(9),
+                address_topic(1),
+                address_topic(2),
+            ],
+            data.clone(),
+        );
+
+        let transfers = decode_log_transfers(&batch);
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[1].token_id, Some(evm::U256::from_bytes(&uint(2))));
+        assert_eq!(transfers[1].amount, Some(evm::U256::from_bytes(&uint(20))));
+
+        // Truncated data is ignored.
+        let truncated = log(batch.topics.clone(), data[..data.len() - 32].to_vec());
+        assert!(decode_log_transfers(&truncated).is_empty());
+    }
+}
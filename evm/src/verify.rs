@@ -0,0 +1,249 @@
+//! Verify the data returned by the RPC against the block header.
+//!
+//! RPC providers sometimes return incomplete receipts or logs from a different fork. The header
+//! commits to the block's transactions, receipts and logs, so we can detect these issues by
+//! recomputing the commitments locally.
+use alloy_consensus::proofs::ordered_trie_root_with_encoder;
+use alloy_eips::eip2718::Encodable2718;
+use apibara_dna_common::ingestion::IngestionError;
+use error_stack::{Result, ResultExt};
+
+use crate::provider::models;
+
+/// Check that the transactions and receipts match the commitments in the block header.
+///
+/// Notice that this only works for chains that use the same transaction and receipt encoding
+/// as Ethereum mainnet.
+pub fn verify_block_data(
+    header: &models::Header,
+    transactions: &[models::Transaction],
+    receipts: &[models::TransactionReceipt],
+) -> Result<(), IngestionError> {
+    if transactions.len() != receipts.len() {
+        return Err(IngestionError::Verification)
+            .attach_printable("transactions and receipts count mismatch")
+            .attach_printable_lazy(|| format!("transactions: {}", transactions.len()))
+            .attach_printable_lazy(|| format!("receipts: {}", receipts.len()));
+    }
+
+    for (transaction, receipt) in transactions.iter().zip(receipts) {
+        if transaction.inner.tx_hash() != &receipt.transaction_hash {
+            return Err(IngestionError::Verification)
+                .attach_printable("receipt does not match transaction")
+                .attach_printable_lazy(|| {
+                    format!("transaction hash: {}", transaction.inner.tx_hash())
+                })
+                .attach_printable_lazy(|| {
+                    format!("receipt transaction hash: {}", receipt.transaction_hash)
+                });
+        }
+    }
+
+    let envelopes = transactions
+        .iter()
+        .map(|transaction| transaction.inner.inner())
+        .collect::<Vec<_>>();
+    let transactions_root = ordered_trie_root_with_encoder(&envelopes, |tx, buf| {
+        tx.encode_2718(buf);
+    });
+
+    if transactions_root != header.transactions_root {
+        return Err(IngestionError::Verification)
+            .attach_printable("transactions root mismatch")
+            .attach_printable_lazy(|| format!("expected: {}", header.transactions_root))
+            .attach_printable_lazy(|| format!("actual: {}", transactions_root));
+    }
+
+    let receipt_envelopes = receipts
+        .iter()
+        .map(|receipt| receipt.inner.clone().map_logs(|log| log.inner))
+        .collect::<Vec<_>>();
+    let receipts_root = ordered_trie_root_with_encoder(&receipt_envelopes, |receipt, buf| {
+        receipt.encode_2718(buf);
+    });
+
+    if receipts_root != header.receipts_root {
+        return Err(IngestionError::Verification)
+            .attach_printable("receipts root mismatch")
+            .attach_printable_lazy(|| format!("expected: {}", header.receipts_root))
+            .attach_printable_lazy(|| format!("actual: {}", receipts_root));
+    }
+
+    // The receipts root already covers the logs, rebuilding the bloom from the logs is a cheap
+    // extra check that the logs belong to this block.
+    let mut logs_bloom = models::Bloom::ZERO;
+    for log in receipts.iter().flat_map(|receipt| receipt.inner.logs()) {
+        logs_bloom.accrue_log(&log.inner);
+    }
+
+    if logs_bloom != header.logs_bloom {
+        return Err(IngestionError::Verification)
+            .attach_printable("logs bloom mismatch")
+            .attach_printable_lazy(|| format!("block number: {}", header.number));
+    }
+
+    Ok(())
+}
+
+/// Check that the transaction traces belong to the block's transactions.
+///
+/// Traces fetched by block number may belong to another block if the chain reorged after
+/// the block was fetched.
+pub fn verify_transaction_traces(
+    transactions: &[models::Transaction],
+    traces: &[models::TraceResultsWithTransactionHash],
+) -> Result<(), IngestionError> {
+    if transactions.len() != traces.len() {
+        return Err(IngestionError::Verification)
+            .attach_printable("transactions and traces count mismatch")
+            .attach_printable_lazy(|| format!("transactions: {}", transactions.len()))
+            .attach_printable_lazy(|| format!("traces: {}", traces.len()));
+    }
+
+    for (transaction, trace) in transactions.iter().zip(traces) {
+        if transaction.inner.tx_hash() != &trace.transaction_hash {
+            return Err(IngestionError::Verification)
+                .attach_printable("trace does not match transaction")
+                .attach_printable_lazy(|| {
+                    format!("transaction hash: {}", transaction.inner.tx_hash())
+                })
+                .attach_printable_lazy(|| {
+                    format!("trace transaction hash: {}", trace.transaction_hash)
+                });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use apibara_dna_common::ingestion::IngestionError;
+    use error_stack::Report;
+    use serde_json::Value;
+
+    use super::{verify_block_data, verify_transaction_traces};
+    use crate::{call_tracer::flatten_block_call_frames, provider::models};
+
+    const BLOCK: &str =
+        include_str!("../tests/fixtures/get_block_with_transactions/1000000001.json");
+    const RECEIPTS: &str = include_str!(
+        "../tests/fixtures/get_block_receipts/0x0bce7b859199014bd4724a8c45faecada3285c4c73bed48984b7e88ce9e1453a.json"
+    );
+    const TRACES: &str = include_str!("../tests/fixtures/debug_trace_block/1000000001.json");
+
+    /// Verify the recorded block after applying `tamper` to its JSON representation.
+    fn verify(tamper: impl FnOnce(&mut Value, &mut Value)) -> Result<(), Report<IngestionError>> {
+        let mut block: Value = serde_json::from_str(BLOCK).unwrap();
+        let mut receipts: Value = serde_json::from_str(RECEIPTS).unwrap();
+
+        tamper(&mut block, &mut receipts);
+
+        let block: models::Block = serde_json::from_value(block).unwrap();
+        let receipts: Vec<models::TransactionReceipt> = serde_json::from_value(receipts).unwrap();
+        let transactions = block.transactions.as_transactions().unwrap();
+
+        verify_block_data(&block.header, transactions, &receipts)
+    }
+
+    fn assert_verification_error(result: Result<(), Report<IngestionError>>, message: &str) {
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            IngestionError::Verification
+        ));
+        assert!(
+            format!("{err:?}").contains(message),
+            "expected `{message}` in {err:?}"
+        );
+    }
+
+    #[test]
+    fn test_valid_block() {
+        verify(|_, _| {}).unwrap();
+    }
+
+    #[test]
+    fn test_missing_receipt() {
+        let result = verify(|_, receipts| {
+            receipts.as_array_mut().unwrap().pop();
+        });
+        assert_verification_error(result, "transactions and receipts count mismatch");
+    }
+
+    #[test]
+    fn test_receipt_of_another_transaction() {
+        let result = verify(|_, receipts| {
+            receipts.as_array_mut().unwrap().swap(0, 1);
+        });
+        assert_verification_error(result, "receipt does not match transaction");
+    }
+
+    #[test]
+    fn test_reordered_transactions() {
+        let result = verify(|block, receipts| {
+            block["transactions"].as_array_mut().unwrap().swap(0, 1);
+            receipts.as_array_mut().unwrap().swap(0, 1);
+        });
+        assert_verification_error(result, "transactions root mismatch");
+    }
+
+    #[test]
+    fn test_tampered_receipt() {
+        let result = verify(|_, receipts| {
+            receipts[0]["status"] = "0x0".into();
+        });
+        assert_verification_error(result, "receipts root mismatch");
+    }
+
+    #[test]
+    fn test_tampered_log() {
+        let result = verify(|_, receipts| {
+            receipts[1]["logs"][0]["data"] = format!("0x{:064x}", 1).into();
+        });
+        assert_verification_error(result, "receipts root mismatch");
+    }
+
+    #[test]
+    fn test_tampered_logs_bloom() {
+        let result = verify(|block, _| {
+            block["logsBloom"] = format!("0x{}", "0".repeat(512)).into();
+        });
+        assert_verification_error(result, "logs bloom mismatch");
+    }
+
+    /// Verify the recorded traces after applying `tamper` to the flattened traces.
+    fn verify_traces(
+        tamper: impl FnOnce(&mut Vec<models::TraceResultsWithTransactionHash>),
+    ) -> Result<(), Report<IngestionError>> {
+        let block: models::Block = serde_json::from_str(BLOCK).unwrap();
+        let traces: Vec<models::CallFrameResult> = serde_json::from_str(TRACES).unwrap();
+        let mut traces = flatten_block_call_frames(traces).unwrap();
+
+        tamper(&mut traces);
+
+        let transactions = block.transactions.as_transactions().unwrap();
+        verify_transaction_traces(transactions, &traces)
+    }
+
+    #[test]
+    fn test_valid_traces() {
+        verify_traces(|_| {}).unwrap();
+    }
+
+    #[test]
+    fn test_missing_trace() {
+        let result = verify_traces(|traces| {
+            traces.pop();
+        });
+        assert_verification_error(result, "transactions and traces count mismatch");
+    }
+
+    #[test]
+    fn test_trace_of_another_transaction() {
+        let result = verify_traces(|traces| {
+            traces.swap(0, 1);
+        });
+        assert_verification_error(result, "trace does not match transaction");
+    }
+}
@@ -0,0 +1,156 @@
+//! Check that ingesting blocks from Era1 files produces the same data as the RPC.
+//!
+//! The Era1 files in `evm/tests/fixtures/era1` must contain at least one of the blocks in
+//! `evm/tests/fixtures`.
+//!
+//! The committed Era1 file is synthetic: it's generated from the RPC fixtures of the synthetic
+//! pre-merge block by `test_synthetic_era1_file`. Regenerate it with:
+//!
+//! ```sh
+//! DNA_UPDATE_GOLDEN=1 cargo test -p apibara-dna-evm --test test_era1
+//! ```
+use std::{io::Write, path::PathBuf};
+
+use alloy_consensus::{BlockBody, ReceiptEnvelope, TxEnvelope};
+use alloy_rlp::Encodable;
+use apibara_dna_common::{
+    fixture::{assert_golden, FixtureStore},
+    ingestion::BlockIngestion,
+};
+use apibara_dna_evm::{
+    era1::{e2store, Era1Archive},
+    ingestion::{ChainFlavour, EvmBlockIngestion, EvmBlockIngestionOptions, TraceBackend},
+    provider::{models, BlockId, JsonRpcProvider},
+};
+
+/// The synthetic pre-merge block stored in the Era1 file.
+const SYNTHETIC_BLOCK_NUMBER: u64 = 1_000_000_000;
+const SYNTHETIC_ERA1_FILE: &str = "era1/synthetic-122070-e7b4b4d5.era1";
+
+fn entry(entry_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&entry_type.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(data);
+    out
+}
+
+fn compressed_entry(entry_type: u16, value: &impl Encodable) -> Vec<u8> {
+    let mut data = Vec::new();
+    value.encode(&mut data);
+
+    let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+    encoder.write_all(&data).unwrap();
+    entry(entry_type, &encoder.into_inner().unwrap())
+}
+
+/// Encode a block returned by the RPC as an Era1 file that contains only that block.
+///
+/// The accumulator is not computed, it's all zeros.
+fn era1_file(block: models::Block, receipts: Vec<models::TransactionReceipt>) -> Vec<u8> {
+    let header = block.header.inner;
+    let total_difficulty = block.header.total_difficulty.unwrap_or_default();
+    let body = BlockBody::<TxEnvelope> {
+        transactions: block
+            .transactions
+            .into_transactions()
+            .map(|transaction| transaction.inner.into_inner())
+            .collect(),
+        ommers: Vec::new(),
+        withdrawals: None,
+    };
+    let receipts = receipts
+        .into_iter()
+        .map(|receipt| receipt.inner.map_logs(|log| log.inner))
+        .collect::<Vec<ReceiptEnvelope>>();
+
+    let mut file = entry(e2store::VERSION, &[]);
+    let block_offset = file.len() as i64;
+    file.extend(compressed_entry(e2store::COMPRESSED_HEADER, &header));
+    file.extend(compressed_entry(e2store::COMPRESSED_BODY, &body));
+    file.extend(compressed_entry(e2store::COMPRESSED_RECEIPTS, &receipts));
+    file.extend(entry(
+        e2store::TOTAL_DIFFICULTY,
+        &total_difficulty.to_le_bytes::<32>(),
+    ));
+    file.extend(entry(e2store::ACCUMULATOR, &[0; 32]));
+
+    let index_offset = file.len() as i64;
+    let mut index = Vec::new();
+    index.extend_from_slice(&header.number.to_le_bytes());
+    index.extend_from_slice(&(block_offset - index_offset).to_le_bytes());
+    index.extend_from_slice(&1u64.to_le_bytes());
+    file.extend(entry(e2store::BLOCK_INDEX, &index));
+
+    file
+}
+
+#[tokio::test]
+async fn test_synthetic_era1_file() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let provider = JsonRpcProvider::replay(FixtureStore::new(&dir));
+
+    let block = provider
+        .get_block_with_transactions(BlockId::number(SYNTHETIC_BLOCK_NUMBER))
+        .await
+        .unwrap();
+    let receipts = provider
+        .get_block_receipts(BlockId::hash(block.header.hash))
+        .await
+        .unwrap();
+
+    assert_golden(dir.join(SYNTHETIC_ERA1_FILE), &era1_file(block, receipts));
+}
+
+#[tokio::test]
+async fn test_era1_matches_rpc() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let fixtures = FixtureStore::new(&dir);
+    let era1 = Era1Archive::open(dir.join("era1")).await.unwrap();
+    assert!(era1.block_range().is_some(), "no era1 files in fixtures");
+
+    let options = EvmBlockIngestionOptions {
+        ingest_pending: false,
+        ingest_traces: false,
+        trace_backend: TraceBackend::Parity,
+        ingest_state_diffs: false,
+        ingest_uncles: false,
+        chain_flavour: ChainFlavour::Ethereum,
+        verify_block_data: true,
+        head_subscription: None,
+    };
+
+    let rpc_ingestion =
+        EvmBlockIngestion::new(JsonRpcProvider::replay(fixtures.clone()), options.clone());
+    let era1_ingestion = EvmBlockIngestion::new(JsonRpcProvider::replay(fixtures.clone()), options)
+        .with_era1(era1.clone());
+
+    let blocks = fixtures.keys("get_block_with_transactions").await.unwrap();
+    let blocks = blocks
+        .iter()
+        .filter_map(|key| key.parse::<u64>().ok())
+        .filter(|block_number| era1.contains(*block_number))
+        .collect::<Vec<_>>();
+    assert!(!blocks.is_empty(), "no recorded blocks in the era1 files");
+
+    for block_number in blocks {
+        let (rpc_info, rpc_block) = rpc_ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .unwrap();
+        let (era1_info, era1_block) = era1_ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .unwrap();
+
+        assert_eq!(rpc_info, era1_info);
+
+        let rpc_data = rkyv::to_bytes::<rkyv::rancor::Error>(&rpc_block).unwrap();
+        let era1_data = rkyv::to_bytes::<rkyv::rancor::Error>(&era1_block).unwrap();
+        assert!(
+            rpc_data.as_slice() == era1_data.as_slice(),
+            "era1 block {block_number} differs from rpc"
+        );
+    }
+}
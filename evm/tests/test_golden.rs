@@ -0,0 +1,87 @@
+//! Ingest the blocks in `tests/fixtures` and compare them with the golden files.
+//!
+//! The committed blocks are synthetic, see `tests/fixtures/README.md`. Record real blocks with:
+//!
+//! ```sh
+//! apibara-dna-evm dbg-rpc record --fixtures-dir evm/tests/fixtures --from-block N --to-block M
+//! DNA_UPDATE_GOLDEN=1 cargo test -p apibara-dna-evm --test test_golden
+//! ```
+//!
+//! Blocks are verified against their header, so the recorded transactions and receipts must
+//! match the block's roots.
+use std::path::PathBuf;
+
+use apibara_dna_common::{
+    fixture::{assert_golden, FixtureStore},
+    ingestion::BlockIngestion,
+};
+use apibara_dna_evm::{
+    ingestion::{ChainFlavour, EvmBlockIngestion, EvmBlockIngestionOptions, TraceBackend},
+    provider::JsonRpcProvider,
+};
+
+#[tokio::test]
+async fn test_golden_blocks() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let fixtures = FixtureStore::new(&dir);
+
+    let blocks = fixtures.keys("get_block_with_transactions").await.unwrap();
+    assert!(
+        !blocks.is_empty(),
+        "no blocks recorded in {}",
+        dir.display()
+    );
+
+    let ingest_parity_traces = !fixtures
+        .keys("trace_block_transactions")
+        .await
+        .unwrap()
+        .is_empty();
+    let ingest_geth_traces = !fixtures.keys("debug_trace_block").await.unwrap().is_empty();
+    let ingest_traces = ingest_parity_traces || ingest_geth_traces;
+    let trace_backend = if ingest_parity_traces {
+        TraceBackend::Parity
+    } else {
+        TraceBackend::Geth
+    };
+    let ingest_state_diffs = !fixtures
+        .keys("trace_block_state_diffs")
+        .await
+        .unwrap()
+        .is_empty();
+    let ingest_uncles = !fixtures
+        .keys("get_uncle_by_block_number_and_index")
+        .await
+        .unwrap()
+        .is_empty();
+
+    let ingestion = EvmBlockIngestion::new(
+        JsonRpcProvider::replay(fixtures),
+        EvmBlockIngestionOptions {
+            ingest_pending: false,
+            ingest_traces,
+            trace_backend,
+            ingest_state_diffs,
+            ingest_uncles,
+            chain_flavour: ChainFlavour::Ethereum,
+            verify_block_data: true,
+            head_subscription: None,
+        },
+    );
+
+    for key in blocks {
+        let block_number = key
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("invalid block fixture key: {key}"));
+        let (block_info, block) = ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .unwrap_or_else(|err| panic!("failed to ingest block {block_number}: {err:?}"));
+
+        assert_eq!(block_info.number, block_number);
+        assert!(!block.body.is_empty());
+
+        let data = rkyv::to_bytes::<rkyv::rancor::Error>(&block).unwrap();
+        assert_golden(dir.join(format!("golden/{block_number}.bin")), &data);
+    }
+}
@@ -0,0 +1,210 @@
+//! Check that the Geth call tracer produces the same traces as Parity.
+//!
+//! The fixtures contain the traces of the synthetic blocks from both APIs. Record real blocks
+//! from a node that supports both APIs with:
+//!
+//! ```sh
+//! apibara-dna-evm dbg-rpc record --fixtures-dir evm/tests/fixtures --from-block N --to-block M --ingest-traces
+//! apibara-dna-evm dbg-rpc record --fixtures-dir evm/tests/fixtures --from-block N --to-block M --ingest-traces --trace-backend geth
+//! ```
+use std::path::PathBuf;
+
+use apibara_dna_common::{fixture::FixtureStore, ingestion::BlockIngestion};
+use apibara_dna_evm::{
+    call_tracer::flatten_block_call_frames,
+    fragment::CALL_TRACE_FRAGMENT_ID,
+    ingestion::{ChainFlavour, EvmBlockIngestion, EvmBlockIngestionOptions, TraceBackend},
+    proto::ModelExt,
+    provider::{models, BlockId, JsonRpcProvider},
+};
+use apibara_dna_protocol::evm;
+use prost::Message;
+
+const BLOCK_NUMBER: u64 = 1_000_000_001;
+
+fn fixtures() -> FixtureStore {
+    FixtureStore::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"))
+}
+
+fn address(hex: &str) -> Option<evm::Address> {
+    Some(hex.parse::<models::Address>().unwrap().to_proto())
+}
+
+#[tokio::test]
+async fn test_geth_traces_match_parity() {
+    let fixtures = fixtures();
+
+    let parity_blocks = fixtures.keys("trace_block_transactions").await.unwrap();
+    let geth_blocks = fixtures.keys("debug_trace_block").await.unwrap();
+
+    let provider = JsonRpcProvider::replay(fixtures);
+
+    // Geth traces are recorded by block number, Parity traces by block hash.
+    let mut blocks = Vec::new();
+    for key in geth_blocks {
+        let block_number = key.parse::<u64>().unwrap();
+        let block = provider
+            .get_block_with_transactions(BlockId::number(block_number))
+            .await
+            .unwrap();
+        if parity_blocks.contains(&block.header.hash.to_string()) {
+            blocks.push((block_number, block.header.hash));
+        }
+    }
+    assert!(!blocks.is_empty(), "no blocks recorded with both APIs");
+
+    for (block_number, block_hash) in blocks {
+        let parity = provider
+            .trace_block_transactions(BlockId::hash(block_hash))
+            .await
+            .unwrap();
+        let geth = provider
+            .debug_trace_block(BlockId::number(block_number))
+            .await
+            .unwrap();
+        let geth = flatten_block_call_frames(geth).unwrap();
+
+        assert_eq!(parity.len(), geth.len(), "block {block_hash}");
+
+        for (parity, geth) in parity.iter().zip(geth.iter()) {
+            let transaction_hash = parity.transaction_hash;
+            assert_eq!(transaction_hash, geth.transaction_hash);
+
+            let parity = &parity.full_trace.trace;
+            let geth = &geth.full_trace.trace;
+
+            assert_eq!(parity.len(), geth.len(), "{transaction_hash}");
+
+            for (parity, geth) in parity.iter().zip(geth.iter()) {
+                assert_eq!(parity.trace_address, geth.trace_address);
+                assert_eq!(parity.subtraces, geth.subtraces);
+                assert_eq!(parity.error.is_some(), geth.error.is_some());
+                assert_eq!(
+                    without_gas(&parity.action),
+                    without_gas(&geth.action),
+                    "{transaction_hash} {:?}",
+                    parity.trace_address
+                );
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ingest_geth_call_traces() {
+    let ingestion = EvmBlockIngestion::new(
+        JsonRpcProvider::replay(fixtures()),
+        EvmBlockIngestionOptions {
+            ingest_pending: false,
+            ingest_traces: true,
+            trace_backend: TraceBackend::Geth,
+            ingest_state_diffs: false,
+            ingest_uncles: false,
+            chain_flavour: ChainFlavour::Ethereum,
+            verify_block_data: false,
+            head_subscription: None,
+        },
+    );
+
+    let (_, block) = ingestion
+        .ingest_block_by_number(BLOCK_NUMBER)
+        .await
+        .unwrap();
+
+    let fragment = block
+        .body
+        .iter()
+        .find(|fragment| fragment.fragment_id == CALL_TRACE_FRAGMENT_ID)
+        .unwrap();
+
+    let call_traces = fragment
+        .data
+        .iter()
+        .map(|data| evm::CallTrace::decode(data.as_slice()).unwrap())
+        .collect::<Vec<_>>();
+
+    let summary = call_traces
+        .iter()
+        .map(|call_trace| {
+            let trace = call_trace.trace.as_ref().unwrap();
+            (
+                call_trace.call_index,
+                call_trace.transaction_index,
+                trace.trace_address.clone(),
+                trace.subtraces,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            (0, 0, vec![], 0),
+            (1, 1, vec![], 1),
+            (2, 1, vec![0], 0),
+            (3, 2, vec![], 0),
+        ]
+    );
+
+    let actions = call_traces
+        .iter()
+        .map(|call_trace| call_trace.trace.as_ref().unwrap().action.clone().unwrap())
+        .collect::<Vec<_>>();
+
+    let evm::trace::Action::Call(transfer) = &actions[1] else {
+        panic!("expected call, got {:?}", actions[1]);
+    };
+    assert_eq!(transfer.r#type, evm::CallType::Call as i32);
+    assert_eq!(
+        transfer.to_address,
+        address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+    );
+    assert_eq!(&transfer.input[..4], &[0xa9, 0x05, 0x9c, 0xbb]);
+
+    let evm::trace::Action::Call(delegate) = &actions[2] else {
+        panic!("expected call, got {:?}", actions[2]);
+    };
+    assert_eq!(delegate.r#type, evm::CallType::DelegateCall as i32);
+    assert_eq!(
+        delegate.from_address,
+        address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+    );
+
+    let evm::trace::Action::Create(create) = &actions[3] else {
+        panic!("expected create, got {:?}", actions[3]);
+    };
+    assert_eq!(
+        create.from_address,
+        address("0x0f89f1868af3b14a5eca86ed9a5404a742a11454")
+    );
+
+    let Some(evm::trace::Output::CreateOutput(output)) =
+        call_traces[3].trace.as_ref().unwrap().output.as_ref()
+    else {
+        panic!("expected create output");
+    };
+    assert_eq!(
+        output.address,
+        address("0x6675ee558ca26c06ea6b06612a33c919ea1c993b")
+    );
+}
+
+/// Gas is reported differently by the two APIs, so ignore it.
+///
+/// Geth doesn't report the value of delegate calls, so ignore it too.
+fn without_gas(action: &models::Action) -> models::Action {
+    let mut action = action.clone();
+    match &mut action {
+        models::Action::Call(call) => {
+            call.gas = 0;
+            if call.call_type == models::CallType::DelegateCall {
+                call.value = Default::default();
+            }
+        }
+        models::Action::Create(create) => {
+            create.gas = 0;
+            create.creation_method = Default::default();
+        }
+        _ => {}
+    }
+    action
+}
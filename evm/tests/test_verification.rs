@@ -0,0 +1,98 @@
+//! Check that blocks that fail verification are fetched again before giving up.
+use std::{path::PathBuf, time::Duration};
+
+use apibara_dna_common::{
+    fixture::FixtureStore,
+    ingestion::{BlockIngestion, IngestionError},
+};
+use apibara_dna_evm::{
+    ingestion::{ChainFlavour, EvmBlockIngestion, EvmBlockIngestionOptions, TraceBackend},
+    provider::JsonRpcProvider,
+};
+use serde_json::Value;
+
+const BLOCK_NUMBER: u64 = 1_000_000_001;
+const BLOCK_HASH: &str = "0x0bce7b859199014bd4724a8c45faecada3285c4c73bed48984b7e88ce9e1453a";
+
+/// Copy the recorded block to `dir` and return the path and content of its receipts.
+async fn copy_fixtures(dir: &std::path::Path) -> (PathBuf, Vec<u8>) {
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+    for method in ["get_block_with_transactions", "get_block_receipts"] {
+        tokio::fs::create_dir_all(dir.join(method)).await.unwrap();
+    }
+
+    let block = format!("get_block_with_transactions/{BLOCK_NUMBER}.json");
+    tokio::fs::copy(source.join(&block), dir.join(&block))
+        .await
+        .unwrap();
+
+    let receipts = format!("get_block_receipts/{BLOCK_HASH}.json");
+    let data = tokio::fs::read(source.join(&receipts)).await.unwrap();
+
+    (dir.join(receipts), data)
+}
+
+/// Returns the receipts with the data of the first log changed.
+fn tampered(receipts: &[u8]) -> Vec<u8> {
+    let mut receipts: Value = serde_json::from_slice(receipts).unwrap();
+    receipts[1]["logs"][0]["data"] = format!("0x{:064x}", 1).into();
+    serde_json::to_vec_pretty(&receipts).unwrap()
+}
+
+fn new_ingestion(dir: &std::path::Path) -> EvmBlockIngestion {
+    EvmBlockIngestion::new(
+        JsonRpcProvider::replay(FixtureStore::new(dir)),
+        EvmBlockIngestionOptions {
+            ingest_pending: false,
+            ingest_traces: false,
+            trace_backend: TraceBackend::Parity,
+            ingest_state_diffs: false,
+            ingest_uncles: false,
+            chain_flavour: ChainFlavour::Ethereum,
+            verify_block_data: true,
+            head_subscription: None,
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_retry_until_verified() {
+    let dir = tempfile::tempdir().unwrap();
+    let (receipts_path, receipts) = copy_fixtures(dir.path()).await;
+    tokio::fs::write(&receipts_path, tampered(&receipts))
+        .await
+        .unwrap();
+
+    let ingestion = new_ingestion(dir.path());
+    let handle = tokio::spawn(async move { ingestion.ingest_block_by_number(BLOCK_NUMBER).await });
+
+    // The first attempt fails, fix the receipts while waiting to retry.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!handle.is_finished());
+    tokio::fs::write(&receipts_path, &receipts).await.unwrap();
+
+    let (block_info, _) = handle.await.unwrap().unwrap();
+    assert_eq!(block_info.number, BLOCK_NUMBER);
+}
+
+#[tokio::test]
+async fn test_give_up_after_max_attempts() {
+    let dir = tempfile::tempdir().unwrap();
+    let (receipts_path, receipts) = copy_fixtures(dir.path()).await;
+    tokio::fs::write(&receipts_path, tampered(&receipts))
+        .await
+        .unwrap();
+
+    let ingestion = new_ingestion(dir.path());
+    let err = ingestion
+        .ingest_block_by_number(BLOCK_NUMBER)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        err.current_context(),
+        IngestionError::Verification
+    ));
+    assert!(format!("{err:?}").contains("attempts: 3"));
+}
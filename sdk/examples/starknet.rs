@@ -16,17 +16,17 @@ const STARKNET_MAINNET_URL: &str = "https://mainnet.starknet.a5a.ch";
 pub async fn main() -> Result<(), Whatever> {
     let url: Uri = STARKNET_MAINNET_URL
         .parse()
-        .with_whatever_context(|_| format!("could not parse stream url"))?;
+        .with_whatever_context(|_| "could not parse stream url".to_string())?;
     let mut client = StreamClient::builder()
         .with_bearer_token_provider(Arc::new(BearerTokenFromEnv::default()))
         .connect(url)
         .await
-        .with_whatever_context(|_| format!("could not connect to stream"))?;
+        .with_whatever_context(|_| "could not connect to stream".to_string())?;
 
     let status = client
         .status()
         .await
-        .with_whatever_context(|_| format!("could not get status"))?;
+        .with_whatever_context(|_| "could not get status".to_string())?;
 
     let last_ingested = status.last_ingested.unwrap_or_default();
     let start_block = Cursor::new_with_block_number(last_ingested.order_key.saturating_sub(100));
@@ -35,11 +35,11 @@ pub async fn main() -> Result<(), Whatever> {
     let usdc_address = starknet::FieldElement::from_hex(
         "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8",
     )
-    .with_whatever_context(|_| format!("could not parse usdc address"))?;
+    .with_whatever_context(|_| "could not parse usdc address".to_string())?;
     let transfer_hash = starknet::FieldElement::from_hex(
         "0x99cd8bde557814842a3121e8ddfd433a539b8c9f14bf31ebf108d12e6196e9",
     )
-    .with_whatever_context(|_| format!("could not parse transfer signature hash"))?;
+    .with_whatever_context(|_| "could not parse transfer signature hash".to_string())?;
 
     // Create a request to get all USDC events (together with the transaction).
     //
@@ -62,12 +62,12 @@ pub async fn main() -> Result<(), Whatever> {
     let mut stream = client
         .stream_data(request)
         .await
-        .with_whatever_context(|_| format!("failed to start stream"))?;
+        .with_whatever_context(|_| "failed to start stream".to_string())?;
 
     while let Some(message) = stream
         .try_next()
         .await
-        .with_whatever_context(|_| format!("failed to get next message in stream"))?
+        .with_whatever_context(|_| "failed to get next message in stream".to_string())?
     {
         // Ignore non-data messages
         let DnaMessage::Data(data) = message else {
@@ -84,7 +84,7 @@ pub async fn main() -> Result<(), Whatever> {
         for block_bytes in data.data {
             // Decode the raw bytes into a Starknet block.
             let block = starknet::Block::decode(block_bytes)
-                .with_whatever_context(|_| format!("failed to parse starknet block"))?;
+                .with_whatever_context(|_| "failed to parse starknet block".to_string())?;
             // Notice that gRPC does not send values over if they have their default values.
             // So if something is `Option<_>`, it's safe to call `unwrap_or_default()` on it.
             let header = block.header.unwrap_or_default();
@@ -1,8 +1,8 @@
 use std::{pin::Pin, task::Poll, time::Duration};
 
 use apibara_dna_protocol::dna::stream::{
-    dna_stream_client::DnaStreamClient, stream_data_response, StatusRequest, StatusResponse,
-    StreamDataRequest, StreamDataResponse,
+    dna_stream_client::DnaStreamClient, stream_data_response, ListReorgsRequest,
+    ListReorgsResponse, StatusRequest, StatusResponse, StreamDataRequest, StreamDataResponse,
 };
 use pin_project::pin_project;
 use snafu::Snafu;
@@ -85,6 +85,15 @@ impl StreamClient {
         let response = self.inner.status(request).await?;
         Ok(response.into_inner())
     }
+
+    /// List the chain reorganizations seen by the DNA server.
+    pub async fn list_reorgs(
+        &mut self,
+        request: ListReorgsRequest,
+    ) -> Result<ListReorgsResponse, tonic::Status> {
+        let response = self.inner.list_reorgs(request).await?;
+        Ok(response.into_inner())
+    }
 }
 
 impl Stream for DataStream {
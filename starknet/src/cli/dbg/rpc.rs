@@ -1,3 +1,6 @@
+use std::path::PathBuf;
+
+use apibara_dna_common::{fixture::FixtureStore, ingestion::BlockIngestion};
 use clap::Subcommand;
 use error_stack::{Result, ResultExt};
 use tracing::info;
@@ -5,6 +8,7 @@ use tracing::info;
 use crate::{
     cli::rpc::RpcArgs,
     error::StarknetError,
+    ingestion::{StarknetBlockIngestion, StarknetBlockIngestionOptions},
     provider::{models, BlockId, StarknetProvider},
 };
 
@@ -18,15 +22,34 @@ pub enum DebugRpcCommand {
         #[arg(long, env, default_value = "head")]
         block_id: String,
     },
+    /// Record the responses needed to ingest a range of blocks.
+    ///
+    /// The recorded responses can be replayed offline, for example in tests.
+    Record {
+        #[clap(flatten)]
+        rpc: RpcArgs,
+        /// Directory where to store the responses.
+        #[arg(long)]
+        fixtures_dir: PathBuf,
+        /// First block to record.
+        #[arg(long)]
+        from_block: u64,
+        /// Last block to record (inclusive).
+        #[arg(long)]
+        to_block: u64,
+        /// Also record the block transaction traces.
+        #[arg(long)]
+        ingest_traces: bool,
+    },
 }
 
 impl DebugRpcCommand {
     pub async fn run(self) -> Result<(), StarknetError> {
         let rpc_provider = self.rpc_provider()?;
-        let block_id = self.block_id()?;
 
         match self {
-            DebugRpcCommand::GetBlockWithReceipts { .. } => {
+            DebugRpcCommand::GetBlockWithReceipts { ref block_id, .. } => {
+                let block_id = parse_block_id(block_id)?;
                 info!(block_id = ?block_id, "getting block with receipts");
                 let block_with_receipts = rpc_provider
                     .get_block_with_receipts(&block_id)
@@ -37,33 +60,77 @@ impl DebugRpcCommand {
 
                 Ok(())
             }
+            DebugRpcCommand::Record {
+                fixtures_dir,
+                from_block,
+                to_block,
+                ingest_traces,
+                ..
+            } => {
+                record_blocks(
+                    rpc_provider,
+                    fixtures_dir,
+                    from_block,
+                    to_block,
+                    ingest_traces,
+                )
+                .await
+            }
         }
     }
 
     fn rpc_provider(&self) -> Result<StarknetProvider, StarknetError> {
         match self {
             DebugRpcCommand::GetBlockWithReceipts { rpc, .. } => rpc.to_starknet_provider(),
+            DebugRpcCommand::Record { rpc, .. } => rpc.to_starknet_provider(),
         }
     }
+}
 
-    fn block_id(&self) -> Result<BlockId, StarknetError> {
-        let block_id = match self {
-            DebugRpcCommand::GetBlockWithReceipts { block_id, .. } => block_id,
-        };
-
-        match block_id.as_str() {
-            "head" => Ok(BlockId::Head),
-            str_value => {
-                if let Ok(number) = str_value.parse::<u64>() {
-                    return Ok(BlockId::Number(number));
-                }
-                if let Ok(hash) = models::FieldElement::from_hex(str_value) {
-                    return Ok(BlockId::Hash(hash));
-                }
-                Err(StarknetError)
-                    .attach_printable("invalid block id")
-                    .attach_printable_lazy(|| format!("block id: {}", block_id))
+fn parse_block_id(block_id: &str) -> Result<BlockId, StarknetError> {
+    match block_id {
+        "head" => Ok(BlockId::Head),
+        str_value => {
+            if let Ok(number) = str_value.parse::<u64>() {
+                return Ok(BlockId::Number(number));
             }
+            if let Ok(hash) = models::FieldElement::from_hex(str_value) {
+                return Ok(BlockId::Hash(hash));
+            }
+            Err(StarknetError)
+                .attach_printable("invalid block id")
+                .attach_printable_lazy(|| format!("block id: {}", block_id))
         }
     }
 }
+
+async fn record_blocks(
+    provider: StarknetProvider,
+    fixtures_dir: PathBuf,
+    from_block: u64,
+    to_block: u64,
+    ingest_traces: bool,
+) -> Result<(), StarknetError> {
+    let provider = provider.with_recorder(FixtureStore::new(&fixtures_dir));
+    let ingestion = StarknetBlockIngestion::new(
+        provider,
+        StarknetBlockIngestionOptions {
+            ingest_pending: false,
+            ingest_traces,
+            head_subscription: None,
+        },
+    );
+
+    for block_number in from_block..=to_block {
+        info!(block_number, "recording block");
+        ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .change_context(StarknetError)
+            .attach_printable_lazy(|| format!("failed to record block {block_number}"))?;
+    }
+
+    info!(dir = %fixtures_dir.display(), "recorded blocks");
+
+    Ok(())
+}
@@ -1,16 +1,22 @@
 mod canon;
 mod dbg;
+mod repair;
 mod rpc;
 mod start;
 
+use apibara_dna_common::{
+    compaction::{GarbageCollectCommand, RecompactCommand},
+    dbg::DebugReorgsCommand,
+    snapshot::{ExportCommand, ImportCommand},
+};
 use clap::{Parser, Subcommand};
 use dbg::DebugPrefetchCommand;
-use error_stack::Result;
+use error_stack::{Result, ResultExt};
 use tokio_util::sync::CancellationToken;
 
 use crate::{cli::canon::CanonCommand, error::StarknetError};
 
-use self::{dbg::DebugRpcCommand, start::StartCommand};
+use self::{dbg::DebugRpcCommand, repair::RepairCommand, start::StartCommand};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -38,6 +44,19 @@ pub enum Command {
         #[clap(subcommand)]
         command: CanonCommand,
     },
+    /// List the chain reorganizations seen by the ingestion service.
+    #[command(name = "dbg-reorgs")]
+    DebugReorgs(Box<DebugReorgsCommand>),
+    /// Rebuild segments and groups with a different segment or group size.
+    Recompact(Box<RecompactCommand>),
+    /// Re-ingest a range of finalized blocks and overwrite the stored data.
+    Repair(Box<RepairCommand>),
+    /// Export a snapshot of the bucket and ingestion state.
+    Export(Box<ExportCommand>),
+    /// Import a snapshot exported with the `export` command.
+    Import(Box<ImportCommand>),
+    /// Delete objects that are not reachable anymore.
+    Gc(Box<GarbageCollectCommand>),
 }
 
 impl Cli {
@@ -47,6 +66,12 @@ impl Cli {
             Command::DebugRpc { command } => command.run().await,
             Command::DebugPrefetch(command) => command.run(ct).await,
             Command::Canon { command } => command.run().await,
+            Command::DebugReorgs(command) => command.run(ct).await.change_context(StarknetError),
+            Command::Recompact(command) => command.run(ct).await.change_context(StarknetError),
+            Command::Repair(command) => command.run(ct).await,
+            Command::Export(command) => command.run(ct).await.change_context(StarknetError),
+            Command::Import(command) => command.run(ct).await.change_context(StarknetError),
+            Command::Gc(command) => command.run(ct).await.change_context(StarknetError),
         }
     }
 }
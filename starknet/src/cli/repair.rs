@@ -0,0 +1,43 @@
+use apibara_dna_common::{compaction::RepairArgs, ChainSupport};
+use clap::Args;
+use error_stack::{Result, ResultExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{error::StarknetError, StarknetBlockIngestionOptions, StarknetChainSupport};
+
+use super::rpc::RpcArgs;
+
+#[derive(Args, Debug)]
+pub struct RepairCommand {
+    #[clap(flatten)]
+    rpc: RpcArgs,
+    #[clap(flatten)]
+    repair: RepairArgs,
+
+    /// Ingest traces.
+    ///
+    /// Must match the option used by the ingestion service.
+    #[arg(
+        long = "starknet.ingest-traces",
+        env = "STARKNET_INGEST_TRACES",
+        default_value = "false"
+    )]
+    ingest_traces: bool,
+}
+
+impl RepairCommand {
+    pub async fn run(self, ct: CancellationToken) -> Result<(), StarknetError> {
+        let provider = self.rpc.to_starknet_provider()?;
+        let starknet_ingestion_options = StarknetBlockIngestionOptions {
+            ingest_pending: false,
+            ingest_traces: self.ingest_traces,
+            head_subscription: None,
+        };
+        let starknet_chain = StarknetChainSupport::new(provider, starknet_ingestion_options);
+
+        self.repair
+            .run(starknet_chain.block_ingestion(), ct)
+            .await
+            .change_context(StarknetError)
+    }
+}
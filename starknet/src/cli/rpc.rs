@@ -1,10 +1,13 @@
 use std::time::Duration;
 
+use apibara_dna_common::{
+    ingestion::{HeadEvent, JsonRpcWsSubscription},
+    provider_pool::ProviderPoolArgs,
+};
 use backon::ExponentialBuilder;
 use clap::Args;
 use error_stack::{Result, ResultExt};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use url::Url;
 
 use crate::{
     error::StarknetError,
@@ -13,13 +16,16 @@ use crate::{
 
 #[derive(Args, Debug)]
 pub struct RpcArgs {
-    /// Starknet RPC URL.
+    /// Starknet RPC URLs.
+    ///
+    /// Pass multiple URLs to spread requests and fail over between them.
     #[arg(
         long = "rpc.url",
         env = "STARKNET_RPC_URL",
+        value_delimiter = ',',
         default_value = "http://localhost:8545"
     )]
-    pub rpc_url: String,
+    pub rpc_url: Vec<String>,
 
     /// Request timeout.
     #[arg(
@@ -32,16 +38,37 @@ pub struct RpcArgs {
     /// Headers to send with the requests.
     #[arg(long = "rpc.headers", env = "STARKNET_RPC_HEADERS")]
     pub rpc_headers: Vec<String>,
+
+    /// WebSocket URL used to subscribe to new heads with `starknet_subscribeNewHeads`.
+    ///
+    /// The RPC is polled for new heads if not set, or while the subscription is disconnected.
+    #[arg(long = "rpc.ws-url", env = "STARKNET_RPC_WS_URL")]
+    pub rpc_ws_url: Option<String>,
+
+    #[clap(flatten)]
+    pub pool: ProviderPoolArgs,
 }
 
 impl RpcArgs {
+    pub fn to_head_subscription(&self) -> Option<JsonRpcWsSubscription> {
+        let url = self.rpc_ws_url.as_ref()?;
+        Some(JsonRpcWsSubscription::new(
+            url,
+            "starknet_subscribeNewHeads",
+            serde_json::json!({}),
+            HeadEvent::Head,
+        ))
+    }
+
     pub fn to_starknet_provider(&self) -> Result<StarknetProvider, StarknetError> {
-        let url = self
-            .rpc_url
-            .parse::<Url>()
-            .change_context(StarknetError)
-            .attach_printable("failed to parse RPC URL")
-            .attach_printable_lazy(|| format!("url: {}", self.rpc_url))?;
+        let upstreams = self
+            .pool
+            .to_upstreams(&self.rpc_url)
+            .change_context(StarknetError)?;
+        let pool = self
+            .pool
+            .to_provider_pool_options()
+            .change_context(StarknetError)?;
 
         let headers = {
             let mut headers = HeaderMap::default();
@@ -75,8 +102,9 @@ impl RpcArgs {
             timeout,
             headers,
             exponential_backoff: ExponentialBuilder::default().with_max_delay(max_delay),
+            pool,
         };
 
-        StarknetProvider::new(url, options).change_context(StarknetError)
+        StarknetProvider::new(upstreams, options).change_context(StarknetError)
     }
 }
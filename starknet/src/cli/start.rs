@@ -31,6 +31,7 @@ impl StartCommand {
         let starknet_ingestion_options = StarknetBlockIngestionOptions {
             ingest_pending: false,
             ingest_traces: self.ingest_traces,
+            head_subscription: self.rpc.to_head_subscription(),
         };
         let starknet_chain = StarknetChainSupport::new(provider, starknet_ingestion_options);
 
@@ -5,7 +5,7 @@ use apibara_dna_common::{
         JoinFragment, JoinGroupFragment,
     },
     index::{BitmapIndexBuilder, ScalarValue},
-    ingestion::{BlockIngestion, IngestionError},
+    ingestion::{BlockIngestion, HeadNotifications, IngestionError, JsonRpcWsSubscription},
     join::{JoinToManyIndexBuilder, JoinToOneIndexBuilder},
     Cursor, Hash,
 };
@@ -13,6 +13,7 @@ use apibara_dna_protocol::starknet;
 use error_stack::{Result, ResultExt};
 use prost::Message;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 use tracing::trace;
 
 use crate::{
@@ -38,6 +39,8 @@ use crate::{
 pub struct StarknetBlockIngestionOptions {
     pub ingest_pending: bool,
     pub ingest_traces: bool,
+    /// Subscribe to new heads instead of polling.
+    pub head_subscription: Option<JsonRpcWsSubscription>,
 }
 
 pub struct StarknetBlockIngestion {
@@ -68,6 +71,11 @@ impl BlockIngestion for StarknetBlockIngestion {
         self.options.ingest_pending
     }
 
+    fn head_notifications(&self, ct: CancellationToken) -> Option<HeadNotifications> {
+        let subscription = self.options.head_subscription.clone()?;
+        Some(HeadNotifications::spawn(subscription, ct))
+    }
+
     #[tracing::instrument("starknet_get_head_cursor", skip_all, err(Debug), level = "debug")]
     async fn get_head_cursor(&self) -> Result<Cursor, IngestionError> {
         let cursor = self
@@ -1,13 +1,20 @@
 use std::{sync::Arc, time::Duration};
 
+use apibara_dna_common::{
+    fixture::FixtureStore,
+    provider_pool::{
+        ConsistentRead, ProviderPool, ProviderPoolOptions, UpstreamConfig, UpstreamError,
+    },
+};
 use backon::{ExponentialBuilder, Retryable};
 use error_stack::{Report, Result, ResultExt};
 use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{de::DeserializeOwned, Serialize};
 use starknet_rust::providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider};
 use tracing::warn;
 use url::Url;
 
-use super::models;
+use super::models::{self, BlockExt};
 
 #[derive(Debug)]
 pub enum StarknetProviderError {
@@ -37,10 +44,27 @@ pub struct StarknetProviderOptions {
     pub headers: HeaderMap<HeaderValue>,
     /// Exponential backoff configuration.
     pub exponential_backoff: ExponentialBuilder,
+    /// Upstream pool options.
+    pub pool: ProviderPoolOptions,
 }
 
 #[derive(Clone)]
 pub struct StarknetProvider {
+    backend: StarknetBackend,
+    recorder: Option<FixtureStore>,
+}
+
+#[derive(Clone)]
+enum StarknetBackend {
+    /// Send requests to the upstream nodes.
+    Pool(ProviderPool<StarknetUpstream>),
+    /// Serve responses recorded previously.
+    Replay(FixtureStore),
+}
+
+/// A client for a single Starknet RPC endpoint.
+#[derive(Clone)]
+struct StarknetUpstream {
     client: Arc<JsonRpcClient<HttpTransport>>,
     options: StarknetProviderOptions,
 }
@@ -50,7 +74,208 @@ pub trait StarknetProviderErrorExt {
 }
 
 impl StarknetProvider {
-    pub fn new(url: Url, options: StarknetProviderOptions) -> Result<Self, StarknetProviderError> {
+    pub fn new(
+        upstreams: Vec<UpstreamConfig>,
+        options: StarknetProviderOptions,
+    ) -> Result<Self, StarknetProviderError> {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|upstream| {
+                let provider = StarknetUpstream::new(upstream.url.clone(), options.clone())?;
+                Ok((upstream, provider))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pool = ProviderPool::new(upstreams, options.pool)
+            .change_context(StarknetProviderError::Configuration)?;
+
+        Ok(Self {
+            backend: StarknetBackend::Pool(pool),
+            recorder: None,
+        })
+    }
+
+    /// Create a provider that serves the responses recorded in the given fixture store.
+    pub fn replay(fixtures: FixtureStore) -> Self {
+        Self {
+            backend: StarknetBackend::Replay(fixtures),
+            recorder: None,
+        }
+    }
+
+    /// Record all responses to the given fixture store.
+    pub fn with_recorder(mut self, fixtures: FixtureStore) -> Self {
+        self.recorder = Some(fixtures);
+        self
+    }
+
+    pub async fn get_block_with_tx_hashes(
+        &self,
+        block_id: &BlockId,
+    ) -> Result<models::MaybePreConfirmedBlockWithTxHashes, StarknetProviderError> {
+        let key = block_id.fixture_key();
+        let pool = match &self.backend {
+            StarknetBackend::Pool(pool) => pool,
+            StarknetBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_with_tx_hashes", &key).await;
+            }
+        };
+
+        let request = |upstream: StarknetUpstream| {
+            let block_id = block_id.clone();
+            async move { upstream.get_block_with_tx_hashes(&block_id).await }
+        };
+
+        let response = match block_id {
+            BlockId::Head => {
+                pool.request_consistent(
+                    ConsistentRead::Head,
+                    "get_block_with_tx_hashes",
+                    request,
+                    |block: &models::MaybePreConfirmedBlockWithTxHashes| {
+                        block
+                            .cursor()
+                            .map(|cursor| cursor.number)
+                            .unwrap_or_default()
+                    },
+                )
+                .await?
+            }
+            _ => pool.request("get_block_with_tx_hashes", request).await?,
+        };
+
+        self.record("get_block_with_tx_hashes", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_block_with_receipts(
+        &self,
+        block_id: &BlockId,
+    ) -> Result<models::MaybePreConfirmedBlockWithReceipts, StarknetProviderError> {
+        let key = block_id.fixture_key();
+        let pool = match &self.backend {
+            StarknetBackend::Pool(pool) => pool,
+            StarknetBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_with_receipts", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_block_with_receipts", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_block_with_receipts(&block_id).await }
+            })
+            .await?;
+
+        self.record("get_block_with_receipts", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_state_update(
+        &self,
+        block_id: &BlockId,
+    ) -> Result<models::MaybePreConfirmedStateUpdate, StarknetProviderError> {
+        let key = block_id.fixture_key();
+        let pool = match &self.backend {
+            StarknetBackend::Pool(pool) => pool,
+            StarknetBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_state_update", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_state_update", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_state_update(&block_id).await }
+            })
+            .await?;
+
+        self.record("get_state_update", &key, &response).await?;
+
+        Ok(response)
+    }
+
+    pub async fn get_block_transaction_traces(
+        &self,
+        block_id: &BlockId,
+    ) -> Result<Vec<models::TransactionTraceWithHash>, StarknetProviderError> {
+        let key = block_id.fixture_key();
+        let pool = match &self.backend {
+            StarknetBackend::Pool(pool) => pool,
+            StarknetBackend::Replay(fixtures) => {
+                return replay(fixtures, "get_block_transaction_traces", &key).await;
+            }
+        };
+
+        let response = pool
+            .hedged_request("get_block_transaction_traces", |upstream| {
+                let block_id = block_id.clone();
+                async move { upstream.get_block_transaction_traces(&block_id).await }
+            })
+            .await?;
+
+        self.record("get_block_transaction_traces", &key, &response)
+            .await?;
+
+        Ok(response)
+    }
+
+    async fn record<T>(
+        &self,
+        method: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), StarknetProviderError>
+    where
+        T: Serialize,
+    {
+        let Some(recorder) = &self.recorder else {
+            return Ok(());
+        };
+
+        recorder
+            .put(method, key, value)
+            .await
+            .change_context(StarknetProviderError::Request)
+            .attach_printable("failed to record response")
+    }
+}
+
+async fn replay<T>(
+    fixtures: &FixtureStore,
+    method: &str,
+    key: &str,
+) -> Result<T, StarknetProviderError>
+where
+    T: DeserializeOwned,
+{
+    fixtures
+        .get(method, key)
+        .await
+        .change_context(StarknetProviderError::Request)?
+        .ok_or(StarknetProviderError::NotFound)
+        .attach_printable("response not recorded")
+        .attach_printable_lazy(|| format!("method: {method}, key: {key}"))
+}
+
+impl BlockId {
+    /// Returns the key used to record responses for this block.
+    fn fixture_key(&self) -> String {
+        match self {
+            BlockId::Head => "head".to_string(),
+            BlockId::Pending => "pending".to_string(),
+            BlockId::Number(number) => number.to_string(),
+            BlockId::Hash(hash) => format!("{hash:#x}"),
+        }
+    }
+}
+
+impl StarknetUpstream {
+    fn new(url: Url, options: StarknetProviderOptions) -> Result<Self, StarknetProviderError> {
         let mut transport = HttpTransport::new(url);
         for (key, value) in options.headers.iter() {
             let key = key.to_string();
@@ -67,7 +292,7 @@ impl StarknetProvider {
         Ok(Self { client, options })
     }
 
-    pub async fn get_block_with_tx_hashes(
+    async fn get_block_with_tx_hashes(
         &self,
         block_id: &BlockId,
     ) -> Result<models::MaybePreConfirmedBlockWithTxHashes, StarknetProviderError> {
@@ -94,7 +319,7 @@ impl StarknetProvider {
             .attach_printable_lazy(|| format!("block id: {block_id:?}"))
     }
 
-    pub async fn get_block_with_receipts(
+    async fn get_block_with_receipts(
         &self,
         block_id: &BlockId,
     ) -> Result<models::MaybePreConfirmedBlockWithReceipts, StarknetProviderError> {
@@ -117,7 +342,7 @@ impl StarknetProvider {
             .attach_printable_lazy(|| format!("block id: {block_id:?}"))
     }
 
-    pub async fn get_state_update(
+    async fn get_state_update(
         &self,
         block_id: &BlockId,
     ) -> Result<models::MaybePreConfirmedStateUpdate, StarknetProviderError> {
@@ -140,7 +365,7 @@ impl StarknetProvider {
             .attach_printable_lazy(|| format!("block id: {block_id:?}"))
     }
 
-    pub async fn get_block_transaction_traces(
+    async fn get_block_transaction_traces(
         &self,
         block_id: &BlockId,
     ) -> Result<Vec<models::TransactionTraceWithHash>, StarknetProviderError> {
@@ -233,6 +458,15 @@ fn convert_error<T>(
     }
 }
 
+impl UpstreamError for StarknetProviderError {
+    fn is_upstream_failure(&self) -> bool {
+        matches!(
+            self,
+            StarknetProviderError::Request | StarknetProviderError::Timeout
+        )
+    }
+}
+
 impl StarknetProviderErrorExt for Report<StarknetProviderError> {
     fn is_not_found(&self) -> bool {
         matches!(self.current_context(), StarknetProviderError::NotFound)
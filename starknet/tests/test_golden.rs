@@ -0,0 +1,62 @@
+//! Ingest the blocks in `tests/fixtures` and compare them with the golden files.
+//!
+//! The committed blocks are synthetic, see `tests/fixtures/README.md`. Record real blocks with:
+//!
+//! ```sh
+//! apibara-dna-starknet dbg-rpc record --fixtures-dir starknet/tests/fixtures --from-block N --to-block M
+//! DNA_UPDATE_GOLDEN=1 cargo test -p apibara-dna-starknet --test test_golden
+//! ```
+use std::path::PathBuf;
+
+use apibara_dna_common::{
+    fixture::{assert_golden, FixtureStore},
+    ingestion::BlockIngestion,
+};
+use apibara_dna_starknet::{
+    ingestion::{StarknetBlockIngestion, StarknetBlockIngestionOptions},
+    provider::StarknetProvider,
+};
+
+#[tokio::test]
+async fn test_golden_blocks() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let fixtures = FixtureStore::new(&dir);
+
+    let blocks = fixtures.keys("get_block_with_receipts").await.unwrap();
+    assert!(
+        !blocks.is_empty(),
+        "no blocks recorded in {}",
+        dir.display()
+    );
+
+    let ingest_traces = !fixtures
+        .keys("get_block_transaction_traces")
+        .await
+        .unwrap()
+        .is_empty();
+
+    let ingestion = StarknetBlockIngestion::new(
+        StarknetProvider::replay(fixtures),
+        StarknetBlockIngestionOptions {
+            ingest_pending: false,
+            ingest_traces,
+            head_subscription: None,
+        },
+    );
+
+    for key in blocks {
+        let block_number = key
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("invalid block fixture key: {key}"));
+        let (block_info, block) = ingestion
+            .ingest_block_by_number(block_number)
+            .await
+            .unwrap_or_else(|err| panic!("failed to ingest block {block_number}: {err:?}"));
+
+        assert_eq!(block_info.number, block_number);
+        assert!(!block.body.is_empty());
+
+        let data = rkyv::to_bytes::<rkyv::rancor::Error>(&block).unwrap();
+        assert_golden(dir.join(format!("golden/{block_number}.bin")), &data);
+    }
+}